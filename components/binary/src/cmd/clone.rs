@@ -0,0 +1,96 @@
+use std::{
+    fs::File,
+    io,
+    os::{
+        fd::AsRawFd,
+        unix::{ffi::OsStrExt, fs::MetadataExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use kiseki_common::MAX_NAME_LENGTH;
+use kiseki_types::{
+    ino::Ino,
+    ioctl::{CloneRequest, IOC_CLONE},
+};
+use snafu::{ensure_whatever, whatever, OptionExt, ResultExt, Whatever};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Clone a file or a directory tree of a mounted kiseki-fs. The clone borrows
+the data of the source, so it is done in a moment no matter how large the
+source is. The source and the destination must be in the same mount, and a
+symbolic link source is followed.
+Examples:
+
+# Clone the dataset for a job
+kiseki clone /tmp/kiseki/dataset /tmp/kiseki/job-1
+")]
+pub struct CloneArgs {
+    #[arg(help = "The file or directory to clone", value_name = "SRC")]
+    pub src:      PathBuf,
+    #[arg(help = "The path of the clone, it must not exist", value_name = "DST")]
+    pub dst:      PathBuf,
+    #[arg(
+        long,
+        short,
+        help = "Preserve the owner and the timestamps of the source"
+    )]
+    pub preserve: bool,
+}
+
+impl CloneArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        let name = self
+            .dst
+            .file_name()
+            .with_whatever_context(|| format!("invalid destination {}", self.dst.display()))?;
+        ensure_whatever!(
+            name.len() <= MAX_NAME_LENGTH,
+            "the name of {} is too long",
+            self.dst.display()
+        );
+        let parent = match self.dst.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let parent_meta = std::fs::metadata(parent)
+            .with_whatever_context(|e| format!("failed to stat {}, {}", parent.display(), e))?;
+        let src = File::open(&self.src)
+            .with_whatever_context(|e| format!("failed to open {}, {}", self.src.display(), e))?;
+        let src_meta = src
+            .metadata()
+            .with_whatever_context(|e| format!("failed to stat {}, {}", self.src.display(), e))?;
+        ensure_whatever!(
+            src_meta.dev() == parent_meta.dev(),
+            "{} and {} are not in the same mount",
+            self.src.display(),
+            parent.display()
+        );
+
+        let req = CloneRequest {
+            parent:   Ino(parent_meta.ino()),
+            name:     name.as_bytes().to_vec(),
+            preserve: self.preserve,
+        };
+        let buf = req.encode();
+        // SAFETY: the buf lives through the call, and its size is the one
+        // encoded in the command.
+        let ret = unsafe { libc::ioctl(src.as_raw_fd(), IOC_CLONE as _, buf.as_ptr()) };
+        if ret != 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() == Some(libc::ENOTTY) {
+                whatever!("{} is not in a kiseki-fs mount", self.src.display());
+            }
+            whatever!(
+                "failed to clone {} to {}, {}",
+                self.src.display(),
+                self.dst.display(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::Whatever;
+
+use crate::cmd::control::{call, inode_of};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Compact the files, or the files under the directories, of a mounted
+kiseki-fs: the slices totally covered by the later writes are dropped, which
+makes the reads of the files overwritten many times faster.
+Examples:
+
+kiseki compact /tmp/kiseki/logs
+")]
+pub struct CompactArgs {
+    #[arg(
+        help = "The files or directories to compact",
+        value_name = "PATH",
+        required = true
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+impl CompactArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        for path in &self.paths {
+            let req = ControlRequest::Compact {
+                inode: inode_of(path)?,
+            };
+            println!("{}: {}", path.display(), call(path, &req)?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use clap::Args;
+use kiseki_utils::readable_size::ReadableSize;
+use snafu::{ResultExt, Whatever};
+use tracing::info;
+
+use crate::cmd::format::{validate_capacity, validate_trash_day};
+
+const MANAGEMENT_OPTIONS_HEADER: &str = "MANAGEMENT";
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Change the settings of an existing volume. Only the settings which don't
+affect the data layout can be changed here, print the current settings if
+nothing is given.
+Examples:
+
+# Show the current settings
+kiseki config rocksdb://:/tmp/kiseki.meta
+
+# Limit the volume to 100GiB and 1 million inodes
+kiseki config rocksdb://:/tmp/kiseki.meta --capacity 100G --inodes 1000000
+")]
+pub struct ConfigArgs {
+    #[arg(
+        help = "Specify the address of the meta store",
+        value_name = "META_DSN"
+    )]
+    pub meta_dsn: String,
+
+    #[arg(
+    long,
+    short,
+    help = "hard quota of the volume limiting its usage of space, 0 for unlimited",
+    help_heading = MANAGEMENT_OPTIONS_HEADER,
+    value_parser = validate_capacity,
+    )]
+    pub capacity: Option<ReadableSize>,
+
+    #[arg(
+    long,
+    short,
+    help = "hard quota of the volume limiting its number of inodes, 0 for unlimited",
+    help_heading = MANAGEMENT_OPTIONS_HEADER,
+    )]
+    pub inodes: Option<usize>,
+
+    #[arg(
+    long,
+    help = "number of days after which removed files will be permanently deleted",
+    help_heading = MANAGEMENT_OPTIONS_HEADER,
+    value_parser = validate_trash_day,
+    )]
+    pub trash_days: Option<usize>,
+}
+
+impl ConfigArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        kiseki_utils::logger::install_fmt_log();
+        let mut format = kiseki_meta::load_format(&self.meta_dsn)
+            .with_whatever_context(|e| format!("failed to load the settings, {}", e))?;
+
+        let mut changed = false;
+        if let Some(capacity) = self.capacity {
+            let capacity = capacity.as_bytes_usize();
+            format.max_capacity = (capacity > 0).then_some(capacity);
+            changed = true;
+        }
+        if let Some(inodes) = self.inodes {
+            format.max_inodes = (inodes > 0).then_some(inodes);
+            changed = true;
+        }
+        if let Some(trash_days) = self.trash_days {
+            format.trash_days = trash_days;
+            changed = true;
+        }
+
+        if changed {
+            kiseki_meta::update_format(&self.meta_dsn, format.clone(), false)
+                .with_whatever_context(|e| format!("failed to update the settings, {}", e))?;
+            info!("update the settings of {} success", format.name);
+        }
+        println!("{:#?}", format);
+        Ok(())
+    }
+}
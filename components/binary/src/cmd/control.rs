@@ -0,0 +1,96 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    os::unix::fs::MetadataExt,
+    path::Path,
+};
+
+use kiseki_types::{
+    control::{decode_frame, encode_frame, ControlRequest, ControlResponse},
+    ino::Ino,
+    internal_nodes::CONTROL_INODE_NAME,
+};
+use snafu::{whatever, ResultExt, Whatever};
+
+/// [inode_of] returns the inode of the path in the mount, the symbolic links
+/// are followed.
+pub fn inode_of(path: &Path) -> Result<Ino, Whatever> {
+    let meta = std::fs::metadata(path)
+        .with_whatever_context(|e| format!("failed to stat {}, {}", path.display(), e))?;
+    Ok(Ino(meta.ino()))
+}
+
+/// [call] runs the request in the mount the path belongs to, through the
+/// `.control` file in its root, and returns the result. The progress of a
+/// long running request is shown on the terminal meanwhile.
+pub fn call(path: &Path, req: &ControlRequest) -> Result<String, Whatever> {
+    let mut control = open_internal(path, CONTROL_INODE_NAME, true)?;
+    control
+        .write_all(&encode_frame(req))
+        .with_whatever_context(|e| format!("failed to send the request, {}", e))?;
+    let show_progress = io::stderr().is_terminal();
+    let mut buf = Vec::new();
+    let resp = loop {
+        match decode_frame::<ControlResponse>(&buf) {
+            Some(Ok(resp)) if !resp.done => {
+                if show_progress {
+                    eprint!("\r\x1b[K{}", resp.message.replace('\n', ", "));
+                }
+                buf.clear();
+            }
+            Some(resp) => break resp,
+            None => {}
+        }
+        let mut chunk = [0; 4096];
+        let n = control
+            .read(&mut chunk)
+            .with_whatever_context(|e| format!("failed to read the response, {}", e))?;
+        if n == 0 {
+            whatever!("the response is truncated");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    if show_progress {
+        eprint!("\r\x1b[K");
+    }
+    let Ok(resp) = resp else {
+        whatever!("the response is corrupted");
+    };
+    if resp.errno != 0 {
+        whatever!(
+            "{}, {}",
+            io::Error::from_raw_os_error(resp.errno),
+            resp.message
+        );
+    }
+    Ok(resp.message)
+}
+
+/// [open_internal] opens the internal node of the name, like `.stats`, in the
+/// root of the mount the path belongs to, the root is the topmost directory
+/// on the same device as the path.
+pub fn open_internal(path: &Path, name: &str, write: bool) -> Result<File, Whatever> {
+    let path = path
+        .canonicalize()
+        .with_whatever_context(|e| format!("failed to resolve {}, {}", path.display(), e))?;
+    let dev = std::fs::metadata(&path)
+        .with_whatever_context(|e| format!("failed to stat {}, {}", path.display(), e))?
+        .dev();
+    let mut root = path.as_path();
+    for p in path.ancestors().skip(1) {
+        match std::fs::metadata(p) {
+            Ok(meta) if meta.dev() == dev => root = p,
+            _ => break,
+        }
+    }
+    // the internal nodes may be prefixed, see Config::prefix_internal.
+    let candidates = [root.join(name), root.join(format!(".kfs{name}"))];
+    for node in &candidates {
+        match OpenOptions::new().read(true).write(write).open(node) {
+            Ok(f) => return Ok(f),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => whatever!("failed to open {}, {}", node.display(), e),
+        }
+    }
+    whatever!("{} is not in a kiseki-fs mount", path.display())
+}
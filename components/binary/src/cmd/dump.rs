@@ -0,0 +1,39 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use clap::Args;
+use snafu::{ResultExt, Whatever};
+use tracing::info;
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Dump the metadata of a volume into a JSON file. The dump is taken from a
+consistent snapshot of the meta store.
+Examples:
+
+# Dump the metadata into meta-dump.json
+kiseki dump rocksdb://:/tmp/kiseki.meta meta-dump.json
+")]
+pub struct DumpArgs {
+    #[arg(
+        help = "Specify the address of the meta store",
+        value_name = "META_DSN"
+    )]
+    pub meta_dsn: String,
+
+    #[arg(help = "The file to write the metadata to", value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+impl DumpArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        kiseki_utils::logger::install_fmt_log();
+        let file = File::create(&self.file).with_whatever_context(|e| {
+            format!("failed to create {}, {}", self.file.display(), e)
+        })?;
+        kiseki_meta::dump(&self.meta_dsn, BufWriter::new(file))
+            .with_whatever_context(|e| format!("failed to dump metadata, {:?}", e))?;
+        info!("dump metadata to {} success", self.file.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::Whatever;
+
+use crate::cmd::control::{call, inode_of};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Write the data of the files buffered by a mounted kiseki-fs to the object
+storage, without waiting for the writers to close or sync them.
+Examples:
+
+kiseki flush /tmp/kiseki/output.log
+")]
+pub struct FlushArgs {
+    #[arg(help = "The files to flush", value_name = "PATH", required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+impl FlushArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        for path in &self.paths {
+            let req = ControlRequest::Flush {
+                inode: inode_of(path)?,
+            };
+            println!("{}: {}", path.display(), call(path, &req)?);
+        }
+        Ok(())
+    }
+}
@@ -41,9 +41,22 @@ pub struct FormatArgs {
     )]
     pub meta_dsn: Option<String>,
 
-    #[arg(long, short, help = "overwrite existing format", help_heading = FORMAT_OPTIONS_HEADER)]
+    #[arg(
+    long,
+    short,
+    help = "overwrite the data layout of an existing but empty volume",
+    help_heading = FORMAT_OPTIONS_HEADER
+    )]
     pub force: bool,
 
+    #[arg(
+    long,
+    help = "Specify the address of the object storage",
+    help_heading = FORMAT_OPTIONS_HEADER,
+    default_value = kiseki_common::KISEKI_DEBUG_OBJECT_STORAGE,
+    )]
+    pub storage: String,
+
     // #[arg(long, help = "compression algorithm", help_heading = FORMAT_OPTIONS_HEADER)]
     // pub compression: Option<Compression>,
     #[arg(
@@ -71,6 +84,15 @@ pub struct FormatArgs {
     help_heading = MANAGEMENT_OPTIONS_HEADER,
     )]
     pub inodes: Option<usize>,
+
+    #[arg(
+    long,
+    help = "number of days after which removed files will be permanently deleted",
+    help_heading = MANAGEMENT_OPTIONS_HEADER,
+    default_value = "1",
+    value_parser = validate_trash_day,
+    )]
+    pub trash_days: usize,
 }
 
 impl FormatArgs {
@@ -82,6 +104,8 @@ impl FormatArgs {
         }
         format.block_size = self.block_size.as_bytes_usize();
         format.name = self.name.clone();
+        format.storage = self.storage.clone();
+        format.trash_days = self.trash_days;
         format
     }
 
@@ -92,7 +116,8 @@ impl FormatArgs {
             .clone()
             .expect("meta_dsn should be validated in the argument parser");
         let format = self.generate_format();
-        kiseki_meta::update_format(&dsn, format, true).unwrap();
+        kiseki_meta::update_format(&dsn, format, self.force)
+            .with_whatever_context(|e| format!("failed to format {}, {}", self.name, e))?;
         info!("format file system {:?} success", self.name);
         Ok(())
     }
@@ -116,7 +141,7 @@ fn validate_name(name: &str) -> Result<String, String> {
     Ok(name.to_string())
 }
 
-fn validate_trash_day(s: &str) -> Result<usize, String> {
+pub(crate) fn validate_trash_day(s: &str) -> Result<usize, String> {
     clap_num::number_range(s, 1, u64::MAX as usize)
 }
 
@@ -133,7 +158,7 @@ fn validate_block_size(s: &str) -> Result<ReadableSize, String> {
     Ok(ReadableSize(n as u64))
 }
 
-fn validate_capacity(s: &str) -> Result<ReadableSize, String> {
+pub(crate) fn validate_capacity(s: &str) -> Result<ReadableSize, String> {
     let n = ReadableSize::from_str(s).map_err(|e| format!("invalid capacity: {}", e))?;
     let n = n.as_bytes() as usize;
 
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::Whatever;
+
+use crate::cmd::control::call;
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Delete the chunks left behind by the removed files of the volume, as the
+client crashed or failed to delete them. The files removed within the last
+hour are left to the client which removed them.
+Examples:
+
+kiseki gc /tmp/kiseki
+")]
+pub struct GcArgs {
+    #[arg(
+        help = "Any directory of the mounted volume",
+        value_name = "MOUNT_POINT",
+        default_value = "/tmp/kiseki"
+    )]
+    pub mount_point: PathBuf,
+}
+
+impl GcArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        println!("{}", call(&self.mount_point, &ControlRequest::Gc)?);
+        Ok(())
+    }
+}
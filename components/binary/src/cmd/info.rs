@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::Whatever;
+
+use crate::cmd::control::{call, inode_of};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Show the inode of a file or a directory of a mounted kiseki-fs, along with
+how many files and directories are under it and their sizes.
+Examples:
+
+kiseki info /tmp/kiseki/dataset
+")]
+pub struct InfoArgs {
+    #[arg(
+        help = "The files or directories to show",
+        value_name = "PATH",
+        required = true
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+impl InfoArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        for path in &self.paths {
+            let req = ControlRequest::Info {
+                inode: inode_of(path)?,
+            };
+            println!("{}:\n{}", path.display(), call(path, &req)?);
+        }
+        Ok(())
+    }
+}
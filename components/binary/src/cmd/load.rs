@@ -0,0 +1,38 @@
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use clap::Args;
+use snafu::{ResultExt, Whatever};
+use tracing::info;
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Load the metadata from a JSON file produced by `kiseki dump` into an empty
+meta store. The meta store can be of any supported kind.
+Examples:
+
+# Load the metadata from meta-dump.json
+kiseki load rocksdb://:/tmp/kiseki.meta meta-dump.json
+")]
+pub struct LoadArgs {
+    #[arg(
+        help = "Specify the address of the meta store",
+        value_name = "META_DSN"
+    )]
+    pub meta_dsn: String,
+
+    #[arg(help = "The file to read the metadata from", value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+impl LoadArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        kiseki_utils::logger::install_fmt_log();
+        let file = File::open(&self.file)
+            .with_whatever_context(|e| format!("failed to open {}, {}", self.file.display(), e))?;
+        kiseki_meta::load(&self.meta_dsn, BufReader::new(file))
+            .with_whatever_context(|e| format!("failed to load metadata, {:?}", e))?;
+        info!("load metadata from {} success", self.file.display());
+        Ok(())
+    }
+}
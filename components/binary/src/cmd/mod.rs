@@ -1,3 +1,18 @@
+pub mod clone;
+pub mod compact;
+pub mod config;
+pub mod control;
+pub mod dump;
+pub mod flush;
 pub mod format;
+pub mod gc;
+pub mod info;
+pub mod load;
 pub mod mount;
+pub mod profile;
+pub mod restore;
+pub mod rmr;
+pub mod stats;
 pub mod unmount;
+pub mod upgrade;
+pub mod warmup;
@@ -15,14 +15,18 @@
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
 use clap::{Args, Parser};
 use fuser::MountOption;
 use kiseki_common::{KISEKI, KISEKI_DEBUG_META_ADDR};
 use kiseki_fuse::{null, FuseConfig};
-use kiseki_meta::MetaConfig;
-use kiseki_utils::logger::{LoggingOptions, DEFAULT_LOG_DIR};
+use kiseki_meta::{AccessTimeMode, MetaConfig};
+use kiseki_utils::{
+    logger::{LoggingOptions, DEFAULT_LOG_DIR},
+    readable_size::ReadableSize,
+};
 use kiseki_vfs::{Config as VFSConfig, KisekiVFS};
 use snafu::{whatever, ResultExt, Whatever};
 use tracing::info;
@@ -78,6 +82,15 @@ pub struct MountArgs {
     )]
     pub allow_other: bool,
 
+    #[arg(
+    long,
+    help = "When to update the access time: noatime, relatime or strictatime",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    value_name = "MODE",
+    default_value = "noatime",
+    )]
+    pub atime_mode: AccessTimeMode,
+
     #[arg(
     long,
     help = "Number of threads to use for tokio async runtime",
@@ -86,6 +99,73 @@ pub struct MountArgs {
     )]
     pub async_work_threads: usize,
 
+    #[arg(
+    long,
+    help = "Max number of FUSE requests served at the same time",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    default_value = "256",
+    )]
+    pub max_concurrent_requests: usize,
+
+    #[arg(
+    long,
+    help = "Skip reading the supplementary groups of the caller for permission checks",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub no_supplementary_groups: bool,
+
+    #[arg(
+    long,
+    help = "The largest write request the kernel sends",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    default_value = "1M",
+    )]
+    pub max_write: ReadableSize,
+
+    #[arg(
+    long,
+    help = "How far the kernel reads ahead",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    default_value = "1M",
+    )]
+    pub max_readahead: ReadableSize,
+
+    #[arg(
+    long,
+    help = "Max number of background requests, such as readahead and writeback, of the kernel",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    default_value = "64",
+    )]
+    pub max_background: u16,
+
+    #[arg(
+    long,
+    help = "Cache the writes in the kernel and send them in batches",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub writeback_cache: bool,
+
+    #[arg(
+    long,
+    help = "List directories without their attributes",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub no_readdirplus: bool,
+
+    #[arg(
+    long,
+    help = "Serialize the lookups and readdirs of a directory",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub no_parallel_dirops: bool,
+
+    #[arg(
+    long,
+    help = "Truncate by a separate setattr instead of passing O_TRUNC to open",
+    help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub no_atomic_o_trunc: bool,
+
     #[clap(
     long,
     help = "Write log files to a directory [default: logs written to syslog]",
@@ -162,6 +242,68 @@ pub struct MountArgs {
     default_value = kiseki_common::KISEKI_DEBUG_META_ADDR,
     )]
     pub meta_dsn: String,
+
+    #[arg(
+    long,
+    help = "Interval in seconds to back up the metadata into the object storage, 0 to disable",
+    help_heading = META_OPTIONS_HEADER,
+    value_name = "SECONDS",
+    default_value = "3600",
+    )]
+    pub backup_meta: u64,
+
+    #[arg(
+    long,
+    help = "Number of metadata backups to keep, at least 1",
+    help_heading = META_OPTIONS_HEADER,
+    default_value = "7",
+    value_parser = clap::value_parser!(u32).range(1..),
+    )]
+    pub backup_meta_copies: u32,
+
+    #[arg(
+    long,
+    help = "Seconds to cache the attributes on the client, 0 to disable",
+    help_heading = META_OPTIONS_HEADER,
+    value_name = "SECONDS",
+    default_value = "1.0",
+    )]
+    pub attr_cache: f64,
+
+    #[arg(
+    long,
+    help = "Seconds to cache the directory entries on the client, 0 to disable",
+    help_heading = META_OPTIONS_HEADER,
+    value_name = "SECONDS",
+    default_value = "1.0",
+    )]
+    pub entry_cache: f64,
+
+    #[arg(
+    long,
+    help = "Seconds to remember the missing names on the client, 0 to disable",
+    help_heading = META_OPTIONS_HEADER,
+    value_name = "SECONDS",
+    default_value = "1.0",
+    )]
+    pub negative_entry_cache: f64,
+
+    #[arg(
+    long,
+    help = "Max number of items of each kind in the metadata cache, 0 for unlimited",
+    help_heading = META_OPTIONS_HEADER,
+    default_value = "100000",
+    )]
+    pub meta_cache_limit: usize,
+
+    #[arg(
+    long,
+    help = "Seconds between the exchanges of the changes with the other clients, 0 to disable",
+    help_heading = META_OPTIONS_HEADER,
+    value_name = "SECONDS",
+    default_value = "1.0",
+    )]
+    pub change_poll: f64,
 }
 
 impl MountArgs {
@@ -169,8 +311,14 @@ impl MountArgs {
         let mut options = vec![
             MountOption::DefaultPermissions,
             MountOption::FSName(KISEKI.to_string()),
-            MountOption::NoAtime,
         ];
+        // the atime is maintained by the meta engine, the mount option only
+        // reflects the mode to the kernel.
+        if matches!(self.atime_mode, AccessTimeMode::Never) {
+            options.push(MountOption::NoAtime);
+        } else {
+            options.push(MountOption::Atime);
+        }
         if self.read_only {
             options.push(MountOption::RO);
         }
@@ -184,15 +332,29 @@ impl MountArgs {
             options.push(MountOption::AllowOther);
         }
         FuseConfig {
-            mount_point:        self.mount_point.clone(),
-            mount_options:      options,
-            async_work_threads: self.async_work_threads,
+            mount_point:             self.mount_point.clone(),
+            mount_options:           options,
+            async_work_threads:      self.async_work_threads,
+            max_concurrent_requests: self.max_concurrent_requests,
+            supplementary_groups:    !self.no_supplementary_groups,
+            max_write:               self.max_write.as_bytes().min(u32::MAX as u64) as u32,
+            max_readahead:           self.max_readahead.as_bytes().min(u32::MAX as u64) as u32,
+            max_background:          self.max_background,
+            writeback_cache:         self.writeback_cache,
+            readdirplus:             !self.no_readdirplus,
+            parallel_dirops:         !self.no_parallel_dirops,
+            atomic_o_trunc:          !self.no_atomic_o_trunc,
         }
     }
 
     fn meta_config(&self) -> Result<MetaConfig, Whatever> {
         let mut mc = MetaConfig::default();
-        mc.with_dsn(&self.meta_dsn);
+        mc.with_dsn(&self.meta_dsn).with_atime_mode(self.atime_mode);
+        mc.attr_cache_ttl = cache_ttl(self.attr_cache, "attr-cache")?;
+        mc.entry_cache_ttl = cache_ttl(self.entry_cache, "entry-cache")?;
+        mc.negative_entry_cache_ttl = cache_ttl(self.negative_entry_cache, "negative-entry-cache")?;
+        mc.meta_cache_limit = self.meta_cache_limit;
+        mc.change_poll_interval = cache_ttl(self.change_poll, "change-poll")?;
         Ok(mc)
     }
 
@@ -214,7 +376,13 @@ impl MountArgs {
         Some(opts)
     }
 
-    fn vfs_config(&self) -> VFSConfig { VFSConfig::default() }
+    fn vfs_config(&self) -> VFSConfig {
+        VFSConfig {
+            backup_meta_interval: Duration::from_secs(self.backup_meta),
+            backup_meta_copies: self.backup_meta_copies as usize,
+            ..Default::default()
+        }
+    }
 
     pub fn run(self) -> Result<(), Whatever> {
         human_panic::setup_panic!();
@@ -245,6 +413,11 @@ impl MountArgs {
     }
 }
 
+fn cache_ttl(seconds: f64, name: &str) -> Result<Duration, Whatever> {
+    Duration::try_from_secs_f64(seconds)
+        .with_whatever_context(|e| format!("invalid --{} {}: {}", name, seconds, e))
+}
+
 pub fn print_versions() {
     // Report app version as gauge.
     // APP_VERSION
@@ -284,15 +457,31 @@ fn mount(args: MountArgs) -> Result<(), Whatever> {
         .with_whatever_context(|e| format!("failed to create file system, {:?}", e))?;
 
     let fs = kiseki_fuse::KisekiFuse::create(fuse_config.clone(), file_system)?;
-    fuser::mount2(fs, &args.mount_point, &fuse_config.mount_options).with_whatever_context(
-        |e| {
+    let init_error = fs.init_error();
+    let invalidator = fs.invalidator();
+    // The session thread is the only reader of /dev/fuse, it hands every
+    // request over to the runtime, see KisekiFuse::spawn. More readers need
+    // a session on a clone of the fd (FUSE_DEV_IOC_CLONE), which fuser 0.14
+    // can't build: it neither exposes the fd of a session nor takes one.
+    fuser::Session::new(fs, &args.mount_point, &fuse_config.mount_options)
+        .and_then(|mut session| {
+            invalidator.attach(session.notifier());
+            session.run()
+        })
+        .with_whatever_context(|e| {
             format!(
                 "failed to mount kiseki on {}; {}",
                 args.mount_point.display(),
                 e
             )
-        },
-    )?;
+        })?;
+    if let Some(e) = init_error.lock().unwrap().take() {
+        whatever!(
+            "failed to init kiseki on {}; {}",
+            args.mount_point.display(),
+            e
+        );
+    }
     Ok(())
 }
 fn validate_mount_point(path: impl AsRef<Path>) -> Result<(), Whatever> {
@@ -333,3 +522,26 @@ fn validate_mount_point(path: impl AsRef<Path>) -> Result<(), Whatever> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        mount: MountArgs,
+    }
+
+    #[test]
+    fn backup_meta_copies() {
+        let parse = |copies: &str| {
+            Cli::try_parse_from(["kiseki", "/tmp/kiseki", "--backup-meta-copies", copies])
+        };
+        assert_eq!(parse("3").unwrap().mount.backup_meta_copies, 3);
+        // the backup just uploaded is always kept.
+        assert!(parse("0").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use kiseki_types::{accesslog::AccessRecord, ino::LOG_INODE, internal_nodes::LOG_INODE_NAME};
+use snafu::{ResultExt, Whatever};
+
+use crate::cmd::control::open_internal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    Op,
+    Uid,
+    Pid,
+    Inode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortBy {
+    Count,
+    Total,
+    Avg,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Sum up the operations served by a mounted kiseki-fs from its access log, the
+count and the total and average latency of each operation, by the uid, the pid
+or the inode they come from. A directory of the mount is followed live, with
+the table refreshed every interval, and a saved access log is replayed. The
+access log shows the operations of every user, only root may read it.
+Examples:
+
+kiseki profile /tmp/kiseki
+kiseki profile /tmp/kiseki --by pid --sort total
+cat /tmp/kiseki/.accesslog > access.log
+kiseki profile access.log --by inode
+")]
+pub struct ProfileArgs {
+    #[arg(
+        help = "Any directory of the mounted volume, or a saved access log",
+        value_name = "PATH",
+        default_value = "/tmp/kiseki"
+    )]
+    pub path: PathBuf,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "What the operations are grouped by",
+        default_value_t = GroupBy::Op
+    )]
+    pub by: GroupBy,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "What the rows are sorted by",
+        default_value_t = SortBy::Total
+    )]
+    pub sort: SortBy,
+
+    #[arg(long, help = "How many rows to show", default_value_t = 20)]
+    pub top: usize,
+
+    #[arg(
+        long,
+        help = "Seconds between two refreshes of a live mount",
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub interval: u64,
+}
+
+impl ProfileArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        let mut profile = Profile::new(self.by);
+        // the live access log is a file as well, yet it never ends.
+        if self.path.is_file() && !is_live_log(&self.path) {
+            let log = File::open(&self.path).with_whatever_context(|e| {
+                format!("failed to open {}, {}", self.path.display(), e)
+            })?;
+            for line in BufReader::new(log).lines() {
+                let line = line.with_whatever_context(|e| {
+                    format!("failed to read {}, {}", self.path.display(), e)
+                })?;
+                profile.add(&line);
+            }
+            print!("{}", profile.render(self.sort, self.top));
+            return Ok(());
+        }
+        self.follow(profile)
+    }
+
+    // follow reads the access log of the mount in the background, and shows
+    // the table every interval until the log ends.
+    fn follow(&self, mut profile: Profile) -> Result<(), Whatever> {
+        let log = open_internal(&self.path, LOG_INODE_NAME, false)?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(log).lines() {
+                if tx.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        loop {
+            let deadline = Instant::now() + Duration::from_secs(self.interval);
+            loop {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match rx.recv_timeout(timeout) {
+                    Ok(line) => {
+                        let line = line.with_whatever_context(|e| {
+                            format!("failed to read the access log, {}", e)
+                        })?;
+                        profile.add(&line);
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            // clear the screen and draw the table from the top, like top.
+            print!("\x1b[2J\x1b[H{}", profile.render(self.sort, self.top));
+        }
+    }
+}
+
+// is_live_log tells whether the path is the access log of a mount, by its
+// name, which may be prefixed, or by its inode.
+fn is_live_log(path: &Path) -> bool {
+    let named = path.file_name().is_some_and(|name| {
+        name == LOG_INODE_NAME || name.to_str() == Some(&format!(".kfs{LOG_INODE_NAME}"))
+    });
+    named || std::fs::metadata(path).is_ok_and(|meta| meta.ino() == LOG_INODE.0)
+}
+
+// Stat sums up the operations of a row.
+#[derive(Debug, Default, Clone, Copy)]
+struct Stat {
+    count: u64,
+    total: Duration,
+}
+
+impl Stat {
+    fn avg(&self) -> Duration {
+        let nanos = self.total.as_nanos() / self.count.max(1) as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+// Profile is the table, keyed by the group and the operation.
+#[derive(Debug)]
+struct Profile {
+    by:      GroupBy,
+    rows:    HashMap<(String, String), Stat>,
+    records: u64,
+}
+
+impl Profile {
+    fn new(by: GroupBy) -> Self {
+        Self {
+            by,
+            rows: HashMap::new(),
+            records: 0,
+        }
+    }
+
+    // add counts the line if it is a record of the access log, the other
+    // lines, like the note of the dropped ones, are skipped.
+    fn add(&mut self, line: &str) {
+        let Some(record) = AccessRecord::parse(line) else {
+            return;
+        };
+        let group = match self.by {
+            GroupBy::Op => String::new(),
+            GroupBy::Uid => record.uid.to_string(),
+            GroupBy::Pid => record.pid.to_string(),
+            GroupBy::Inode => record.inode.to_string(),
+        };
+        self.records += 1;
+        let stat = self.rows.entry((group, record.op)).or_default();
+        stat.count += 1;
+        stat.total += record.latency;
+    }
+
+    fn render(&self, sort: SortBy, top: usize) -> String {
+        let mut rows = self.rows.iter().collect::<Vec<_>>();
+        rows.sort_by(|a, b| match sort {
+            SortBy::Count => b.1.count.cmp(&a.1.count),
+            SortBy::Total => b.1.total.cmp(&a.1.total),
+            SortBy::Avg => b.1.avg().cmp(&a.1.avg()),
+        });
+        // the column of the group goes first, unless it is the op itself.
+        let column = |group: &str| match self.by {
+            GroupBy::Op => String::new(),
+            _ => format!("{group:>12} "),
+        };
+        let key = match self.by {
+            GroupBy::Op => "",
+            GroupBy::Uid => "UID",
+            GroupBy::Pid => "PID",
+            GroupBy::Inode => "INODE",
+        };
+        let mut buf = format!("{} operations\n", self.records);
+        buf += &format!(
+            "{}{:<16} {:>10} {:>12} {:>10}\n",
+            column(key),
+            "OP",
+            "COUNT",
+            "TOTAL(ms)",
+            "AVG(ms)"
+        );
+        for ((group, op), stat) in rows.into_iter().take(top) {
+            buf += &format!(
+                "{}{op:<16} {:>10} {:>12.3} {:>10.3}\n",
+                column(group),
+                stat.count,
+                stat.total.as_secs_f64() * 1000.0,
+                stat.avg().as_secs_f64() * 1000.0
+            );
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(uid: u32, pid: u32, op: &str, inode: u64, latency_us: u64) -> String {
+        format!("1760774771.003330 [uid:{uid},gid:0,pid:{pid}] {op} ({inode}) <0.{latency_us:06}>")
+    }
+
+    #[test]
+    fn add_records() {
+        let mut profile = Profile::new(GroupBy::Op);
+        profile.add(&line(0, 1, "read", 2, 100));
+        profile.add(&line(0, 1, "read", 3, 300));
+        profile.add("#### dropped 3 lines ####");
+        profile.add("");
+        assert_eq!(profile.records, 2);
+        let stat = profile.rows[&(String::new(), "read".to_string())];
+        assert_eq!(stat.count, 2);
+        assert_eq!(stat.total, Duration::from_micros(400));
+        assert_eq!(stat.avg(), Duration::from_micros(200));
+    }
+
+    #[test]
+    fn group_records() {
+        let lines = [
+            line(1000, 7, "read", 2, 100),
+            line(1000, 8, "write", 2, 100),
+            line(0, 7, "read", 3, 100),
+        ];
+        let groups = |by| {
+            let mut profile = Profile::new(by);
+            lines.iter().for_each(|l| profile.add(l));
+            let mut keys = profile.rows.into_keys().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+        let key = |group: &str, op: &str| (group.to_string(), op.to_string());
+        assert_eq!(groups(GroupBy::Op), vec![key("", "read"), key("", "write")]);
+        assert_eq!(
+            groups(GroupBy::Uid),
+            vec![key("0", "read"), key("1000", "read"), key("1000", "write")]
+        );
+        assert_eq!(
+            groups(GroupBy::Pid),
+            vec![key("7", "read"), key("8", "write")]
+        );
+        assert_eq!(
+            groups(GroupBy::Inode),
+            vec![key("2", "read"), key("2", "write"), key("3", "read")]
+        );
+    }
+
+    #[test]
+    fn render_sorted() {
+        let mut profile = Profile::new(GroupBy::Op);
+        // lookup: 3 calls of 10us, read: 1 call of 500us, write: 2 calls of 200us.
+        for _ in 0..3 {
+            profile.add(&line(0, 1, "lookup", 1, 10));
+        }
+        profile.add(&line(0, 1, "read", 2, 500));
+        profile.add(&line(0, 1, "write", 2, 200));
+        profile.add(&line(0, 1, "write", 2, 200));
+        let ops = |sort, top| {
+            profile
+                .render(sort, top)
+                .lines()
+                .skip(2)
+                .map(|l| l.split_whitespace().next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ops(SortBy::Count, 10), ["lookup", "write", "read"]);
+        assert_eq!(ops(SortBy::Total, 10), ["read", "write", "lookup"]);
+        assert_eq!(ops(SortBy::Avg, 10), ["read", "write", "lookup"]);
+        assert_eq!(ops(SortBy::Count, 1), ["lookup"]);
+        assert!(
+            profile
+                .render(SortBy::Total, 10)
+                .starts_with("6 operations\n")
+        );
+    }
+
+    #[test]
+    fn live_log() {
+        assert!(is_live_log(Path::new("/tmp/kiseki/.accesslog")));
+        assert!(is_live_log(Path::new("/tmp/kiseki/.kfs.accesslog")));
+        assert!(!is_live_log(Path::new("/tmp/access.log")));
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use clap::Args;
+use flate2::bufread::GzDecoder;
+use snafu::{ResultExt, Whatever};
+use tracing::info;
+
+// The magic number at the beginning of a gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Rebuild the metadata from a backup into an empty meta store. The backups are
+uploaded by the mount as meta/dump-<timestamp>.json.gz in the bucket of the
+volume, both gzipped and plain JSON dumps are accepted.
+Examples:
+
+# Restore the metadata from a downloaded backup
+kiseki restore rocksdb://:/tmp/kiseki.meta dump-1700000000.json.gz
+")]
+pub struct RestoreArgs {
+    #[arg(
+        help = "Specify the address of the meta store",
+        value_name = "META_DSN"
+    )]
+    pub meta_dsn: String,
+
+    #[arg(help = "The backup file to restore from", value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+impl RestoreArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        kiseki_utils::logger::install_fmt_log();
+        let file = File::open(&self.file)
+            .with_whatever_context(|e| format!("failed to open {}, {}", self.file.display(), e))?;
+        let mut reader = BufReader::new(file);
+        let gzipped = reader
+            .fill_buf()
+            .with_whatever_context(|e| format!("failed to read {}, {}", self.file.display(), e))?
+            .starts_with(&GZIP_MAGIC);
+        let result = if gzipped {
+            kiseki_meta::load(&self.meta_dsn, GzDecoder::new(reader))
+        } else {
+            kiseki_meta::load(&self.meta_dsn, reader)
+        };
+        result.with_whatever_context(|e| format!("failed to restore metadata, {:?}", e))?;
+        info!("restore metadata from {} success", self.file.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::{OptionExt, Whatever};
+
+use crate::cmd::control::{call, inode_of};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Remove the files or the directory trees of a mounted kiseki-fs. The mount
+removes them by itself, which is much faster than rm -r as there is no round
+trip through the kernel per entry, the permissions are checked all the same.
+Examples:
+
+# Remove the output of a job
+kiseki rmr /tmp/kiseki/job-1
+")]
+pub struct RmrArgs {
+    #[arg(
+        help = "The files or directories to remove",
+        value_name = "PATH",
+        required = true
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+impl RmrArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        for path in &self.paths {
+            let name = path
+                .file_name()
+                .with_whatever_context(|| format!("invalid path {}", path.display()))?;
+            // the entry itself is removed, a symbolic link isn't followed.
+            let parent = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            let req = ControlRequest::Rmr {
+                parent: inode_of(parent)?,
+                name:   name.as_bytes().to_vec(),
+            };
+            println!("{}: {}", path.display(), call(parent, &req)?);
+        }
+        Ok(())
+    }
+}
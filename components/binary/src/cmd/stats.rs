@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use kiseki_types::internal_nodes::STATS_INODE_NAME;
+use kiseki_utils::readable_size::ReadableSize;
+use snafu::{ensure_whatever, whatever, ResultExt, Whatever};
+
+use crate::cmd::control::open_internal;
+
+// The header is shown again after this many lines.
+const HEADER_INTERVAL: u64 = 20;
+const COLUMN_WIDTH: usize = 9;
+
+// The sections of the schema, with their titles and columns.
+const SECTIONS: &[Section] = &[
+    ('f', "fuse", &["ops", "lat", "read", "write"]),
+    ('m', "meta", &["ops", "lat"]),
+    ('o', "object", &["get", "get_b", "put", "put_b"]),
+    ('b', "buffer", &["used", "handles"]),
+    ('c', "cache", &["mem", "stage"]),
+];
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Show the performance of a mounted kiseki-fs, one line for each interval, from
+the counters its .stats reads. The sections of the schema are:
+
+f: the FUSE operations per second, their average latency and the read and
+   write throughput
+m: the operations on the meta backend per second and their average latency
+o: the GET and PUT requests per second to the object storage and their bytes
+b: the usage of the buffer pool and the open handles
+c: the hit ratio of the mem cache and the stage cache
+Examples:
+
+kiseki stats /tmp/kiseki
+kiseki stats /tmp/kiseki --schema fo --interval 5
+")]
+pub struct StatsArgs {
+    #[arg(
+        help = "Any directory of the mounted volume",
+        value_name = "MOUNT_POINT",
+        default_value = "/tmp/kiseki"
+    )]
+    pub mount_point: PathBuf,
+
+    #[arg(
+        long,
+        help = "The sections to show, see above",
+        default_value = "fmobc"
+    )]
+    pub schema: String,
+
+    #[arg(
+        long,
+        help = "Seconds between two lines",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub interval: u64,
+
+    #[arg(
+        long,
+        help = "How many lines to show, 0 for no limit",
+        default_value_t = 0
+    )]
+    pub count: u64,
+}
+
+impl StatsArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        let sections = parse_schema(&self.schema)?;
+        let mut prev = read_stats(&self.mount_point)?;
+        let mut at = Instant::now();
+        for n in 0.. {
+            if self.count != 0 && n >= self.count {
+                break;
+            }
+            if n % HEADER_INTERVAL == 0 {
+                print_header(&sections);
+            }
+            std::thread::sleep(Duration::from_secs(self.interval));
+            let cur = read_stats(&self.mount_point)?;
+            let sample = Sample {
+                prev: &prev,
+                cur:  &cur,
+                secs: at.elapsed().as_secs_f64(),
+            };
+            at = Instant::now();
+            let row = sections
+                .iter()
+                .map(|s| {
+                    values(s.0, &sample)
+                        .iter()
+                        .map(|v| format!("{v:>COLUMN_WIDTH$}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>();
+            println!("{}", row.join("|"));
+            prev = cur;
+        }
+        Ok(())
+    }
+}
+
+type Section = (char, &'static str, &'static [&'static str]);
+
+// parse_schema picks the sections by their keys, in the given order.
+fn parse_schema(schema: &str) -> Result<Vec<&'static Section>, Whatever> {
+    let sections = schema
+        .chars()
+        .map(|key| SECTIONS.iter().find(|s| s.0 == key))
+        .collect::<Option<Vec<_>>>();
+    let Some(sections) = sections.filter(|s| !s.is_empty()) else {
+        let keys = SECTIONS.iter().map(|s| s.0).collect::<String>();
+        whatever!("invalid schema {:?}, the sections are {keys}", schema);
+    };
+    Ok(sections)
+}
+
+// read_stats reads the counters of the mount.
+fn read_stats(path: &Path) -> Result<HashMap<String, f64>, Whatever> {
+    let mut buf = String::new();
+    open_internal(path, STATS_INODE_NAME, false)?
+        .read_to_string(&mut buf)
+        .with_whatever_context(|e| format!("failed to read the stats, {}", e))?;
+    let stats = parse_stats(&buf);
+    ensure_whatever!(!stats.is_empty(), "{} has no stats", path.display());
+    Ok(stats)
+}
+
+// parse_stats parses the "kiseki_name value" lines, and skips the others.
+fn parse_stats(buf: &str) -> HashMap<String, f64> {
+    buf.lines()
+        .filter_map(|line| line.strip_prefix("kiseki_")?.split_once(' '))
+        .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+        .collect()
+}
+
+fn print_header(sections: &[&Section]) {
+    let titles = sections
+        .iter()
+        .map(|(_, title, columns)| {
+            let width = columns.len() * (COLUMN_WIDTH + 1) - 1;
+            format!("{title:-^width$}")
+        })
+        .collect::<Vec<_>>();
+    let columns = sections
+        .iter()
+        .map(|(_, _, columns)| {
+            columns
+                .iter()
+                .map(|c| format!("{c:>COLUMN_WIDTH$}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>();
+    println!("{}", titles.join("|"));
+    println!("{}", columns.join("|"));
+}
+
+// Sample is the counters read at the start and the end of an interval.
+struct Sample<'a> {
+    prev: &'a HashMap<String, f64>,
+    cur:  &'a HashMap<String, f64>,
+    secs: f64,
+}
+
+impl Sample<'_> {
+    fn gauge(&self, name: &str) -> f64 { self.cur.get(name).copied().unwrap_or_default() }
+
+    // delta is how much the counter grows in the interval, the counters
+    // start over once the volume is mounted again.
+    fn delta(&self, name: &str) -> f64 {
+        let prev = self.prev.get(name).copied().unwrap_or_default();
+        (self.gauge(name) - prev).max(0.0)
+    }
+
+    fn rate(&self, name: &str) -> f64 { self.delta(name) / self.secs.max(f64::EPSILON) }
+
+    // latency is the average latency of the calls in the interval.
+    fn latency(&self, latency_us: &str, calls: &str) -> String {
+        match self.delta(calls) {
+            n if n > 0.0 => format!("{:.2}ms", self.delta(latency_us) / n / 1000.0),
+            _ => "-".to_string(),
+        }
+    }
+
+    // hit_ratio is the ratio of the cache hits in the interval.
+    fn hit_ratio(&self, cache: &str) -> String {
+        let hits = self.delta(&format!("{cache}_hits"));
+        let misses = self.delta(&format!("{cache}_misses"));
+        if hits + misses > 0.0 {
+            format!("{:.1}%", hits * 100.0 / (hits + misses))
+        } else {
+            "-".to_string()
+        }
+    }
+}
+
+fn values(key: char, s: &Sample) -> Vec<String> {
+    let count = |v: f64| format!("{:.0}", v);
+    let bytes = |v: f64| ReadableSize(v as u64).to_string();
+    match key {
+        'f' => vec![
+            count(s.rate("fuse_ops")),
+            s.latency("fuse_latency_us", "fuse_ops"),
+            bytes(s.rate("read_bytes")),
+            bytes(s.rate("written_bytes")),
+        ],
+        'm' => vec![
+            count(s.rate("meta_ops")),
+            s.latency("meta_latency_us", "meta_ops"),
+        ],
+        'o' => vec![
+            count(s.rate("object_gets")),
+            bytes(s.rate("object_get_bytes")),
+            count(s.rate("object_puts")),
+            bytes(s.rate("object_put_bytes")),
+        ],
+        'b' => vec![
+            format!("{:.1}%", s.gauge("buffer_pool_used_ratio") * 100.0),
+            count(s.gauge("open_handles")),
+        ],
+        'c' => vec![s.hit_ratio("mem_cache"), s.hit_ratio("stage_cache")],
+        _ => unreachable!("the schema is checked"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema() {
+        let keys = |schema| {
+            parse_schema(schema)
+                .map(|sections| sections.iter().map(|s| s.0).collect::<String>())
+                .ok()
+        };
+        assert_eq!(keys("fmobc").as_deref(), Some("fmobc"));
+        // the sections are shown in the given order.
+        assert_eq!(keys("of").as_deref(), Some("of"));
+        assert_eq!(keys(""), None);
+        assert_eq!(keys("fx"), None);
+    }
+
+    #[test]
+    fn stats_lines() {
+        let stats = parse_stats("kiseki_fuse_ops 10\nkiseki_ratio 0.5000\nother 1\nkiseki_bad x\n");
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats["fuse_ops"], 10.0);
+        assert_eq!(stats["ratio"], 0.5);
+    }
+
+    #[test]
+    fn rates_and_deltas() {
+        let prev = parse_stats(
+            "kiseki_fuse_ops 100
+kiseki_fuse_latency_us 1000
+kiseki_mem_cache_hits 10
+kiseki_mem_cache_misses 10
+kiseki_meta_ops 50",
+        );
+        let cur = parse_stats(
+            "kiseki_fuse_ops 300
+kiseki_fuse_latency_us 5000
+kiseki_mem_cache_hits 40
+kiseki_mem_cache_misses 20
+kiseki_meta_ops 20
+kiseki_open_handles 3",
+        );
+        let s = Sample {
+            prev: &prev,
+            cur:  &cur,
+            secs: 2.0,
+        };
+        assert_eq!(s.delta("fuse_ops"), 200.0);
+        assert_eq!(s.rate("fuse_ops"), 100.0);
+        // 4000us over 200 ops.
+        assert_eq!(s.latency("fuse_latency_us", "fuse_ops"), "0.02ms");
+        // 30 hits and 10 misses in the interval.
+        assert_eq!(s.hit_ratio("mem_cache"), "75.0%");
+        assert_eq!(s.hit_ratio("stage_cache"), "-");
+        // the counters start over after a remount.
+        assert_eq!(s.delta("meta_ops"), 0.0);
+        assert_eq!(s.latency("meta_latency_us", "meta_ops"), "-");
+        assert_eq!(s.gauge("open_handles"), 3.0);
+        assert_eq!(s.delta("open_handles"), 3.0);
+        assert_eq!(values('m', &s), vec!["0".to_string(), "-".to_string()]);
+    }
+}
@@ -0,0 +1,40 @@
+use clap::Args;
+use kiseki_types::setting::SCHEMA_VERSION;
+use snafu::{ResultExt, Whatever};
+use tracing::info;
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Upgrade the metadata of a volume created by an older version to the latest
+layout in place. Unmount the volume from all clients before upgrading, it is
+safe to run it again if it gets interrupted.
+Examples:
+
+# Upgrade the volume
+kiseki upgrade rocksdb://:/tmp/kiseki.meta
+")]
+pub struct UpgradeArgs {
+    #[arg(
+        help = "Specify the address of the meta store",
+        value_name = "META_DSN"
+    )]
+    pub meta_dsn: String,
+}
+
+impl UpgradeArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        kiseki_utils::logger::install_fmt_log();
+        let from = kiseki_meta::upgrade(&self.meta_dsn)
+            .with_whatever_context(|e| format!("failed to upgrade metadata, {}", e))?;
+        if from == SCHEMA_VERSION {
+            info!("metadata is already at schema version {}", SCHEMA_VERSION);
+        } else {
+            info!(
+                "upgrade metadata from schema version {} to {} success",
+                from, SCHEMA_VERSION
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use kiseki_types::control::ControlRequest;
+use snafu::Whatever;
+
+use crate::cmd::control::{call, inode_of};
+
+#[derive(Debug, Clone, Args)]
+#[command(long_about = r"
+
+Read the files, or the files under the directories, of a mounted kiseki-fs
+into its cache ahead of time, so the first reads of a job don't have to wait
+for the object storage.
+Examples:
+
+# Warm up the dataset for a job
+kiseki warmup /tmp/kiseki/dataset
+")]
+pub struct WarmupArgs {
+    #[arg(
+        help = "The files or directories to warm up",
+        value_name = "PATH",
+        required = true
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+impl WarmupArgs {
+    pub fn run(&self) -> Result<(), Whatever> {
+        for path in &self.paths {
+            let req = ControlRequest::Warmup {
+                inode: inode_of(path)?,
+            };
+            println!("{}:\n{}", path.display(), call(path, &req)?);
+        }
+        Ok(())
+    }
+}
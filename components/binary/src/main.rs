@@ -4,7 +4,12 @@ mod cmd;
 use clap::{Parser, Subcommand};
 use snafu::Whatever;
 
-use crate::cmd::{format::FormatArgs, mount::MountArgs, unmount::UmountArgs};
+use crate::cmd::{
+    clone::CloneArgs, compact::CompactArgs, config::ConfigArgs, dump::DumpArgs, flush::FlushArgs,
+    format::FormatArgs, gc::GcArgs, info::InfoArgs, load::LoadArgs, mount::MountArgs,
+    profile::ProfileArgs, restore::RestoreArgs, rmr::RmrArgs, stats::StatsArgs,
+    unmount::UmountArgs, upgrade::UpgradeArgs, warmup::WarmupArgs,
+};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -22,6 +27,20 @@ enum Commands {
     Mount(MountArgs),
     Umount(UmountArgs),
     Format(FormatArgs),
+    Config(ConfigArgs),
+    Dump(DumpArgs),
+    Load(LoadArgs),
+    Restore(RestoreArgs),
+    Upgrade(UpgradeArgs),
+    Clone(CloneArgs),
+    Rmr(RmrArgs),
+    Warmup(WarmupArgs),
+    Info(InfoArgs),
+    Compact(CompactArgs),
+    Gc(GcArgs),
+    Flush(FlushArgs),
+    Stats(StatsArgs),
+    Profile(ProfileArgs),
 }
 
 fn main() -> Result<(), Whatever> {
@@ -30,5 +49,19 @@ fn main() -> Result<(), Whatever> {
         Commands::Mount(mount_args) => mount_args.run(),
         Commands::Umount(umount_args) => umount_args.run(),
         Commands::Format(format_args) => format_args.run(),
+        Commands::Config(config_args) => config_args.run(),
+        Commands::Dump(dump_args) => dump_args.run(),
+        Commands::Load(load_args) => load_args.run(),
+        Commands::Restore(restore_args) => restore_args.run(),
+        Commands::Upgrade(upgrade_args) => upgrade_args.run(),
+        Commands::Clone(clone_args) => clone_args.run(),
+        Commands::Rmr(rmr_args) => rmr_args.run(),
+        Commands::Warmup(warmup_args) => warmup_args.run(),
+        Commands::Info(info_args) => info_args.run(),
+        Commands::Compact(compact_args) => compact_args.run(),
+        Commands::Gc(gc_args) => gc_args.run(),
+        Commands::Flush(flush_args) => flush_args.run(),
+        Commands::Stats(stats_args) => stats_args.run(),
+        Commands::Profile(profile_args) => profile_args.run(),
     }
 }
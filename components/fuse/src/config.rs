@@ -3,8 +3,35 @@ use std::path::PathBuf;
 /// Configuration for a FUSE background session.
 #[derive(Debug, Clone)]
 pub struct FuseConfig {
-    pub mount_point:        PathBuf,
-    pub mount_options:      Vec<fuser::MountOption>,
+    pub mount_point:             PathBuf,
+    pub mount_options:           Vec<fuser::MountOption>,
     /// work threads count for tokio runtime.
-    pub async_work_threads: usize,
+    pub async_work_threads:      usize,
+    /// how many requests are served at the same time, the later ones wait
+    /// until some of them finish. The reads of the access log aren't
+    /// counted, they wait for the other requests.
+    pub max_concurrent_requests: usize,
+    /// check the permissions against the supplementary groups of the caller
+    /// as well, they are read from /proc.
+    pub supplementary_groups:    bool,
+
+    // The rest are negotiated with the kernel on init, the kernel may grant
+    // less than asked.
+    /// the largest write request in bytes.
+    pub max_write:       u32,
+    /// how far the kernel reads ahead in bytes.
+    pub max_readahead:   u32,
+    /// how many background requests, such as the readahead and the
+    /// writeback, the kernel keeps in flight.
+    pub max_background:  u16,
+    /// let the kernel cache the writes in the page cache and send them in
+    /// batches.
+    pub writeback_cache: bool,
+    /// list the directories with their attributes, the kernel picks
+    /// readdirplus only when the attributes are wanted.
+    pub readdirplus:     bool,
+    /// let the lookups and the readdirs of a directory run in parallel.
+    pub parallel_dirops: bool,
+    /// pass O_TRUNC to open instead of truncating by a separate setattr.
+    pub atomic_o_trunc:  bool,
 }
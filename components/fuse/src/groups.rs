@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+// The groups of a process rarely change, yet a pid can be reused, so they
+// are only trusted for a short while.
+const GROUPS_TTL: Duration = Duration::from_secs(1);
+// The expired records are dropped once the cache grows this large.
+const MAX_CACHED_PROCESSES: usize = 4096;
+
+/// [GroupResolver] finds the supplementary groups of the process issuing a
+/// request, the kernel only tells us its uid and primary gid.
+#[derive(Debug, Default)]
+pub(crate) struct GroupResolver {
+    // (pid, uid, gid) -> (resolved at, groups)
+    cache: Mutex<HashMap<(u32, u32, u32), (Instant, Vec<u32>)>>,
+}
+
+impl GroupResolver {
+    /// [resolve] returns the groups of the process, the primary gid comes
+    /// first. Only the primary gid is returned if the process is gone.
+    pub(crate) fn resolve(&self, pid: u32, uid: u32, gid: u32) -> Vec<u32> {
+        let key = (pid, uid, gid);
+        let now = Instant::now();
+        if let Some((at, groups)) = self.cache.lock().unwrap().get(&key) {
+            if now.duration_since(*at) < GROUPS_TTL {
+                return groups.clone();
+            }
+        }
+
+        let mut groups = vec![gid];
+        match std::fs::read_to_string(format!("/proc/{pid}/status")) {
+            Ok(status) => groups.extend(parse_groups(&status).filter(|g| *g != gid)),
+            Err(e) => debug!("failed to read the groups of process {pid}, {e}"),
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_PROCESSES {
+            cache.retain(|_, (at, _)| now.duration_since(*at) < GROUPS_TTL);
+        }
+        cache.insert(key, (now, groups.clone()));
+        groups
+    }
+}
+
+// parse_groups picks the gids from the "Groups:" line of /proc/<pid>/status.
+fn parse_groups(status: &str) -> impl Iterator<Item = u32> + '_ {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|g| g.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_line() {
+        let groups = |status| parse_groups(status).collect::<Vec<_>>();
+        assert_eq!(
+            groups("Name:\tcat\nGroups:\t4 24 27 \nNgid:\t0\n"),
+            vec![4, 24, 27]
+        );
+        assert_eq!(
+            groups("Name:\tcat\nGroups:\t\nNgid:\t0\n"),
+            Vec::<u32>::new()
+        );
+        assert_eq!(groups("Name:\tcat\n"), Vec::<u32>::new());
+        // the malformed ones are skipped.
+        assert_eq!(groups("Groups:\t4 x -1 27\n"), vec![4, 27]);
+    }
+
+    #[test]
+    fn cached_until_expired() {
+        let resolver = GroupResolver::default();
+        // the process has exited, only the primary gid is left.
+        let gone = u32::MAX;
+        assert_eq!(resolver.resolve(gone, 1000, 100), vec![100]);
+
+        let fresh = Instant::now();
+        resolver
+            .cache
+            .lock()
+            .unwrap()
+            .insert((gone, 1000, 100), (fresh, vec![100, 4]));
+        assert_eq!(resolver.resolve(gone, 1000, 100), vec![100, 4]);
+
+        let expired = fresh.checked_sub(GROUPS_TTL * 2).unwrap();
+        resolver
+            .cache
+            .lock()
+            .unwrap()
+            .insert((gone, 1000, 100), (expired, vec![100, 4]));
+        assert_eq!(resolver.resolve(gone, 1000, 100), vec![100]);
+    }
+
+    #[test]
+    fn primary_gid_first() {
+        let resolver = GroupResolver::default();
+        let groups = resolver.resolve(std::process::id(), 0, 12345);
+        assert_eq!(groups[0], 12345);
+        assert_eq!(groups.iter().filter(|g| **g == 12345).count(), 1);
+    }
+}
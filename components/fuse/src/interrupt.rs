@@ -0,0 +1,155 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use kiseki_meta::context::FuseContext;
+use tracing::debug;
+
+// How often the callers of the slow requests are checked for signals, a
+// request served quicker than this is never checked.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// [Inflight] tracks the requests being served, so that they can be
+/// interrupted: their cancellation token is cancelled, and the waits on it
+/// return EINTR.
+///
+/// fuser answers FUSE_INTERRUPT with ENOSYS by itself, after which the kernel
+/// never sends it again. The kernel sends it once the caller has a signal
+/// pending, so we look for such signals in /proc instead.
+///
+/// Only the waits which check the token are interrupted: the locks of a
+/// handle, the reads of the object storage, the reads of the access log and
+/// the long tree walks. A write or a flush which holds the lock of the
+/// handle ignores the token and runs to the end.
+#[derive(Debug, Default)]
+pub(crate) struct Inflight {
+    // unique -> ctx
+    requests: Mutex<HashMap<u64, Arc<FuseContext>>>,
+}
+
+impl Inflight {
+    pub(crate) fn register(&self, ctx: Arc<FuseContext>) {
+        self.requests.lock().unwrap().insert(ctx.unique, ctx);
+    }
+
+    pub(crate) fn finish(&self, unique: u64) { self.requests.lock().unwrap().remove(&unique); }
+
+    /// [interrupt] cancels the request, returns false if it has finished.
+    pub(crate) fn interrupt(&self, unique: u64) -> bool {
+        match self.requests.lock().unwrap().get(&unique) {
+            Some(ctx) => {
+                ctx.cancellation_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// [watch_signals] interrupts the slow requests whose callers have got a
+    /// signal, it runs until the runtime shuts down.
+    pub(crate) async fn watch_signals(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let slow = self
+                .requests
+                .lock()
+                .unwrap()
+                .values()
+                // the kernel itself issues some requests, with pid 0.
+                .filter(|ctx| ctx.pid != 0 && !ctx.is_cancelled())
+                .filter(|ctx| ctx.start_at.elapsed() >= CHECK_INTERVAL)
+                .map(|ctx| (ctx.unique, ctx.pid))
+                .collect::<Vec<_>>();
+            let mut signaled = HashSet::new();
+            for (unique, pid) in slow {
+                if signaled.contains(&pid) || signal_pending(pid) {
+                    debug!("interrupt request {unique}, process {pid} has got a signal");
+                    signaled.insert(pid);
+                    self.interrupt(unique);
+                }
+            }
+        }
+    }
+}
+
+// signal_pending tells if the thread has a signal which is neither blocked
+// nor ignored, the pid of a request is the id of the calling thread.
+fn signal_pending(pid: u32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{pid}/status")) {
+        Ok(status) => status_signal_pending(&status),
+        Err(_) => false,
+    }
+}
+
+// status_signal_pending reads the signal masks of /proc/<pid>/status.
+fn status_signal_pending(status: &str) -> bool {
+    let mask = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|v| u64::from_str_radix(v.trim(), 16).ok())
+            .unwrap_or(0)
+    };
+    let pending = mask("SigPnd:") | mask("ShdPnd:");
+    pending & !mask("SigBlk:") & !mask("SigIgn:") != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(pending: &str, shared: &str, blocked: &str, ignored: &str) -> String {
+        [
+            ("Name", "cat"),
+            ("SigQ", "0/63448"),
+            ("SigPnd", pending),
+            ("ShdPnd", shared),
+            ("SigBlk", blocked),
+            ("SigIgn", ignored),
+            ("SigCgt", "0000000000000000"),
+        ]
+        .iter()
+        .map(|(k, v)| format!("{k}:\t{v}\n"))
+        .collect()
+    }
+
+    #[test]
+    fn pending_signals() {
+        let none = "0000000000000000";
+        // SIGINT is bit 1.
+        let sigint = "0000000000000002";
+        assert!(!status_signal_pending(&status(none, none, none, none)));
+        assert!(status_signal_pending(&status(sigint, none, none, none)));
+        assert!(status_signal_pending(&status(none, sigint, none, none)));
+        assert!(!status_signal_pending(&status(sigint, none, sigint, none)));
+        assert!(!status_signal_pending(&status(none, sigint, none, sigint)));
+        // another signal is still pending.
+        assert!(status_signal_pending(&status(
+            "0000000000000102",
+            none,
+            sigint,
+            none
+        )));
+        assert!(!status_signal_pending(""));
+        // the thread has exited.
+        assert!(!signal_pending(u32::MAX));
+    }
+
+    #[test]
+    fn interrupt_inflight() {
+        let inflight = Inflight::default();
+        let mut ctx = FuseContext::background();
+        ctx.unique = 7;
+        let ctx = Arc::new(ctx);
+        inflight.register(ctx.clone());
+        assert!(inflight.interrupt(7));
+        assert!(ctx.is_cancelled());
+        inflight.finish(7);
+        assert!(!inflight.interrupt(7));
+        assert!(!inflight.interrupt(8));
+    }
+}
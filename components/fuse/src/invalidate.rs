@@ -0,0 +1,72 @@
+use std::{
+    ffi::OsStr,
+    fmt::{Debug, Formatter},
+    os::unix::ffi::OsStrExt,
+    sync::{Arc, OnceLock},
+};
+
+use fuser::Notifier;
+use kiseki_meta::Change;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{debug, warn};
+
+/// [Invalidator] drops the caches of the kernel once the metadata has been
+/// changed behind its back, by the other clients or by the operations which
+/// don't go through the kernel, instead of waiting for the ttl.
+///
+/// The notifier comes with the session, which takes over the filesystem, so
+/// it is attached after the filesystem is created.
+#[derive(Clone, Default)]
+pub struct Invalidator {
+    notifier: Arc<OnceLock<Notifier>>,
+}
+
+impl Debug for Invalidator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Invalidator")
+            .field("attached", &self.notifier.get().is_some())
+            .finish()
+    }
+}
+
+impl Invalidator {
+    /// [attach] hands over the notifier of the session, the changes before
+    /// it are dropped since the kernel has cached nothing yet.
+    pub fn attach(&self, notifier: Notifier) {
+        if self.notifier.set(notifier).is_err() {
+            warn!("the notifier has been attached already");
+        }
+    }
+
+    /// [watch] passes the changes to the kernel until the engine goes away.
+    pub(crate) async fn watch(self, mut changes: Receiver<Change>) {
+        loop {
+            match changes.recv().await {
+                Ok(change) => self.invalidate(&change),
+                Err(RecvError::Lagged(n)) => {
+                    warn!("missed {n} changes, the kernel may serve them from its cache");
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    fn invalidate(&self, change: &Change) {
+        let Some(notifier) = self.notifier.get() else {
+            return;
+        };
+        let r = match change {
+            // drop the attributes and all the cached pages.
+            Change::Inode(inode) => notifier.inval_inode(inode.0, 0, 0),
+            Change::Entry { parent, name } => {
+                notifier.inval_entry(parent.0, OsStr::from_bytes(name))
+            }
+        };
+        // the kernel answers ENOENT if it hasn't cached the inode at all.
+        if let Err(e) = r {
+            if e.raw_os_error() != Some(libc::ENOENT) {
+                debug!("failed to invalidate {:?} in the kernel, {e}", change);
+            }
+        }
+    }
+}
@@ -15,23 +15,29 @@
 use std::{
     cmp::max,
     ffi::{OsStr, OsString},
+    future::Future,
+    os::unix::ffi::OsStrExt,
     path::Path,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, SystemTime},
 };
 
 pub use config::FuseConfig;
+pub use invalidate::Invalidator;
 use fuser::{
-    FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
-    ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, Request,
-    TimeOrNow,
+    consts, fuse_forget_one, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyLseek, ReplyOpen,
+    ReplyStatfs, ReplyWrite, Request, TimeOrNow,
 };
-use kiseki_common::{BLOCK_SIZE, MAX_NAME_LENGTH};
+use kiseki_common::{BLOCK_SIZE, DOT, DOT_DOT, MAX_NAME_LENGTH};
 use kiseki_meta::context::{FuseContext, EMPTY_CONTEXT};
 use kiseki_types::{
     attr::InodeAttr,
     entry::{Entry, FullEntry},
-    ino::Ino,
+    ino::{Ino, LOG_INODE},
+    ioctl::{
+        from_fs_flags, to_fs_flags, CloneRequest, FS_IOC_GETFLAGS, FS_IOC_SETFLAGS, IOC_CLONE,
+    },
     stat::FSStat,
     ToErrno,
 };
@@ -39,20 +45,40 @@ use kiseki_utils::readable_size::ReadableSize;
 use kiseki_vfs::KisekiVFS;
 use libc::{__u64, c_int};
 use snafu::{ResultExt, Snafu, Whatever};
-use tokio::runtime;
-use tracing::{debug, error, field, info, instrument, Instrument};
+use tokio::{runtime, sync::Semaphore};
+use tracing::{debug, error, field, info, instrument, warn, Instrument};
 
-use crate::err::Error;
+use crate::{
+    err::Error,
+    groups::GroupResolver,
+    interrupt::Inflight,
+    negotiate::{negotiate, writeback_open_flags},
+};
 
 mod config;
 mod err;
+mod groups;
+mod interrupt;
+mod invalidate;
+mod negotiate;
 pub mod null;
 
+/// [KisekiFuse] serves the requests read by the fuser session. A callback
+/// only decodes the request and hands it over to a task of the runtime along
+/// with the reply, so a slow request doesn't hold up the others.
 #[derive(Debug)]
 pub struct KisekiFuse {
-    config:  FuseConfig,
-    vfs:     Arc<KisekiVFS>,
-    runtime: runtime::Runtime,
+    config:      FuseConfig,
+    vfs:         Arc<KisekiVFS>,
+    runtime:     runtime::Runtime,
+    groups:      Option<GroupResolver>,
+    // bounds the requests being served at the same time.
+    limiter:     Arc<Semaphore>,
+    inflight:    Arc<Inflight>,
+    init_error:  Arc<Mutex<Option<String>>>,
+    invalidator: Invalidator,
+    // the kernel has granted the writeback cache on init.
+    writeback:   bool,
 }
 
 impl KisekiFuse {
@@ -65,85 +91,180 @@ impl KisekiFuse {
             .build()
             .with_whatever_context(|e| format!("unable to built tokio runtime {e} "))?;
         info!(
-            "build tokio runtime with {} working threads",
-            fuse_config.async_work_threads
+            "build tokio runtime with {} working threads, serving at most {} requests at once",
+            fuse_config.async_work_threads, fuse_config.max_concurrent_requests
         );
+        let groups = fuse_config
+            .supplementary_groups
+            .then(GroupResolver::default);
+        let limiter = Arc::new(Semaphore::new(fuse_config.max_concurrent_requests.max(1)));
+        let inflight = Arc::new(Inflight::default());
+        runtime.spawn(inflight.clone().watch_signals());
+        let invalidator = Invalidator::default();
+        runtime.spawn(invalidator.clone().watch(vfs.subscribe_changes()));
         Ok(Self {
             config: fuse_config,
             vfs: Arc::new(vfs),
             runtime,
+            groups,
+            limiter,
+            inflight,
+            init_error: Default::default(),
+            invalidator,
+            writeback: false,
         })
     }
 
-    fn reply_entry(&self, ctx: &FuseContext, reply: ReplyEntry, mut entry: FullEntry) {
-        let inode = entry.inode;
-        if !inode.is_special()
-            && entry.attr.is_file()
-            && self.vfs.modified_since(inode, ctx.start_at)
-        {
-            debug!("refresh attr for {:?}", inode);
-            match self
-                .runtime
-                .block_on(self.vfs.get_attr(inode).in_current_span())
-            {
-                Ok(new_attr) => {
-                    debug!("refresh attr for {:?} to {:?}", inode, new_attr);
-                    entry.attr = new_attr;
-                }
-                Err(e) => {
-                    debug!("failed to refresh attr for {:?} {:?}", inode, e);
-                }
-            }
+    /// [init_error] tells why the init failed after the session ends, the
+    /// kernel only gets the errno.
+    pub fn init_error(&self) -> Arc<Mutex<Option<String>>> { self.init_error.clone() }
+
+    /// [invalidator] should be attached to the notifier of the session, so
+    /// the stale caches of the kernel are dropped.
+    pub fn invalidator(&self) -> Invalidator { self.invalidator.clone() }
+
+    // open_flags is how a file is opened for the flags of the request.
+    fn open_flags(&self, flags: i32) -> i32 {
+        if self.writeback {
+            writeback_open_flags(flags)
+        } else {
+            flags
         }
-        self.runtime.block_on(
-            self.vfs
-                .try_update_file_reader_length(inode, &mut entry.attr)
-                .in_current_span(),
-        );
+    }
+
+    // context builds the context of the request, the permission checks also
+    // consider the supplementary groups of the caller unless it is disabled.
+    fn context(&self, req: &Request<'_>) -> FuseContext {
+        let mut ctx = FuseContext::from(req);
+        if let Some(groups) = &self.groups {
+            ctx.gid_list = groups.resolve(ctx.pid, ctx.uid, ctx.gid);
+        }
+        ctx
+    }
 
-        reply.entry(
-            self.vfs.get_entry_ttl(entry.attr.kind),
-            &entry.attr.to_fuse_attr(entry.inode),
-            1,
+    // spawn serves the request in the runtime and returns at once, so the
+    // session can go on reading the next one. The session waits here when
+    // too many requests are in flight, which holds the kernel back as well.
+    // The request can be interrupted until it is served, then it goes to the
+    // access log as the op on the inode.
+    fn spawn<S, F>(&self, req: &Request<'_>, op: &'static str, ino: u64, serve: S)
+    where
+        S: FnOnce(Arc<FuseContext>) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let ctx = Arc::new(self.context(req));
+        let (inflight, vfs) = (self.inflight.clone(), self.vfs.clone());
+        inflight.register(ctx.clone());
+        let serve = serve(ctx.clone());
+        // a long poll waits for the other requests, so it must not hold a
+        // permit they wait for.
+        let limiter = (!long_poll(op, ino)).then_some(&self.limiter);
+        dispatch(
+            &self.runtime,
+            limiter,
+            async move {
+                serve.await;
+                inflight.finish(ctx.unique);
+                vfs.log_access(&ctx, op, Ino(ino));
+            }
+            .in_current_span(),
         );
     }
+}
 
-    fn reply_attr(
-        &self,
-        ctx: &FuseContext,
-        reply: ReplyAttr,
-        inode: Ino,
-        mut attr: InodeAttr,
-        // we reply the attr directly without refresh check.
-        directly: bool,
-    ) {
-        if !directly
-            && !inode.is_special()
-            && attr.is_file()
-            && self.vfs.modified_since(inode, ctx.start_at)
-        {
-            debug!("refresh attr for {:?}", inode);
-            match self
-                .runtime
-                .block_on(self.vfs.get_attr(inode).in_current_span())
-            {
-                Ok(new_attr) => {
-                    debug!("refresh attr for {:?} to {:?}", inode, new_attr);
-                    attr = new_attr;
-                }
-                Err(e) => {
-                    debug!("failed to refresh attr for {:?} {:?}", inode, e);
-                }
+// dispatch runs the task in the runtime once the limiter, if any, has a
+// permit for it, and blocks the caller until then.
+fn dispatch<F>(runtime: &runtime::Runtime, limiter: Option<&Arc<Semaphore>>, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let permit = limiter.map(|limiter| {
+        runtime
+            .block_on(limiter.clone().acquire_owned())
+            .expect("the semaphore is never closed")
+    });
+    runtime.spawn(async move {
+        task.await;
+        drop(permit);
+    });
+}
+
+// long_poll tells if the request waits for the others to be served, a read of
+// the access log waits until some request is logged.
+fn long_poll(op: &str, ino: u64) -> bool { op == "read" && Ino(ino) == LOG_INODE }
+
+// unmount detaches the mount point, only fusermount is able to unmount it
+// if it is mounted by an unprivileged user.
+fn unmount(mount_point: &Path) {
+    #[cfg(target_os = "linux")]
+    if let Ok(path) = std::ffi::CString::new(mount_point.as_os_str().as_bytes()) {
+        // SAFETY: the path is a valid C string which lives through the call.
+        if unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) } == 0 {
+            return;
+        }
+    }
+    for fusermount in ["fusermount3", "fusermount"] {
+        let status = std::process::Command::new(fusermount)
+            .args(["-u", "-z"])
+            .arg(mount_point)
+            .status();
+        if matches!(status, Ok(status) if status.success()) {
+            return;
+        }
+    }
+    error!("failed to unmount {}", mount_point.display());
+}
+
+async fn reply_entry(vfs: &KisekiVFS, ctx: &FuseContext, reply: ReplyEntry, mut entry: FullEntry) {
+    let inode = entry.inode;
+    if !inode.is_special() && entry.attr.is_file() && vfs.modified_since(inode, ctx.start_at) {
+        debug!("refresh attr for {:?}", inode);
+        match vfs.get_attr(inode).await {
+            Ok(new_attr) => {
+                debug!("refresh attr for {:?} to {:?}", inode, new_attr);
+                entry.attr = new_attr;
+            }
+            Err(e) => {
+                debug!("failed to refresh attr for {:?} {:?}", inode, e);
             }
-            self.runtime.block_on(
-                self.vfs
-                    .try_update_file_reader_length(inode, &mut attr)
-                    .in_current_span(),
-            );
         }
+    }
+    vfs.try_update_file_reader_length(inode, &mut entry.attr)
+        .await;
 
-        reply.attr(self.vfs.get_entry_ttl(attr.kind), &attr.to_fuse_attr(inode))
+    vfs.add_lookup(inode);
+    reply.entry(
+        vfs.get_entry_ttl(entry.attr.kind),
+        &entry.attr.to_fuse_attr(entry.inode),
+        1,
+    );
+}
+
+async fn reply_attr(
+    vfs: &KisekiVFS,
+    ctx: &FuseContext,
+    reply: ReplyAttr,
+    inode: Ino,
+    mut attr: InodeAttr,
+    // we reply the attr directly without refresh check.
+    directly: bool,
+) {
+    if !directly && !inode.is_special() && attr.is_file() && vfs.modified_since(inode, ctx.start_at)
+    {
+        debug!("refresh attr for {:?}", inode);
+        match vfs.get_attr(inode).await {
+            Ok(new_attr) => {
+                debug!("refresh attr for {:?} to {:?}", inode, new_attr);
+                attr = new_attr;
+            }
+            Err(e) => {
+                debug!("failed to refresh attr for {:?} {:?}", inode, e);
+            }
+        }
+        vfs.try_update_file_reader_length(inode, &mut attr).await;
     }
+
+    reply.attr(vfs.get_entry_ttl(attr.kind), &attr.to_fuse_attr(inode))
 }
 
 impl Filesystem for KisekiFuse {
@@ -151,67 +272,92 @@ impl Filesystem for KisekiFuse {
     /// Called before any other filesystem method.
     /// The kernel module connection can be configured using the KernelConfig
     /// object
-    fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+    fn init(&mut self, req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
         debug!("init kiseki...");
-        let ctx = FuseContext::from(_req);
-        match self.runtime.block_on(self.vfs.init(&ctx).in_current_span()) {
-            Ok(_) => {
-                // TODO
-            }
-            Err(_) => {
-                // TODO
-            }
+        let ctx = self.context(req);
+        if let Err(e) = self.runtime.block_on(self.vfs.init(&ctx).in_current_span()) {
+            error!("failed to init kiseki, unmount it: {:?}", e);
+            *self.init_error.lock().unwrap() = Some(e.to_string());
+            // the kernel refuses every request once the init fails, yet the
+            // mount stays until someone unmounts it, which ends the session.
+            let mount_point = self.config.mount_point.clone();
+            std::thread::spawn(move || unmount(&mount_point));
+            return Err(e.to_errno());
         }
+        let granted = negotiate(&self.config, config);
+        self.writeback = granted & consts::FUSE_WRITEBACK_CACHE != 0;
         Ok(())
     }
 
+    /// Clean up filesystem.
+    /// Called on filesystem exit.
+    fn destroy(&mut self) {
+        debug!("destroy kiseki...");
+        self.runtime.block_on(self.vfs.destroy().in_current_span());
+    }
+
     #[instrument(level = "info", skip_all, fields(req = _req.unique(), ino = parent, name = ? name))]
     fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        let name = match name.to_str() {
-            Some(n) => n,
-            None => {
-                reply.error(libc::EINVAL);
-                return;
-            }
-        };
-
         if name.len() > MAX_NAME_LENGTH {
             reply.error(libc::ENAMETOOLONG);
             return;
         }
 
-        let entry = match self.runtime.block_on(
-            self.vfs
-                .lookup(ctx.clone(), Ino::from(parent), name)
-                .in_current_span(),
-        ) {
-            Ok(n) => n,
-            Err(e) => {
-                // TODO: handle this error
-                reply.error(e.to_errno());
-                return;
-            }
-        };
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        self.spawn(_req, "lookup", parent, move |ctx| async move {
+            let entry = match vfs.lookup(ctx.clone(), Ino::from(parent), &name).await {
+                Ok(n) => n,
+                Err(e) => {
+                    // TODO: handle this error
+                    reply.error(e.to_errno());
+                    return;
+                }
+            };
 
-        debug!("lookup {:?} {:?}", parent, entry);
+            debug!("lookup {:?} {:?}", parent, entry);
 
-        self.reply_entry(&ctx, reply, entry);
+            reply_entry(&vfs, &ctx, reply, entry).await;
+        });
+    }
+
+    /// The kernel drops nlookup references of the inode, no reply is sent.
+    #[instrument(level = "debug", skip_all, fields(ino = ino, nlookup = nlookup))]
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        let vfs = self.vfs.clone();
+        self.runtime
+            .spawn(async move { vfs.forget(Ino(ino), nlookup).await }.in_current_span());
+    }
+
+    #[instrument(level = "debug", skip_all, fields(count = nodes.len()))]
+    fn batch_forget(&mut self, _req: &Request<'_>, nodes: &[fuse_forget_one]) {
+        let vfs = self.vfs.clone();
+        let nodes = nodes
+            .iter()
+            .map(|n| (Ino(n.nodeid), n.nlookup))
+            .collect::<Vec<_>>();
+        self.runtime.spawn(
+            async move {
+                for (inode, nlookup) in nodes {
+                    vfs.forget(inode, nlookup).await;
+                }
+            }
+            .in_current_span(),
+        );
     }
 
     #[instrument(level = "info", skip_all, fields(req = _req.unique(), ino = ino, name = field::Empty))]
     fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
-        match self
-            .runtime
-            .block_on(self.vfs.get_attr(Ino::from(ino)).in_current_span())
-        // .block_on(self.vfs.get_attr(Ino::from(ino)))
-        {
-            Ok(attr) => self.reply_attr(&EMPTY_CONTEXT, reply, Ino(ino), attr, true),
-            Err(e) => {
-                error!("getattr {:?} {:?}", ino, e);
-                reply.error(e.to_errno())
-            }
-        };
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "getattr", ino, move |_| async move {
+            match vfs.get_attr(Ino::from(ino)).await {
+                Ok(attr) => reply_attr(&vfs, &EMPTY_CONTEXT, reply, Ino(ino), attr, true).await,
+                Err(e) => {
+                    error!("getattr {:?} {:?}", ino, e);
+                    reply.error(e.to_errno())
+                }
+            };
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), ino = ino, name = field::Empty))]
@@ -233,9 +379,9 @@ impl Filesystem for KisekiFuse {
         flags: Option<u32>,
         reply: ReplyAttr,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "setattr", ino, move |ctx| async move {
+            match vfs
                 .set_attr(
                     ctx.clone(),
                     Ino(ino),
@@ -248,23 +394,23 @@ impl Filesystem for KisekiFuse {
                     size,
                     fh,
                 )
-                .in_current_span(),
-        ) {
-            Ok(new_attr) => self.reply_attr(&ctx, reply, Ino(ino), new_attr, false),
-            Err(e) => reply.error(e.to_errno()),
-        }
+                .await
+            {
+                Ok(new_attr) => reply_attr(&vfs, &ctx, reply, Ino(ino), new_attr, false).await,
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     /// Read symbolic link.
     fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self
-            .runtime
-            .block_on(self.vfs.readlink(ctx, Ino(ino)).in_current_span())
-        {
-            Ok(target) => reply.data(target.as_ref()),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "readlink", ino, move |ctx| async move {
+            match vfs.readlink(ctx, Ino(ino)).await {
+                Ok(target) => reply.data(target.as_ref()),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     /// In UNIX-like operating systems, when a new file or directory is created,
@@ -291,17 +437,17 @@ impl Filesystem for KisekiFuse {
         reply: ReplyEntry,
     ) {
         // mode_t is u32 on Linux but u16 on macOS, so cast it here
-        let ctx = Arc::new(FuseContext::from(_req));
-        let name = name.to_string_lossy().to_string();
-
-        match self.runtime.block_on(
-            self.vfs
-                .mknod(ctx.clone(), Ino(parent), name, mode, umask, rdev)
-                .in_current_span(),
-        ) {
-            Ok(entry) => self.reply_entry(&ctx, reply, entry),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        self.spawn(_req, "mknod", parent, move |ctx| async move {
+            match vfs
+                .mknod(ctx.clone(), Ino(parent), &name, mode, umask, rdev)
+                .await
+            {
+                Ok(entry) => reply_entry(&vfs, &ctx, reply, entry).await,
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), parent = parent, name = ? name))]
@@ -314,41 +460,40 @@ impl Filesystem for KisekiFuse {
         umask: u32,
         reply: ReplyEntry,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        let name = name.to_string_lossy().to_string();
-        match self.runtime.block_on(
-            self.vfs
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        self.spawn(_req, "mkdir", parent, move |ctx| async move {
+            match vfs
                 .mkdir(ctx.clone(), Ino(parent), &name, mode, umask)
-                .in_current_span(),
-        ) {
-            Ok(entry) => self.reply_entry(&ctx, reply, entry),
-            Err(e) => reply.error(e.to_errno()),
-        }
+                .await
+            {
+                Ok(entry) => reply_entry(&vfs, &ctx, reply, entry).await,
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
-                .unlink(ctx, Ino(parent), name.to_str().unwrap())
-                .in_current_span(),
-        ) {
-            Ok(()) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        self.spawn(_req, "unlink", parent, move |ctx| async move {
+            match vfs.unlink(ctx, Ino(parent), &name).await {
+                Ok(()) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), parent = parent, name = ? name))]
     fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
-                .rmdir(ctx, Ino(parent), name.to_str().unwrap())
-                .in_current_span(),
-        ) {
-            Ok(_) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        };
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        self.spawn(_req, "rmdir", parent, move |ctx| async move {
+            match vfs.rmdir(ctx, Ino(parent), &name).await {
+                Ok(_) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            };
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), parent = parent, name = ? link_name))]
@@ -360,20 +505,17 @@ impl Filesystem for KisekiFuse {
         target: &Path,
         reply: ReplyEntry,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
-                .symlink(
-                    ctx.clone(),
-                    Ino(parent),
-                    link_name.to_str().unwrap(),
-                    target,
-                )
-                .in_current_span(),
-        ) {
-            Ok(e) => self.reply_entry(&ctx, reply, e),
-            Err(e) => reply.error(e.to_errno()),
-        };
+        let vfs = self.vfs.clone();
+        let (link_name, target) = (link_name.to_owned(), target.to_owned());
+        self.spawn(_req, "symlink", parent, move |ctx| async move {
+            match vfs
+                .symlink(ctx.clone(), Ino(parent), &link_name, &target)
+                .await
+            {
+                Ok(e) => reply_entry(&vfs, &ctx, reply, e).await,
+                Err(e) => reply.error(e.to_errno()),
+            };
+        });
     }
 
     #[instrument(level = "info",
@@ -393,22 +535,17 @@ impl Filesystem for KisekiFuse {
         flags: u32,
         reply: ReplyEmpty,
     ) {
-        let ctx = Arc::new(FuseContext::from(req));
-        match self.runtime.block_on(
-            self.vfs
-                .rename(
-                    ctx,
-                    Ino(parent),
-                    name.to_str().unwrap(),
-                    Ino(newparent),
-                    newname.to_str().unwrap(),
-                    flags,
-                )
-                .in_current_span(),
-        ) {
-            Ok(()) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let (name, newname) = (name.to_owned(), newname.to_owned());
+        self.spawn(req, "rename", parent, move |ctx| async move {
+            match vfs
+                .rename(ctx, Ino(parent), &name, Ino(newparent), &newname, flags)
+                .await
+            {
+                Ok(()) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), ino = ino, new_parent = new_parent, new_name = ? new_name))]
@@ -420,32 +557,29 @@ impl Filesystem for KisekiFuse {
         new_name: &OsStr,
         reply: ReplyEntry,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
-                .link(
-                    ctx.clone(),
-                    Ino(ino),
-                    Ino(new_parent),
-                    new_name.to_str().unwrap(),
-                )
-                .in_current_span(),
-        ) {
-            Ok(entry) => self.reply_entry(&ctx, reply, entry),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let new_name = new_name.to_owned();
+        self.spawn(_req, "link", ino, move |ctx| async move {
+            match vfs
+                .link(ctx.clone(), Ino(ino), Ino(new_parent), &new_name)
+                .await
+            {
+                Ok(entry) => reply_entry(&vfs, &ctx, reply, entry).await,
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), ino = _ino, pid = _req.pid(), name = field::Empty))]
     fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        let ctx = FuseContext::from(_req);
-        match self
-            .runtime
-            .block_on(self.vfs.open(&ctx, Ino(_ino), _flags).in_current_span())
-        {
-            Ok(opened) => reply.opened(opened.fh, opened.flags),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let flags = self.open_flags(_flags);
+        self.spawn(_req, "open", _ino, move |ctx| async move {
+            match vfs.open(&ctx, Ino(_ino), flags).await {
+                Ok(opened) => reply.opened(opened.fh, opened.flags),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), ino = ino, fh = fh, offset = offset, size = size, name = field::Empty))]
@@ -460,30 +594,31 @@ impl Filesystem for KisekiFuse {
         lock_owner: Option<u64>,
         reply: ReplyData,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        let mut bytes_read = 0;
-        match self.runtime.block_on(
-            self.vfs
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "read", ino, move |ctx| async move {
+            let mut bytes_read = 0;
+            match vfs
                 .read(ctx, Ino(ino), fh, offset, size, flags, lock_owner)
-                .in_current_span(),
-        ) {
-            Ok(data) => {
-                bytes_read = data.len();
-                reply.data(&data);
-            }
-            Err(e) => {
-                error!("read {:?} {:?}", Ino(ino), e);
-                reply.error(e.to_errno())
+                .await
+            {
+                Ok(data) => {
+                    bytes_read = data.len();
+                    reply.data(&data);
+                }
+                Err(e) => {
+                    error!("read {:?} {:?}", Ino(ino), e);
+                    reply.error(e.to_errno())
+                }
             }
-        }
 
-        debug!(
-            "read {:?} FH: {:?} offset: {:?} read_count: {:?}",
-            Ino(ino),
-            fh,
-            offset,
-            bytes_read
-        );
+            debug!(
+                "read {:?} FH: {:?} offset: {:?} read_count: {:?}",
+                Ino(ino),
+                fh,
+                offset,
+                bytes_read
+            );
+        });
     }
 
     #[instrument(level = "debug", skip_all, fields(req = _req.unique(), ino = ino, fh = fh, offset = ReadableSize(offset as u64).to_string(), length = ReadableSize(data.len() as u64).to_string(), pid = _req.pid(), name = field::Empty))]
@@ -499,39 +634,39 @@ impl Filesystem for KisekiFuse {
         lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
+        let vfs = self.vfs.clone();
+        let data = data.to_vec();
+        self.spawn(_req, "write", ino, move |ctx| async move {
+            match vfs
                 .write(
                     ctx,
                     Ino(ino),
                     fh,
                     offset,
-                    data,
+                    &data,
                     write_flags,
                     flags,
                     lock_owner,
                 )
-                .in_current_span(),
-        ) {
-            Ok(bytes_written) => {
-                reply.written(bytes_written);
+                .await
+            {
+                Ok(bytes_written) => {
+                    reply.written(bytes_written);
+                }
+                Err(e) => reply.error(e.to_errno()),
             }
-            Err(e) => reply.error(e.to_errno()),
-        }
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = req.unique(), ino = ino, fh = fh, pid = req.pid(), name = field::Empty))]
     fn flush(&mut self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
-        let ctx = Arc::new(FuseContext::from(req));
-        match self.runtime.block_on(
-            self.vfs
-                .flush(ctx, Ino(ino), fh, lock_owner)
-                .in_current_span(),
-        ) {
-            Ok(()) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        self.spawn(req, "flush", ino, move |ctx| async move {
+            match vfs.flush(ctx, Ino(ino), fh, lock_owner).await {
+                Ok(()) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = req.unique(), ino = ino, fh = fh, name = field::Empty))]
@@ -545,39 +680,35 @@ impl Filesystem for KisekiFuse {
         _flush: bool,
         reply: ReplyEmpty,
     ) {
-        let ctx = Arc::new(FuseContext::from(req));
-        match self
-            .runtime
-            .block_on(self.vfs.release(ctx, Ino(ino), fh).in_current_span())
-        {
-            Ok(_) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        };
+        let vfs = self.vfs.clone();
+        self.spawn(req, "release", ino, move |ctx| async move {
+            match vfs.release(ctx, Ino(ino), fh).await {
+                Ok(_) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            };
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = _req.unique(), ino = ino, fh = fh, datasync = datasync, name = field::Empty))]
     fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, datasync: bool, _reply: ReplyEmpty) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        match self.runtime.block_on(
-            self.vfs
-                .fsync(ctx, Ino(ino), fh, datasync)
-                .in_current_span(),
-        ) {
-            Ok(()) => _reply.ok(),
-            Err(e) => _reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "fsync", ino, move |ctx| async move {
+            match vfs.fsync(ctx, Ino(ino), fh, datasync).await {
+                Ok(()) => _reply.ok(),
+                Err(e) => _reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = _req.unique(), ino = ino, flags = flags, name = field::Empty))]
     fn opendir(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
-        let ctx = FuseContext::from(_req);
-        match self
-            .runtime
-            .block_on(self.vfs.open_dir(&ctx, ino, flags).in_current_span())
-        {
-            Ok(fh) => reply.opened(fh, flags as u32),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "opendir", ino, move |ctx| async move {
+            match vfs.open_dir(&ctx, ino, flags).await {
+                Ok(fh) => reply.opened(fh, flags as u32),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "warn", skip_all, fields(req = _req.unique(), ino = ino, fh = fh, offset = offset))]
@@ -589,34 +720,32 @@ impl Filesystem for KisekiFuse {
         offset: i64,
         mut reply: ReplyDirectory,
     ) {
-        let ctx = FuseContext::from(_req);
-        let entries = match self.runtime.block_on(
-            self.vfs
-                .read_dir(&ctx, ino, fh, offset, false)
-                .in_current_span(),
-        ) {
-            Ok(n) => n,
-            Err(e) => {
-                reply.error(e.to_errno());
-                return;
-            }
-        };
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "readdir", ino, move |ctx| async move {
+            let entries = match vfs.read_dir(&ctx, ino, fh, offset, false).await {
+                Ok(n) => n,
+                Err(e) => {
+                    reply.error(e.to_errno());
+                    return;
+                }
+            };
 
-        let mut offset = offset + 1;
-        debug!("get entry length: { }", entries.len());
-        for entry in entries.iter() {
-            if reply.add(
-                entry.get_inode().0,
-                offset,
-                entry.get_file_type(),
-                &entry.get_name(),
-            ) {
-                break;
-            } else {
-                offset += 1;
+            let mut offset = offset + 1;
+            debug!("get entry length: { }", entries.len());
+            for entry in entries.iter() {
+                if reply.add(
+                    entry.get_inode().0,
+                    offset,
+                    entry.get_file_type(),
+                    entry.get_name(),
+                ) {
+                    break;
+                } else {
+                    offset += 1;
+                }
             }
-        }
-        reply.ok();
+            reply.ok();
+        });
     }
 
     fn readdirplus(
@@ -627,41 +756,44 @@ impl Filesystem for KisekiFuse {
         offset: i64,
         mut reply: ReplyDirectoryPlus,
     ) {
-        let ctx = FuseContext::from(_req);
-        let entries = match self.runtime.block_on(
-            self.vfs
-                .read_dir(&ctx, ino, fh, offset, true)
-                .in_current_span(),
-        ) {
-            Ok(n) => n,
-            Err(e) => {
-                reply.error(e.to_errno());
-                return;
-            }
-        };
-
-        let mut offset = offset + 1;
-        debug!("get entry length: { }", entries.len());
-        for entry in entries.iter() {
-            match entry {
-                Entry::Full(fe) => {
-                    if reply.add(
-                        entry.get_inode().0,
-                        offset,
-                        &entry.get_name(),
-                        self.vfs.get_entry_ttl(entry.get_file_type()),
-                        &fe.attr.to_fuse_attr(fe.inode),
-                        1,
-                    ) {
-                        break;
-                    } else {
-                        offset += 1;
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "readdirplus", ino, move |ctx| async move {
+            let entries = match vfs.read_dir(&ctx, ino, fh, offset, true).await {
+                Ok(n) => n,
+                Err(e) => {
+                    reply.error(e.to_errno());
+                    return;
+                }
+            };
+
+            let mut offset = offset + 1;
+            debug!("get entry length: { }", entries.len());
+            for entry in entries.iter() {
+                match entry {
+                    Entry::Full(fe) => {
+                        if reply.add(
+                            entry.get_inode().0,
+                            offset,
+                            entry.get_name(),
+                            vfs.get_entry_ttl(entry.get_file_type()),
+                            &fe.attr.to_fuse_attr(fe.inode),
+                            1,
+                        ) {
+                            break;
+                        } else {
+                            offset += 1;
+                        }
+                        // the kernel looks up every entry but the dots.
+                        let name = entry.get_name();
+                        if name != DOT && name != DOT_DOT {
+                            vfs.add_lookup(fe.inode);
+                        }
                     }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
-        reply.ok();
+            reply.ok();
+        });
     }
 
     fn releasedir(
@@ -672,13 +804,13 @@ impl Filesystem for KisekiFuse {
         _flags: i32,
         reply: ReplyEmpty,
     ) {
-        match self
-            .runtime
-            .block_on(self.vfs.release_dir(Ino(ino), fh).in_current_span())
-        {
-            Ok(_) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        self.spawn(_req, "releasedir", ino, move |_| async move {
+            match vfs.release_dir(Ino(ino), fh).await {
+                Ok(_) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = _req.unique(), ino = _ino, name = field::Empty))]
@@ -702,7 +834,7 @@ impl Filesystem for KisekiFuse {
         // Padding bytes reserved for future use
         // };
 
-        let ctx = Arc::new(FuseContext::from(_req));
+        let ctx = Arc::new(self.context(_req));
         // in case we can't get the stat_fs, we just return a default one.
         let state = self.vfs.stat_fs(ctx, _ino).unwrap_or(FSStat::default());
 
@@ -744,23 +876,27 @@ impl Filesystem for KisekiFuse {
         flags: i32,
         reply: ReplyCreate,
     ) {
-        let ctx = Arc::new(FuseContext::from(_req));
-        let name = name.to_string_lossy().to_string();
-
-        match self.runtime.block_on(
-            self.vfs
-                .create(ctx.clone(), Ino(parent), &name, mode, umask, flags)
-                .in_current_span(),
-        ) {
-            Ok((entry, fh)) => reply.created(
-                self.vfs.get_entry_ttl(entry.attr.kind),
-                &entry.attr.to_fuse_attr(entry.inode),
-                1,
-                fh,
-                flags as u32,
-            ),
-            Err(e) => reply.error(e.to_errno()),
-        }
+        let vfs = self.vfs.clone();
+        let name = name.to_owned();
+        let open_flags = self.open_flags(flags);
+        self.spawn(_req, "create", parent, move |ctx| async move {
+            match vfs
+                .create(ctx.clone(), Ino(parent), &name, mode, umask, open_flags)
+                .await
+            {
+                Ok((entry, fh)) => {
+                    vfs.add_lookup(entry.inode);
+                    reply.created(
+                        vfs.get_entry_ttl(entry.attr.kind),
+                        &entry.attr.to_fuse_attr(entry.inode),
+                        1,
+                        fh,
+                        flags as u32,
+                    )
+                }
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
     }
 
     #[instrument(level = "info", skip_all, fields(req = req.unique(), ino = ino, name = field::Empty))]
@@ -774,14 +910,238 @@ impl Filesystem for KisekiFuse {
         mode: i32,
         reply: ReplyEmpty,
     ) {
-        let ctx = Arc::new(FuseContext::from(req));
-        match self.runtime.block_on(
-            self.vfs
+        let vfs = self.vfs.clone();
+        self.spawn(req, "fallocate", ino, move |ctx| async move {
+            match vfs
                 .fallocate(ctx, Ino(ino), fh, offset, length, mode as u8)
-                .in_current_span(),
-        ) {
-            Ok(()) => reply.ok(),
-            Err(e) => reply.error(e.to_errno()),
+                .await
+            {
+                Ok(()) => reply.ok(),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
+    }
+
+    #[instrument(level = "debug", skip_all, fields(req = req.unique(), ino = ino, fh = fh, offset = offset, whence = whence))]
+    fn lseek(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        let vfs = self.vfs.clone();
+        self.spawn(req, "lseek", ino, move |ctx| async move {
+            match vfs.lseek(ctx, Ino(ino), fh, offset, whence).await {
+                Ok(offset) => reply.offset(offset),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
+    }
+
+    #[instrument(level = "info", skip_all, fields(req = req.unique(), ino_in = ino_in, ino_out = ino_out, len = ReadableSize(len).to_string()))]
+    fn copy_file_range(
+        &mut self,
+        req: &Request<'_>,
+        ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        // the copied length is replied as an u32, the kernel comes back for the rest.
+        let len = len.min(u32::MAX as u64);
+        let vfs = self.vfs.clone();
+        self.spawn(req, "copy_file_range", ino_in, move |ctx| async move {
+            match vfs
+                .copy_file_range(
+                    ctx,
+                    Ino(ino_in),
+                    fh_in,
+                    offset_in,
+                    Ino(ino_out),
+                    fh_out,
+                    offset_out,
+                    len,
+                    flags,
+                )
+                .await
+            {
+                Ok(copied) => reply.written(copied as u32),
+                Err(e) => reply.error(e.to_errno()),
+            }
+        });
+    }
+
+    /// Serves the chattr flags and the private commands of kiseki. FICLONE and
+    /// FICLONERANGE never get here: the kernel handles them by itself and
+    /// FUSE can't remap a file range, `cp --reflink=auto` falls back to
+    /// copy_file_range which borrows the slices as well.
+    #[instrument(level = "info", skip_all, fields(req = req.unique(), ino = ino, cmd = cmd))]
+    fn ioctl(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        _out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        let vfs = self.vfs.clone();
+        match cmd {
+            IOC_CLONE => {
+                let Some(clone_req) = CloneRequest::decode(in_data) else {
+                    reply.error(libc::EINVAL);
+                    return;
+                };
+                self.spawn(req, "ioctl", ino, move |ctx| async move {
+                    match vfs
+                        .clone_entry(
+                            ctx,
+                            Ino(ino),
+                            clone_req.parent,
+                            OsStr::from_bytes(&clone_req.name),
+                            clone_req.preserve,
+                        )
+                        .await
+                    {
+                        Ok(_) => reply.ioctl(0, &[]),
+                        Err(e) => reply.error(e.to_errno()),
+                    }
+                });
+            }
+            FS_IOC_GETFLAGS => {
+                self.spawn(req, "ioctl", ino, move |_| async move {
+                    match vfs.get_flags(Ino(ino)).await {
+                        Ok(flags) => reply.ioctl(0, &(to_fs_flags(flags) as c_int).to_ne_bytes()),
+                        Err(e) => reply.error(e.to_errno()),
+                    }
+                });
+            }
+            FS_IOC_SETFLAGS => {
+                // the kernel may pass a long, the flags are in the int at the front.
+                let Some(fs_flags) = in_data
+                    .get(..std::mem::size_of::<c_int>())
+                    .and_then(|buf| buf.try_into().ok())
+                    .map(c_int::from_ne_bytes)
+                else {
+                    reply.error(libc::EINVAL);
+                    return;
+                };
+                let Some(flags) = from_fs_flags(fs_flags as u32) else {
+                    reply.error(libc::EOPNOTSUPP);
+                    return;
+                };
+                self.spawn(req, "ioctl", ino, move |ctx| async move {
+                    match vfs.set_flags(ctx, Ino(ino), flags).await {
+                        Ok(_) => reply.ioctl(0, &[]),
+                        Err(e) => reply.error(e.to_errno()),
+                    }
+                });
+            }
+            _ => reply.error(libc::ENOTTY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use kiseki_meta::test_util::test_meta;
+    use kiseki_types::ino::ROOT_INO;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn make_vfs() -> (TempDir, KisekiVFS) {
+        let (dir, meta) = test_meta();
+        let vfs = KisekiVFS::new(kiseki_vfs::Config::default(), meta).unwrap();
+        (dir, vfs)
+    }
+
+    #[test]
+    fn dispatch_within_limit() {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap();
+        let limiter = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        for _ in 0..6 {
+            let (running, most, done_tx) = (running.clone(), most.clone(), done_tx.clone());
+            dispatch(&runtime, Some(&limiter), async move {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                done_tx.send(()).unwrap();
+            });
+        }
+        for _ in 0..6 {
+            done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        // the tasks run side by side, yet never more than the permits.
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn access_log_outside_limit() {
+        let runtime = Arc::new(
+            runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap(),
+        );
+        let (_dir, vfs) = runtime.block_on(async { make_vfs() });
+        let vfs = Arc::new(vfs);
+        let ctx = Arc::new(FuseContext::background());
+        let opened = runtime
+            .block_on(vfs.open(&ctx, LOG_INODE, libc::O_RDONLY))
+            .unwrap();
+
+        // a single permit, the open reader of the log waits without it.
+        let limiter = Arc::new(Semaphore::new(1));
+        assert!(long_poll("read", LOG_INODE.0));
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        {
+            let (vfs, ctx) = (vfs.clone(), ctx.clone());
+            dispatch(&runtime, None, async move {
+                let log = vfs
+                    .read(ctx, LOG_INODE, opened.fh, 0, 4096, 0, None)
+                    .await
+                    .unwrap();
+                done_tx.send(log).unwrap();
+            });
+        }
+
+        // the request which feeds the log gets the permit, the session
+        // would wait for it forever if the reader held it.
+        assert!(!long_poll("getattr", ROOT_INO.0));
+        assert!(!long_poll("read", ROOT_INO.0));
+        {
+            let (runtime, limiter) = (runtime.clone(), limiter.clone());
+            let (vfs, ctx) = (vfs.clone(), ctx.clone());
+            std::thread::spawn(move || {
+                dispatch(&runtime, Some(&limiter), async move {
+                    vfs.log_access(&ctx, "getattr", ROOT_INO);
+                });
+            });
         }
+        let log = done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let log = String::from_utf8(log.to_vec()).unwrap();
+        assert!(log.contains("getattr"), "{log}");
     }
 }
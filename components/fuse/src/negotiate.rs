@@ -0,0 +1,219 @@
+use std::cmp::max;
+
+use fuser::{consts, KernelConfig};
+use tracing::{debug, warn};
+
+use crate::FuseConfig;
+
+/// [Kernel] is what the init negotiates with, the [KernelConfig] of fuser.
+pub(crate) trait Kernel {
+    fn add_capabilities(&mut self, capabilities: u32) -> Result<(), u32>;
+    fn set_max_write(&mut self, value: u32) -> Result<u32, u32>;
+    fn set_max_readahead(&mut self, value: u32) -> Result<u32, u32>;
+    fn set_max_background(&mut self, value: u16) -> Result<u16, u16>;
+    fn set_congestion_threshold(&mut self, value: u16) -> Result<u16, u16>;
+}
+
+impl Kernel for KernelConfig {
+    fn add_capabilities(&mut self, capabilities: u32) -> Result<(), u32> {
+        KernelConfig::add_capabilities(self, capabilities)
+    }
+
+    fn set_max_write(&mut self, value: u32) -> Result<u32, u32> {
+        KernelConfig::set_max_write(self, value)
+    }
+
+    fn set_max_readahead(&mut self, value: u32) -> Result<u32, u32> {
+        KernelConfig::set_max_readahead(self, value)
+    }
+
+    fn set_max_background(&mut self, value: u16) -> Result<u16, u16> {
+        KernelConfig::set_max_background(self, value)
+    }
+
+    fn set_congestion_threshold(&mut self, value: u16) -> Result<u16, u16> {
+        KernelConfig::set_congestion_threshold(self, value)
+    }
+}
+
+/// [negotiate] asks the kernel for the capabilities and the limits of the
+/// mount options, falls back to what the kernel supports. It returns the
+/// capabilities granted.
+pub(crate) fn negotiate(config: &FuseConfig, kernel: &mut impl Kernel) -> u32 {
+    // fuser asks for the async read anyway.
+    let mut capabilities = consts::FUSE_ASYNC_READ;
+    if config.writeback_cache {
+        capabilities |= consts::FUSE_WRITEBACK_CACHE;
+    }
+    if config.readdirplus {
+        capabilities |= consts::FUSE_DO_READDIRPLUS | consts::FUSE_READDIRPLUS_AUTO;
+    }
+    if config.parallel_dirops {
+        capabilities |= consts::FUSE_PARALLEL_DIROPS;
+    }
+    if config.atomic_o_trunc {
+        capabilities |= consts::FUSE_ATOMIC_O_TRUNC;
+    }
+    if let Err(unsupported) = kernel.add_capabilities(capabilities) {
+        warn!("the kernel doesn't support the capabilities {unsupported:#x}");
+        capabilities &= !unsupported;
+        let _ = kernel.add_capabilities(capabilities);
+    }
+
+    if let Err(nearest) = kernel.set_max_write(config.max_write) {
+        warn!(
+            "max write {} is out of range, use {nearest}",
+            config.max_write
+        );
+        let _ = kernel.set_max_write(nearest);
+    }
+    if let Err(nearest) = kernel.set_max_readahead(config.max_readahead) {
+        warn!(
+            "max readahead {} is out of range, use {nearest}",
+            config.max_readahead
+        );
+        let _ = kernel.set_max_readahead(nearest);
+    }
+    let max_background = config.max_background.max(1);
+    let _ = kernel.set_max_background(max_background);
+    // the kernel holds the async requests back above the threshold.
+    let _ = kernel.set_congestion_threshold(max(max_background / 4 * 3, 1));
+    debug!("the kernel grants capabilities {capabilities:#x}, max background {max_background}");
+    capabilities
+}
+
+/// [writeback_open_flags] adjusts the flags of an open under the writeback
+/// cache. The kernel may read a page back to fill a partial write, so a
+/// write-only file is opened for read as well, and the kernel appends by
+/// itself with the size it caches, so O_APPEND is dropped.
+pub(crate) fn writeback_open_flags(flags: i32) -> i32 {
+    let mut flags = flags;
+    if flags & libc::O_ACCMODE == libc::O_WRONLY {
+        flags = flags & !libc::O_ACCMODE | libc::O_RDWR;
+    }
+    flags & !libc::O_APPEND
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // FakeKernel supports the capabilities and the limits it is built with.
+    #[derive(Default)]
+    struct FakeKernel {
+        supported:    u32,
+        max_write:    u32,
+        capabilities: u32,
+        write:        u32,
+        readahead:    u32,
+        background:   u16,
+        congestion:   u16,
+    }
+
+    impl Kernel for FakeKernel {
+        fn add_capabilities(&mut self, capabilities: u32) -> Result<(), u32> {
+            if capabilities & !self.supported != 0 {
+                return Err(capabilities & !self.supported);
+            }
+            self.capabilities |= capabilities;
+            Ok(())
+        }
+
+        fn set_max_write(&mut self, value: u32) -> Result<u32, u32> {
+            if value > self.max_write {
+                return Err(self.max_write);
+            }
+            self.write = value;
+            Ok(value)
+        }
+
+        fn set_max_readahead(&mut self, value: u32) -> Result<u32, u32> {
+            self.readahead = value;
+            Ok(value)
+        }
+
+        fn set_max_background(&mut self, value: u16) -> Result<u16, u16> {
+            self.background = value;
+            Ok(value)
+        }
+
+        fn set_congestion_threshold(&mut self, value: u16) -> Result<u16, u16> {
+            self.congestion = value;
+            Ok(value)
+        }
+    }
+
+    fn config() -> FuseConfig {
+        FuseConfig {
+            mount_point:             PathBuf::from("/tmp/kiseki"),
+            mount_options:           vec![],
+            async_work_threads:      1,
+            max_concurrent_requests: 1,
+            supplementary_groups:    false,
+            max_write:               1 << 20,
+            max_readahead:           1 << 20,
+            max_background:          64,
+            writeback_cache:         true,
+            readdirplus:             true,
+            parallel_dirops:         false,
+            atomic_o_trunc:          true,
+        }
+    }
+
+    #[test]
+    fn grant_all() {
+        let mut kernel = FakeKernel {
+            supported: u32::MAX,
+            max_write: u32::MAX,
+            ..Default::default()
+        };
+        let granted = negotiate(&config(), &mut kernel);
+        assert_eq!(granted, kernel.capabilities);
+        assert_ne!(granted & consts::FUSE_WRITEBACK_CACHE, 0);
+        assert_ne!(granted & consts::FUSE_READDIRPLUS_AUTO, 0);
+        assert_ne!(granted & consts::FUSE_ATOMIC_O_TRUNC, 0);
+        // not asked for.
+        assert_eq!(granted & consts::FUSE_PARALLEL_DIROPS, 0);
+        assert_eq!(kernel.write, 1 << 20);
+        assert_eq!(kernel.readahead, 1 << 20);
+        assert_eq!(kernel.background, 64);
+        assert_eq!(kernel.congestion, 48);
+    }
+
+    #[test]
+    fn fall_back() {
+        let mut kernel = FakeKernel {
+            supported: consts::FUSE_ASYNC_READ | consts::FUSE_ATOMIC_O_TRUNC,
+            max_write: 128 << 10,
+            ..Default::default()
+        };
+        let mut config = config();
+        config.max_background = 0;
+        let granted = negotiate(&config, &mut kernel);
+        // the supported ones are still asked for.
+        assert_eq!(
+            granted,
+            consts::FUSE_ASYNC_READ | consts::FUSE_ATOMIC_O_TRUNC
+        );
+        assert_eq!(kernel.capabilities, granted);
+        assert_eq!(kernel.write, 128 << 10);
+        assert_eq!(kernel.background, 1);
+        assert_eq!(kernel.congestion, 1);
+    }
+
+    #[test]
+    fn writeback_flags() {
+        assert_eq!(writeback_open_flags(libc::O_WRONLY), libc::O_RDWR);
+        assert_eq!(
+            writeback_open_flags(libc::O_WRONLY | libc::O_APPEND | libc::O_TRUNC),
+            libc::O_RDWR | libc::O_TRUNC
+        );
+        assert_eq!(writeback_open_flags(libc::O_RDONLY), libc::O_RDONLY);
+        assert_eq!(
+            writeback_open_flags(libc::O_RDWR | libc::O_APPEND),
+            libc::O_RDWR
+        );
+    }
+}
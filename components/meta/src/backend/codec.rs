@@ -0,0 +1,135 @@
+//! Encodings of the values stored in the backend.
+//!
+//! The structured values are written as one byte of version followed by the
+//! bincode of the value. When the layout of a value changes, bump its
+//! [Versioned::VERSION], keep the old layout around as a frozen struct, and
+//! decode it in [Versioned::decode_version], so the volumes written by an
+//! older build can still be read.
+//!
+//! The counters and hard link counts are plain u64 and are stored as bare
+//! bincode, the chunk slices are fixed width records whose bincode enum tag
+//! tells the layout apart, see [kiseki_types::slice::SLICE_BYTES].
+
+use kiseki_types::{attr::InodeAttr, entry::DEntry, setting::Format, stat::DirStat};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub(crate) trait Versioned: Serialize + DeserializeOwned {
+    /// The version written by this build.
+    const VERSION: u8;
+
+    /// [decode_version] decodes a value written with an older version.
+    fn decode_version(version: u8, _buf: &[u8]) -> bincode::Result<Self> {
+        Err(unknown_version(version))
+    }
+}
+
+impl Versioned for InodeAttr {
+    const VERSION: u8 = 1;
+}
+
+impl Versioned for DEntry {
+    const VERSION: u8 = 1;
+}
+
+impl Versioned for DirStat {
+    const VERSION: u8 = 1;
+}
+
+pub(crate) fn encode<V: Versioned>(value: &V) -> bincode::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(1 + bincode::serialized_size(value)? as usize);
+    buf.push(V::VERSION);
+    bincode::serialize_into(&mut buf, value)?;
+    Ok(buf)
+}
+
+pub(crate) fn decode<V: Versioned>(buf: &[u8]) -> bincode::Result<V> {
+    match buf.split_first() {
+        Some((version, rest)) if *version == V::VERSION => bincode::deserialize(rest),
+        Some((version, rest)) => V::decode_version(*version, rest),
+        None => Err(Box::new(bincode::ErrorKind::Custom(
+            "empty value".to_string(),
+        ))),
+    }
+}
+
+/// [decode_legacy] decodes a value written by schema version 0, which has no
+/// version byte at all.
+pub(crate) fn decode_legacy<V: DeserializeOwned>(buf: &[u8]) -> bincode::Result<V> {
+    bincode::deserialize(buf)
+}
+
+fn unknown_version(version: u8) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(format!(
+        "unknown value version {}",
+        version
+    )))
+}
+
+/// [LegacyFormat] is the [Format] of schema version 0.
+#[derive(Deserialize)]
+struct LegacyFormat {
+    name:         String,
+    chunk_size:   usize,
+    block_size:   usize,
+    page_size:    usize,
+    max_capacity: Option<usize>,
+    max_inodes:   Option<usize>,
+}
+
+pub(crate) fn encode_format(format: &Format) -> bincode::Result<Vec<u8>> {
+    serde_json::to_vec(format).map_err(|e| Box::new(bincode::ErrorKind::Custom(e.to_string())))
+}
+
+/// [decode_format] decodes the JSON format, or the bincode one written by
+/// the first builds of schema version 0, whose first byte is the length of a
+/// short name and never the opening brace.
+pub(crate) fn decode_format(buf: &[u8]) -> bincode::Result<Format> {
+    if buf.first() == Some(&b'{') {
+        return serde_json::from_slice(buf)
+            .map_err(|e| Box::new(bincode::ErrorKind::Custom(e.to_string())));
+    }
+    let legacy: LegacyFormat = decode_legacy(buf)?;
+    Ok(Format {
+        name: legacy.name,
+        chunk_size: legacy.chunk_size,
+        block_size: legacy.block_size,
+        page_size: legacy.page_size,
+        max_capacity: legacy.max_capacity,
+        max_inodes: legacy.max_inodes,
+        schema_version: 0,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use kiseki_types::setting::SCHEMA_VERSION;
+
+    use super::*;
+
+    #[test]
+    fn decode_legacy_format() {
+        let legacy = (
+            String::from("legacy"),
+            1usize << 26,
+            1usize << 22,
+            1usize << 16,
+        );
+        let mut buf = bincode::serialize(&legacy).unwrap();
+        buf.extend(bincode::serialize(&(Some(1usize << 30), None::<usize>)).unwrap());
+        let format = decode_format(&buf).unwrap();
+        assert_eq!(format.name, "legacy");
+        assert_eq!(format.max_capacity, Some(1 << 30));
+        assert_eq!(format.trash_days, Format::default().trash_days);
+        assert_eq!(format.schema_version, 0);
+
+        // the JSON format of version 0 has no schema version.
+        let json = br#"{"name":"legacy","block_size":4194304}"#;
+        let format = decode_format(json).unwrap();
+        assert_eq!(format.block_size, 1 << 22);
+        assert_eq!(format.schema_version, 0);
+
+        let format = decode_format(&encode_format(&Format::default()).unwrap()).unwrap();
+        assert_eq!(format.schema_version, SCHEMA_VERSION);
+    }
+}
@@ -1,4 +1,9 @@
-use kiseki_types::{ino::Ino, slice::SliceID};
+use kiseki_types::{
+    ino::{Ino, ROOT_INO},
+    slice::SliceID,
+};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 
 pub const CURRENT_FORMAT: &str = "current_format";
 pub const USED_SPACE: &str = "used_space";
@@ -7,8 +12,11 @@ pub const LEGACY_SESSIONS: &str = "legacy_sessions";
 pub const NEXT_TRASH: &str = "next_trash";
 pub const NEXT_INODE: &str = "next_inode";
 pub const NEXT_SLICE: &str = "next_slice";
+pub const LAST_BACKUP: &str = "last_backup";
+pub const BACKUP_LEASE: &str = "backup_lease";
+pub const NEXT_CHANGE: &str = "next_change";
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, EnumIter)]
 pub(crate) enum Counter {
     UsedSpace,
     TotalInodes,
@@ -16,35 +24,30 @@ pub(crate) enum Counter {
     NextTrash,
     NextInode,
     NextSlice,
+    LastBackup,
+    BackupLease,
+    NextChange,
 }
 
 impl Into<Vec<u8>> for Counter {
-    fn into(self) -> Vec<u8> {
-        match self {
-            Counter::UsedSpace => USED_SPACE.as_bytes().to_vec(),
-            Counter::TotalInodes => TOTAL_INODES.as_bytes().to_vec(),
-            Counter::LegacySessions => LEGACY_SESSIONS.as_bytes().to_vec(),
-            Counter::NextTrash => NEXT_TRASH.as_bytes().to_vec(),
-            Counter::NextInode => NEXT_INODE.as_bytes().to_vec(),
-            Counter::NextSlice => NEXT_SLICE.as_bytes().to_vec(),
-        }
-    }
+    fn into(self) -> Vec<u8> { setting(self.name()) }
 }
 
-impl AsRef<[u8]> for Counter {
-    fn as_ref(&self) -> &[u8] {
+impl Counter {
+    pub fn name(&self) -> &'static str {
         match self {
-            Counter::UsedSpace => USED_SPACE.as_bytes(),
-            Counter::TotalInodes => TOTAL_INODES.as_bytes(),
-            Counter::LegacySessions => LEGACY_SESSIONS.as_bytes(),
-            Counter::NextTrash => NEXT_TRASH.as_bytes(),
-            Counter::NextInode => NEXT_INODE.as_bytes(),
-            Counter::NextSlice => NEXT_SLICE.as_bytes(),
+            Counter::UsedSpace => USED_SPACE,
+            Counter::TotalInodes => TOTAL_INODES,
+            Counter::LegacySessions => LEGACY_SESSIONS,
+            Counter::NextTrash => NEXT_TRASH,
+            Counter::NextInode => NEXT_INODE,
+            Counter::NextSlice => NEXT_SLICE,
+            Counter::LastBackup => LAST_BACKUP,
+            Counter::BackupLease => BACKUP_LEASE,
+            Counter::NextChange => NEXT_CHANGE,
         }
     }
-}
 
-impl Counter {
     pub fn get_step(&self) -> usize {
         match self {
             Counter::NextTrash => 1,
@@ -55,40 +58,128 @@ impl Counter {
     }
 }
 
-pub fn attr(inode: Ino) -> Vec<u8> { format!("A{:0>8}I", inode.0).into_bytes() }
+// The keys are binary, each starts with a one byte type tag, and the integers
+// are fixed width big-endian, so the keys of the same inode are grouped and
+// sorted by the numbers. The tags are below any printable character, which
+// keeps them apart from the string keys of schema version 1 and earlier.
+const TAG_SETTING: u8 = 0x01;
+const TAG_INODE: u8 = 0x02;
+const TAG_SUSTAINED: u8 = 0x03;
+const TAG_DELETE_CHUNK: u8 = 0x04;
+const TAG_SLICE_REF: u8 = 0x05;
+const TAG_DIR_STAT: u8 = 0x06;
+const TAG_CHANGE: u8 = 0x07;
+const TAG_DELETE_SLICE: u8 = 0x08;
+const TAG_CLIENT: u8 = 0x09;
+
+// The second tag of the keys which belong to an inode.
+const INODE_ATTR: u8 = b'I';
+const INODE_XATTR: u8 = b'X';
+const INODE_DENTRY: u8 = b'D';
+const INODE_PARENT: u8 = b'P';
+const INODE_SYMLINK: u8 = b'S';
+const INODE_CHUNK: u8 = b'C';
+
+/// [setting] is the key of a volume wide value, like the format or a counter.
+///
+/// Key: 0x01 name
+pub fn setting(name: &str) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(1 + name.len());
+    buf.push(TAG_SETTING);
+    buf.extend_from_slice(name.as_bytes());
+    buf
+}
+
+pub fn format() -> Vec<u8> { setting(CURRENT_FORMAT) }
 
-pub fn xattr(inode: Ino, name: &str) -> Vec<u8> {
-    format!("A{:0>8}X{}", inode.0, name).into_bytes()
+/// [inode_keys_prefix] is the prefix of all the keys of the inode: its
+/// attributes, xattrs, entries, parents, symlink and chunks.
+pub fn inode_keys_prefix(inode: Ino) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_INODE);
+    buf.extend_from_slice(&inode.0.to_be_bytes());
+    buf
 }
-pub fn xattr_prefix(inode: Ino) -> Vec<u8> { format!("A{:0>8}X", inode.0).into_bytes() }
 
-pub fn dentry(parent: Ino, name: &str) -> Vec<u8> {
-    format!("A{:0>8}D/{}", parent.0, name).into_bytes()
+/// [inode_keys_range] is the key range of the keys of the inodes from start
+/// on.
+pub fn inode_keys_range(start: Ino) -> (Vec<u8>, Vec<u8>) {
+    (inode_keys_prefix(start), vec![TAG_SUSTAINED])
 }
 
-pub fn dentry_prefix(parent: Ino) -> Vec<u8> { format!("A{:0>8}D/", parent.0).into_bytes() }
+/// [parse_inode] returns the inode which a key of [inode_keys_prefix]
+/// belongs to.
+pub fn parse_inode(key: &[u8]) -> Option<Ino> {
+    let rest = key.strip_prefix(&[TAG_INODE])?;
+    Some(Ino(u64::from_be_bytes(rest.get(..8)?.try_into().ok()?)))
+}
+
+// Key: 0x02 inode(8) kind
+fn inode_prefix(inode: Ino, kind: u8) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(32);
+    buf.push(TAG_INODE);
+    buf.extend_from_slice(&inode.0.to_be_bytes());
+    buf.push(kind);
+    buf
+}
+
+fn inode_key(inode: Ino, kind: u8, suffix: &[u8]) -> Vec<u8> {
+    let mut buf = inode_prefix(inode, kind);
+    buf.extend_from_slice(suffix);
+    buf
+}
+
+pub fn attr(inode: Ino) -> Vec<u8> { inode_prefix(inode, INODE_ATTR) }
+
+pub fn xattr(inode: Ino, name: &[u8]) -> Vec<u8> { inode_key(inode, INODE_XATTR, name) }
+pub fn xattr_prefix(inode: Ino) -> Vec<u8> { inode_prefix(inode, INODE_XATTR) }
+
+pub fn dentry(parent: Ino, name: &[u8]) -> Vec<u8> { inode_key(parent, INODE_DENTRY, name) }
+
+pub fn dentry_prefix(parent: Ino) -> Vec<u8> { inode_prefix(parent, INODE_DENTRY) }
 
 /// [parent] generate key for hard links.
 ///
 /// This key is used to store the hard link count of a file.
 pub fn parent(inode: Ino, parent: Ino) -> Vec<u8> {
-    // AiiiiiiiiPiiiiiiii parents
-    format!("A{:0>8}P{:0>8}", inode.0, parent.0).into_bytes()
+    inode_key(inode, INODE_PARENT, &parent.0.to_be_bytes())
 }
-pub fn parent_prefix(inode: Ino) -> Vec<u8> { format!("A{:0>8}P", inode.0).into_bytes() }
+pub fn parent_prefix(inode: Ino) -> Vec<u8> { inode_prefix(inode, INODE_PARENT) }
 
-pub fn symlink(inode: Ino) -> Vec<u8> { format!("A{:0>8}S", inode.0).into_bytes() }
+pub fn symlink(inode: Ino) -> Vec<u8> { inode_prefix(inode, INODE_SYMLINK) }
 
 // sustained is used when we cannot delete inode since it was being used at
 // right now.
+//
+// Key: 0x03 sid(8) inode(8)
 pub fn sustained(sid: u64, inode: Ino) -> Vec<u8> {
-    format!("SS{:0>8}{:0>8}", sid, inode.0).into_bytes()
+    let mut buf = Vec::with_capacity(17);
+    buf.push(TAG_SUSTAINED);
+    buf.extend_from_slice(&sid.to_be_bytes());
+    buf.extend_from_slice(&inode.0.to_be_bytes());
+    buf
+}
+
+pub fn sustained_prefix() -> Vec<u8> { vec![TAG_SUSTAINED] }
+
+/// [parse_sustained] returns the session and the inode of a key built by
+/// [sustained].
+pub fn parse_sustained(key: &[u8]) -> Option<(u64, Ino)> {
+    let rest = key.strip_prefix(&[TAG_SUSTAINED])?;
+    if rest.len() != 16 {
+        return None;
+    }
+    let (sid, inode) = rest.split_at(8);
+    Some((
+        u64::from_be_bytes(sid.try_into().ok()?),
+        Ino(u64::from_be_bytes(inode.try_into().ok()?)),
+    ))
 }
 
 // delete_chunk_after is a marker used to indicate that when we need to delete
 // the chunks.
 //
-// Key: Diiiiiiii
+// Key: 0x04 inode(8)
 // Val: timestamp
 //
 // Don't know why juicefs doesn't delete the chunk info directly, it setups a
@@ -96,15 +187,249 @@ pub fn sustained(sid: u64, inode: Ino) -> Vec<u8> {
 // Maybe a performance optimization since the slices amount is huge.
 //
 // Juice also put the length in the key, doesn't get the point.
-pub fn delete_chunk_after(inode: Ino) -> Vec<u8> { format!("D{:0>8}", inode.0).into_bytes() }
+pub fn delete_chunk_after(inode: Ino) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_DELETE_CHUNK);
+    buf.extend_from_slice(&inode.0.to_be_bytes());
+    buf
+}
+
+pub fn delete_chunk_prefix() -> Vec<u8> { vec![TAG_DELETE_CHUNK] }
+
+/// [parse_delete_chunk_inode] returns the inode of a key built by
+/// [delete_chunk_after].
+pub fn parse_delete_chunk_inode(key: &[u8]) -> Option<Ino> {
+    let inode = key.strip_prefix(&[TAG_DELETE_CHUNK])?;
+    Some(Ino(u64::from_be_bytes(inode.try_into().ok()?)))
+}
 
 pub fn chunk_slices(inode: Ino, chunk_idx: kiseki_common::ChunkIndex) -> Vec<u8> {
-    format!("A{:0>8}C/{}", inode.0, chunk_idx).into_bytes()
+    inode_key(inode, INODE_CHUNK, &(chunk_idx as u64).to_be_bytes())
 }
-pub fn chunk_slices_prefix(inode: Ino) -> Vec<u8> { format!("A{:0>8}C/", inode.0).into_bytes() }
+pub fn chunk_slices_prefix(inode: Ino) -> Vec<u8> { inode_prefix(inode, INODE_CHUNK) }
 
-/// slice_ref tracks how many borrow slices are referencing to an Owned slice.
-/// We can only delete the slice when the reference count is zero.
-pub fn slice_ref(slice_id: SliceID) -> Vec<u8> { format!("K{:0>8}", slice_id).into_bytes() }
+/// [parse_chunk_index] returns the chunk index of a key built by
+/// [chunk_slices] with the given prefix.
+pub fn parse_chunk_index(prefix: &[u8], key: &[u8]) -> Option<kiseki_common::ChunkIndex> {
+    let idx = key.strip_prefix(prefix)?;
+    Some(u64::from_be_bytes(idx.try_into().ok()?) as kiseki_common::ChunkIndex)
+}
+
+/// slice_ref tracks how many borrow slices are referencing to an Owned slice,
+/// the first reference of a slice isn't counted, so the key is absent for the
+/// slices which have never been borrowed. We can only delete the slice when
+/// its last reference is gone.
+pub fn slice_ref(slice_id: SliceID) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_SLICE_REF);
+    buf.extend_from_slice(&slice_id.to_be_bytes());
+    buf
+}
 
-pub fn dir_stat(inode: Ino) -> Vec<u8> { format!("U{:0>8}I", inode.0).into_bytes() }
+pub fn dir_stat(inode: Ino) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(10);
+    buf.push(TAG_DIR_STAT);
+    buf.extend_from_slice(&inode.0.to_be_bytes());
+    buf.push(INODE_ATTR);
+    buf
+}
+
+/// [change] is the key of a record in the change log, the sequence numbers
+/// are handed out by [Counter::NextChange].
+///
+/// Key: 0x07 seq(8)
+pub fn change(seq: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_CHANGE);
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf
+}
+
+pub fn change_prefix() -> Vec<u8> { vec![TAG_CHANGE] }
+
+/// [delete_slice] marks a slice which no one references anymore, its objects
+/// are waiting to be removed from the object storage.
+///
+/// Key: 0x08 slice_id(8)
+// Val: the size of the slice
+pub fn delete_slice(slice_id: SliceID) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_DELETE_SLICE);
+    buf.extend_from_slice(&slice_id.to_be_bytes());
+    buf
+}
+
+pub fn delete_slice_prefix() -> Vec<u8> { vec![TAG_DELETE_SLICE] }
+
+/// [parse_delete_slice_id] returns the slice id of a key built by
+/// [delete_slice].
+pub fn parse_delete_slice_id(key: &[u8]) -> Option<SliceID> {
+    let id = key.strip_prefix(&[TAG_DELETE_SLICE])?;
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
+
+/// [client] marks a client which follows the change log, so the others
+/// write their changes to the log for it.
+///
+/// Key: 0x09 client_id(8)
+// Val: when the client is seen last, in seconds since the epoch
+pub fn client(client_id: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    buf.push(TAG_CLIENT);
+    buf.extend_from_slice(&client_id.to_be_bytes());
+    buf
+}
+
+pub fn client_prefix() -> Vec<u8> { vec![TAG_CLIENT] }
+
+/// [parse_client_id] returns the client id of a key built by [client].
+pub fn parse_client_id(key: &[u8]) -> Option<u64> {
+    let id = key.strip_prefix(&[TAG_CLIENT])?;
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
+
+/// [non_root_range] is the key range of what a volume holds besides its root
+/// and settings: the other inodes, the sustained and removed files, and the
+/// slice references.
+pub fn non_root_range() -> (Vec<u8>, Vec<u8>) {
+    let mut start = vec![TAG_INODE];
+    start.extend_from_slice(&(ROOT_INO.0 + 1).to_be_bytes());
+    (start, vec![TAG_DIR_STAT])
+}
+
+/// [parse_change_seq] returns the sequence number of a key built by [change].
+pub fn parse_change_seq(key: &[u8]) -> Option<u64> {
+    let seq = key.strip_prefix(&[TAG_CHANGE])?;
+    Some(u64::from_be_bytes(seq.try_into().ok()?))
+}
+
+/// [is_legacy] tells whether the key is in the string layout of schema
+/// version 1 and earlier.
+pub(crate) fn is_legacy(key: &[u8]) -> bool { matches!(key.first(), Some(b) if *b > TAG_CLIENT) }
+
+/// [from_legacy] translates a string key of schema version 1 and earlier to
+/// the current layout, None if it is not a known key.
+///
+/// The legacy keys pad the numbers to 8 digits but don't limit them, so the
+/// sustained key relies on the session id, which is always 0 so far, having
+/// exactly 8 digits.
+pub(crate) fn from_legacy(key: &[u8]) -> Option<Vec<u8>> {
+    fn split_num(buf: &[u8]) -> Option<(u64, &[u8])> {
+        let n = buf.iter().take_while(|b| b.is_ascii_digit()).count();
+        let num = std::str::from_utf8(&buf[..n]).ok()?.parse().ok()?;
+        Some((num, &buf[n..]))
+    }
+
+    if let Some(counter) = Counter::iter().find(|c| c.name().as_bytes() == key) {
+        return Some(counter.into());
+    }
+    if key == CURRENT_FORMAT.as_bytes() {
+        return Some(format());
+    }
+    let (tag, rest) = key.split_first()?;
+    match tag {
+        b'A' => {
+            let (inode, rest) = split_num(rest)?;
+            let inode = Ino(inode);
+            match rest {
+                [b'I'] => Some(attr(inode)),
+                [b'X', name @ ..] => Some(inode_key(inode, INODE_XATTR, name)),
+                [b'D', b'/', name @ ..] => Some(inode_key(inode, INODE_DENTRY, name)),
+                [b'P', parent @ ..] => match split_num(parent)? {
+                    (p, []) => Some(self::parent(inode, Ino(p))),
+                    _ => None,
+                },
+                [b'S'] => Some(symlink(inode)),
+                [b'C', b'/', idx @ ..] => match split_num(idx)? {
+                    (idx, []) => Some(chunk_slices(inode, idx as kiseki_common::ChunkIndex)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        b'S' => {
+            let rest = rest.strip_prefix(b"S")?;
+            if rest.len() <= 8 {
+                return None;
+            }
+            let (sid, _) = split_num(&rest[..8])?;
+            match split_num(&rest[8..])? {
+                (inode, []) => Some(sustained(sid, Ino(inode))),
+                _ => None,
+            }
+        }
+        b'D' => match split_num(rest)? {
+            (inode, []) => Some(delete_chunk_after(Ino(inode))),
+            _ => None,
+        },
+        b'K' => match split_num(rest)? {
+            (id, []) => Some(slice_ref(id)),
+            _ => None,
+        },
+        b'U' => match split_num(rest)? {
+            (inode, [b'I']) => Some(dir_stat(Ino(inode))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_sort_by_number() {
+        let small = attr(Ino(99_999_999));
+        let large = attr(Ino(100_000_000));
+        assert!(small < large);
+        // the keys of an inode never fall into the prefix of another one.
+        assert!(!dentry(Ino(10), b"a").starts_with(&dentry_prefix(Ino(1))));
+        assert!(chunk_slices(Ino(1), 2) < chunk_slices(Ino(1), 10));
+        let prefix = chunk_slices_prefix(Ino(1));
+        assert_eq!(
+            parse_chunk_index(&prefix, &chunk_slices(Ino(1), 10)),
+            Some(10)
+        );
+        assert!(change(255) < change(256));
+        assert_eq!(parse_change_seq(&change(256)), Some(256));
+        assert_eq!(
+            parse_delete_chunk_inode(&delete_chunk_after(Ino(6))),
+            Some(Ino(6))
+        );
+        assert!(delete_chunk_after(Ino(6)).starts_with(&delete_chunk_prefix()));
+        assert_eq!(parse_sustained(&sustained(2, Ino(5))), Some((2, Ino(5))));
+        assert_eq!(parse_client_id(&client(9)), Some(9));
+        assert!(client(u64::MAX) > delete_slice(u64::MAX));
+        assert_eq!(parse_inode(&dentry(Ino(7), b"a")), Some(Ino(7)));
+        let (start, end) = inode_keys_range(Ino(7));
+        assert!(start <= attr(Ino(7)) && attr(Ino(8)) < end);
+        assert!(sustained(0, Ino(1)) >= end);
+    }
+
+    #[test]
+    fn translate_legacy_keys() {
+        let cases = [
+            ("current_format".to_string(), format()),
+            ("next_inode".to_string(), Counter::NextInode.into()),
+            (format!("A{:0>8}I", 1), attr(Ino(1))),
+            (format!("A{:0>8}I", 123_456_789), attr(Ino(123_456_789))),
+            (format!("A{:0>8}Xuser.k", 2), xattr(Ino(2), b"user.k")),
+            (format!("A{:0>8}D/a", 2), dentry(Ino(2), b"a")),
+            (format!("A{:0>8}P{:0>8}", 3, 2), parent(Ino(3), Ino(2))),
+            (format!("A{:0>8}S", 4), symlink(Ino(4))),
+            (format!("A{:0>8}C/{}", 3, 12), chunk_slices(Ino(3), 12)),
+            (format!("SS{:0>8}{:0>8}", 0, 5), sustained(0, Ino(5))),
+            (format!("D{:0>8}", 6), delete_chunk_after(Ino(6))),
+            (format!("K{:0>8}", 7), slice_ref(7)),
+            (format!("U{:0>8}I", 1), dir_stat(Ino(1))),
+        ];
+        for (legacy, expected) in cases {
+            assert!(is_legacy(legacy.as_bytes()));
+            assert!(!is_legacy(&expected));
+            assert_eq!(from_legacy(legacy.as_bytes()), Some(expected), "{}", legacy);
+        }
+        assert_eq!(from_legacy(b"unknown"), None);
+        assert_eq!(from_legacy(b"A0000000"), None);
+    }
+}
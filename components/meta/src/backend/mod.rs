@@ -1,17 +1,29 @@
-use std::{path::Path, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use bytes::Bytes;
 use kiseki_common::ChunkIndex;
 use kiseki_types::{
-    attr::InodeAttr, entry::DEntry, ino::Ino, setting::Format, slice::Slices, stat, stat::DirStat,
+    attr::InodeAttr,
+    entry::DEntry,
+    ino::Ino,
+    setting::Format,
+    slice::{Slice, SliceID, Slices},
+    stat,
+    stat::DirStat,
     FileType,
 };
 use snafu::ensure;
 use strum_macros::EnumString;
 use tracing::debug;
 
-use crate::{backend::key::Counter, context::FuseContext, err::Result};
+use crate::{backend::key::Counter, changes::ChangeRecord, context::FuseContext, err::Result};
 
+pub(crate) mod codec;
 pub mod key;
 #[cfg(feature = "meta-rocksdb")]
 mod rocksdb;
@@ -60,26 +72,69 @@ pub trait Backend: Send + Sync {
 
     fn increase_count_by(&self, counter: Counter, step: usize) -> Result<u64>;
     fn load_count(&self, counter: Counter) -> Result<u64>;
+    fn set_count(&self, counter: Counter, value: u64) -> Result<()>;
+    /// [compare_and_set_count] sets the counter to new only if it still
+    /// equals to old, a missing counter is treated as 0. Returns false if
+    /// someone else has changed it.
+    fn compare_and_set_count(&self, counter: Counter, old: u64, new: u64) -> Result<bool>;
 
     fn get_attr(&self, inode: Ino) -> Result<InodeAttr>;
+    /// [batch_get_attr] fetches the attributes of several inodes at once, in
+    /// the order of the given inodes, None if the inode doesn't exist.
+    fn batch_get_attr(&self, inodes: &[Ino]) -> Result<Vec<Option<InodeAttr>>>;
     fn set_attr(&self, inode: Ino, attr: &InodeAttr) -> Result<()>;
+    /// [do_kill_suid] drops the set-id bits of the file which go away once it
+    /// is modified, leaving the rest of the attr as it is. Returns the new
+    /// attr if any bit is dropped.
+    fn do_kill_suid(&self, inode: Ino) -> Result<Option<InodeAttr>>;
+    /// [batch_update_atime] writes the access time of several inodes at once,
+    /// the stored atime is kept if it is already newer than the given one.
+    fn batch_update_atime(&self, updates: &[(Ino, SystemTime)]) -> Result<()>;
+
+    // the names are raw bytes, they are not necessarily valid UTF-8.
+    fn get_dentry(&self, parent: Ino, name: &[u8]) -> Result<DEntry>;
+    fn set_dentry(&self, parent: Ino, name: &[u8], inode: Ino, typ: FileType) -> Result<()>;
+    /// [list_dentry] lists the entries of the parent in the order of their
+    /// names, starting right after the cursor `after`. -1 means no limit.
+    fn list_dentry(&self, parent: Ino, after: Option<&[u8]>, limit: i64) -> Result<Vec<DEntry>>;
+
+    fn set_symlink(&self, inode: Ino, path: Vec<u8>) -> Result<()>;
+    fn get_symlink(&self, inode: Ino) -> Result<Vec<u8>>;
 
-    fn get_dentry(&self, parent: Ino, name: &str) -> Result<DEntry>;
-    fn set_dentry(&self, parent: Ino, name: &str, inode: Ino, typ: FileType) -> Result<()>;
-    fn list_dentry(&self, parent: Ino, limit: i64) -> Result<Vec<DEntry>>;
+    fn set_xattr(&self, inode: Ino, name: &[u8], value: &[u8]) -> Result<()>;
 
-    fn set_symlink(&self, inode: Ino, path: String) -> Result<()>;
-    fn get_symlink(&self, inode: Ino) -> Result<String>;
+    /// [set_hard_link_count] records how many entries of the parent point to
+    /// the hard linked inode.
+    fn set_hard_link_count(&self, inode: Ino, parent: Ino, count: u64) -> Result<()>;
 
     fn set_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex, slices: Slices) -> Result<()>;
     fn set_raw_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex, buf: Vec<u8>)
     -> Result<()>;
     fn get_raw_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Option<Vec<u8>>>;
     fn get_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Slices>;
+    /// [get_slice_ref] returns how many references the slice has besides the
+    /// first one.
+    fn get_slice_ref(&self, slice_id: SliceID) -> Result<u64>;
+    fn set_slice_ref(&self, slice_id: SliceID, count: u64) -> Result<()>;
 
     fn set_dir_stat(&self, inode: Ino, dir_stat: DirStat) -> Result<()>;
     fn get_dir_stat(&self, inode: Ino) -> Result<DirStat>;
 
+    /// [append_changes] puts the records at the end of the change log, the
+    /// other clients of the volume replay them to drop their stale caches.
+    fn append_changes(&self, records: &[ChangeRecord]) -> Result<()>;
+    /// [load_changes] returns at most limit records after the sequence
+    /// number `after`, along with their sequence numbers.
+    fn load_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, ChangeRecord)>>;
+    /// [trim_changes] drops the records up to the sequence number `until`.
+    fn trim_changes(&self, until: u64) -> Result<()>;
+    /// [touch_client] records that the client is seen at now, in seconds
+    /// since the epoch.
+    fn touch_client(&self, client: u64, now: u64) -> Result<()>;
+    /// [list_clients] returns the clients and when they are seen last.
+    fn list_clients(&self) -> Result<Vec<(u64, u64)>>;
+    fn remove_clients(&self, clients: &[u64]) -> Result<()>;
+
     /// [do_mknod] creates a node in a directory with given name, type and
     /// permissions.
     fn do_mknod(
@@ -88,9 +143,9 @@ pub trait Backend: Send + Sync {
         new_inode: Ino,
         new_inode_attr: InodeAttr,
         parent: Ino,
-        name: &str,
+        name: &[u8],
         typ: FileType,
-        path: String,
+        path: Vec<u8>,
     ) -> Result<(Ino, InodeAttr)>;
 
     /// [do_rmdir] removes a directory from the filesystem. The directory must
@@ -99,7 +154,7 @@ pub trait Backend: Send + Sync {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &[u8],
         // skip updating attribute of a directory if the mtime difference is smaller
         // than this value
         skip_dir_mtime: Duration,
@@ -122,7 +177,7 @@ pub trait Backend: Send + Sync {
         ctx: Arc<FuseContext>,
         inode: Ino,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &[u8],
     ) -> Result<InodeAttr>;
 
     /// [do_unlink] removes a file entry from a directory.
@@ -131,28 +186,177 @@ pub trait Backend: Send + Sync {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: String,
+        name: Vec<u8>,
         session_id: u64,
         open_files_ref: OpenFilesRef,
     ) -> Result<UnlinkResult>;
 
     /// do_delete_chunks try to delete all [free] slices of a file,
     /// free means that slice is not been borrowed.
-    fn do_delete_chunks(&self, inode: Ino);
+    /// return the slices which are no longer referenced by anyone.
+    fn do_delete_chunks(&self, inode: Ino) -> Result<Vec<Slice>>;
+    /// [list_delete_chunks] returns at most limit removed files from the
+    /// inode `start` on, whose chunks are still waiting for deletion, along
+    /// with when they were removed, in seconds since the epoch.
+    fn list_delete_chunks(&self, start: Ino, limit: usize) -> Result<Vec<(Ino, u64)>>;
+    /// [list_delete_slices] returns at most limit freed slices whose objects
+    /// are waiting for deletion, along with their sizes.
+    fn list_delete_slices(&self, limit: usize) -> Result<Vec<(SliceID, usize)>>;
+    /// [remove_delete_slices] forgets the freed slices once their objects
+    /// are deleted.
+    fn remove_delete_slices(&self, slice_ids: &[SliceID]) -> Result<()>;
+
+    /// [do_write_slice] appends the slice to the chunk and grows the file to
+    /// cover it in one transaction. It returns None if the slice has been
+    /// written already.
+    fn do_write_slice(
+        &self,
+        inode: Ino,
+        chunk_index: ChunkIndex,
+        chunk_pos: usize,
+        slice: &Slice,
+    ) -> Result<Option<WriteResult>>;
+
+    /// [do_copy_file_range] copies the range of the src file into the dst file
+    /// by borrowing the slices of the src, no data is moved.
+    fn do_copy_file_range(
+        &self,
+        src: Ino,
+        src_off: u64,
+        dst: Ino,
+        dst_off: u64,
+        size: u64,
+    ) -> Result<CopyResult>;
+
+    /// [do_compact_chunk] drops the slices of the chunk which are totally
+    /// covered by the later ones, return the slices which are no longer
+    /// referenced by anyone.
+    fn do_compact_chunk(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Vec<Slice>>;
+
+    /// [do_clone_entry] makes the new inode a copy of the src: the attributes,
+    /// extended attributes, symlink target and slices are copied, the slices
+    /// are borrowed so no data is moved. The children of a directory are not
+    /// copied. The new inode is linked in the parent as name if attach is
+    /// set, otherwise it stays invisible until [do_attach_entry].
+    #[allow(clippy::too_many_arguments)]
+    fn do_clone_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        src: Ino,
+        new_inode: Ino,
+        parent: Ino,
+        name: &[u8],
+        attach: bool,
+        preserve: bool,
+    ) -> Result<InodeAttr>;
+
+    /// [do_attach_entry] links a detached clone in the parent as name.
+    fn do_attach_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        inode: Ino,
+        parent: Ino,
+        name: &[u8],
+    ) -> Result<InodeAttr>;
+
+    /// [do_purge_inode] drops all the keys of an inode which no entry links
+    /// to, like a part of a tree which failed to be cloned, and returns the
+    /// slices which are no longer referenced by anyone.
+    fn do_purge_inode(&self, inode: Ino) -> Result<Vec<Slice>>;
 
     async fn do_rename(
         &self,
         ctx: Arc<FuseContext>,
         session_id: u64,
         old_parent: Ino,
-        old_name: &str,
+        old_name: &[u8],
         new_parent: Ino,
-        new_name: &str,
+        new_name: &[u8],
         flags: RenameFlags,
         open_files_ref: OpenFilesRef,
     ) -> Result<RenameResult>;
 
     fn do_readlink(&self, inode: Ino) -> Result<Bytes>;
+
+    /// [snapshot] returns a consistent read-only view of the whole metadata,
+    /// writes happen after the snapshot is taken are invisible to it.
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + '_>>;
+
+    /// [scan_raw] returns at most limit key-value pairs in key order,
+    /// starting from the given key (inclusive), the values are untouched.
+    /// It is meant for the schema upgrade and the checks of the keyspace.
+    fn scan_raw(&self, start: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+    /// [write_raw] applies the puts and deletes atomically.
+    fn write_raw(&self, puts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<()>;
+    /// [write_load_batch] writes the records rebuilt from a dump atomically.
+    fn write_load_batch(&self, batch: &LoadBatch) -> Result<()>;
+}
+
+/// [LoadBatch] collects the records rebuilt from a dump, see [crate::load],
+/// they are written to the backend a batch at a time.
+#[derive(Debug, Default)]
+pub struct LoadBatch {
+    pub attrs:         Vec<(Ino, InodeAttr)>,
+    pub symlinks:      Vec<(Ino, Vec<u8>)>,
+    pub xattrs:        Vec<(Ino, Vec<u8>, Vec<u8>)>,
+    /// the encoded slices of the chunks.
+    pub chunks:        Vec<(Ino, ChunkIndex, Vec<u8>)>,
+    pub dentries:      Vec<DEntry>,
+    /// (inode, parent, count) of the hard links.
+    pub hard_links:    Vec<(Ino, Ino, u64)>,
+    pub slice_refs:    Vec<(SliceID, u64)>,
+    /// (session, inode) of the removed files which are still open.
+    pub sustained:     Vec<(u64, Ino)>,
+    /// (inode, removed at) of the removed files whose chunks are left.
+    pub delete_chunks: Vec<(Ino, u64)>,
+    /// (slice, size) of the slices whose objects are left.
+    pub delete_slices: Vec<(SliceID, usize)>,
+}
+
+impl LoadBatch {
+    /// [len] counts the records in the batch.
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+            + self.symlinks.len()
+            + self.xattrs.len()
+            + self.chunks.len()
+            + self.dentries.len()
+            + self.hard_links.len()
+            + self.slice_refs.len()
+            + self.sustained.len()
+            + self.delete_chunks.len()
+            + self.delete_slices.len()
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+/// [Snapshot] is a point-in-time view of the backend, it is used for
+/// exporting the metadata of a live volume.
+pub trait Snapshot: Send + Sync {
+    fn load_format(&self) -> Result<Format>;
+    fn load_count(&self, counter: Counter) -> Result<u64>;
+    fn get_attr(&self, inode: Ino) -> Result<InodeAttr>;
+    fn list_dentry(&self, parent: Ino) -> Result<Vec<DEntry>>;
+    fn get_symlink(&self, inode: Ino) -> Result<Vec<u8>>;
+    /// [list_xattr] returns all the extended attributes of the inode, by
+    /// their raw names.
+    fn list_xattr(&self, inode: Ino) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+    /// [list_chunk_slices] returns the slices of every non-empty chunk of the
+    /// inode, ordered by the chunk index.
+    fn list_chunk_slices(&self, inode: Ino) -> Result<Vec<(ChunkIndex, Slices)>>;
+    /// [list_inodes] returns at most limit inodes which have an attr, in
+    /// order, starting from the given one (inclusive).
+    fn list_inodes(&self, start: Ino, limit: usize) -> Result<Vec<Ino>>;
+    /// [list_sustained] returns the (session, inode) of the removed files
+    /// which are still open.
+    fn list_sustained(&self) -> Result<Vec<(u64, Ino)>>;
+    /// [list_delete_chunks] returns the (inode, removed at) of the removed
+    /// files whose chunks are left.
+    fn list_delete_chunks(&self) -> Result<Vec<(Ino, u64)>>;
+    /// [list_delete_slices] returns the (slice, size) of the slices whose
+    /// objects are left.
+    fn list_delete_slices(&self) -> Result<Vec<(SliceID, usize)>>;
 }
 
 pub struct UnlinkResult {
@@ -168,7 +372,29 @@ pub struct UnlinkResult {
     pub is_opened:   bool,
 }
 
+pub struct WriteResult {
+    // the attr of the file after writing
+    pub attr:   InodeAttr,
+    // how much the file grows
+    pub grown:  u64,
+    // how many slices the chunk has
+    pub slices: usize,
+}
+
+pub struct CopyResult {
+    // the copied length
+    pub copied: u64,
+    // the attr of the dst file after copying
+    pub attr:   InodeAttr,
+    // how much the dst file grows
+    pub grown:  u64,
+}
+
 pub struct RenameResult {
+    // the inode which has been moved
+    pub inode:       Ino,
+    // the inode which used to be at the destination
+    pub replaced:    Option<Ino>,
     // may need to delete the replaced file
     pub need_delete: Option<(Ino, bool)>,
     pub freed_space: u64,
@@ -1,5 +1,6 @@
 use std::{
     cmp::{max, min},
+    collections::{BTreeMap, HashMap},
     fmt::{Debug, Formatter},
     ops::Sub,
     path::{Path, PathBuf},
@@ -9,13 +10,13 @@ use std::{
 
 use bitflags::Flags;
 use bytes::Bytes;
-use kiseki_common::ChunkIndex;
+use kiseki_common::{ChunkIndex, CHUNK_SIZE};
 use kiseki_types::{
     attr::InodeAttr,
     entry::DEntry,
     ino::{Ino, ZERO_INO},
     setting::Format,
-    slice::Slices,
+    slice::{Slice, SliceID, Slices, SLICE_BYTES},
     stat::DirStat,
     FileType,
 };
@@ -25,8 +26,12 @@ use serde::{Deserialize, Serialize};
 use snafu::{ensure, OptionExt, ResultExt};
 use tracing::{debug, error, info};
 
-use super::{key, key::Counter, Backend, RenameResult, UnlinkResult};
+use super::{
+    codec, codec::Versioned, key, key::Counter, Backend, CopyResult, LoadBatch, RenameResult,
+    Snapshot, UnlinkResult, WriteResult,
+};
 use crate::{
+    changes::ChangeRecord,
     context::FuseContext,
     engine::RenameFlags,
     err::{
@@ -113,7 +118,7 @@ fn do_get_hard_link_count<Layer: DBAccess>(db: &Layer, inode: Ino, parent: Ino)
     }
 }
 
-fn do_get_dentry<Layer: DBAccess>(db: &Layer, parent: Ino, name: &str) -> Result<DEntry> {
+fn do_get_dentry<Layer: DBAccess>(db: &Layer, parent: Ino, name: &[u8]) -> Result<DEntry> {
     let entry_key = key::dentry(parent, name);
     let entry_buf = db
         .get_pinned_opt(&entry_key, &rocksdb::ReadOptions::default())
@@ -124,7 +129,7 @@ fn do_get_dentry<Layer: DBAccess>(db: &Layer, parent: Ino, name: &str) -> Result
         })
         .context(ModelSnafu)?;
 
-    let entry_info: DEntry = bincode::deserialize(&entry_buf)
+    let entry_info: DEntry = codec::decode(&entry_buf)
         .context(model_err::CorruptionSnafu {
             kind: ModelKind::DEntry,
             key:  String::from_utf8_lossy(&entry_key).to_string(),
@@ -144,7 +149,7 @@ fn do_get_attr<Layer: DBAccess>(db: &Layer, inode: Ino) -> Result<InodeAttr> {
         })
         .context(ModelSnafu)?;
 
-    let attr: InodeAttr = bincode::deserialize(&buf)
+    let attr: InodeAttr = codec::decode(&buf)
         .context(model_err::CorruptionSnafu {
             kind: ModelKind::Attr,
             key:  String::from_utf8_lossy(&attr_key).to_string(),
@@ -162,9 +167,29 @@ fn do_check_exist_children<DB>(txn: &rocksdb::Transaction<DB>, parent: Ino) -> R
     Ok(iter.valid())
 }
 
+// txn_get_attr_for_update reads the attr of the inode, the transaction fails
+// to commit if someone else changes the attr in the meantime.
+fn txn_get_attr_for_update<DB>(txn: &rocksdb::Transaction<DB>, inode: Ino) -> Result<InodeAttr> {
+    let attr_key = key::attr(inode);
+    let buf = txn
+        .get_for_update(&attr_key, true)
+        .context(RocksdbSnafu)?
+        .context(model_err::NotFoundSnafu {
+            kind: ModelKind::Attr,
+            key:  String::from_utf8_lossy(&attr_key).to_string(),
+        })
+        .context(ModelSnafu)?;
+    codec::decode(&buf)
+        .context(model_err::CorruptionSnafu {
+            kind: ModelKind::Attr,
+            key:  String::from_utf8_lossy(&attr_key).to_string(),
+        })
+        .context(ModelSnafu)
+}
+
 fn txn_put_attr<DB>(txn: &rocksdb::Transaction<DB>, inode: Ino, attr: &InodeAttr) -> Result<()> {
     let attr_key = key::attr(inode);
-    let buf = bincode::serialize(attr)
+    let buf = codec::encode(attr)
         .context(model_err::CorruptionSnafu {
             kind: ModelKind::Attr,
             key:  String::from_utf8_lossy(&attr_key).to_string(),
@@ -174,21 +199,223 @@ fn txn_put_attr<DB>(txn: &rocksdb::Transaction<DB>, inode: Ino, attr: &InodeAttr
     Ok(())
 }
 
+// TXN_RETRIES is how many times a conflicting transaction is run again.
+const TXN_RETRIES: usize = 50;
+
+// retry_txn runs the transaction again when another writer has changed the
+// keys it read for update before it commits.
+fn retry_txn<T>(mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut retries = 0;
+    loop {
+        match f() {
+            Err(e) if e.is_conflict() && retries < TXN_RETRIES => {
+                retries += 1;
+                debug!("transaction conflicts, retry {retries}");
+            }
+            r => return r,
+        }
+    }
+}
+
+fn decode_slices(key: &[u8], buf: &[u8]) -> Result<Slices> {
+    Slices::decode(buf)
+        .map_err(|e| model_err::Error::CorruptionString {
+            kind:   ModelKind::ChunkSlices,
+            key:    String::from_utf8_lossy(key).to_string(),
+            reason: e.to_string(),
+        })
+        .context(ModelSnafu)
+}
+
+// txn_get_slice_ref reads the reference count of the slice, the transaction
+// fails to commit if someone else changes the count in the meantime.
+fn txn_get_slice_ref<DB>(txn: &rocksdb::Transaction<DB>, slice_id: SliceID) -> Result<u64> {
+    let key = key::slice_ref(slice_id);
+    let count = txn
+        .get_for_update(&key, true)
+        .context(RocksdbSnafu)?
+        .map(|v| {
+            bincode::deserialize(&v)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::SliceRef,
+                    key:  String::from_utf8_lossy(&key).to_string(),
+                })
+                .context(ModelSnafu)
+        })
+        .transpose()?;
+    Ok(count.unwrap_or(0))
+}
+
+fn txn_set_slice_ref<DB>(
+    txn: &rocksdb::Transaction<DB>,
+    slice_id: SliceID,
+    count: u64,
+) -> Result<()> {
+    let key = key::slice_ref(slice_id);
+    if count == 0 {
+        txn.delete(&key).context(RocksdbSnafu)?;
+        return Ok(());
+    }
+    let buf = bincode::serialize(&count)
+        .context(model_err::CorruptionSnafu {
+            kind: ModelKind::SliceRef,
+            key:  String::from_utf8_lossy(&key).to_string(),
+        })
+        .context(ModelSnafu)?;
+    txn.put(&key, buf).context(RocksdbSnafu)?;
+    Ok(())
+}
+
+// txn_unref_slices drops one reference of each slice, and returns the slices
+// which are no longer referenced by anyone, their objects are queued for
+// deletion.
+fn txn_unref_slices<'a, DB>(
+    txn: &rocksdb::Transaction<DB>,
+    slices: impl IntoIterator<Item = &'a Slice>,
+) -> Result<Vec<Slice>> {
+    let mut freed = vec![];
+    for slice in slices.into_iter().filter(|s| !s.is_hole()) {
+        match txn_get_slice_ref(txn, slice.get_id())? {
+            0 => {
+                // write the ref key as well, so a transaction borrowing the
+                // slice in the meantime conflicts with us.
+                txn_set_slice_ref(txn, slice.get_id(), 0)?;
+                txn_set_delete_slice(txn, slice)?;
+                freed.push(slice.clone());
+            }
+            count => txn_set_slice_ref(txn, slice.get_id(), count - 1)?,
+        }
+    }
+    Ok(freed)
+}
+
+// txn_set_delete_slice queues the objects of the freed slice for deletion.
+fn txn_set_delete_slice<DB>(txn: &rocksdb::Transaction<DB>, slice: &Slice) -> Result<()> {
+    let key = key::delete_slice(slice.get_id());
+    let buf = bincode::serialize(&(slice.get_underlying_size() as u64))
+        .context(model_err::CorruptionSnafu {
+            kind: ModelKind::DeleteSlice,
+            key:  String::from_utf8_lossy(&key).to_string(),
+        })
+        .context(ModelSnafu)?;
+    txn.put(&key, buf).context(RocksdbSnafu)?;
+    Ok(())
+}
+
+// txn_ref_slices adds one reference to each slice.
+fn txn_ref_slices<'a, DB>(
+    txn: &rocksdb::Transaction<DB>,
+    slices: impl IntoIterator<Item = &'a Slice>,
+) -> Result<()> {
+    let mut refs: HashMap<SliceID, u64> = HashMap::new();
+    for slice in slices.into_iter().filter(|s| !s.is_hole()) {
+        *refs.entry(slice.get_id()).or_default() += 1;
+    }
+    for (slice_id, count) in refs {
+        let old = txn_get_slice_ref(txn, slice_id)?;
+        txn_set_slice_ref(txn, slice_id, old + count)?;
+    }
+    Ok(())
+}
+
+// txn_link_entry links the inode in the parent as name.
+fn txn_link_entry<DB>(
+    txn: &rocksdb::Transaction<DB>,
+    ctx: &FuseContext,
+    parent: Ino,
+    name: &[u8],
+    inode: Ino,
+    typ: FileType,
+) -> Result<()> {
+    let mut parent_attr = do_get_attr(txn, parent)?;
+    ensure!(
+        parent_attr.is_dir(),
+        LibcSnafu {
+            errno: libc::ENOTDIR,
+        }
+    );
+    ctx.check_access(&parent_attr, kiseki_common::MODE_MASK_W)?;
+    ensure!(
+        !parent_attr.is_immutable(),
+        LibcSnafu { errno: libc::EPERM }
+    );
+    let entry_key = key::dentry(parent, name);
+    ensure!(
+        txn.get_for_update(&entry_key, true)
+            .context(RocksdbSnafu)?
+            .is_none(),
+        LibcSnafu {
+            errno: libc::EEXIST,
+        }
+    );
+    let entry = DEntry {
+        parent,
+        name: name.to_vec(),
+        inode,
+        typ,
+    };
+    let buf = codec::encode(&entry)
+        .context(model_err::CorruptionSnafu {
+            kind: ModelKind::DEntry,
+            key:  String::from_utf8_lossy(&entry_key).to_string(),
+        })
+        .context(ModelSnafu)?;
+    txn.put(&entry_key, buf).context(RocksdbSnafu)?;
+
+    if typ == FileType::Directory {
+        parent_attr.set_nlink(parent_attr.nlink + 1);
+    }
+    parent_attr.update_modification_time();
+    txn_put_attr(txn, parent, &parent_attr)
+}
+
+// txn_copy_prefix copies the keys under the src prefix to the dst prefix and
+// returns the copied keys and values. The transaction fails to commit if
+// someone else changes the src keys in the meantime.
+fn txn_copy_prefix<DB>(
+    txn: &rocksdb::Transaction<DB>,
+    src_prefix: &[u8],
+    dst_prefix: &[u8],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut ro = rocksdb::ReadOptions::default();
+    ro.set_iterate_range(rocksdb::PrefixRange(src_prefix));
+    let mut keys = vec![];
+    let mut iter = txn.raw_iterator_opt(ro);
+    iter.seek_to_first();
+    while let Some(k) = iter.key() {
+        keys.push(k.to_vec());
+        iter.next();
+    }
+    drop(iter);
+
+    let mut copied = vec![];
+    for k in keys {
+        let Some(v) = txn.get_for_update(&k, true).context(RocksdbSnafu)? else {
+            continue;
+        };
+        let mut dst_key = dst_prefix.to_vec();
+        dst_key.extend_from_slice(&k[src_prefix.len()..]);
+        txn.put(&dst_key, &v).context(RocksdbSnafu)?;
+        copied.push((k, v));
+    }
+    Ok(copied)
+}
+
 fn set_dentry_in_write_batch<const TRANSACTION: bool>(
     batch: &mut rocksdb::WriteBatchWithTransaction<TRANSACTION>,
     parent: Ino,
-    name: &str,
+    name: &[u8],
     inode: Ino,
     typ: FileType,
 ) -> Result<()> {
     let entry_key = key::dentry(parent, name);
-    set_value_in_write_batch(
+    set_versioned_in_write_batch(
         batch,
         ModelKind::DEntry,
         entry_key.as_slice(),
         &DEntry {
             parent,
-            name: name.to_string(),
+            name: name.to_vec(),
             inode,
             typ,
         },
@@ -201,7 +428,7 @@ fn set_attr_in_write_batch<const TRANSACTION: bool>(
     attr: &InodeAttr,
 ) -> Result<()> {
     let attr_key = key::attr(inode);
-    set_value_in_write_batch(batch, ModelKind::Attr, attr_key.as_slice(), attr)
+    set_versioned_in_write_batch(batch, ModelKind::Attr, attr_key.as_slice(), attr)
 }
 
 fn set_hard_link_count_in_write_batch<const TRANSACTION: bool>(
@@ -261,6 +488,25 @@ where
     Ok(())
 }
 
+fn set_versioned_in_write_batch<const TRANSACTION: bool, V>(
+    batch: &mut rocksdb::WriteBatchWithTransaction<TRANSACTION>,
+    kind: ModelKind,
+    key: &[u8],
+    value: &V,
+) -> Result<()>
+where
+    V: Versioned,
+{
+    let buf = codec::encode(value)
+        .context(model_err::CorruptionSnafu {
+            kind,
+            key: String::from_utf8_lossy(key).to_string(),
+        })
+        .context(ModelSnafu)?;
+    batch.put(key, buf);
+    Ok(())
+}
+
 fn delete_prefix_in_txn_write_batch(
     txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB<MultiThreaded>>,
     batch: &mut rocksdb::WriteBatchWithTransaction<true>,
@@ -286,7 +532,7 @@ fn delete_prefix_in_txn_write_batch(
 impl Backend for RocksdbBackend {
     // TODO: merge the exists format
     fn set_format(&self, format: &Format) -> Result<()> {
-        let setting_buf = bincode::serialize(format)
+        let setting_buf = codec::encode_format(format)
             .context(model_err::CorruptionSnafu {
                 kind: ModelKind::Setting,
                 key:  key::CURRENT_FORMAT.to_string(),
@@ -294,18 +540,22 @@ impl Backend for RocksdbBackend {
             .context(ModelSnafu)?;
 
         self.db
-            .put(key::CURRENT_FORMAT, setting_buf)
+            .put(key::format(), setting_buf)
             .context(RocksdbSnafu)?;
         Ok(())
     }
 
     fn load_format(&self) -> Result<Format> {
-        let setting_buf = self
-            .db
-            .get_pinned(key::CURRENT_FORMAT)
-            .context(RocksdbSnafu)?
-            .context(UninitializedEngineSnafu)?;
-        let setting: Format = bincode::deserialize(&setting_buf)
+        let setting_buf = match self.db.get_pinned(key::format()).context(RocksdbSnafu)? {
+            Some(buf) => buf,
+            // the volumes before schema version 2 keep it under a string key.
+            None => self
+                .db
+                .get_pinned(key::CURRENT_FORMAT)
+                .context(RocksdbSnafu)?
+                .context(UninitializedEngineSnafu)?,
+        };
+        let setting: Format = codec::decode_format(&setting_buf)
             .context(model_err::CorruptionSnafu {
                 kind: ModelKind::Setting,
                 key:  key::CURRENT_FORMAT.to_string(),
@@ -363,11 +613,88 @@ impl Backend for RocksdbBackend {
         Ok(count)
     }
 
+    fn set_count(&self, counter: Counter, value: u64) -> Result<()> {
+        let key: Vec<u8> = counter.into();
+        let buf = bincode::serialize(&value)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Counter,
+                key:  String::from_utf8_lossy(&key).to_string(),
+            })
+            .context(ModelSnafu)?;
+        self.db.put(&key, buf).context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn compare_and_set_count(&self, counter: Counter, old: u64, new: u64) -> Result<bool> {
+        let key: Vec<u8> = counter.into();
+        let transaction = self.db.transaction();
+        let current = transaction
+            .get_for_update(&key, true)
+            .context(RocksdbSnafu)?
+            .map(|v| {
+                bincode::deserialize(&v)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::Counter,
+                        key:  String::from_utf8_lossy(&key).to_string(),
+                    })
+                    .context(ModelSnafu)
+            })
+            .transpose()?
+            .unwrap_or(0u64);
+        if current != old {
+            return Ok(false);
+        }
+
+        let new_buf = bincode::serialize(&new)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Counter,
+                key:  String::from_utf8_lossy(&key).to_string(),
+            })
+            .context(ModelSnafu)?;
+        transaction.put(&key, new_buf).context(RocksdbSnafu)?;
+        match transaction.commit() {
+            Ok(()) => Ok(true),
+            // another writer has committed the same key after we read it.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    rocksdb::ErrorKind::Busy | rocksdb::ErrorKind::TryAgain
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e).context(RocksdbSnafu),
+        }
+    }
+
     fn get_attr(&self, inode: Ino) -> Result<InodeAttr> { do_get_attr(&self.db, inode) }
 
+    fn batch_get_attr(&self, inodes: &[Ino]) -> Result<Vec<Option<InodeAttr>>> {
+        let keys = inodes
+            .iter()
+            .map(|inode| key::attr(*inode))
+            .collect::<Vec<_>>();
+        let values = self.db.multi_get(keys.iter());
+        keys.iter()
+            .zip(values)
+            .map(|(k, v)| {
+                let Some(buf) = v.context(RocksdbSnafu)? else {
+                    return Ok(None);
+                };
+                let attr = codec::decode(&buf)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::Attr,
+                        key:  String::from_utf8_lossy(k).to_string(),
+                    })
+                    .context(ModelSnafu)?;
+                Ok(Some(attr))
+            })
+            .collect()
+    }
+
     fn set_attr(&self, inode: Ino, attr: &InodeAttr) -> Result<()> {
         let attr_key = key::attr(inode);
-        let buf = bincode::serialize(attr)
+        let buf = codec::encode(attr)
             .context(model_err::CorruptionSnafu {
                 kind: ModelKind::Attr,
                 key:  String::from_utf8_lossy(&attr_key).to_string(),
@@ -377,15 +704,51 @@ impl Backend for RocksdbBackend {
         Ok(())
     }
 
-    fn get_dentry(&self, parent: Ino, name: &str) -> Result<DEntry> {
+    fn do_kill_suid(&self, inode: Ino) -> Result<Option<InodeAttr>> {
+        let txn = self.db.transaction();
+        let mut attr = txn_get_attr_for_update(&txn, inode)?;
+        let kill = attr.suid_kill_bits();
+        if kill == 0 {
+            return Ok(None);
+        }
+        attr.mode &= !kill;
+        attr.ctime = SystemTime::now();
+        txn_put_attr(&txn, inode, &attr)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(Some(attr))
+    }
+
+    fn batch_update_atime(&self, updates: &[(Ino, SystemTime)]) -> Result<()> {
+        // read the attrs for update, so we never undo a change committed in
+        // the meantime.
+        retry_txn(|| {
+            let txn = self.db.transaction();
+            for (inode, atime) in updates {
+                let mut attr = match txn_get_attr_for_update(&txn, *inode) {
+                    Ok(attr) => attr,
+                    // the inode may have been removed in the meantime.
+                    Err(e) if e.is_not_found() => continue,
+                    Err(e) => return Err(e),
+                };
+                if attr.atime >= *atime {
+                    continue;
+                }
+                attr.atime = *atime;
+                txn_put_attr(&txn, *inode, &attr)?;
+            }
+            txn.commit().context(RocksdbSnafu)
+        })
+    }
+
+    fn get_dentry(&self, parent: Ino, name: &[u8]) -> Result<DEntry> {
         do_get_dentry(&self.db, parent, name)
     }
 
-    fn set_dentry(&self, parent: Ino, name: &str, inode: Ino, typ: FileType) -> Result<()> {
+    fn set_dentry(&self, parent: Ino, name: &[u8], inode: Ino, typ: FileType) -> Result<()> {
         let entry_key = key::dentry(parent, name);
-        let entry_buf = bincode::serialize(&DEntry {
+        let entry_buf = codec::encode(&DEntry {
             parent,
-            name: name.to_string(),
+            name: name.to_vec(),
             inode,
             typ,
         })
@@ -398,12 +761,22 @@ impl Backend for RocksdbBackend {
         Ok(())
     }
 
-    fn list_dentry(&self, parent: Ino, limit: i64) -> Result<Vec<DEntry>> {
+    fn list_dentry(&self, parent: Ino, after: Option<&[u8]>, limit: i64) -> Result<Vec<DEntry>> {
         let prefix = key::dentry_prefix(parent);
         let mut ro = rocksdb::ReadOptions::default();
         ro.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
         let mut iter = self.db.raw_iterator_opt(ro);
-        iter.seek_to_first();
+        match after {
+            Some(name) => {
+                let cursor = key::dentry(parent, name);
+                iter.seek(&cursor);
+                // the cursor itself has been listed already.
+                if iter.valid() && iter.key() == Some(cursor.as_slice()) {
+                    iter.next();
+                }
+            }
+            None => iter.seek_to_first(),
+        }
         let mut res = Vec::default();
         // Scan the keys in the iterator
         while iter.valid() {
@@ -413,7 +786,7 @@ impl Backend for RocksdbBackend {
                 let (k, v) = (iter.key(), iter.value());
                 // Check the key and value
                 if let (Some(k), Some(v)) = (k, v) {
-                    let dentry: DEntry = bincode::deserialize(v)
+                    let dentry: DEntry = codec::decode(v)
                         .context(model_err::CorruptionSnafu {
                             kind: ModelKind::DEntry,
                             key:  String::from_utf8_lossy(k).to_string(),
@@ -430,15 +803,13 @@ impl Backend for RocksdbBackend {
         Ok(res)
     }
 
-    fn set_symlink(&self, inode: Ino, path: String) -> Result<()> {
+    fn set_symlink(&self, inode: Ino, path: Vec<u8>) -> Result<()> {
         let symlink_key = key::symlink(inode);
-        self.db
-            .put(&symlink_key, path.into_bytes())
-            .context(RocksdbSnafu)?;
+        self.db.put(&symlink_key, path).context(RocksdbSnafu)?;
         Ok(())
     }
 
-    fn get_symlink(&self, inode: Ino) -> Result<String> {
+    fn get_symlink(&self, inode: Ino) -> Result<Vec<u8>> {
         let symlink_key = key::symlink(inode);
         let path_buf = self
             .db
@@ -449,7 +820,26 @@ impl Backend for RocksdbBackend {
                 key:  String::from_utf8_lossy(&symlink_key).to_string(),
             })
             .context(ModelSnafu)?;
-        Ok(String::from_utf8_lossy(path_buf.as_ref()).to_string())
+        Ok(path_buf.to_vec())
+    }
+
+    fn set_xattr(&self, inode: Ino, name: &[u8], value: &[u8]) -> Result<()> {
+        self.db
+            .put(key::xattr(inode, name), value)
+            .context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn set_hard_link_count(&self, inode: Ino, parent: Ino, count: u64) -> Result<()> {
+        let key = key::parent(inode, parent);
+        let buf = bincode::serialize(&count)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::HardLinkCount,
+                key:  String::from_utf8_lossy(&key).to_string(),
+            })
+            .context(ModelSnafu)?;
+        self.db.put(&key, buf).context(RocksdbSnafu)?;
+        Ok(())
     }
 
     fn set_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex, slices: Slices) -> Result<()> {
@@ -504,9 +894,21 @@ impl Backend for RocksdbBackend {
         Ok(slices)
     }
 
+    fn get_slice_ref(&self, slice_id: SliceID) -> Result<u64> {
+        let txn = self.db.transaction();
+        txn_get_slice_ref(&txn, slice_id)
+    }
+
+    fn set_slice_ref(&self, slice_id: SliceID, count: u64) -> Result<()> {
+        let txn = self.db.transaction();
+        txn_set_slice_ref(&txn, slice_id, count)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(())
+    }
+
     fn set_dir_stat(&self, inode: Ino, dir_stat: DirStat) -> Result<()> {
         let key = key::dir_stat(inode);
-        let buf = bincode::serialize(&dir_stat)
+        let buf = codec::encode(&dir_stat)
             .context(model_err::CorruptionSnafu {
                 kind: ModelKind::DirStat,
                 key:  String::from_utf8_lossy(&key).to_string(),
@@ -527,7 +929,7 @@ impl Backend for RocksdbBackend {
                 key:  String::from_utf8_lossy(&key).to_string(),
             })
             .context(ModelSnafu)?;
-        let dir_stat = bincode::deserialize::<DirStat>(&buf)
+        let dir_stat = codec::decode::<DirStat>(&buf)
             .context(model_err::CorruptionSnafu {
                 kind: ModelKind::DirStat,
                 key:  String::from_utf8_lossy(&key).to_string(),
@@ -536,15 +938,134 @@ impl Backend for RocksdbBackend {
         Ok(dir_stat)
     }
 
+    fn append_changes(&self, records: &[ChangeRecord]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let counter_key: Vec<u8> = Counter::NextChange.into();
+        let txn = self.db.transaction();
+        let last = txn
+            .get_for_update(&counter_key, true)
+            .context(RocksdbSnafu)?
+            .map(|v| {
+                bincode::deserialize::<u64>(&v)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::Counter,
+                        key:  String::from_utf8_lossy(&counter_key).to_string(),
+                    })
+                    .context(ModelSnafu)
+            })
+            .transpose()?
+            .unwrap_or(0);
+        for (i, record) in records.iter().enumerate() {
+            let key = key::change(last + 1 + i as u64);
+            let buf = codec::encode(record)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::Change,
+                    key:  String::from_utf8_lossy(&key).to_string(),
+                })
+                .context(ModelSnafu)?;
+            txn.put(&key, &buf).context(RocksdbSnafu)?;
+        }
+        let next = last + records.len() as u64;
+        txn.put(&counter_key, bincode::serialize(&next).unwrap())
+            .context(RocksdbSnafu)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn load_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, ChangeRecord)>> {
+        let mut iter = self.db.raw_iterator();
+        iter.seek(key::change(after + 1));
+        let mut records = Vec::new();
+        while iter.valid() && records.len() < limit {
+            let (Some(k), Some(v)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            let Some(seq) = key::parse_change_seq(k) else {
+                break;
+            };
+            let record = codec::decode::<ChangeRecord>(v)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::Change,
+                    key:  String::from_utf8_lossy(k).to_string(),
+                })
+                .context(ModelSnafu)?;
+            records.push((seq, record));
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(records)
+    }
+
+    fn trim_changes(&self, until: u64) -> Result<()> {
+        let mut iter = self.db.raw_iterator();
+        iter.seek(key::change_prefix());
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        while iter.valid() {
+            match iter.key().and_then(key::parse_change_seq) {
+                Some(seq) if seq <= until => batch.delete(iter.key().unwrap()),
+                _ => break,
+            }
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        if !batch.is_empty() {
+            self.db.write(batch).context(RocksdbSnafu)?;
+        }
+        Ok(())
+    }
+
+    fn touch_client(&self, client: u64, now: u64) -> Result<()> {
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        set_value_in_write_batch(&mut batch, ModelKind::Client, &key::client(client), now)?;
+        self.db.write(batch).context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn list_clients(&self) -> Result<Vec<(u64, u64)>> {
+        let prefix = key::client_prefix();
+        let mut iter = self.db.raw_iterator();
+        iter.seek(&prefix);
+        let mut clients = Vec::new();
+        while iter.valid() {
+            let (Some(k), Some(v)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            let Some(client) = key::parse_client_id(k) else {
+                break;
+            };
+            let seen: u64 = bincode::deserialize(v)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::Client,
+                    key:  String::from_utf8_lossy(k).to_string(),
+                })
+                .context(ModelSnafu)?;
+            clients.push((client, seen));
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(clients)
+    }
+
+    fn remove_clients(&self, clients: &[u64]) -> Result<()> {
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for client in clients {
+            batch.delete(key::client(*client));
+        }
+        self.db.write(batch).context(RocksdbSnafu)?;
+        Ok(())
+    }
+
     fn do_mknod(
         &self,
         ctx: Arc<FuseContext>,
         mut new_inode: Ino,
         mut new_inode_attr: InodeAttr,
         parent: Ino,
-        name: &str,
+        name: &[u8],
         typ: FileType,
-        path: String,
+        path: Vec<u8>,
     ) -> Result<(Ino, InodeAttr)> {
         let txn = self.db.transaction();
         debug!("get attr {} from backend", parent);
@@ -609,31 +1130,21 @@ impl Backend for RocksdbBackend {
             attr.set_gid(parent_attr.gid);
         }
 
-        // TODO: review the logic here
         #[cfg(target_os = "linux")]
         {
-            // if the parent directory has the set group ID (SGID) bit (02000) set in its
-            // mode (pattr.Mode). If so, it sets the group ID (attr.Gid) of the new node to
-            // the group ID of the parent directory (pattr.Gid).
+            // the new node belongs to the group of a set-group-ID parent, and a
+            // new directory inherits the bit, so does the whole subtree.
             if parent_attr.mode & 0o2000 != 0 {
                 new_inode_attr.set_gid(parent_attr.gid);
-                // If the type of the node being created is a directory (_type ==
-                // TypeDirectory), it sets the SGID bit (02000) in the mode (attr.Mode) of the
-                // new node. This ensures that newly created directories inherit the group ID of
-                // their parent directory.
                 if typ == FileType::Directory {
                     new_inode_attr.mode |= 0o2000;
                 } else if new_inode_attr.mode & 0o2010 == 0o2010
                     && ctx.uid != 0
-                    && !ctx.gid_list.contains(&parent_attr.gid)
+                    && !ctx.contains_gid(parent_attr.gid)
                 {
-                    // If the mode of the new node has both the set group ID bit (02000) and the set
-                    // group execute bit (010) (attr.Mode&02010 == 02010), and if the user ID
-                    // (ctx.Uid()) is not 0 (i.e., the user is not root), it further checks if the
-                    // user belongs to the group of the parent directory (pattr.Gid). If not, it
-                    // removes the SGID bit from the mode of the new node (attr.Mode &=
-                    // ^uint16(02000)).
-                    new_inode_attr.mode &= !0o2010;
+                    // the caller can't make an executable run as a group
+                    // it doesn't belong to, only the set-group-ID bit goes.
+                    new_inode_attr.mode &= !0o2000;
                 }
             }
         }
@@ -650,7 +1161,7 @@ impl Backend for RocksdbBackend {
         }
         if typ == FileType::Symlink {
             let symlink_key = key::symlink(new_inode);
-            batch.put(&symlink_key, path.into_bytes());
+            batch.put(&symlink_key, path);
         }
         self.db.write(batch).context(RocksdbSnafu)?;
         txn.commit().context(RocksdbSnafu)?;
@@ -661,7 +1172,7 @@ impl Backend for RocksdbBackend {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &[u8],
         skip_dir_mtime: Duration,
     ) -> Result<(DEntry, InodeAttr)> {
         let txn = self.db.transaction();
@@ -701,29 +1212,15 @@ impl Backend for RocksdbBackend {
                 && !parent_flag.contains(kiseki_types::attr::Flags::IMMUTABLE),
             LibcSnafu { errno: libc::EPERM }
         );
+        ensure!(child_attr.is_normal(), LibcSnafu { errno: libc::EPERM });
         ensure!(
             !do_check_exist_children(&txn, entry_info.inode)?,
             LibcSnafu {
                 errno: libc::ENOTEMPTY,
             }
         );
-        // The octal number 01000 specifically represents the setuid bit in POSIX
-        // permissions. In a file's mode, the setuid bit is represented by the fourth
-        // bit from the left. When set, it indicates that the file should be executed
-        // with the privileges of its owner, rather than the privileges of the user who
-        // executed it. This is commonly used for executable files that need special
-        // permissions to perform certain tasks, such as changing passwords or accessing
-        // sensitive resources.
-        if ctx.uid != 0
-            && parent_attr.mode & 0o1000 != 0
-            && ctx.uid != parent_attr.uid
-            && ctx.uid != child_attr.uid
-        {
-            return LibcSnafu {
-                errno: libc::EACCES,
-            }
-            .fail();
-        }
+        // the sticky bit of the parent keeps others' directories.
+        ctx.check_sticky(&parent_attr, &child_attr)?;
         parent_attr.nlink -= 1;
         let now = SystemTime::now();
 
@@ -749,7 +1246,7 @@ impl Backend for RocksdbBackend {
             // update parent attr
             batch.put(
                 &parent_attr_key,
-                bincode::serialize(&parent_attr)
+                codec::encode(&parent_attr)
                     .context(model_err::CorruptionSnafu {
                         kind: ModelKind::Attr,
                         key:  String::from_utf8_lossy(&parent_attr_key).to_string(),
@@ -801,7 +1298,7 @@ impl Backend for RocksdbBackend {
         ctx: Arc<FuseContext>,
         inode: Ino,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &[u8],
     ) -> Result<InodeAttr> {
         let txn = self.db.transaction();
 
@@ -863,7 +1360,7 @@ impl Backend for RocksdbBackend {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: String,
+        name: Vec<u8>,
         session_id: u64,
         open_files_ref: OpenFilesRef,
     ) -> Result<UnlinkResult> {
@@ -895,16 +1392,7 @@ impl Backend for RocksdbBackend {
         let mut attr_place_holder = InodeAttr::empty();
         // the target exist
         if let Ok(mut attr) = do_get_attr(&txn, entry.inode) {
-            if ctx.uid != 0
-                && parent_attr.mode & 0o1000 != 0
-                && ctx.uid != parent_attr.uid
-                && ctx.uid != attr.uid
-            {
-                return LibcSnafu {
-                    errno: libc::EACCES,
-                }
-                .fail();
-            }
+            ctx.check_sticky(&parent_attr, &attr)?;
             ensure!(attr.is_normal(), LibcSnafu { errno: libc::EPERM });
             attr.ctime = now;
             attr.nlink -= 1;
@@ -982,40 +1470,417 @@ impl Backend for RocksdbBackend {
         Ok(r)
     }
 
-    fn do_delete_chunks(&self, inode: Ino) {
+    fn do_delete_chunks(&self, inode: Ino) -> Result<Vec<Slice>> {
         let txn = self.db.transaction();
-        let mut batch = txn.get_writebatch();
         let mut ro = rocksdb::ReadOptions::default();
 
         let prefix = key::chunk_slices_prefix(inode);
-        let prefix = prefix.as_slice();
-        ro.set_iterate_range(rocksdb::PrefixRange(prefix));
+        ro.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        let mut chunks = vec![];
         let mut iter = txn.raw_iterator_opt(ro);
         iter.seek_to_first();
-        // Scan the keys in the iterator
-        while iter.valid() {
-            if let Some(k) = iter.key() {
-                if k.starts_with(prefix) {
-                    // at present, we delete the slice directly, since we haven't implemented the
-                    // borrow mechanism.
-                    batch.delete(k);
-                    iter.next();
-                    continue;
-                }
-            }
-            break;
+        while let (Some(k), Some(v)) = (iter.key(), iter.value()) {
+            chunks.push((k.to_vec(), decode_slices(k, v)?));
+            iter.next();
         }
         drop(iter);
+
+        // the borrowed slices stay alive until their last reference is gone.
+        let mut freed = vec![];
+        for (k, slices) in chunks {
+            freed.extend(txn_unref_slices(&txn, slices.0.iter())?);
+            txn.delete(&k).context(RocksdbSnafu)?;
+        }
         // clear the delete notification
-        batch.delete(key::delete_chunk_after(inode));
+        txn.delete(key::delete_chunk_after(inode))
+            .context(RocksdbSnafu)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(freed)
+    }
+
+    fn list_delete_chunks(&self, start: Ino, limit: usize) -> Result<Vec<(Ino, u64)>> {
+        let mut ro = rocksdb::ReadOptions::default();
+        let prefix = key::delete_chunk_prefix();
+        ro.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        let mut iter = self.db.raw_iterator_opt(ro);
+        iter.seek(key::delete_chunk_after(start));
+        let mut files = Vec::new();
+        while files.len() < limit {
+            let (Some(k), Some(v)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            let key = String::from_utf8_lossy(k).to_string();
+            let inode = key::parse_delete_chunk_inode(k)
+                .context(model_err::CorruptionStringSnafu {
+                    kind:   ModelKind::DeleteInode,
+                    key:    key.clone(),
+                    reason: "invalid inode",
+                })
+                .context(ModelSnafu)?;
+            let removed_at: u64 = bincode::deserialize(v)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::DeleteInode,
+                    key,
+                })
+                .context(ModelSnafu)?;
+            files.push((inode, removed_at));
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(files)
+    }
+
+    fn list_delete_slices(&self, limit: usize) -> Result<Vec<(SliceID, usize)>> {
+        let mut ro = rocksdb::ReadOptions::default();
+        let prefix = key::delete_slice_prefix();
+        ro.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        let mut iter = self.db.raw_iterator_opt(ro);
+        iter.seek_to_first();
+        let mut slices = Vec::new();
+        while slices.len() < limit {
+            let (Some(k), Some(v)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            let key = String::from_utf8_lossy(k).to_string();
+            let slice_id = key::parse_delete_slice_id(k)
+                .context(model_err::CorruptionStringSnafu {
+                    kind:   ModelKind::DeleteSlice,
+                    key:    key.clone(),
+                    reason: "invalid slice id",
+                })
+                .context(ModelSnafu)?;
+            let size: u64 = bincode::deserialize(v)
+                .context(model_err::CorruptionSnafu {
+                    kind: ModelKind::DeleteSlice,
+                    key,
+                })
+                .context(ModelSnafu)?;
+            slices.push((slice_id, size as usize));
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(slices)
+    }
+
+    fn remove_delete_slices(&self, slice_ids: &[SliceID]) -> Result<()> {
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for slice_id in slice_ids {
+            batch.delete(key::delete_slice(*slice_id));
+        }
+        self.db.write(batch).context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn do_write_slice(
+        &self,
+        inode: Ino,
+        chunk_index: ChunkIndex,
+        chunk_pos: usize,
+        slice: &Slice,
+    ) -> Result<Option<WriteResult>> {
+        // a compaction of the chunk may commit in the meantime, read the chunk
+        // for update so we never write back the slices it has dropped.
+        retry_txn(|| {
+            let txn = self.db.transaction();
+            let mut attr = txn_get_attr_for_update(&txn, inode)?;
+            ensure!(attr.is_file(), LibcSnafu { errno: libc::EPERM });
+            let key = key::chunk_slices(inode, chunk_index);
+            let mut buf = txn
+                .get_for_update(&key, true)
+                .context(RocksdbSnafu)?
+                .unwrap_or_default();
+            let val = slice.encode();
+            if buf == val {
+                return Ok(None);
+            }
+            buf.extend_from_slice(&val);
+            let slices = buf.len() / SLICE_BYTES;
+            txn.put(&key, buf).context(RocksdbSnafu)?;
+
+            let end = (chunk_index * CHUNK_SIZE + chunk_pos + slice.get_size()) as u64;
+            let grown = end.saturating_sub(attr.length);
+            if grown > 0 {
+                attr.length = end;
+            }
+            attr.update_modification_time();
+            txn_put_attr(&txn, inode, &attr)?;
+            txn.commit().context(RocksdbSnafu)?;
+            Ok(Some(WriteResult {
+                attr,
+                grown,
+                slices,
+            }))
+        })
+    }
+
+    fn do_copy_file_range(
+        &self,
+        src: Ino,
+        src_off: u64,
+        dst: Ino,
+        dst_off: u64,
+        size: u64,
+    ) -> Result<CopyResult> {
+        retry_txn(|| {
+            let txn = self.db.transaction();
+            let src_attr = do_get_attr(&txn, src)?;
+            ensure!(
+                !src_attr.is_dir(),
+                LibcSnafu {
+                    errno: libc::EISDIR,
+                }
+            );
+            ensure!(
+                src_attr.is_file(),
+                LibcSnafu {
+                    errno: libc::EINVAL,
+                }
+            );
+            let mut dst_attr = txn_get_attr_for_update(&txn, dst)?;
+            ensure!(
+                !dst_attr.is_dir(),
+                LibcSnafu {
+                    errno: libc::EISDIR,
+                }
+            );
+            ensure!(
+                dst_attr.is_file(),
+                LibcSnafu {
+                    errno: libc::EINVAL,
+                }
+            );
+            ensure!(dst_attr.is_normal(), LibcSnafu { errno: libc::EPERM });
+            if src_off >= src_attr.length || size == 0 {
+                return Ok(CopyResult {
+                    copied: 0,
+                    attr:   dst_attr,
+                    grown:  0,
+                });
+            }
+            let size = min(size, src_attr.length - src_off);
+            ensure!(
+                src != dst || src_off + size <= dst_off || dst_off + size <= src_off,
+                LibcSnafu {
+                    errno: libc::EINVAL,
+                }
+            );
+
+            // the slices borrowed by each chunk of the dst.
+            let mut borrowed: BTreeMap<ChunkIndex, Vec<Slice>> = BTreeMap::new();
+            let mut copied = 0;
+            while copied < size {
+                let pos = src_off + copied;
+                let chunk_idx = pos as usize / CHUNK_SIZE;
+                let start = pos as usize % CHUNK_SIZE;
+                let end = min(CHUNK_SIZE, start + (size - copied) as usize);
+                let key = key::chunk_slices(src, chunk_idx);
+                // read for update, so an overwrite or a compaction of the src
+                // which frees the slices conflicts with the copy.
+                let slices = match txn.get_for_update(&key, true).context(RocksdbSnafu)? {
+                    Some(buf) => decode_slices(&key, &buf)?,
+                    None => Slices(vec![]),
+                };
+                let visible = slices.overlook();
+                let range = start..end;
+                let mut parts = visible.gaps(&range).map(|r| (r, None)).collect::<Vec<_>>();
+                for (r, s) in visible.overlapping(&range) {
+                    let r = max(r.start, start)..min(r.end, end);
+                    parts.push((r, Some(s).filter(|s| !s.is_hole())));
+                }
+
+                for (r, s) in parts {
+                    // where the part starts in the src file and in the underlying data.
+                    let mut src_pos = (chunk_idx * CHUNK_SIZE + r.start) as u64;
+                    let mut off = s.map_or(0, |s| s.get_off() + r.start - s.get_chunk_pos());
+                    let mut left = r.len();
+                    // the part may cross the chunk boundary of the dst.
+                    while left > 0 {
+                        let dst_pos = (dst_off + src_pos - src_off) as usize;
+                        let dst_chunk_pos = dst_pos % CHUNK_SIZE;
+                        let len = min(left, CHUNK_SIZE - dst_chunk_pos);
+                        let slice = match s {
+                            Some(s) => Slice::new_borrowed(
+                                dst_chunk_pos,
+                                s.get_id(),
+                                s.get_underlying_size(),
+                                off,
+                                len,
+                            ),
+                            None => Slice::new_hole(dst_chunk_pos, len),
+                        };
+                        borrowed
+                            .entry(dst_pos / CHUNK_SIZE)
+                            .or_default()
+                            .push(slice);
+                        src_pos += len as u64;
+                        off += len;
+                        left -= len;
+                    }
+                }
+                copied += (end - start) as u64;
+            }
+
+            for (chunk_idx, slices) in &borrowed {
+                let key = key::chunk_slices(dst, *chunk_idx);
+                let mut buf = txn
+                    .get_for_update(&key, true)
+                    .context(RocksdbSnafu)?
+                    .unwrap_or_default();
+                for slice in slices {
+                    // nothing to overwrite.
+                    if slice.is_hole() && buf.is_empty() {
+                        continue;
+                    }
+                    buf.extend_from_slice(&slice.encode());
+                }
+                if !buf.is_empty() {
+                    txn.put(&key, buf).context(RocksdbSnafu)?;
+                }
+            }
+            txn_ref_slices(&txn, borrowed.values().flatten())?;
+
+            let end = dst_off + size;
+            let grown = end.saturating_sub(dst_attr.length);
+            if grown > 0 {
+                dst_attr.length = end;
+            }
+            dst_attr.update_modification_time();
+            txn_put_attr(&txn, dst, &dst_attr)?;
+            txn.commit().context(RocksdbSnafu)?;
+            Ok(CopyResult {
+                copied: size,
+                attr: dst_attr,
+                grown,
+            })
+        })
+    }
+
+    fn do_compact_chunk(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Vec<Slice>> {
+        let txn = self.db.transaction();
+        let key = key::chunk_slices(inode, chunk_index);
+        let Some(buf) = txn.get_for_update(&key, true).context(RocksdbSnafu)? else {
+            return Ok(vec![]);
+        };
+        let slices = decode_slices(&key, &buf)?;
+        let visible = slices.overlook();
+        let (kept, dropped): (Vec<_>, Vec<_>) = slices
+            .0
+            .into_iter()
+            .partition(|s| visible.iter().any(|(_, v)| v == s));
+        if dropped.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // the dropped slices may still be borrowed by others.
+        let freed = txn_unref_slices(&txn, dropped.iter())?;
+        let buf = kept.iter().flat_map(|s| s.encode()).collect::<Vec<_>>();
+        txn.put(&key, buf).context(RocksdbSnafu)?;
+        txn.commit().context(RocksdbSnafu)?;
+        debug!(
+            "compact chunk {} of {}, drop {} slices",
+            chunk_index,
+            inode,
+            dropped.len()
+        );
+        Ok(freed)
+    }
+
+    fn do_clone_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        src: Ino,
+        new_inode: Ino,
+        parent: Ino,
+        name: &[u8],
+        attach: bool,
+        preserve: bool,
+    ) -> Result<InodeAttr> {
+        let txn = self.db.transaction();
+        let src_attr = do_get_attr(&txn, src)?;
+        let mask = if src_attr.is_dir() {
+            kiseki_common::MODE_MASK_R | kiseki_common::MODE_MASK_X
+        } else {
+            kiseki_common::MODE_MASK_R
+        };
+        ctx.check_access(&src_attr, mask)?;
+
+        let now = SystemTime::now();
+        let mut attr = src_attr.clone();
+        // hard links inside a tree become separate files.
+        attr.set_parent(parent)
+            .set_nlink(if src_attr.is_dir() { 2 } else { 1 })
+            .set_ctime(now);
+        if !preserve {
+            attr.set_uid(ctx.uid)
+                .set_gid(ctx.gid)
+                .set_flags(0)
+                .set_atime(now)
+                .set_mtime(now);
+            // the clone belongs to someone else now.
+            attr.mode &= !0o6000;
+        }
+        txn_put_attr(&txn, new_inode, &attr)?;
+
+        txn_copy_prefix(&txn, &key::xattr_prefix(src), &key::xattr_prefix(new_inode))?;
+        if attr.kind == FileType::Symlink {
+            txn_copy_prefix(&txn, &key::symlink(src), &key::symlink(new_inode))?;
+        }
+        if attr.is_file() {
+            let chunks = txn_copy_prefix(
+                &txn,
+                &key::chunk_slices_prefix(src),
+                &key::chunk_slices_prefix(new_inode),
+            )?;
+            for (k, v) in chunks {
+                txn_ref_slices(&txn, decode_slices(&k, &v)?.0.iter())?;
+            }
+        }
+        if attach {
+            txn_link_entry(&txn, &ctx, parent, name, new_inode, attr.kind)?;
+        }
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(attr)
+    }
+
+    fn do_attach_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        inode: Ino,
+        parent: Ino,
+        name: &[u8],
+    ) -> Result<InodeAttr> {
+        let txn = self.db.transaction();
+        let attr = do_get_attr(&txn, inode)?;
+        txn_link_entry(&txn, &ctx, parent, name, inode, attr.kind)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(attr)
+    }
 
-        if let Err(e) = self.db.write(batch) {
-            error!("write batch failed when do_delete_chunks: {:?}", e);
-            return;
+    fn do_purge_inode(&self, inode: Ino) -> Result<Vec<Slice>> {
+        let txn = self.db.transaction();
+        let mut ro = rocksdb::ReadOptions::default();
+        let prefix = key::inode_keys_prefix(inode);
+        ro.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        let mut kvs = vec![];
+        let mut iter = txn.raw_iterator_opt(ro);
+        iter.seek_to_first();
+        while let (Some(k), Some(v)) = (iter.key(), iter.value()) {
+            kvs.push((k.to_vec(), v.to_vec()));
+            iter.next();
         }
-        if let Err(e) = txn.commit() {
-            error!("commit failed in do_delete_chunks: {:?}", e);
+        drop(iter);
+
+        let chunk_prefix = key::chunk_slices_prefix(inode);
+        let mut freed = vec![];
+        for (k, v) in kvs {
+            if k.starts_with(&chunk_prefix) {
+                freed.extend(txn_unref_slices(&txn, decode_slices(&k, &v)?.0.iter())?);
+            }
+            txn.delete(&k).context(RocksdbSnafu)?;
         }
+        txn.delete(key::dir_stat(inode)).context(RocksdbSnafu)?;
+        txn.commit().context(RocksdbSnafu)?;
+        Ok(freed)
     }
 
     async fn do_rename(
@@ -1023,15 +1888,17 @@ impl Backend for RocksdbBackend {
         ctx: Arc<FuseContext>,
         session_id: u64,
         old_parent: Ino,
-        old_name: &str,
+        old_name: &[u8],
         new_parent: Ino,
-        new_name: &str,
+        new_name: &[u8],
         flags: RenameFlags,
         open_files_ref: OpenFilesRef,
     ) -> Result<RenameResult> {
         let txn = self.db.transaction();
         let old_entry = do_get_dentry(&txn, old_parent, old_name)?;
         let mut rename_result = RenameResult {
+            inode:       old_entry.inode,
+            replaced:    None,
             need_delete: None,
             freed_inode: 0,
             freed_space: 0,
@@ -1053,6 +1920,11 @@ impl Backend for RocksdbBackend {
                 &old_parent_attr,
                 kiseki_common::MODE_MASK_W | kiseki_common::MODE_MASK_X,
             )?;
+            // nothing leaves an immutable or append-only directory.
+            ensure!(
+                old_parent_attr.is_normal(),
+                LibcSnafu { errno: libc::EPERM }
+            );
         }
 
         let mut new_parent_attr = do_get_attr(&txn, new_parent)?;
@@ -1067,6 +1939,10 @@ impl Backend for RocksdbBackend {
                 &new_parent_attr,
                 kiseki_common::MODE_MASK_W | kiseki_common::MODE_MASK_X,
             )?;
+            ensure!(
+                !new_parent_attr.is_immutable(),
+                LibcSnafu { errno: libc::EPERM }
+            );
             ensure!(
                 old_entry.inode != new_parent && old_entry.inode != new_parent_attr.parent,
                 LibcSnafu { errno: libc::EPERM }
@@ -1076,27 +1952,10 @@ impl Backend for RocksdbBackend {
         let mut old_inode_attr = do_get_attr(&txn, old_entry.inode)?;
         {
             ensure!(old_inode_attr.is_normal(), LibcSnafu { errno: libc::EPERM });
-            if old_parent != new_parent
-                && old_parent_attr.mode & 0o1000 != 0
-                && ctx.uid != 0
-                && ctx.uid != old_inode_attr.uid
-                && (ctx.uid != old_parent_attr.uid || old_inode_attr.is_dir())
-            {
-                return LibcSnafu {
-                    errno: libc::EACCES,
-                }
-                .fail();
-            }
-
-            if ctx.uid != 0
-                && (old_parent_attr.mode & 0o1000) != 0
-                && ctx.uid != old_parent_attr.uid
-                && ctx.uid != old_inode_attr.uid
-            {
-                return LibcSnafu {
-                    errno: libc::EACCES,
-                }
-                .fail();
+            ctx.check_sticky(&old_parent_attr, &old_inode_attr)?;
+            // moving a directory to another parent rewrites its "..".
+            if old_parent != new_parent && old_inode_attr.is_dir() {
+                ctx.check_access(&old_inode_attr, kiseki_common::MODE_MASK_W)?;
             }
         }
 
@@ -1117,8 +1976,14 @@ impl Backend for RocksdbBackend {
                     }
                 );
 
+                // the replaced entry leaves the new parent.
+                ensure!(
+                    new_parent_attr.is_normal(),
+                    LibcSnafu { errno: libc::EPERM }
+                );
                 let mut dst_attr = do_get_attr(&txn, dst_entry.inode)?;
                 ensure!(dst_attr.is_normal(), LibcSnafu { errno: libc::EPERM });
+                ctx.check_sticky(&new_parent_attr, &dst_attr)?;
                 dst_attr.ctime = SystemTime::now();
 
                 if matches!(flags, RenameFlags::EXCHANGE) {
@@ -1152,17 +2017,7 @@ impl Backend for RocksdbBackend {
                     }
                 }
 
-                if ctx.uid != 0
-                    && (old_parent_attr.mode & 0o1000) == 0
-                    && ctx.uid != new_parent_attr.uid
-                    && ctx.uid != dst_attr.uid
-                {
-                    return LibcSnafu {
-                        errno: libc::EACCES,
-                    }
-                    .fail();
-                }
-
+                rename_result.replaced = Some(dst_entry.inode);
                 dst_dentry_opt = Some(dst_entry);
                 dst_attr_opt = Some(dst_attr);
             }
@@ -1345,12 +2200,299 @@ impl Backend for RocksdbBackend {
         let symlink = do_get_symlink(&self.db, inode)?;
         Ok(Bytes::from(symlink))
     }
+
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + '_>> {
+        Ok(Box::new(RocksdbSnapshot {
+            snapshot: self.db.snapshot(),
+        }))
+    }
+
+    fn scan_raw(&self, start: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut iter = self.db.raw_iterator();
+        iter.seek(start);
+        let mut res = Vec::new();
+        while iter.valid() && res.len() < limit {
+            if let (Some(k), Some(v)) = (iter.key(), iter.value()) {
+                res.push((k.to_vec(), v.to_vec()));
+            }
+            iter.next();
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(res)
+    }
+
+    fn write_raw(&self, puts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<()> {
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for k in deletes {
+            batch.delete(k);
+        }
+        for (k, v) in puts {
+            batch.put(k, v);
+        }
+        self.db.write(batch).context(RocksdbSnafu)?;
+        Ok(())
+    }
+
+    fn write_load_batch(&self, load: &LoadBatch) -> Result<()> {
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for (inode, attr) in load.attrs.iter() {
+            set_attr_in_write_batch(&mut batch, *inode, attr)?;
+        }
+        for (inode, target) in load.symlinks.iter() {
+            batch.put(key::symlink(*inode), target);
+        }
+        for (inode, name, value) in load.xattrs.iter() {
+            batch.put(key::xattr(*inode, name), value);
+        }
+        for (inode, index, buf) in load.chunks.iter() {
+            batch.put(key::chunk_slices(*inode, *index), buf);
+        }
+        for de in load.dentries.iter() {
+            set_dentry_in_write_batch(&mut batch, de.parent, &de.name, de.inode, de.typ)?;
+        }
+        for (inode, parent, count) in load.hard_links.iter() {
+            set_hard_link_count_in_write_batch(&mut batch, *inode, *parent, *count)?;
+        }
+        for (slice_id, count) in load.slice_refs.iter() {
+            let key = key::slice_ref(*slice_id);
+            set_value_in_write_batch(&mut batch, ModelKind::SliceRef, &key, *count)?;
+        }
+        for (session_id, inode) in load.sustained.iter() {
+            set_sustained_in_write_batch(&mut batch, *session_id, *inode, 1)?;
+        }
+        for (inode, removed_at) in load.delete_chunks.iter() {
+            let key = key::delete_chunk_after(*inode);
+            set_value_in_write_batch(&mut batch, ModelKind::DeleteInode, &key, *removed_at)?;
+        }
+        for (slice_id, size) in load.delete_slices.iter() {
+            let key = key::delete_slice(*slice_id);
+            set_value_in_write_batch(&mut batch, ModelKind::DeleteSlice, &key, *size as u64)?;
+        }
+        self.db.write(batch).context(RocksdbSnafu)?;
+        Ok(())
+    }
+}
+
+/// [RocksdbSnapshot] reads all the keys from the same rocksdb snapshot.
+pub(crate) struct RocksdbSnapshot<'a> {
+    snapshot: rocksdb::SnapshotWithThreadMode<'a, rocksdb::OptimisticTransactionDB<MultiThreaded>>,
+}
+
+impl RocksdbSnapshot<'_> {
+    fn get(&self, key: &[u8], kind: ModelKind) -> Result<Vec<u8>> {
+        let buf = self
+            .snapshot
+            .get(key)
+            .context(RocksdbSnafu)?
+            .context(model_err::NotFoundSnafu {
+                kind,
+                key: String::from_utf8_lossy(key).to_string(),
+            })
+            .context(ModelSnafu)?;
+        Ok(buf)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut ro = rocksdb::ReadOptions::default();
+        ro.set_iterate_range(rocksdb::PrefixRange(prefix));
+        let mut iter = self.snapshot.raw_iterator_opt(ro);
+        iter.seek_to_first();
+        let mut res = Vec::new();
+        while iter.valid() {
+            match (iter.key(), iter.value()) {
+                (Some(k), Some(v)) if k.starts_with(prefix) => {
+                    res.push((k.to_vec(), v.to_vec()));
+                    iter.next();
+                }
+                _ => break,
+            }
+        }
+        res
+    }
+}
+
+impl Snapshot for RocksdbSnapshot<'_> {
+    fn load_format(&self) -> Result<Format> {
+        let buf = match self.snapshot.get(key::format()).context(RocksdbSnafu)? {
+            Some(buf) => buf,
+            None => self
+                .snapshot
+                .get(key::CURRENT_FORMAT)
+                .context(RocksdbSnafu)?
+                .context(UninitializedEngineSnafu)?,
+        };
+        let format = codec::decode_format(&buf)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Setting,
+                key:  key::CURRENT_FORMAT.to_string(),
+            })
+            .context(ModelSnafu)?;
+        Ok(format)
+    }
+
+    fn load_count(&self, counter: Counter) -> Result<u64> {
+        let key: Vec<u8> = counter.into();
+        let buf = self.get(&key, ModelKind::Counter)?;
+        let count = bincode::deserialize(&buf)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Counter,
+                key:  counter.name().to_string(),
+            })
+            .context(ModelSnafu)?;
+        Ok(count)
+    }
+
+    fn get_attr(&self, inode: Ino) -> Result<InodeAttr> {
+        let key = key::attr(inode);
+        let buf = self.get(&key, ModelKind::Attr)?;
+        let attr = codec::decode(&buf)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Attr,
+                key:  String::from_utf8_lossy(&key).to_string(),
+            })
+            .context(ModelSnafu)?;
+        Ok(attr)
+    }
+
+    fn list_dentry(&self, parent: Ino) -> Result<Vec<DEntry>> {
+        self.scan_prefix(&key::dentry_prefix(parent))
+            .into_iter()
+            .map(|(k, v)| {
+                codec::decode(&v)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::DEntry,
+                        key:  String::from_utf8_lossy(&k).to_string(),
+                    })
+                    .context(ModelSnafu)
+            })
+            .collect()
+    }
+
+    fn get_symlink(&self, inode: Ino) -> Result<Vec<u8>> {
+        self.get(&key::symlink(inode), ModelKind::Symlink)
+    }
+
+    fn list_xattr(&self, inode: Ino) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let prefix = key::xattr_prefix(inode);
+        Ok(self
+            .scan_prefix(&prefix)
+            .into_iter()
+            .map(|(k, v)| (k[prefix.len()..].to_vec(), v))
+            .collect())
+    }
+
+    fn list_chunk_slices(&self, inode: Ino) -> Result<Vec<(ChunkIndex, Slices)>> {
+        let prefix = key::chunk_slices_prefix(inode);
+        let mut chunks = Vec::new();
+        for (k, v) in self.scan_prefix(&prefix) {
+            let index = key::parse_chunk_index(&prefix, &k)
+                .context(model_err::CorruptionStringSnafu {
+                    kind:   ModelKind::ChunkSlices,
+                    key:    String::from_utf8_lossy(&k).to_string(),
+                    reason: "invalid chunk index",
+                })
+                .context(ModelSnafu)?;
+            let slices = decode_slices(&k, &v)?;
+            if !slices.0.is_empty() {
+                chunks.push((index, slices));
+            }
+        }
+        Ok(chunks)
+    }
+
+    fn list_inodes(&self, start: Ino, limit: usize) -> Result<Vec<Ino>> {
+        let (start, end) = key::inode_keys_range(start);
+        let mut ro = rocksdb::ReadOptions::default();
+        ro.set_iterate_upper_bound(end);
+        let mut iter = self.snapshot.raw_iterator_opt(ro);
+        iter.seek(&start);
+        let mut inodes = Vec::new();
+        while inodes.len() < limit {
+            let Some(inode) = iter.key().and_then(key::parse_inode) else {
+                break;
+            };
+            // the attr sorts after some other keys of the inode, like its
+            // chunks and entries.
+            let attr = key::attr(inode);
+            iter.seek(&attr);
+            if iter.key() == Some(attr.as_slice()) {
+                inodes.push(inode);
+            }
+            iter.seek(key::inode_keys_prefix(Ino(inode.0 + 1)));
+        }
+        iter.status().context(RocksdbSnafu)?;
+        Ok(inodes)
+    }
+
+    fn list_sustained(&self) -> Result<Vec<(u64, Ino)>> {
+        self.scan_prefix(&key::sustained_prefix())
+            .into_iter()
+            .map(|(k, _)| {
+                key::parse_sustained(&k)
+                    .context(model_err::CorruptionStringSnafu {
+                        kind:   ModelKind::Sustained,
+                        key:    String::from_utf8_lossy(&k).to_string(),
+                        reason: "invalid session or inode",
+                    })
+                    .context(ModelSnafu)
+            })
+            .collect()
+    }
+
+    fn list_delete_chunks(&self) -> Result<Vec<(Ino, u64)>> {
+        self.scan_prefix(&key::delete_chunk_prefix())
+            .into_iter()
+            .map(|(k, v)| {
+                let key = String::from_utf8_lossy(&k).to_string();
+                let inode = key::parse_delete_chunk_inode(&k)
+                    .context(model_err::CorruptionStringSnafu {
+                        kind:   ModelKind::DeleteInode,
+                        key:    key.clone(),
+                        reason: "invalid inode",
+                    })
+                    .context(ModelSnafu)?;
+                let removed_at: u64 = bincode::deserialize(&v)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::DeleteInode,
+                        key,
+                    })
+                    .context(ModelSnafu)?;
+                Ok((inode, removed_at))
+            })
+            .collect()
+    }
+
+    fn list_delete_slices(&self) -> Result<Vec<(SliceID, usize)>> {
+        self.scan_prefix(&key::delete_slice_prefix())
+            .into_iter()
+            .map(|(k, v)| {
+                let key = String::from_utf8_lossy(&k).to_string();
+                let slice_id = key::parse_delete_slice_id(&k)
+                    .context(model_err::CorruptionStringSnafu {
+                        kind:   ModelKind::DeleteSlice,
+                        key:    key.clone(),
+                        reason: "invalid slice id",
+                    })
+                    .context(ModelSnafu)?;
+                let size: u64 = bincode::deserialize(&v)
+                    .context(model_err::CorruptionSnafu {
+                        kind: ModelKind::DeleteSlice,
+                        key,
+                    })
+                    .context(ModelSnafu)?;
+                Ok((slice_id, size as usize))
+            })
+            .collect()
+    }
 }
 
 #[cfg(feature = "meta-rocksdb")]
 #[cfg(test)]
 mod tests {
+    use kiseki_types::ToErrno;
+
     use super::*;
+    use crate::test_util::test_backend;
 
     #[test]
     fn basic() {
@@ -1378,21 +2520,362 @@ mod tests {
         backend.set_attr(Ino(2), &InodeAttr::default()).unwrap();
         // insert a dentry
         backend
-            .set_dentry(Ino(1), "test", Ino(2), FileType::RegularFile)
+            .set_dentry(Ino(1), b"test", Ino(2), FileType::RegularFile)
             .unwrap();
         // now it should exist
         let exist = do_check_exist_children(&backend.db.transaction(), Ino(1)).unwrap();
         assert_eq!(exist, true);
 
         backend
-            .list_dentry(Ino(1), -1)
+            .list_dentry(Ino(1), None, -1)
             .unwrap()
             .iter()
             .for_each(|e| println!("{:?}", e));
         backend
-            .list_dentry(Ino(2), -1)
+            .list_dentry(Ino(2), None, -1)
             .unwrap()
             .iter()
             .for_each(|e| println!("{:?}", e));
     }
+
+    #[test]
+    fn list_dentry_with_cursor() {
+        let (_dir, backend) = test_backend();
+        for (i, name) in [b"a", b"c", b"e", b"g"].iter().enumerate() {
+            backend
+                .set_dentry(
+                    Ino(1),
+                    name.as_slice(),
+                    Ino(i as u64 + 2),
+                    FileType::RegularFile,
+                )
+                .unwrap();
+        }
+        let names = |entries: Vec<DEntry>| entries.into_iter().map(|e| e.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(backend.list_dentry(Ino(1), None, 2).unwrap()),
+            [b"a", b"c"]
+        );
+        assert_eq!(
+            names(backend.list_dentry(Ino(1), Some(b"c"), 2).unwrap()),
+            [b"e", b"g"]
+        );
+        // the cursor doesn't have to exist anymore.
+        assert_eq!(
+            names(backend.list_dentry(Ino(1), Some(b"d"), -1).unwrap()),
+            [b"e", b"g"]
+        );
+        assert!(
+            backend
+                .list_dentry(Ino(1), Some(b"g"), -1)
+                .unwrap()
+                .is_empty()
+        );
+        // any bytes but '/' and NUL are legal in a name.
+        let latin1 = b"caf\xe9".as_slice();
+        backend
+            .set_dentry(Ino(1), latin1, Ino(10), FileType::RegularFile)
+            .unwrap();
+        assert_eq!(backend.get_dentry(Ino(1), latin1).unwrap().name, latin1);
+    }
+
+    #[test]
+    fn copy_file_range_borrows_slices() {
+        let (_dir, backend) = test_backend();
+        let file = |length| {
+            InodeAttr::default()
+                .set_kind(FileType::RegularFile)
+                .set_length(length)
+                .to_owned()
+        };
+        let (src, dst) = (Ino(2), Ino(3));
+        backend.set_attr(src, &file(4096)).unwrap();
+        backend.set_attr(dst, &file(0)).unwrap();
+        backend
+            .set_raw_chunk_slices(src, 0, Slice::new_owned(0, 10, 4096).encode())
+            .unwrap();
+
+        let r = backend
+            .do_copy_file_range(src, 1024, dst, CHUNK_SIZE as u64 - 1024, 8192)
+            .unwrap();
+        // limited by the length of the src.
+        assert_eq!(r.copied, 3072);
+        assert_eq!(r.attr.length, CHUNK_SIZE as u64 + 2048);
+        assert_eq!(r.grown, r.attr.length);
+        // the range crosses the chunk boundary of the dst.
+        assert_eq!(
+            backend.get_chunk_slices(dst, 0).unwrap().0,
+            vec![Slice::new_borrowed(CHUNK_SIZE - 1024, 10, 4096, 1024, 1024)]
+        );
+        assert_eq!(
+            backend.get_chunk_slices(dst, 1).unwrap().0,
+            vec![Slice::new_borrowed(0, 10, 4096, 2048, 2048)]
+        );
+        assert_eq!(backend.get_slice_ref(10).unwrap(), 2);
+        // the ranges of the same file must not overlap.
+        assert!(backend.do_copy_file_range(src, 0, src, 1024, 2048).is_err());
+
+        // the slice lives until the last reference is gone.
+        assert!(backend.do_delete_chunks(src).unwrap().is_empty());
+        assert!(backend.list_delete_slices(10).unwrap().is_empty());
+        let freed = backend.do_delete_chunks(dst).unwrap();
+        assert_eq!(freed.len(), 1);
+        assert_eq!(freed[0].get_id(), 10);
+        assert_eq!(backend.get_slice_ref(10).unwrap(), 0);
+        // its objects are queued for deletion.
+        assert_eq!(backend.list_delete_slices(10).unwrap(), vec![(10, 4096)]);
+        backend.remove_delete_slices(&[10]).unwrap();
+        assert!(backend.list_delete_slices(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn compact_chunk_keeps_borrowed_slices() {
+        let (_dir, backend) = test_backend();
+        let file = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .set_length(100)
+            .to_owned();
+        backend.set_attr(Ino(2), &file).unwrap();
+        backend.set_attr(Ino(3), &file).unwrap();
+        backend
+            .set_raw_chunk_slices(Ino(2), 0, Slice::new_owned(0, 11, 100).encode())
+            .unwrap();
+        backend
+            .do_copy_file_range(Ino(2), 0, Ino(3), 0, 100)
+            .unwrap();
+
+        // overwrite both files, the covered slices are dropped.
+        for inode in [Ino(2), Ino(3)] {
+            let r = backend
+                .do_write_slice(inode, 0, 0, &Slice::new_owned(0, 12 + inode.0, 100))
+                .unwrap()
+                .unwrap();
+            assert_eq!(r.slices, 2);
+            assert_eq!(r.grown, 0);
+        }
+        assert!(backend.do_compact_chunk(Ino(2), 0).unwrap().is_empty());
+        assert_eq!(backend.get_chunk_slices(Ino(2), 0).unwrap().len(), 1);
+        let freed = backend.do_compact_chunk(Ino(3), 0).unwrap();
+        assert_eq!(freed.len(), 1);
+        assert_eq!(freed[0].get_id(), 11);
+        // nothing left to compact.
+        assert!(backend.do_compact_chunk(Ino(3), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn batch_update_atime() {
+        let (_dir, backend) = test_backend();
+        let file = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .to_owned();
+        backend.set_attr(Ino(2), &file).unwrap();
+        // a chmod lands after the atime is buffered.
+        let mut chmod = file.clone();
+        chmod.mode = 0o600;
+        backend.set_attr(Ino(2), &chmod).unwrap();
+
+        let atime = file.atime + Duration::from_secs(10);
+        // the inode 3 has been removed.
+        backend
+            .batch_update_atime(&[(Ino(2), atime), (Ino(3), atime)])
+            .unwrap();
+        let attr = backend.get_attr(Ino(2)).unwrap();
+        assert_eq!(attr.atime, atime);
+        assert_eq!(attr.mode, 0o600);
+        assert!(backend.get_attr(Ino(3)).unwrap_err().is_not_found());
+        // an older atime never goes back.
+        backend.batch_update_atime(&[(Ino(2), file.atime)]).unwrap();
+        assert_eq!(backend.get_attr(Ino(2)).unwrap().atime, atime);
+    }
+
+    #[test]
+    fn write_slice() {
+        let (_dir, backend) = test_backend();
+        let file = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .to_owned();
+        backend.set_attr(Ino(2), &file).unwrap();
+        let slice = Slice::new_owned(100, 11, 100);
+        let r = backend
+            .do_write_slice(Ino(2), 1, 100, &slice)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.grown, (CHUNK_SIZE + 200) as u64);
+        assert_eq!(r.attr.length, (CHUNK_SIZE + 200) as u64);
+        assert_eq!(r.slices, 1);
+        assert_eq!(backend.get_attr(Ino(2)).unwrap().length, r.attr.length);
+        // the same slice is written only once.
+        assert!(
+            backend
+                .do_write_slice(Ino(2), 1, 100, &slice)
+                .unwrap()
+                .is_none()
+        );
+
+        let dir = InodeAttr::default()
+            .set_kind(FileType::Directory)
+            .to_owned();
+        backend.set_attr(Ino(3), &dir).unwrap();
+        assert!(matches!(
+            backend.do_write_slice(Ino(3), 0, 0, &slice),
+            Err(e) if e.to_errno() == libc::EPERM
+        ));
+    }
+
+    #[test]
+    fn freed_slice_conflicts_with_borrow() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = rocksdb::OptimisticTransactionDB::open_default(tempdir.path()).unwrap();
+        let backend = RocksdbBackend {
+            db,
+            skip_dir_mtime: Duration::from_millis(100),
+        };
+        let file = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .to_owned();
+        backend.set_attr(Ino(2), &file).unwrap();
+        let borrowed = Slice::new_owned(0, 11, 100);
+        backend.do_write_slice(Ino(2), 0, 0, &borrowed).unwrap();
+        backend
+            .do_write_slice(Ino(2), 0, 0, &Slice::new_owned(0, 12, 100))
+            .unwrap();
+
+        // a copy borrows the slice while the compaction frees it, the copy
+        // must not commit.
+        let txn = backend.db.transaction();
+        txn_ref_slices(&txn, [&borrowed]).unwrap();
+        let freed = backend.do_compact_chunk(Ino(2), 0).unwrap();
+        assert_eq!(freed.len(), 1);
+        assert_eq!(freed[0].get_id(), 11);
+        let err = txn.commit().context(RocksdbSnafu).unwrap_err();
+        assert!(err.is_conflict());
+    }
+
+    #[test]
+    fn clone_entry_borrows_slices() {
+        let (_dir, backend) = test_backend();
+        let ctx = Arc::new(FuseContext::background());
+        let dir = InodeAttr::default()
+            .set_kind(FileType::Directory)
+            .set_mode(0o777)
+            .set_nlink(2)
+            .to_owned();
+        let file = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .set_mode(0o4755)
+            .set_length(100)
+            .to_owned();
+        backend.set_attr(Ino(1), &dir).unwrap();
+        backend.set_attr(Ino(2), &file).unwrap();
+        backend
+            .set_dentry(Ino(1), b"f", Ino(2), FileType::RegularFile)
+            .unwrap();
+        backend
+            .set_raw_chunk_slices(Ino(2), 0, Slice::new_owned(0, 11, 100).encode())
+            .unwrap();
+        backend.set_xattr(Ino(2), b"user.k", b"v").unwrap();
+
+        let attr = backend
+            .do_clone_entry(ctx.clone(), Ino(2), Ino(3), Ino(1), b"g", true, false)
+            .unwrap();
+        assert_eq!(attr.length, 100);
+        assert_eq!(attr.uid, ctx.uid);
+        assert_eq!(attr.mode, 0o755);
+        assert_eq!(backend.get_dentry(Ino(1), b"g").unwrap().inode, Ino(3));
+        assert_eq!(
+            backend.get_chunk_slices(Ino(3), 0).unwrap().0,
+            backend.get_chunk_slices(Ino(2), 0).unwrap().0
+        );
+        assert_eq!(backend.get_slice_ref(11).unwrap(), 1);
+        assert_eq!(
+            backend.snapshot().unwrap().list_xattr(Ino(3)).unwrap(),
+            vec![(b"user.k".to_vec(), b"v".to_vec())]
+        );
+        // the name is taken, nothing changes.
+        assert!(
+            backend
+                .do_clone_entry(ctx.clone(), Ino(2), Ino(4), Ino(1), b"g", true, false)
+                .is_err()
+        );
+        assert_eq!(backend.get_slice_ref(11).unwrap(), 1);
+
+        // a detached directory shows up once it is attached.
+        backend.set_attr(Ino(5), &dir).unwrap();
+        backend
+            .do_clone_entry(ctx.clone(), Ino(5), Ino(6), Ino(1), b"d", false, true)
+            .unwrap();
+        assert!(backend.get_dentry(Ino(1), b"d").is_err());
+        backend.do_attach_entry(ctx, Ino(6), Ino(1), b"d").unwrap();
+        assert_eq!(backend.get_dentry(Ino(1), b"d").unwrap().inode, Ino(6));
+        assert_eq!(backend.get_attr(Ino(1)).unwrap().nlink, 3);
+    }
+
+    #[test]
+    fn list_delete_chunks() {
+        let (_dir, backend) = test_backend();
+        let marker = |inode, at: u64| {
+            (
+                key::delete_chunk_after(Ino(inode)),
+                bincode::serialize(&at).unwrap(),
+            )
+        };
+        backend
+            .write_raw(vec![marker(3, 20), marker(2, 10)], vec![])
+            .unwrap();
+        assert_eq!(
+            backend.list_delete_chunks(Ino(0), 10).unwrap(),
+            vec![(Ino(2), 10), (Ino(3), 20)]
+        );
+        assert_eq!(
+            backend.list_delete_chunks(Ino(0), 1).unwrap(),
+            vec![(Ino(2), 10)]
+        );
+        assert_eq!(
+            backend.list_delete_chunks(Ino(3), 10).unwrap(),
+            vec![(Ino(3), 20)]
+        );
+
+        // the marker goes away with the chunks.
+        backend.do_delete_chunks(Ino(2)).unwrap();
+        assert_eq!(
+            backend.list_delete_chunks(Ino(0), 10).unwrap(),
+            vec![(Ino(3), 20)]
+        );
+    }
+
+    #[test]
+    fn change_log() {
+        let (_dir, backend) = test_backend();
+        let record = |client, inode| ChangeRecord {
+            client,
+            change: crate::changes::Change::Inode(Ino(inode)),
+        };
+        assert!(backend.load_changes(0, 10).unwrap().is_empty());
+
+        backend
+            .append_changes(&[record(1, 2), record(1, 3)])
+            .unwrap();
+        backend.append_changes(&[record(2, 4)]).unwrap();
+        let seqs = |after, limit| {
+            backend
+                .load_changes(after, limit)
+                .unwrap()
+                .into_iter()
+                .map(|(seq, _)| seq)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seqs(0, 10), vec![1, 2, 3]);
+        assert_eq!(seqs(1, 1), vec![2]);
+        assert_eq!(
+            backend.load_changes(2, 10).unwrap(),
+            vec![(3, record(2, 4))]
+        );
+
+        // the sequence goes on after the old records are trimmed.
+        backend.trim_changes(2).unwrap();
+        assert_eq!(seqs(0, 10), vec![3]);
+        backend.append_changes(&[record(1, 5)]).unwrap();
+        assert_eq!(seqs(0, 10), vec![3, 4]);
+    }
 }
@@ -0,0 +1,27 @@
+use kiseki_types::ino::Ino;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::codec::Versioned;
+
+/// [Change] tells which cached metadata goes stale after a modification, so
+/// the caches of the clients, including the kernel's, can be dropped before
+/// they expire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Change {
+    /// The attributes or the content of the inode have changed.
+    Inode(Ino),
+    /// The name in the directory points to another inode, or to nothing.
+    Entry { parent: Ino, name: Vec<u8> },
+}
+
+/// [ChangeRecord] is a [Change] in the change log of the volume, the client
+/// which made it skips it on replay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ChangeRecord {
+    pub(crate) client: u64,
+    pub(crate) change: Change,
+}
+
+impl Versioned for ChangeRecord {
+    const VERSION: u8 = 1;
+}
@@ -48,6 +48,32 @@ impl Default for AccessTimeMode {
     fn default() -> Self { Self::Never }
 }
 
+impl Display for AccessTimeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessTimeMode::Never => write!(f, "noatime"),
+            AccessTimeMode::Relative => write!(f, "relatime"),
+            AccessTimeMode::Everytime => write!(f, "strictatime"),
+        }
+    }
+}
+
+impl FromStr for AccessTimeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "never" | "noatime" => Ok(AccessTimeMode::Never),
+            "relative" | "relatime" => Ok(AccessTimeMode::Relative),
+            "everytime" | "strictatime" => Ok(AccessTimeMode::Everytime),
+            _ => Err(format!(
+                "invalid atime mode {:?}, expect one of noatime, relatime, strictatime",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
 pub struct MetaConfig {
     pub dsn: String,
@@ -61,6 +87,26 @@ pub struct MetaConfig {
     /// [skip_dir_mtime] skip updating attribute of a directory if the mtime
     /// difference is smaller than this value
     pub skip_dir_mtime:   Duration,
+    /// [atime_mode] controls when the access time of a file or directory
+    /// gets updated on open/read/readdir.
+    pub atime_mode:       AccessTimeMode,
+
+    /// How long the attributes are cached on the client (0 means disable).
+    /// The changes made by other clients may be invisible for this long.
+    pub attr_cache_ttl:           Duration,
+    /// How long the directory entries are cached on the client.
+    pub entry_cache_ttl:          Duration,
+    /// How long a missing name is remembered on the client.
+    pub negative_entry_cache_ttl: Duration,
+    /// How long the symlink targets are cached on the client.
+    pub symlink_cache_ttl:        Duration,
+    /// max number of items of each kind in the metadata cache (0 means
+    /// unlimited)
+    pub meta_cache_limit:         usize,
+    /// How often the changes are exchanged with the other clients through
+    /// the change log, so the caches are dropped before they expire (0
+    /// means disable).
+    pub change_poll_interval:     Duration,
 }
 
 impl MetaConfig {
@@ -68,6 +114,11 @@ impl MetaConfig {
         self.dsn = dsn.to_string();
         self
     }
+
+    pub fn with_atime_mode(&mut self, mode: AccessTimeMode) -> &mut Self {
+        self.atime_mode = mode;
+        self
+    }
 }
 
 impl Default for MetaConfig {
@@ -78,6 +129,14 @@ impl Default for MetaConfig {
             open_cache:       Duration::default(),
             open_cache_limit: 10_000,
             skip_dir_mtime:   Duration::from_millis(100),
+            atime_mode:       AccessTimeMode::default(),
+
+            attr_cache_ttl:           Duration::from_secs(1),
+            entry_cache_ttl:          Duration::from_secs(1),
+            negative_entry_cache_ttl: Duration::from_secs(1),
+            symlink_cache_ttl:        Duration::from_secs(60),
+            meta_cache_limit:         100_000,
+            change_poll_interval:     Duration::from_secs(1),
         }
     }
 }
@@ -28,7 +28,7 @@ impl<'a> From<&'a kiseki_types::Request<'a>> for FuseContext {
         Self {
             unique:             req.unique(),
             gid:                req.gid(),
-            gid_list:           vec![],
+            gid_list:           vec![req.gid()],
             uid:                req.uid(),
             pid:                req.pid(),
             check_permission:   true,
@@ -64,6 +64,19 @@ impl FuseContext {
         return Ok(());
     }
 
+    // check_sticky checks if the entry can be removed or renamed from the
+    // parent, only the owner of the entry or of the sticky parent can.
+    pub fn check_sticky(&self, parent_attr: &InodeAttr, attr: &InodeAttr) -> Result<()> {
+        if self.uid == 0 || !self.check_permission || parent_attr.mode & 0o1000 == 0 {
+            return Ok(());
+        }
+        ensure!(
+            self.uid == parent_attr.uid || self.uid == attr.uid,
+            LibcSnafu { errno: libc::EPERM }
+        );
+        Ok(())
+    }
+
     pub fn is_cancelled(&self) -> bool { self.cancellation_token.is_cancelled() }
 }
 
@@ -82,5 +95,5 @@ impl FuseContext {
         }
     }
 
-    pub fn contains_gid(&self, gid: u32) -> bool { self.gid_list.contains(&gid) }
+    pub fn contains_gid(&self, gid: u32) -> bool { self.gid == gid || self.gid_list.contains(&gid) }
 }
@@ -0,0 +1,696 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
+    io::{Read, Write},
+    marker::PhantomData,
+    time::Duration,
+};
+
+use kiseki_common::ChunkIndex;
+use kiseki_types::{
+    attr::InodeAttr,
+    entry::DEntry,
+    ino::{Ino, ROOT_INO},
+    setting::{Format, SCHEMA_VERSION},
+    slice::{Slice, SliceID, SLICE_BYTES},
+    FileType,
+};
+use serde::{
+    de::{DeserializeSeed, Error as _, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use snafu::{IntoError, ResultExt};
+use strum::IntoEnumIterator;
+use tracing::{debug, info};
+
+use crate::{
+    backend::{key::Counter, open_backend, Backend, LoadBatch, Snapshot},
+    err::{Error, IOSnafu, InitializedEngineSnafu, JsonSnafu, Result},
+    upgrade::check_schema_version,
+};
+
+// How many inodes are listed at a time when looking for the ones out of the
+// directory tree.
+const DUMP_PAGE_SIZE: usize = 1024;
+// How many records are written to the backend at a time by load.
+const LOAD_BATCH_SIZE: usize = 10_000;
+
+/// [DumpedMeta] is the portable representation of the whole metadata of a
+/// volume. It doesn't depend on the key layout of any backend, so it can be
+/// used to move a volume between different kinds of backend.
+///
+/// The inodes are listed in the breadth-first order of the directory tree,
+/// so a directory always shows up before its children.
+///
+/// The inodes out of the tree, like the removed files which are still open,
+/// come after the others, and the removed files and slices which are waiting
+/// to be cleaned up are kept as well.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedMeta {
+    pub format:        Format,
+    pub counters:      BTreeMap<String, u64>,
+    pub inodes:        Vec<DumpedInode>,
+    #[serde(default)]
+    pub sustained:     Vec<DumpedSustained>,
+    #[serde(default)]
+    pub delete_chunks: Vec<DumpedDeleteChunk>,
+    #[serde(default)]
+    pub delete_slices: Vec<DumpedDeleteSlice>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedInode {
+    pub inode:   Ino,
+    pub attr:    InodeAttr,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "raw_name::option"
+    )]
+    pub symlink: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub xattrs:  Vec<DumpedXattr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks:  Vec<DumpedChunk>,
+    /// the children of a directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<DumpedDEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedXattr {
+    #[serde(with = "raw_name")]
+    pub name:  Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedChunk {
+    pub index:  ChunkIndex,
+    pub slices: Vec<Slice>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedDEntry {
+    #[serde(with = "raw_name")]
+    pub name:  Vec<u8>,
+    pub inode: Ino,
+    pub typ:   FileType,
+}
+
+/// [DumpedSustained] is a removed file which is still open in the session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedSustained {
+    pub session: u64,
+    pub inode:   Ino,
+}
+
+/// [DumpedDeleteChunk] is a removed file whose chunks are yet to be deleted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedDeleteChunk {
+    pub inode:      Ino,
+    pub removed_at: u64,
+    /// the chunks left, empty if the inode is dumped along with its chunks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks:     Vec<DumpedChunk>,
+}
+
+/// [DumpedDeleteSlice] is a slice no one references, whose objects are yet
+/// to be deleted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpedDeleteSlice {
+    pub id:   SliceID,
+    pub size: usize,
+}
+
+/// [raw_name] keeps the names, xattr names and symlink targets readable in the
+/// dump: they are written as strings when they are valid UTF-8, and as arrays
+/// of bytes otherwise. Both forms are accepted when loading.
+mod raw_name {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawName {
+        Str(String),
+        Bytes(Vec<u8>),
+    }
+
+    impl From<RawName> for Vec<u8> {
+        fn from(name: RawName) -> Self {
+            match name {
+                RawName::Str(s) => s.into_bytes(),
+                RawName::Bytes(b) => b,
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(name: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(name) {
+            Ok(name) => s.serialize_str(name),
+            Err(_) => s.collect_seq(name),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        Ok(RawName::deserialize(d)?.into())
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(name: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+            match name {
+                Some(name) => super::serialize(name, s),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+            Ok(Option::<super::RawName>::deserialize(d)?.map(Into::into))
+        }
+    }
+}
+
+/// [dump] writes the metadata of the volume behind the dsn as a single JSON
+/// document.
+pub fn dump<W: Write>(dsn: &str, w: W) -> Result<()> {
+    let backend = open_backend(dsn, Duration::from_millis(100))?;
+    dump_backend(backend.as_ref(), w)
+}
+
+/// [load] rebuilds the metadata from a JSON document produced by [dump], the
+/// target backend must not have been formatted.
+pub fn load<R: Read>(dsn: &str, r: R) -> Result<()> {
+    let backend = open_backend(dsn, Duration::from_millis(100))?;
+    load_backend(backend.as_ref(), r)
+}
+
+/// [dump_backend] reads everything from a snapshot of the backend, so it is
+/// safe to run against a live volume. The inodes are written one by one
+/// instead of building the whole [DumpedMeta] in memory.
+pub(crate) fn dump_backend<W: Write>(backend: &dyn Backend, mut w: W) -> Result<()> {
+    let snapshot = backend.snapshot()?;
+    let format = snapshot.load_format()?;
+    check_schema_version(&format)?;
+    let mut counters = BTreeMap::new();
+    for counter in Counter::iter() {
+        match snapshot.load_count(counter) {
+            Ok(v) => {
+                counters.insert(counter.name().to_string(), v);
+            }
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    w.write_all(b"{\"format\":").context(IOSnafu)?;
+    serde_json::to_writer(&mut w, &format).context(JsonSnafu)?;
+    w.write_all(b",\"counters\":").context(IOSnafu)?;
+    serde_json::to_writer(&mut w, &counters).context(JsonSnafu)?;
+    w.write_all(b",\"inodes\":[").context(IOSnafu)?;
+
+    let mut count = 0;
+    let mut write_inode = |w: &mut W, inode| {
+        let dumped = dump_inode(snapshot.as_ref(), inode)?;
+        if count > 0 {
+            w.write_all(b",").context(IOSnafu)?;
+        }
+        serde_json::to_writer(&mut *w, &dumped).context(JsonSnafu)?;
+        count += 1;
+        Ok::<_, Error>(dumped)
+    };
+    // hard links share the same inode, dump it only once.
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([ROOT_INO]);
+    while let Some(inode) = queue.pop_front() {
+        if !visited.insert(inode) {
+            continue;
+        }
+        let dumped = write_inode(&mut w, inode)?;
+        for e in dumped.entries.iter() {
+            queue.push_back(e.inode);
+        }
+    }
+    // the inodes out of the tree, like the removed files which are still
+    // open.
+    let mut start = ROOT_INO;
+    loop {
+        let inodes = snapshot.list_inodes(start, DUMP_PAGE_SIZE)?;
+        for inode in inodes.iter() {
+            if visited.insert(*inode) {
+                write_inode(&mut w, *inode)?;
+            }
+        }
+        match inodes.last() {
+            Some(last) if inodes.len() == DUMP_PAGE_SIZE => start = Ino(last.0 + 1),
+            _ => break,
+        }
+    }
+
+    let sustained = snapshot
+        .list_sustained()?
+        .into_iter()
+        .map(|(session, inode)| DumpedSustained { session, inode })
+        .collect::<Vec<_>>();
+    w.write_all(b"],\"sustained\":").context(IOSnafu)?;
+    serde_json::to_writer(&mut w, &sustained).context(JsonSnafu)?;
+    w.write_all(b",\"delete_chunks\":[").context(IOSnafu)?;
+    for (i, (inode, removed_at)) in snapshot.list_delete_chunks()?.into_iter().enumerate() {
+        let chunks = if visited.contains(&inode) {
+            vec![]
+        } else {
+            dump_chunks(snapshot.as_ref(), inode)?
+        };
+        if i > 0 {
+            w.write_all(b",").context(IOSnafu)?;
+        }
+        let dumped = DumpedDeleteChunk {
+            inode,
+            removed_at,
+            chunks,
+        };
+        serde_json::to_writer(&mut w, &dumped).context(JsonSnafu)?;
+    }
+    let delete_slices = snapshot
+        .list_delete_slices()?
+        .into_iter()
+        .map(|(id, size)| DumpedDeleteSlice { id, size })
+        .collect::<Vec<_>>();
+    w.write_all(b"],\"delete_slices\":").context(IOSnafu)?;
+    serde_json::to_writer(&mut w, &delete_slices).context(JsonSnafu)?;
+    w.write_all(b"}\n").context(IOSnafu)?;
+    w.flush().context(IOSnafu)?;
+    info!("dump {} inodes of {}", visited.len(), format.name);
+    Ok(())
+}
+
+fn dump_inode(snapshot: &dyn Snapshot, inode: Ino) -> Result<DumpedInode> {
+    let attr = snapshot.get_attr(inode)?;
+    let mut dumped = DumpedInode {
+        inode,
+        attr,
+        symlink: None,
+        xattrs: snapshot
+            .list_xattr(inode)?
+            .into_iter()
+            .map(|(name, value)| DumpedXattr { name, value })
+            .collect(),
+        chunks: vec![],
+        entries: vec![],
+    };
+    match dumped.attr.kind {
+        FileType::Directory => {
+            dumped.entries = snapshot
+                .list_dentry(inode)?
+                .into_iter()
+                .map(|de| DumpedDEntry {
+                    name:  de.name,
+                    inode: de.inode,
+                    typ:   de.typ,
+                })
+                .collect();
+        }
+        FileType::Symlink => {
+            dumped.symlink = Some(snapshot.get_symlink(inode)?);
+        }
+        FileType::RegularFile => {
+            dumped.chunks = dump_chunks(snapshot, inode)?;
+        }
+        _ => {}
+    }
+    Ok(dumped)
+}
+
+fn dump_chunks(snapshot: &dyn Snapshot, inode: Ino) -> Result<Vec<DumpedChunk>> {
+    Ok(snapshot
+        .list_chunk_slices(inode)?
+        .into_iter()
+        .map(|(index, slices)| DumpedChunk {
+            index,
+            slices: slices.0,
+        })
+        .collect())
+}
+
+/// [load_backend] parses the dump as a stream, and writes what it has parsed
+/// to the backend a batch at a time, so the dump of a large volume never
+/// sits in memory as a whole.
+pub(crate) fn load_backend<R: Read>(backend: &dyn Backend, r: R) -> Result<()> {
+    match backend.load_format() {
+        Ok(format) => {
+            return InitializedEngineSnafu { name: format.name }.fail();
+        }
+        Err(e) if matches!(e, Error::UninitializedEngine { .. }) => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut loader = Loader::new(backend);
+    let mut de = serde_json::Deserializer::from_reader(r);
+    let parsed = de.deserialize_map(&mut loader);
+    // the error of the backend stops the parsing, it is the one to report.
+    if let Some(e) = loader.error.take() {
+        return Err(e);
+    }
+    parsed.context(JsonSnafu)?;
+    de.end().context(JsonSnafu)?;
+    loader.finish()
+}
+
+/// [Loader] collects the records of the dump into batches as it is parsed.
+struct Loader<'a> {
+    backend:    &'a dyn Backend,
+    batch:      LoadBatch,
+    format:     Option<Format>,
+    counters:   BTreeMap<String, u64>,
+    inodes:     u64,
+    // (inode, parent) -> count, for rebuilding the hard link counts.
+    parents:    HashMap<(Ino, Ino), u64>,
+    hard_links: HashSet<Ino>,
+    // the references of each slice, for rebuilding the reference counts.
+    slice_refs: HashMap<SliceID, u64>,
+    // the error of the backend which has stopped the parsing.
+    error:      Option<Error>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(backend: &'a dyn Backend) -> Self {
+        Loader {
+            backend,
+            batch: LoadBatch::default(),
+            format: None,
+            counters: BTreeMap::new(),
+            inodes: 0,
+            parents: HashMap::new(),
+            hard_links: HashSet::new(),
+            slice_refs: HashMap::new(),
+            error: None,
+        }
+    }
+
+    fn add_inode(&mut self, inode: DumpedInode) -> Result<()> {
+        self.inodes += 1;
+        if inode.attr.parent.is_zero() {
+            self.hard_links.insert(inode.inode);
+        }
+        if let Some(target) = inode.symlink {
+            self.batch.symlinks.push((inode.inode, target));
+        }
+        for xattr in inode.xattrs {
+            self.batch
+                .xattrs
+                .push((inode.inode, xattr.name, xattr.value));
+        }
+        self.add_chunks(inode.inode, inode.chunks);
+        for de in inode.entries {
+            *self.parents.entry((de.inode, inode.inode)).or_default() += 1;
+            self.batch.dentries.push(DEntry {
+                parent: inode.inode,
+                name:   de.name,
+                inode:  de.inode,
+                typ:    de.typ,
+            });
+        }
+        self.batch.attrs.push((inode.inode, inode.attr));
+        self.flush_if_full()
+    }
+
+    fn add_chunks(&mut self, inode: Ino, chunks: Vec<DumpedChunk>) {
+        for chunk in chunks {
+            if chunk.slices.is_empty() {
+                continue;
+            }
+            let mut buf = Vec::with_capacity(chunk.slices.len() * SLICE_BYTES);
+            for slice in chunk.slices.iter() {
+                buf.extend_from_slice(&slice.encode());
+                if !slice.is_hole() {
+                    *self.slice_refs.entry(slice.get_id()).or_default() += 1;
+                }
+            }
+            self.batch.chunks.push((inode, chunk.index, buf));
+        }
+    }
+
+    fn add_delete_chunk(&mut self, dumped: DumpedDeleteChunk) -> Result<()> {
+        self.add_chunks(dumped.inode, dumped.chunks);
+        self.batch
+            .delete_chunks
+            .push((dumped.inode, dumped.removed_at));
+        self.flush_if_full()
+    }
+
+    fn flush_if_full(&mut self) -> Result<()> {
+        if self.batch.len() >= LOAD_BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if !self.batch.is_empty() {
+            self.backend.write_load_batch(&self.batch)?;
+            self.batch = LoadBatch::default();
+        }
+        Ok(())
+    }
+
+    // keep stops the parsing on the error of the backend, which is kept to
+    // be returned instead of the parse error it causes.
+    fn keep(&mut self, r: Result<()>) -> std::result::Result<(), &'static str> {
+        r.map_err(|e| {
+            self.error = Some(e);
+            "failed to write the backend"
+        })
+    }
+
+    // finish writes what is rebuilt from the whole dump, the format goes at
+    // last, so a partially loaded backend stays uninitialized.
+    fn finish(mut self) -> Result<()> {
+        let Some(mut format) = self.format.take() else {
+            return Err(JsonSnafu.into_error(serde_json::Error::missing_field("format")));
+        };
+        // the dump doesn't depend on the layout, it is always loaded as the latest.
+        format.schema_version = SCHEMA_VERSION;
+
+        for ((inode, parent), count) in std::mem::take(&mut self.parents) {
+            if self.hard_links.contains(&inode) {
+                self.batch.hard_links.push((inode, parent, count));
+                self.flush_if_full()?;
+            }
+        }
+        for (slice_id, count) in std::mem::take(&mut self.slice_refs) {
+            // the first reference isn't counted.
+            if count > 1 {
+                self.batch.slice_refs.push((slice_id, count - 1));
+                self.flush_if_full()?;
+            }
+        }
+        self.flush()?;
+        for counter in Counter::iter() {
+            if let Some(v) = self.counters.get(counter.name()) {
+                self.backend.set_count(counter, *v)?;
+            }
+        }
+
+        self.backend.set_format(&format)?;
+        info!("load {} inodes of {} success", self.inodes, format.name);
+        Ok(())
+    }
+}
+
+impl<'de> Visitor<'de> for &mut Loader<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a dump of the metadata")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format" => self.format = Some(map.next_value()?),
+                "counters" => self.counters = map.next_value()?,
+                "inodes" => map.next_value_seed(ForEach::new(|inode| {
+                    let r = self.add_inode(inode);
+                    self.keep(r)
+                }))?,
+                "sustained" => map.next_value_seed(ForEach::new(|s: DumpedSustained| {
+                    self.batch.sustained.push((s.session, s.inode));
+                    let r = self.flush_if_full();
+                    self.keep(r)
+                }))?,
+                "delete_chunks" => map.next_value_seed(ForEach::new(|dumped| {
+                    let r = self.add_delete_chunk(dumped);
+                    self.keep(r)
+                }))?,
+                "delete_slices" => map.next_value_seed(ForEach::new(|s: DumpedDeleteSlice| {
+                    self.batch.delete_slices.push((s.id, s.size));
+                    let r = self.flush_if_full();
+                    self.keep(r)
+                }))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        debug!("parsed {} inodes", self.inodes);
+        Ok(())
+    }
+}
+
+/// [ForEach] parses a sequence and hands the elements over one by one,
+/// instead of collecting them.
+struct ForEach<T, F> {
+    f:      F,
+    marker: PhantomData<T>,
+}
+
+impl<T, F> ForEach<T, F> {
+    fn new(f: F) -> Self {
+        ForEach {
+            f,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T, F, E> DeserializeSeed<'de> for ForEach<T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T) -> std::result::Result<(), E>,
+    E: fmt::Display,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<(), D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de, T, F, E> Visitor<'de> for ForEach<T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T) -> std::result::Result<(), E>,
+    E: fmt::Display,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("a sequence") }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while let Some(item) = seq.next_element()? {
+            (self.f)(item).map_err(A::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "meta-rocksdb")]
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        context::FuseContext,
+        test_util::{format_test_volume, test_backend, test_dsn},
+    };
+
+    #[test]
+    fn dump_and_load() {
+        let (_src_dir, src_dsn) = test_dsn();
+        format_test_volume(&src_dsn);
+        let src = open_backend(&src_dsn, Duration::from_millis(100)).unwrap();
+        let ctx = Arc::new(FuseContext::background());
+        let new_attr = |typ| {
+            InodeAttr::default()
+                .set_kind(typ)
+                .set_mode(0o755)
+                .set_nlink(1)
+                .set_parent(ROOT_INO)
+                .to_owned()
+        };
+        src.do_mknod(
+            ctx.clone(),
+            Ino(2),
+            new_attr(FileType::Directory),
+            ROOT_INO,
+            b"d1",
+            FileType::Directory,
+            Vec::new(),
+        )
+        .unwrap();
+        src.do_mknod(
+            ctx.clone(),
+            Ino(3),
+            new_attr(FileType::RegularFile),
+            Ino(2),
+            b"f1",
+            FileType::RegularFile,
+            Vec::new(),
+        )
+        .unwrap();
+        src.do_mknod(
+            ctx.clone(),
+            Ino(4),
+            new_attr(FileType::Symlink),
+            ROOT_INO,
+            b"s1",
+            FileType::Symlink,
+            b"d1/f1".to_vec(),
+        )
+        .unwrap();
+        src.set_xattr(Ino(3), b"user.k", b"v").unwrap();
+        src.set_xattr(Ino(3), b"user.\xff", b"w").unwrap();
+        // a name which isn't valid UTF-8.
+        src.do_mknod(
+            ctx.clone(),
+            Ino(5),
+            new_attr(FileType::RegularFile),
+            ROOT_INO,
+            b"caf\xe9",
+            FileType::RegularFile,
+            Vec::new(),
+        )
+        .unwrap();
+        src.set_raw_chunk_slices(Ino(3), 0, Slice::new_owned(0, 10, 4096).encode())
+            .unwrap();
+        // a removed file still open in session 1, a removed file waiting for
+        // its chunks to be deleted, and a slice waiting to be deleted.
+        let mut removed = new_attr(FileType::RegularFile);
+        removed.set_nlink(0);
+        src.write_load_batch(&LoadBatch {
+            attrs: vec![(Ino(6), removed)],
+            sustained: vec![(1, Ino(6))],
+            chunks: vec![(Ino(7), 0, Slice::new_owned(0, 12, 4096).encode())],
+            delete_chunks: vec![(Ino(7), 100)],
+            delete_slices: vec![(11, 4096)],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut dumped = vec![];
+        dump_backend(src.as_ref(), &mut dumped).unwrap();
+        let meta: DumpedMeta = serde_json::from_slice(&dumped).unwrap();
+        assert!(meta.inodes.iter().any(|i| i.inode == Ino(6)));
+        assert_eq!(meta.sustained.len(), 1);
+        assert_eq!(meta.sustained[0].inode, Ino(6));
+        assert_eq!(meta.delete_chunks.len(), 1);
+        assert_eq!(meta.delete_chunks[0].removed_at, 100);
+        assert_eq!(meta.delete_chunks[0].chunks.len(), 1);
+        assert_eq!(meta.delete_slices.len(), 1);
+        assert_eq!(meta.delete_slices[0].id, 11);
+
+        let (_dst_dir, dst) = test_backend();
+        load_backend(dst.as_ref(), dumped.as_slice()).unwrap();
+        // the target has been initialized, load it again should fail.
+        assert!(load_backend(dst.as_ref(), dumped.as_slice()).is_err());
+
+        let mut reloaded = vec![];
+        dump_backend(dst.as_ref(), &mut reloaded).unwrap();
+        assert_eq!(
+            String::from_utf8(dumped).unwrap(),
+            String::from_utf8(reloaded).unwrap()
+        );
+    }
+}
@@ -15,14 +15,17 @@
 use std::{
     cmp::{max, min},
     collections::{HashMap, HashSet},
+    ffi::OsStr,
     fmt::{Display, Formatter},
+    io::Write,
     ops::Add,
+    os::unix::ffi::OsStrExt,
     path::{Component, Path, PathBuf},
     sync::{
         atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use bitflags::{bitflags, Flags};
@@ -30,42 +33,81 @@ use bytes::Bytes;
 use crossbeam::{atomic::AtomicCell, channel::at};
 use dashmap::{DashMap, DashSet};
 use futures::AsyncReadExt;
-use kiseki_common::{ChunkIndex, CHUNK_SIZE, DOT, DOT_DOT, MODE_MASK_R, MODE_MASK_W, MODE_MASK_X};
+use kiseki_common::{
+    ChunkIndex, CHUNK_SIZE, DOT, DOT_DOT, MAX_FILE_SIZE, MODE_MASK_R, MODE_MASK_W, MODE_MASK_X,
+};
 use kiseki_types::{
     attr::{InodeAttr, SetAttrFlags},
-    entry::{DEntry, Entry, FullEntry},
-    ino::{Ino, ROOT_INO},
+    entry::{DEntry, DirPage, Entry, FullEntry},
+    ino::{Ino, ROOT_INO, ZERO_INO},
     internal_nodes::InternalNode,
-    setting::Format,
-    slice::{Slice, SliceID, Slices, SLICE_BYTES},
-    stat::{DirStat, FSStat},
+    setting::{Format, SCHEMA_VERSION},
+    slice::{Slice, SliceID, Slices},
+    stat::{DirStat, FSStat, Summary},
     FileType,
 };
 use kiseki_utils::readable_size::ReadableSize;
-use scopeguard::defer;
 use serde::Serialize;
 use snafu::{ensure, ResultExt};
 use tokio::{
-    sync::{RwLock, Semaphore},
+    sync::{broadcast, RwLock, Semaphore},
     time::{timeout, Instant},
 };
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::{
-    backend::{key::Counter, open_backend, BackendRef},
-    config::MetaConfig,
+    backend::{key, key::Counter, open_backend, BackendRef, WriteResult},
+    changes::{Change, ChangeRecord},
+    config::{AccessTimeMode, MetaConfig},
     context::FuseContext,
-    err::{Error, Error::LibcError, LibcSnafu, Result, TokioJoinSnafu},
+    err::{
+        Error, Error::LibcError, ImmutableSettingSnafu, InvalidFormatSnafu, LibcSnafu, Result,
+        TokioJoinSnafu,
+    },
     id_table::IdTable,
+    meta_cache::MetaCache,
     open_files::{InvalidReq, OpenFiles, OpenFilesRef},
+    stats::{MetaCounters, MetaStats, TrackedBackend},
+    upgrade::check_schema_version,
 };
 
 pub type MetaEngineRef = Arc<MetaEngine>;
 
+// With relatime, the atime is refreshed at least once within this interval.
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// The pending atime updates get flushed once there are this many of them.
+const ATIME_BATCH_SIZE: usize = 128;
+// How many records of the change log are replayed at a time.
+const CHANGE_BATCH_SIZE: usize = 1024;
+// How many records the change log keeps, a client which falls further behind
+// misses some changes and relies on the cache ttl instead.
+const MAX_CHANGE_LOG: u64 = 100_000;
+// How many changes wait for the subscribers, the slow ones miss the oldest.
+const CHANGE_CHANNEL_SIZE: usize = 4096;
+// A client which hasn't synced the changes for this many rounds, and at least
+// CLIENT_MIN_TTL, is taken as gone.
+const CLIENT_TTL_ROUNDS: u32 = 10;
+const CLIENT_MIN_TTL: Duration = Duration::from_secs(60);
+// The chunks of a removed file are left to the deletion in the background
+// for this long before the gc takes them over.
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
 pub fn open(config: MetaConfig) -> Result<MetaEngineRef> {
-    let backend = open_backend(&config.dsn, config.skip_dir_mtime)?;
+    let stats = Arc::new(MetaStats::default());
+    let backend: BackendRef = Arc::new(TrackedBackend::new(
+        open_backend(&config.dsn, config.skip_dir_mtime)?,
+        stats.clone(),
+    ));
     let format = backend.load_format()?;
+    check_schema_version(&format)?;
     let open_files = Arc::new(OpenFiles::new(config.open_cache, config.open_cache_limit));
+    let meta_cache = MetaCache::new(&config);
+    // only the changes made after we start are of interest.
+    let change_cursor = match backend.load_count(Counter::NextChange) {
+        Ok(v) => v,
+        Err(e) if e.is_not_found() => 0,
+        Err(e) => return Err(e),
+    };
 
     let me = MetaEngine {
         config,
@@ -73,32 +115,86 @@ pub fn open(config: MetaConfig) -> Result<MetaEngineRef> {
         root: ROOT_INO,
         session_id: 0,
         open_files,
-        symlinks: Default::default(),
+        meta_cache,
         removed_files: Default::default(),
         // Limit the number of incoming requests being handled at the same time
         delete_semaphore: Arc::new(Semaphore::const_new(100)),
+        compacting_chunks: Default::default(),
         dir_parents: Default::default(),
+        pending_atime: Default::default(),
+        client_id: kiseki_utils::random_id(),
+        pending_changes: Default::default(),
+        change_cursor: AtomicU64::new(change_cursor),
+        changes: broadcast::channel(CHANGE_CHANNEL_SIZE).0,
         fs_stat_used_size: Default::default(),
         fs_stat_file_count: Default::default(),
+        stats,
         free_inodes: IdTable::new(backend.clone(), Counter::NextInode),
         free_slices: IdTable::new(backend.clone(), Counter::NextSlice),
         backend,
     };
 
+    // the others log their changes for us from now on. A read-only client
+    // writes nothing, it only sees the changes logged for the others.
+    if !me.config.read_only && !me.config.change_poll_interval.is_zero() {
+        me.backend.touch_client(me.client_id, unix_now())?;
+    }
     debug!("open meta engine: {}", me);
 
     Ok(Arc::new(me))
 }
 
-// update_format is used to change the file system's setting.
-pub fn update_format(dsn: &str, format: Format, force: bool) -> Result<()> {
+/// [load_format] returns the setting of the file system behind the dsn.
+pub fn load_format(dsn: &str) -> Result<Format> {
+    let backend = open_backend(dsn, Duration::from_millis(100))?;
+    backend.load_format()
+}
+
+/// [update_format] initializes the file system with the given setting, or
+/// updates the setting of an existing one.
+///
+/// The fields which decide how the data is laid out (name, storage and the
+/// chunk/block/page size) can only be changed when the file system is still
+/// empty, and only with `force`. The rest, like the quotas and trash days, are
+/// applied directly.
+pub fn update_format(dsn: &str, mut format: Format, force: bool) -> Result<()> {
+    ensure!(
+        format.trash_days > 0,
+        InvalidFormatSnafu {
+            field:  "trash_days",
+            reason: "it should be at least 1",
+        }
+    );
     let backend = open_backend(dsn, Duration::from_millis(100))?;
+    // the layout is decided by the code, not by the caller.
+    format.schema_version = SCHEMA_VERSION;
 
     let mut need_init_root = false;
     match backend.load_format() {
         Ok(old_format) => {
             debug!("found exists format, need to update");
-            // TODO: update the old format
+            check_schema_version(&old_format)?;
+            let changes = old_format.immutable_changes(&format);
+            if let Some(field) = changes.first() {
+                ensure!(
+                    is_empty(&backend)?,
+                    ImmutableSettingSnafu {
+                        field:  field.to_string(),
+                        reason: "the file system is not empty",
+                    }
+                );
+                ensure!(
+                    force,
+                    ImmutableSettingSnafu {
+                        field:  field.to_string(),
+                        reason: "it requires force to overwrite",
+                    }
+                );
+                warn!(
+                    "overwrite {:?} of empty file system {}",
+                    changes, old_format.name
+                );
+            }
         }
         Err(e) => {
             if matches!(e, Error::UninitializedEngine { .. }) {
@@ -129,6 +225,21 @@ pub fn update_format(dsn: &str, format: Format, force: bool) -> Result<()> {
     Ok(())
 }
 
+// is_empty tells whether the volume holds nothing but its root: no entries
+// in the root, and no other inodes, not even the removed ones which are still
+// open or wait for their chunks to be deleted. The freed slices waiting for
+// their objects to be deleted count too, as the block size locates them.
+fn is_empty(backend: &BackendRef) -> Result<bool> {
+    if !backend.list_dentry(ROOT_INO, None, 1)?.is_empty()
+        || !backend.list_delete_slices(1)?.is_empty()
+    {
+        return Ok(false);
+    }
+    let (start, end) = key::non_root_range();
+    let first = backend.scan_raw(&start, 1)?;
+    Ok(first.first().map_or(true, |(k, _)| *k >= end))
+}
+
 pub struct MetaEngine {
     // config represents the configuration of the meta-engine,
     // like the underlying database engine, etc.
@@ -144,23 +255,38 @@ pub struct MetaEngine {
 
     // track the open files, since we cannot remove the associated
     // info of the file when it is being opened.
-    open_files:       OpenFilesRef,
-    // a cache for symlink content
-    symlinks:         RwLock<HashMap<Ino, Bytes>>,
+    open_files:        OpenFilesRef,
+    // a cache for the attrs, entries and symlink targets
+    meta_cache:        MetaCache,
     // track those inodes that didn't be removed totally since
     // someone has opened it.
     //
     // check this set when closing the inode, when we actually close
     // the inode, we should call the actual delete operation.
-    removed_files:    RwLock<HashSet<Ino>>,
+    removed_files:     RwLock<HashSet<Ino>>,
     // when do actual delete operation, try to acquire the permit first.
-    delete_semaphore: Arc<Semaphore>,
+    delete_semaphore:  Arc<Semaphore>,
+    // the chunks being compacted in the background after a write, so that
+    // a chunk is compacted by one task at a time.
+    compacting_chunks: Arc<DashSet<(Ino, ChunkIndex)>>,
 
     // directory inode -> parent inode
     dir_parents:        RwLock<HashMap<Ino, Ino>>,
+    // access time updates which haven't been written to the backend yet.
+    pending_atime:      DashMap<Ino, SystemTime>,
+    // tells the records of this client apart in the change log.
+    client_id:          u64,
+    // changes made by this client which haven't been written to the change
+    // log yet.
+    pending_changes:    DashSet<Change>,
+    // the sequence number of the last replayed record of the change log.
+    change_cursor:      AtomicU64,
+    // tells the subscribers, like the kernel, which caches have gone stale.
+    changes:            broadcast::Sender<Change>,
     // stats
     fs_stat_used_size:  AtomicU64,
     fs_stat_file_count: AtomicU64,
+    stats:              Arc<MetaStats>,
 
     // id tables
     free_inodes: IdTable,
@@ -198,6 +324,12 @@ impl MetaEngine {
 impl MetaEngine {
     pub fn get_format(&self) -> &Format { &self.format }
 
+    pub fn get_config(&self) -> &MetaConfig { &self.config }
+
+    /// [get_stats] returns how many operations the backend has served, and
+    /// how long they take in total.
+    pub fn get_stats(&self) -> MetaCounters { self.stats.load() }
+
     #[instrument(skip(self))]
     pub async fn next_slice_id(&self) -> Result<SliceID> { self.free_slices.next().await }
 
@@ -233,7 +365,7 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         check_perm: bool,
     ) -> Result<(Ino, InodeAttr)> {
         trace!(parent=?parent, ?name, "lookup");
@@ -242,12 +374,12 @@ impl MetaEngine {
             let parent_attr = self.get_attr(parent).await?;
             ctx.check_access(&parent_attr, MODE_MASK_X)?;
         }
-        let mut name = name;
-        if name == DOT_DOT {
+        let mut name = name.as_bytes();
+        if name == DOT_DOT.as_bytes() {
             if parent == self.root {
                 // If parent is already the root directory,
                 // sets name to "." (current directory).
-                name = DOT;
+                name = DOT.as_bytes();
             } else {
                 // Otherwise, retrieves attributes of parent.
                 // Checks if parent is a directory using attr.Typ != TypeDirectory.
@@ -263,11 +395,11 @@ impl MetaEngine {
                 return Ok((parent_attr.parent, attr));
             }
         }
-        if name == DOT {
+        if name == DOT.as_bytes() {
             let attr = self.get_attr(parent).await?;
             return Ok((parent, attr));
         }
-        let (inode, attr) = self.do_lookup(parent, name)?;
+        let (inode, attr) = self.do_lookup(parent, name).await?;
 
         if attr.kind == FileType::Directory {
             self.add_dir2parent_mapping(inode, parent).await;
@@ -276,37 +408,117 @@ impl MetaEngine {
         Ok((inode, attr))
     }
 
-    fn do_lookup(&self, parent: Ino, name: &str) -> Result<(Ino, InodeAttr)> {
-        let entry_info = self.backend.get_dentry(parent, name)?;
-        let inode = entry_info.inode;
-        let attr = self.backend.get_attr(inode)?;
-        Ok((inode, attr))
+    async fn do_lookup(&self, parent: Ino, name: &[u8]) -> Result<(Ino, InodeAttr)> {
+        let inode = match self.meta_cache.get_entry(parent, name) {
+            Some(Some(inode)) => inode,
+            Some(None) => {
+                return LibcSnafu {
+                    errno: libc::ENOENT,
+                }
+                .fail();
+            }
+            None => match self.backend.get_dentry(parent, name) {
+                Ok(entry_info) => {
+                    self.meta_cache.put_entry(parent, name, entry_info.inode);
+                    entry_info.inode
+                }
+                Err(e) if e.is_not_found() => {
+                    self.meta_cache.put_negative_entry(parent, name);
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        match self.get_attr(inode).await {
+            Ok(attr) => Ok((inode, attr)),
+            Err(e) => {
+                // the cached entry may point to an inode removed by others.
+                self.meta_cache.invalid_entry(parent, name);
+                Err(e)
+            }
+        }
     }
 
     pub async fn get_attr(&self, inode: Ino) -> Result<InodeAttr> {
         let inode = self.check_root(inode);
-        // check cache
+        if let Some(attr) = self.cached_attr(inode).await {
+            return Ok(attr);
+        }
+
+        // TODO: add timeout here
+        let mut attr = self.backend.get_attr(inode)?;
+        self.cache_attr(inode, &mut attr).await;
+        Ok(attr)
+    }
+
+    /// [cached_attr] returns the attr of the inode if it is cached, without
+    /// going to the backend.
+    async fn cached_attr(&self, inode: Ino) -> Option<InodeAttr> {
         if !self.config.open_cache.is_zero() {
             if let Some(attr) = self.open_files.load_attr(inode, false).await {
-                return Ok(attr);
+                return Some(attr);
+            }
+        }
+        let mut attr = self.meta_cache.get_attr(inode)?;
+        self.merge_pending_atime(inode, &mut attr);
+        Some(attr)
+    }
+
+    fn merge_pending_atime(&self, inode: Ino, attr: &mut InodeAttr) {
+        if let Some(atime) = self.pending_atime.get(&inode) {
+            if *atime > attr.atime {
+                attr.atime = *atime;
             }
         }
+    }
 
-        // TODO: add timeout here
-        let mut attr = self.backend.get_attr(inode)?;
+    /// [cache_attr] merges the buffered atime into the attr fetched from the
+    /// backend, and refreshes the caches with it.
+    async fn cache_attr(&self, inode: Ino, attr: &mut InodeAttr) {
+        self.merge_pending_atime(inode, attr);
 
         // update cache
-        self.open_files.refresh_attr(inode, &mut attr).await;
+        self.open_files.refresh_attr(inode, attr).await;
+        self.meta_cache.put_attr(inode, attr);
         if attr.is_filetype(FileType::Directory) && !inode.is_root() {
             self.add_dir2parent_mapping(inode, attr.parent).await;
         }
-        Ok(attr)
+    }
+
+    /// [batch_get_attr] fetches the attributes of the inodes with one round
+    /// trip to the backend, None for the inodes which have been removed.
+    async fn batch_get_attr(&self, inodes: Vec<Ino>) -> Result<Vec<Option<InodeAttr>>> {
+        let backend = self.backend.clone();
+        let query = inodes.clone();
+        let mut attrs = tokio::task::spawn_blocking(move || backend.batch_get_attr(&query))
+            .await
+            .context(TokioJoinSnafu)??;
+        for (inode, attr) in inodes.into_iter().zip(attrs.iter_mut()) {
+            if let Some(attr) = attr {
+                self.cache_attr(inode, attr).await;
+            }
+        }
+        Ok(attrs)
     }
 
     // Readdir returns all entries for given directory, which include attributes if
     // plus is true.
-    pub async fn read_dir(&self, ctx: &FuseContext, inode: Ino, plus: bool) -> Result<Vec<Entry>> {
-        debug!(dir=?inode, "readdir in plus?, {plus}");
+    /// [read_dir] lists at most `limit` entries of the directory in the
+    /// order of their names, starting right after the name `after`, the
+    /// attributes of the entries are returned as well if plus is true.
+    ///
+    /// The name of the last listed entry is the cursor of the next page, so
+    /// the entries created or removed in between never shift the pages, and
+    /// the page tells whether the directory has been listed to its end.
+    pub async fn read_dir(
+        &self,
+        ctx: &FuseContext,
+        inode: Ino,
+        plus: bool,
+        after: Option<&OsStr>,
+        limit: usize,
+    ) -> Result<DirPage> {
+        debug!(dir=?inode, ?after, limit, "readdir in plus?, {plus}");
         let inode = self.check_root(inode);
         let mut attr = self.get_attr(inode).await?;
         let mmask = if plus {
@@ -316,45 +528,75 @@ impl MetaEngine {
         };
 
         ctx.check_access(&attr, mmask)?;
+        // only the first page counts as an access.
+        if after.is_none() {
+            self.refresh_atime(inode, &mut attr).await;
+        }
 
         if inode == self.root {
             attr.parent = self.root;
         }
 
-        // let mut basic_entries = Entry::new_basic_entry_pair(inode, attr.parent);
-        let mut basic_entries = vec![];
-        self.do_read_dir(inode, plus, &mut basic_entries, -1)
+        // the first page starts with "." and "..".
+        let mut page = DirPage {
+            entries: if after.is_none() {
+                Entry::new_basic_entry_pair(inode, attr.parent)
+            } else {
+                vec![]
+            },
+            cursor:  after.map(|name| name.as_bytes().to_vec()),
+            eof:     false,
+        };
+        self.do_read_dir(inode, plus, &mut page, limit as i64)
             .await?;
 
-        debug!("find entries: {:?}", &basic_entries);
+        debug!("find {} entries, eof: {}", page.entries.len(), page.eof);
 
-        Ok(basic_entries)
+        Ok(page)
     }
 
     async fn do_read_dir(
         &self,
         inode: Ino,
         plus: bool,
-        basic_entries: &mut Vec<Entry>,
-        _limit: i64,
+        page: &mut DirPage,
+        limit: i64,
     ) -> Result<()> {
         let backend = self.backend.clone();
-        let entries = tokio::task::spawn_blocking(move || backend.list_dentry(inode, _limit))
-            .await
-            .context(TokioJoinSnafu)??;
-        // let entries = self.backend.list_entry_info(inode, _limit)?;
-        for de in entries {
-            let entry = if plus {
-                let attr = self.backend.get_attr(de.inode)?;
-                Entry::Full(FullEntry {
-                    inode,
-                    name: de.name.clone(),
-                    attr,
-                })
-            } else {
-                Entry::DEntry(de)
+        let after = page.cursor.clone();
+        let entries = tokio::task::spawn_blocking(move || {
+            backend.list_dentry(inode, after.as_deref(), limit)
+        })
+        .await
+        .context(TokioJoinSnafu)??;
+        // the cursor and eof come from the listing itself, the entries
+        // removed since may be left out of readdirplus below, and a page
+        // coming out short doesn't end the listing.
+        page.eof = limit == -1 || entries.len() < limit as usize;
+        if let Some(last) = entries.last() {
+            page.cursor = Some(last.name.clone());
+        }
+        for de in entries.iter() {
+            self.meta_cache.put_entry(inode, &de.name, de.inode);
+        }
+        page.entries.extend(entries.into_iter().map(Entry::DEntry));
+        if !plus {
+            return Ok(());
+        }
+
+        let inodes = page.entries.iter().map(|e| e.get_inode()).collect();
+        let attrs = self.batch_get_attr(inodes).await?;
+        let entries = std::mem::take(&mut page.entries);
+        for (entry, attr) in entries.into_iter().zip(attrs) {
+            // the entry has been removed since it was listed.
+            let Some(attr) = attr else {
+                continue;
             };
-            basic_entries.push(entry);
+            page.entries.push(Entry::Full(FullEntry {
+                inode: entry.get_inode(),
+                name: entry.get_name().as_bytes().to_vec(),
+                attr,
+            }));
         }
         Ok(())
     }
@@ -395,7 +637,7 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         mode: u32,
         umask: u32,
     ) -> Result<(Ino, InodeAttr)> {
@@ -408,7 +650,7 @@ impl MetaEngine {
                 mode,
                 umask,
                 0,
-                String::new(),
+                Vec::new(),
             )
             .await
         {
@@ -421,11 +663,16 @@ impl MetaEngine {
     }
 
     /// [rmdir] removes an empty subdirectory.
-    pub async fn rmdir(&self, ctx: Arc<FuseContext>, parent: Ino, name: &str) -> Result<()> {
+    pub async fn rmdir(&self, ctx: Arc<FuseContext>, parent: Ino, name: &OsStr) -> Result<()> {
         let parent = self.check_root(parent);
+        let name = name.as_bytes();
         let (dentry, _) = self
             .backend
             .do_rmdir(ctx, parent, name, self.config.skip_dir_mtime)?;
+        self.meta_cache.invalid_entry(parent, name);
+        self.meta_cache.invalid_inode(dentry.inode);
+        self.meta_cache.invalid_attr(parent);
+        self.record_entry_change(parent, name);
         self.fs_stat_file_count.fetch_sub(1, Ordering::AcqRel);
         self.fs_stat_used_size.fetch_sub(4096, Ordering::AcqRel);
         self.del_dir2parents_mapping(dentry.inode).await;
@@ -439,12 +686,12 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         typ: FileType,
         mode: u32,
         umask: u32,
         rdev: u32,
-        path: String,
+        path: Vec<u8>,
     ) -> Result<(Ino, InodeAttr)> {
         ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
         ensure!(
@@ -455,6 +702,7 @@ impl MetaEngine {
         );
 
         let parent = self.check_root(parent);
+        let name = name.as_bytes();
 
         let new_inode = Ino::from(self.free_inodes.next().await?);
         debug!("new inode: {}", new_inode);
@@ -480,6 +728,10 @@ impl MetaEngine {
         let r = self
             .backend
             .do_mknod(ctx, new_inode, attr, parent, name, typ, path)?;
+        self.meta_cache.put_entry(parent, name, r.0);
+        self.meta_cache.put_attr(r.0, &r.1);
+        self.meta_cache.invalid_attr(parent);
+        self.record_entry_change(parent, name);
 
         self.fs_stat_file_count.fetch_add(1, Ordering::AcqRel);
         self.fs_stat_used_size.fetch_add(4096, Ordering::Acquire);
@@ -491,7 +743,7 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         mode: u32,
         umask: u32,
         flags: i32,
@@ -509,15 +761,14 @@ impl MetaEngine {
                 mode,
                 umask,
                 0,
-                String::new(),
+                Vec::new(),
             )
             .await
         {
             Ok(r) => r,
             Err(e) if matches!(e, LibcError{errno, ..} if errno == libc::EEXIST) => {
                 warn!("create failed: {:?}", e);
-                let r = self.do_lookup(parent, name)?;
-                r
+                self.do_lookup(parent, name.as_bytes()).await?
             }
             Err(e) => return Err(e),
         };
@@ -556,9 +807,34 @@ impl MetaEngine {
         let mut dirty_attr = self.merge_attr(ctx, flags, ino, &cur_attr, new_attr, now)?;
         dirty_attr.ctime = now;
         self.backend.set_attr(inode, &dirty_attr)?;
+        self.meta_cache.put_attr(inode, &dirty_attr);
+        self.record_change(Change::Inode(inode));
         Ok(())
     }
 
+    /// [kill_suid] drops the set-id bits of a file after someone but the root
+    /// has modified it, so that nobody can plant code which runs as another
+    /// user. Returns the new attr if any bit is dropped.
+    pub async fn kill_suid(&self, ctx: &FuseContext, inode: Ino) -> Result<Option<InodeAttr>> {
+        if ctx.uid == 0 {
+            return Ok(None);
+        }
+        let inode = self.check_root(inode);
+        // hardly any file has the bits, the cached attr tells so without a
+        // round trip to the backend on every write.
+        let cached = self.cached_attr(inode).await;
+        if cached.is_some_and(|attr| attr.suid_kill_bits() == 0) {
+            return Ok(None);
+        }
+        let Some(attr) = self.backend.do_kill_suid(inode)? else {
+            return Ok(None);
+        };
+        self.open_files.invalid(inode, InvalidReq::OnlyAttr).await;
+        self.meta_cache.put_attr(inode, &attr);
+        self.record_change(Change::Inode(inode));
+        Ok(Some(attr))
+    }
+
     fn merge_attr(
         &self,
         ctx: &FuseContext,
@@ -568,31 +844,37 @@ impl MetaEngine {
         new_attr: &mut InodeAttr,
         now: SystemTime,
     ) -> Result<InodeAttr> {
-        let mut dirty_attr = cur.clone();
-        if flags.contains(SetAttrFlags::MODE)
-            && flags.contains(SetAttrFlags::UID | SetAttrFlags::GID)
-        {
-            // This operation isolates the file permission bits representing the user's file
-            // type (setuid, setgid, or sticky bit).
-            dirty_attr.mode |= cur.mode & 0o6000;
+        // only the flags themselves can be changed on an immutable or
+        // append-only inode, see chattr(1).
+        if !cur.is_normal() {
+            ensure!(
+                !flags.intersects(
+                    SetAttrFlags::MODE
+                        | SetAttrFlags::UID
+                        | SetAttrFlags::GID
+                        | SetAttrFlags::ATIME
+                        | SetAttrFlags::MTIME
+                ),
+                LibcSnafu { errno: libc::EPERM }
+            );
+            ensure!(
+                !cur.is_immutable()
+                    || !flags.intersects(
+                        SetAttrFlags::SIZE | SetAttrFlags::ATIME_NOW | SetAttrFlags::MTIME_NOW
+                    ),
+                LibcSnafu { errno: libc::EPERM }
+            );
         }
+        let mut dirty_attr = cur.clone();
         let mut changed = false;
-        if cur.mode & 0o6000 != 0 && flags.contains(SetAttrFlags::UID | SetAttrFlags::GID) {
-            #[cfg(target_os = "linux")]
-            {
-                if !dirty_attr.is_dir() {
-                    if ctx.uid != 0 || (dirty_attr.mode >> 3) & 1 != 0 {
-                        // clear SUID and SGID
-                        dirty_attr.mode &= 0o1777; // WHY ?
-                        new_attr.mode &= 0o1777;
-                    } else {
-                        // keep SGID if the file is non-group-executable
-                        dirty_attr.mode &= 0o3777;
-                        new_attr.mode &= 0o3777;
-                    }
-                }
+        // chown drops the set-id bits, even when the root does it, see chown(2).
+        if flags.intersects(SetAttrFlags::UID | SetAttrFlags::GID) {
+            let kill = cur.suid_kill_bits();
+            if kill != 0 {
+                dirty_attr.mode &= !kill;
+                new_attr.mode &= !kill;
+                changed = true;
             }
-            changed = true;
         }
         if flags.contains(SetAttrFlags::GID) {
             ensure!(
@@ -603,7 +885,7 @@ impl MetaEngine {
             //     return Err(MetaError::ErrLibc { kind: libc::EPERM });
             // }
             if cur.gid != new_attr.gid {
-                if ctx.check_permission && cur.uid != 0 && !ctx.contains_gid(new_attr.gid) {
+                if ctx.check_permission && ctx.uid != 0 && !ctx.contains_gid(new_attr.gid) {
                     LibcSnafu { errno: libc::EPERM }.fail()?;
                 }
                 dirty_attr.gid = new_attr.gid;
@@ -619,7 +901,9 @@ impl MetaEngine {
             changed = true;
         }
         if flags.contains(SetAttrFlags::MODE) {
-            if ctx.uid != 0 && new_attr.mode & 0o2000 != 0 && ctx.uid != cur.gid {
+            // the set-group-ID bit is silently dropped if the caller is not
+            // in the group of the file, see chmod(2).
+            if ctx.uid != 0 && new_attr.mode & 0o2000 != 0 && !ctx.contains_gid(dirty_attr.gid) {
                 new_attr.mode &= 0o5777;
             }
             if cur.mode != new_attr.mode {
@@ -688,7 +972,23 @@ impl MetaEngine {
             changed = true;
         }
         if flags.contains(SetAttrFlags::FLAG) {
-            dirty_attr.flags = flags.0;
+            ensure!(
+                new_attr.flags <= u8::MAX as u32
+                    && kiseki_types::attr::Flags::from_bits(new_attr.flags as u8).is_some(),
+                LibcSnafu {
+                    errno: libc::EINVAL,
+                }
+            );
+            ensure!(
+                ctx.uid == 0 || ctx.uid == cur.uid,
+                LibcSnafu { errno: libc::EPERM }
+            );
+            // setting or clearing them needs CAP_LINUX_IMMUTABLE.
+            ensure!(
+                ctx.uid == 0 || new_attr.flags == cur.flags,
+                LibcSnafu { errno: libc::EPERM }
+            );
+            dirty_attr.flags = new_attr.flags;
             changed = true;
         }
         if !changed {
@@ -705,10 +1005,14 @@ impl MetaEngine {
             LibcSnafu { errno: libc::EROFS }.fail()?;
         }
 
-        defer!(self.refresh_atime(ctx, inode));
+        // only the read access touches the atime.
+        let read_access = flags & libc::O_ACCMODE != libc::O_WRONLY;
 
         if !self.config.open_cache.is_zero() {
-            if let Some(attr) = self.open_files.load_attr(inode, true).await {
+            if let Some(mut attr) = self.open_files.load_attr(inode, true).await {
+                if read_access {
+                    self.refresh_atime(inode, &mut attr).await;
+                }
                 return Ok(attr);
             }
         }
@@ -741,11 +1045,12 @@ impl MetaEngine {
             }
         }
         self.open_files.open(inode, &mut attr).await;
+        if read_access {
+            self.refresh_atime(inode, &mut attr).await;
+        }
         Ok(attr)
     }
 
-    fn refresh_atime(&self, _ctx: &FuseContext, _inode: Ino) {}
-
     // Write put a slice of data on top of the given chunk.
     #[instrument(skip(self, mtime), fields(inode = ? inode, chunk_idx = ? chunk_idx, chunk_pos = ? chunk_pos, slice = ? slice))]
     pub async fn write_slice(
@@ -765,46 +1070,51 @@ impl MetaEngine {
             inode, chunk_idx, chunk_pos, slice, mtime
         );
 
-        // check if the inode is a file
-        let mut attr = self.backend.get_attr(inode)?;
-        ensure!(attr.is_file(), LibcSnafu { errno: libc::EPERM });
-
-        let mut slices_buf = self
+        let Some(WriteResult {
+            attr,
+            grown: grow_len,
+            slices: slice_cnt,
+        }) = self
             .backend
-            .get_raw_chunk_slices(inode, chunk_idx)?
-            .unwrap_or(vec![]);
-
-        let new_len =
-            chunk_idx as u64 * CHUNK_SIZE as u64 + chunk_pos as u64 + slice.get_size() as u64;
-        let grow_len = if new_len > attr.length {
-            debug!(
-                "update inode: {} old_length: {} new_length: {}",
-                inode, attr.length, new_len
-            );
-            let v = new_len - attr.length;
-            attr.length = new_len;
-            v
-        } else {
-            0
-        };
-        attr.update_modification_time();
-        let val = bincode::serialize(&slice).unwrap();
-        if slices_buf.eq(&val) {
+            .do_write_slice(inode, chunk_idx, chunk_pos, &slice)?
+        else {
             warn!(
                 "{inode} try to write the same slice {:?} at {chunk_idx}",
                 slice
             );
             return Ok(());
-        }
-        slices_buf.extend_from_slice(&val);
-        self.backend.set_attr(inode, &attr)?;
-        let slice_cnt = slices_buf.len() / SLICE_BYTES; // number of slices
-        self.backend
-            .set_raw_chunk_slices(inode, chunk_idx, slices_buf)?;
+        };
+        self.meta_cache.put_attr(inode, &attr);
 
-        if slice_cnt > 350 || slice_cnt % 100 == 99 {
-            // start a background task to compact these slices
-            // TODO: we need to do compaction
+        if (slice_cnt > 350 || slice_cnt % 100 == 99)
+            && self.compacting_chunks.insert((inode, chunk_idx))
+        {
+            // start a background task to compact these slices, the backend
+            // queues the freed ones for their objects to be deleted.
+            // TODO: merge the small slices, at present we only drop the covered ones.
+            let backend = self.backend.clone();
+            let open_files = self.open_files.clone();
+            let compacting_chunks = self.compacting_chunks.clone();
+            tokio::spawn(async move {
+                let r =
+                    tokio::task::spawn_blocking(move || backend.do_compact_chunk(inode, chunk_idx))
+                        .await
+                        .context(TokioJoinSnafu)
+                        .and_then(|r| r);
+                compacting_chunks.remove(&(inode, chunk_idx));
+                match r {
+                    Ok(freed) => {
+                        debug!(
+                            "compact {inode} at {chunk_idx}, {} slices freed",
+                            freed.len()
+                        );
+                        open_files
+                            .invalid(inode, InvalidReq::OneChunk(chunk_idx))
+                            .await;
+                    }
+                    Err(e) => debug!("failed to compact {inode} at {chunk_idx}: {:?}", e),
+                }
+            });
         }
 
         // update the used size
@@ -816,6 +1126,7 @@ impl MetaEngine {
         self.open_files
             .invalid(inode, InvalidReq::OneChunk(chunk_idx))
             .await;
+        self.record_change(Change::Inode(inode));
 
         Ok(())
     }
@@ -910,6 +1221,92 @@ impl MetaEngine {
         todo!()
     }
 
+    /// [copy_file_range] copies the data between two files by borrowing the
+    /// slices of the src, only the metadata is touched. Returns the copied
+    /// length and the new attr of the dst.
+    pub async fn copy_file_range(
+        &self,
+        src: Ino,
+        src_off: u64,
+        dst: Ino,
+        dst_off: u64,
+        size: u64,
+        flags: u32,
+    ) -> Result<(u64, InodeAttr)> {
+        ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
+        ensure!(
+            flags == 0,
+            LibcSnafu {
+                errno: libc::EINVAL,
+            }
+        );
+        ensure!(
+            dst_off + size <= MAX_FILE_SIZE as u64,
+            LibcSnafu { errno: libc::EFBIG }
+        );
+
+        let backend = self.backend.clone();
+        let r = tokio::task::spawn_blocking(move || {
+            backend.do_copy_file_range(src, src_off, dst, dst_off, size)
+        })
+        .await
+        .context(TokioJoinSnafu)??;
+        if r.copied == 0 {
+            return Ok((0, r.attr));
+        }
+
+        let mut attr = r.attr;
+        self.open_files.invalid(dst, InvalidReq::All).await;
+        self.cache_attr(dst, &mut attr).await;
+        self.record_change(Change::Inode(dst));
+        if r.grown > 0 {
+            self.fs_stat_used_size.fetch_add(r.grown, Ordering::AcqRel);
+        }
+        Ok((r.copied, attr))
+    }
+
+    /// [lseek] finds the next data or hole at or after the offset for
+    /// SEEK_DATA or SEEK_HOLE, the end of the file counts as a hole.
+    pub async fn lseek(&self, inode: Ino, offset: u64, whence: i32) -> Result<u64> {
+        ensure!(
+            whence == libc::SEEK_DATA || whence == libc::SEEK_HOLE,
+            LibcSnafu {
+                errno: libc::EINVAL,
+            }
+        );
+        let attr = self.get_attr(inode).await?;
+        ensure!(
+            attr.is_file(),
+            LibcSnafu {
+                errno: libc::EINVAL,
+            }
+        );
+        ensure!(offset < attr.length, LibcSnafu { errno: libc::ENXIO });
+
+        let mut pos = offset;
+        while pos < attr.length {
+            let chunk_idx = pos as usize / CHUNK_SIZE;
+            let chunk_start = (chunk_idx * CHUNK_SIZE) as u64;
+            let chunk_end = min(chunk_start + CHUNK_SIZE as u64, attr.length);
+            let range = (pos - chunk_start) as usize..(chunk_end - chunk_start) as usize;
+            // a chunk without slices is a hole as a whole.
+            let found = match self.read_slice(inode, chunk_idx).await? {
+                Some(slices) if whence == libc::SEEK_DATA => slices.seek_data(range),
+                Some(slices) => slices.seek_hole(range),
+                None if whence == libc::SEEK_DATA => None,
+                None => Some(range.start),
+            };
+            if let Some(chunk_pos) = found {
+                return Ok(chunk_start + chunk_pos as u64);
+            }
+            pos = chunk_end;
+        }
+        if whence == libc::SEEK_DATA {
+            return LibcSnafu { errno: libc::ENXIO }.fail();
+        }
+        Ok(attr.length)
+    }
+
     pub async fn flock(
         &self,
         ctx: Arc<FuseContext>,
@@ -924,6 +1321,15 @@ impl MetaEngine {
         Ok(())
     }
 
+    /// [forget] drops what the engine keeps in memory for the inode, it is
+    /// called once the kernel has forgotten the inode. The inode itself
+    /// stays in the backend.
+    pub async fn forget(&self, inode: Ino) {
+        self.del_dir2parents_mapping(inode).await;
+        self.meta_cache.invalid_inode(inode);
+        self.open_files.forget(inode).await;
+    }
+
     /// [close] a file, try to decrease the reference count of the OpenFile,
     /// if the reference count is zero, then we can remove the file from the
     /// cache.
@@ -957,13 +1363,303 @@ impl MetaEngine {
                 .backend
                 .do_truncate(ctx, inode, size, skip_perm_check)?;
             drop(guard); // explicitly drop the guard for keeping holding the lock
+            self.meta_cache.put_attr(inode, &attr);
+            self.record_change(Change::Inode(inode));
             Ok(attr)
         } else {
-            self.backend.do_truncate(ctx, inode, size, skip_perm_check)
+            let attr = self
+                .backend
+                .do_truncate(ctx, inode, size, skip_perm_check)?;
+            self.meta_cache.put_attr(inode, &attr);
+            self.record_change(Change::Inode(inode));
+            Ok(attr)
         };
     }
 }
 
+// Atime
+impl MetaEngine {
+    /// [refresh_atime] updates the access time of the given attr if needed.
+    ///
+    /// The update is buffered in memory and written to the backend in
+    /// batches, see [MetaEngine::flush_atime].
+    async fn refresh_atime(&self, inode: Ino, attr: &mut InodeAttr) {
+        if self.config.read_only || inode.is_special() {
+            return;
+        }
+        let now = SystemTime::now();
+        if !atime_need_update(self.config.atime_mode, attr, now) {
+            return;
+        }
+        attr.atime = now;
+        self.pending_atime.insert(inode, now);
+        self.open_files.update_atime(inode, now).await;
+        self.meta_cache.update_atime(inode, now);
+
+        if self.pending_atime.len() >= ATIME_BATCH_SIZE {
+            if let Err(e) = self.flush_atime().await {
+                warn!("failed to flush atime updates: {:?}", e);
+            }
+        }
+    }
+
+    /// [touch_atime] is called after the content of the inode has been read.
+    ///
+    /// It goes by the cached attr, so that a read doesn't cost a round trip
+    /// to the backend. Without the caches, the atime refreshed on open is
+    /// left as it is.
+    pub async fn touch_atime(&self, inode: Ino) -> Result<()> {
+        if matches!(self.config.atime_mode, AccessTimeMode::Never) {
+            return Ok(());
+        }
+        let inode = self.check_root(inode);
+        if let Some(mut attr) = self.cached_attr(inode).await {
+            self.refresh_atime(inode, &mut attr).await;
+        }
+        Ok(())
+    }
+
+    /// [flush_atime] writes the buffered atime updates to the backend.
+    pub async fn flush_atime(&self) -> Result<()> {
+        let updates = self
+            .pending_atime
+            .iter()
+            .map(|e| (*e.key(), *e.value()))
+            .collect::<Vec<_>>();
+        if updates.is_empty() {
+            return Ok(());
+        }
+        debug!("flush {} atime updates", updates.len());
+
+        let backend = self.backend.clone();
+        let updates = tokio::task::spawn_blocking(move || {
+            backend.batch_update_atime(&updates).map(|_| updates)
+        })
+        .await
+        .context(TokioJoinSnafu)??;
+        // keep the entries which have been touched again during the flush.
+        for (inode, atime) in updates {
+            self.pending_atime.remove_if(&inode, |_, v| *v == atime);
+        }
+        Ok(())
+    }
+}
+
+// unix_now returns the seconds since the unix epoch.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// atime_need_update checks if the access time of the inode should be
+// refreshed according to the [AccessTimeMode].
+fn atime_need_update(mode: AccessTimeMode, attr: &InodeAttr, now: SystemTime) -> bool {
+    match mode {
+        AccessTimeMode::Never => false,
+        AccessTimeMode::Everytime => attr.atime < now,
+        AccessTimeMode::Relative => {
+            attr.atime < attr.mtime
+                || attr.atime < attr.ctime
+                || now
+                    .duration_since(attr.atime)
+                    .map_or(false, |d| d >= RELATIME_INTERVAL)
+        }
+    }
+}
+
+// Freed slices
+impl MetaEngine {
+    /// [list_freed_slices] returns at most limit slices which no one
+    /// references anymore, along with their sizes. Their objects should be
+    /// deleted, then [forget_freed_slices] drops them.
+    pub async fn list_freed_slices(&self, limit: usize) -> Result<Vec<(SliceID, usize)>> {
+        self.backend.list_delete_slices(limit)
+    }
+
+    /// [forget_freed_slices] drops the slices whose objects are deleted.
+    pub async fn forget_freed_slices(&self, slice_ids: &[SliceID]) -> Result<()> {
+        self.backend.remove_delete_slices(slice_ids)
+    }
+}
+
+// Backup
+impl MetaEngine {
+    /// [try_elect_backup] decides whether this client should back up the
+    /// metadata now. Every client calls it periodically, the one which takes
+    /// the lease of the round runs the backup, and moves the last backup time
+    /// forward by [MetaEngine::finish_backup] once the backup is uploaded.
+    /// The lease lasts for an interval, so that a client which fails halfway
+    /// leaves the round to the others.
+    pub async fn try_elect_backup(&self, interval: Duration) -> Result<bool> {
+        if self.config.read_only {
+            return Ok(false);
+        }
+        let now = unix_now();
+        let load = |counter| match self.backend.load_count(counter) {
+            Ok(v) => Ok(v),
+            Err(e) if e.is_not_found() => Ok(0),
+            Err(e) => Err(e),
+        };
+        let last = load(Counter::LastBackup)?;
+        let lease = load(Counter::BackupLease)?;
+        if now < last + interval.as_secs() || now < lease + interval.as_secs() {
+            return Ok(false);
+        }
+        self.backend
+            .compare_and_set_count(Counter::BackupLease, lease, now)
+    }
+
+    /// [finish_backup] records the time of the backup which has just been
+    /// uploaded, the next round starts an interval later.
+    pub async fn finish_backup(&self) -> Result<()> {
+        self.backend.set_count(Counter::LastBackup, unix_now())
+    }
+
+    /// [dump] writes the metadata of the volume as JSON, see [crate::dump].
+    ///
+    /// It reads the backend synchronously, call it in a blocking task.
+    pub fn dump<W: Write>(&self, w: W) -> Result<()> {
+        crate::dump::dump_backend(self.backend.as_ref(), w)
+    }
+}
+
+// Changes
+impl MetaEngine {
+    /// [subscribe_changes] returns the changes which the caches outside of
+    /// the engine haven't seen: the ones replayed from the change log, and
+    /// the ones made by this client behind the back of the kernel.
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<Change> { self.changes.subscribe() }
+
+    // record_change queues the change for the change log, the other clients
+    // learn it on their next sync.
+    fn record_change(&self, change: Change) {
+        if !self.config.change_poll_interval.is_zero() {
+            self.pending_changes.insert(change);
+        }
+    }
+
+    // record_entry_change records the change of the name, the attributes of
+    // the parent have changed as well.
+    fn record_entry_change(&self, parent: Ino, name: &[u8]) {
+        self.record_change(Change::Entry {
+            parent,
+            name: name.to_vec(),
+        });
+        self.record_change(Change::Inode(parent));
+    }
+
+    /// [notify_change] tells the subscribers of this client about a change
+    /// which doesn't go through the kernel, and records it for the others.
+    pub fn notify_change(&self, change: Change) {
+        // nobody is listening before the mount.
+        let _ = self.changes.send(change.clone());
+        self.record_change(change);
+    }
+
+    /// [sync_changes] writes the changes of this client to the change log,
+    /// and replays the ones made by the other clients since the last sync:
+    /// the stale caches are dropped and the subscribers are told about them.
+    ///
+    /// The changes are only logged while other clients follow the log.
+    pub async fn sync_changes(&self) -> Result<()> {
+        let shared = self.touch_clients()?;
+        let pending = self
+            .pending_changes
+            .iter()
+            .map(|c| c.key().clone())
+            .collect::<Vec<_>>();
+        // a change made again from now on is queued for the next sync.
+        for change in &pending {
+            self.pending_changes.remove(change);
+        }
+        if !shared {
+            // a client joining later starts with nothing cached.
+            return self.replay_changes().await;
+        }
+        let records = pending
+            .iter()
+            .map(|change| ChangeRecord {
+                client: self.client_id,
+                change: change.clone(),
+            })
+            .collect::<Vec<_>>();
+        if let Err(e) = self.backend.append_changes(&records) {
+            for change in pending {
+                self.pending_changes.insert(change);
+            }
+            return Err(e);
+        }
+        self.replay_changes().await
+    }
+
+    // replay_changes replays the changes made by the other clients since the
+    // last sync.
+    async fn replay_changes(&self) -> Result<()> {
+        let mut cursor = self.change_cursor.load(Ordering::Acquire);
+        loop {
+            let records = self.backend.load_changes(cursor, CHANGE_BATCH_SIZE)?;
+            let done = records.len() < CHANGE_BATCH_SIZE;
+            for (seq, record) in records {
+                cursor = seq;
+                if record.client == self.client_id {
+                    continue;
+                }
+                self.forget_change(&record.change).await;
+                let _ = self.changes.send(record.change);
+            }
+            if done {
+                break;
+            }
+        }
+        self.change_cursor.store(cursor, Ordering::Release);
+        if cursor > MAX_CHANGE_LOG && !self.config.read_only {
+            self.backend.trim_changes(cursor - MAX_CHANGE_LOG)?;
+        }
+        Ok(())
+    }
+
+    // touch_clients marks this client as seen, drops the clients gone
+    // silent, and tells whether any other client follows the change log. A
+    // read-only client only looks.
+    fn touch_clients(&self) -> Result<bool> {
+        let now = unix_now();
+        if !self.config.read_only {
+            self.backend.touch_client(self.client_id, now)?;
+        }
+        let ttl = (self.config.change_poll_interval * CLIENT_TTL_ROUNDS)
+            .max(CLIENT_MIN_TTL)
+            .as_secs();
+        let (alive, gone): (Vec<_>, Vec<_>) = self
+            .backend
+            .list_clients()?
+            .into_iter()
+            .filter(|(client, _)| *client != self.client_id)
+            .partition(|(_, seen)| seen + ttl >= now);
+        if !gone.is_empty() && !self.config.read_only {
+            let gone = gone
+                .into_iter()
+                .map(|(client, _)| client)
+                .collect::<Vec<_>>();
+            debug!("clients {gone:?} have gone silent, stop logging changes for them");
+            self.backend.remove_clients(&gone)?;
+        }
+        Ok(!alive.is_empty())
+    }
+
+    // forget_change drops what the engine has cached about the change.
+    async fn forget_change(&self, change: &Change) {
+        match change {
+            Change::Inode(inode) => {
+                self.meta_cache.invalid_inode(*inode);
+                self.open_files.invalid(*inode, InvalidReq::All).await;
+            }
+            Change::Entry { parent, name } => self.meta_cache.invalid_entry(*parent, name),
+        }
+    }
+}
+
 // Link
 impl MetaEngine {
     pub async fn link(
@@ -971,26 +1667,33 @@ impl MetaEngine {
         ctx: Arc<FuseContext>,
         inode: Ino,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &OsStr,
     ) -> Result<InodeAttr> {
+        let new_name = new_name.as_bytes();
         let current_attr = self.get_attr(inode).await?;
         ensure!(!current_attr.is_dir(), LibcSnafu { errno: libc::EPERM });
 
         let new_attr = self.backend.do_link(ctx, inode, new_parent, new_name)?;
 
         self.open_files.invalid(inode, InvalidReq::OnlyAttr).await;
+        self.meta_cache.put_entry(new_parent, new_name, inode);
+        self.meta_cache.put_attr(inode, &new_attr);
+        self.meta_cache.invalid_attr(new_parent);
+        self.record_change(Change::Inode(inode));
+        self.record_entry_change(new_parent, new_name);
 
         Ok(new_attr)
     }
 
-    pub async fn unlink(&self, ctx: Arc<FuseContext>, parent: Ino, name: &str) -> Result<()> {
+    pub async fn unlink(&self, ctx: Arc<FuseContext>, parent: Ino, name: &OsStr) -> Result<()> {
+        let name = name.as_bytes();
         let open_files = self.open_files.clone();
         let unlink_result = self
             .backend
             .do_unlink(
                 ctx,
                 parent,
-                name.to_string(),
+                name.to_vec(),
                 self.session_id.clone(),
                 open_files,
             )
@@ -1002,6 +1705,11 @@ impl MetaEngine {
         self.open_files
             .invalid(unlink_result.inode, InvalidReq::OnlyAttr)
             .await;
+        self.meta_cache.invalid_entry(parent, name);
+        self.meta_cache.invalid_inode(unlink_result.inode);
+        self.meta_cache.invalid_attr(parent);
+        self.record_change(Change::Inode(unlink_result.inode));
+        self.record_entry_change(parent, name);
         if let Some(_) = unlink_result.removed {
             self.delete_file(unlink_result.is_opened, unlink_result.inode)
                 .await;
@@ -1014,7 +1722,7 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        link_name: &str,
+        link_name: &OsStr,
         target: &Path,
     ) -> Result<(Ino, InodeAttr)> {
         // mode of symlink is ignored in POSIX.
@@ -1027,25 +1735,464 @@ impl MetaEngine {
                 0o777,
                 0,
                 0,
-                target.to_string_lossy().to_string(),
+                target.as_os_str().as_bytes().to_vec(),
             )
             .await?;
         Ok((inode, attr))
     }
 
     pub async fn readlink(&self, ctx: Arc<FuseContext>, inode: Ino) -> Result<Bytes> {
-        let read_guard = self.symlinks.read().await;
-        if let Some(target) = read_guard.get(&inode) {
-            return Ok(target.clone());
+        if let Some(target) = self.meta_cache.get_symlink(inode) {
+            return Ok(target);
         }
-        drop(read_guard);
         let t = self.backend.do_readlink(inode)?;
-        let mut write_guard = self.symlinks.write().await;
-        write_guard.insert(inode, t.clone());
+        self.meta_cache.put_symlink(inode, t.clone());
         Ok(t)
     }
 }
 
+// Clone
+impl MetaEngine {
+    /// [clone_entry] makes a copy of the src file or directory tree as name in
+    /// the parent. The copies borrow the slices of the src, so no data is
+    /// copied. A directory tree is built aside and shows up in the parent once
+    /// it is complete; the changes made to the src meanwhile may or may not
+    /// be in the copy.
+    pub async fn clone_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        src: Ino,
+        parent: Ino,
+        name: &OsStr,
+        preserve: bool,
+    ) -> Result<(Ino, InodeAttr)> {
+        ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
+        ensure!(
+            !name.is_empty(),
+            LibcSnafu {
+                errno: libc::ENOENT,
+            }
+        );
+        let src = self.check_root(src);
+        let parent = self.check_root(parent);
+        let name = name.as_bytes();
+        // fail before walking a large tree.
+        match self.backend.get_dentry(parent, name) {
+            Ok(_) => {
+                return LibcSnafu {
+                    errno: libc::EEXIST,
+                }
+                .fail();
+            }
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e),
+        }
+        let src_attr = self.get_attr(src).await?;
+        if src_attr.is_dir() {
+            // a directory can't be cloned into itself.
+            let mut p = parent;
+            loop {
+                ensure!(
+                    p != src,
+                    LibcSnafu {
+                        errno: libc::EINVAL,
+                    }
+                );
+                if p == ROOT_INO || p == ZERO_INO {
+                    break;
+                }
+                p = self.get_attr(p).await?.parent;
+            }
+        }
+
+        let new_inode = Ino::from(self.free_inodes.next().await?);
+        let backend = self.backend.clone();
+        let new_name = name.to_vec();
+        let (attr, cloned) = tokio::task::spawn_blocking(move || {
+            clone_tree(&backend, &ctx, src, new_inode, parent, &new_name, preserve)
+        })
+        .await
+        .context(TokioJoinSnafu)??;
+        for (dir, dir_parent) in cloned.dirs {
+            self.add_dir2parent_mapping(dir, dir_parent).await;
+        }
+
+        self.meta_cache.put_entry(parent, name, new_inode);
+        self.meta_cache.put_attr(new_inode, &attr);
+        self.meta_cache.invalid_attr(parent);
+        // the entry is made by an ioctl on the src, the kernel may still
+        // remember the name as missing.
+        self.notify_change(Change::Entry {
+            parent,
+            name: name.to_vec(),
+        });
+        self.notify_change(Change::Inode(parent));
+        self.fs_stat_file_count
+            .fetch_add(cloned.count, Ordering::AcqRel);
+        self.fs_stat_used_size
+            .fetch_add(cloned.used, Ordering::AcqRel);
+        Ok((new_inode, attr))
+    }
+}
+
+// Cloned sums up what a clone made: the count and the size of the cloned
+// inodes, and the cloned directories along with their parents.
+#[derive(Debug, Default)]
+struct Cloned {
+    count: u64,
+    used:  u64,
+    dirs:  Vec<(Ino, Ino)>,
+}
+
+// clone_tree makes a copy of the src as name in the parent. A directory tree
+// is built aside and attached once it is complete, or purged if it fails, so
+// the slices it borrowed are given back. It runs on a blocking thread, as the
+// tree may be large.
+fn clone_tree(
+    backend: &BackendRef,
+    ctx: &Arc<FuseContext>,
+    src: Ino,
+    new_inode: Ino,
+    parent: Ino,
+    name: &[u8],
+    preserve: bool,
+) -> Result<(InodeAttr, Cloned)> {
+    let src_is_dir = backend.get_attr(src)?.is_dir();
+    let attr = backend.do_clone_entry(
+        ctx.clone(),
+        src,
+        new_inode,
+        parent,
+        name,
+        !src_is_dir,
+        preserve,
+    )?;
+    let mut cloned = Cloned {
+        count: 1,
+        used:  clone_space(&attr),
+        dirs:  vec![],
+    };
+    if !attr.is_dir() {
+        return Ok((attr, cloned));
+    }
+    cloned.dirs.push((new_inode, parent));
+    let r = clone_children(backend, ctx, src, new_inode, preserve, &mut cloned)
+        .and_then(|_| backend.do_attach_entry(ctx.clone(), new_inode, parent, name));
+    match r {
+        Ok(attr) => Ok((attr, cloned)),
+        Err(e) => {
+            if let Err(purge_err) = purge_tree(backend, new_inode) {
+                error!(
+                    "failed to purge the detached clone {new_inode}: {:?}",
+                    purge_err
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+// clone_children clones the tree under the src directory into the dst
+// directory. Hard links inside the tree become separate files.
+fn clone_children(
+    backend: &BackendRef,
+    ctx: &Arc<FuseContext>,
+    src: Ino,
+    dst: Ino,
+    preserve: bool,
+    cloned: &mut Cloned,
+) -> Result<()> {
+    const BATCH: i64 = 1024;
+    let mut dirs = vec![(src, dst)];
+    while let Some((src, dst)) = dirs.pop() {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            ensure!(!ctx.is_cancelled(), LibcSnafu { errno: libc::EINTR });
+            let entries = backend.list_dentry(src, after.as_deref(), BATCH)?;
+            if entries.is_empty() {
+                break;
+            }
+            // the inodes of the page are taken at once.
+            let next = backend.increase_count_by(Counter::NextInode, entries.len())?;
+            let first = next - entries.len() as u64;
+            for (i, entry) in entries.iter().enumerate() {
+                let new_inode = Ino(first + i as u64);
+                let attr = backend.do_clone_entry(
+                    ctx.clone(),
+                    entry.inode,
+                    new_inode,
+                    dst,
+                    &entry.name,
+                    true,
+                    preserve,
+                )?;
+                if attr.is_dir() {
+                    cloned.dirs.push((new_inode, dst));
+                    dirs.push((entry.inode, new_inode));
+                }
+                cloned.count += 1;
+                cloned.used += clone_space(&attr);
+            }
+            match entries.last() {
+                Some(last) if entries.len() as i64 == BATCH => {
+                    after = Some(last.name.clone());
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+// purge_tree drops the detached tree under the root, no one else can reach
+// it.
+fn purge_tree(backend: &BackendRef, root: Ino) -> Result<()> {
+    const BATCH: i64 = 1024;
+    let mut inodes = vec![root];
+    let mut dirs = vec![root];
+    while let Some(dir) = dirs.pop() {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let entries = backend.list_dentry(dir, after.as_deref(), BATCH)?;
+            for entry in &entries {
+                inodes.push(entry.inode);
+                if entry.typ == FileType::Directory {
+                    dirs.push(entry.inode);
+                }
+            }
+            match entries.last() {
+                Some(last) if entries.len() as i64 == BATCH => {
+                    after = Some(last.name.clone());
+                }
+                _ => break,
+            }
+        }
+    }
+    for inode in inodes {
+        backend.do_purge_inode(inode)?;
+    }
+    Ok(())
+}
+
+// clone_space is the space accounted for a cloned inode, the same as creating
+// it and writing the content.
+fn clone_space(attr: &InodeAttr) -> u64 {
+    if attr.is_file() {
+        4096 + attr.length
+    } else {
+        4096
+    }
+}
+
+// Admin
+impl MetaEngine {
+    /// [remove_tree] removes the entry, and the tree under it if it is a
+    /// directory, with the permissions of the caller. The removed counts the
+    /// entries removed so far, the ones removed before a failure stay
+    /// removed. Returns how many entries are removed.
+    pub async fn remove_tree(
+        &self,
+        ctx: Arc<FuseContext>,
+        parent: Ino,
+        name: &OsStr,
+        removed: &AtomicU64,
+    ) -> Result<u64> {
+        ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
+        let parent = self.check_root(parent);
+        let entry = self.backend.get_dentry(parent, name.as_bytes())?;
+        let r = if entry.typ == FileType::Directory {
+            match self.remove_children(&ctx, entry.inode, removed).await {
+                Ok(()) => self.rmdir(ctx, parent, name).await.map(|_| ()),
+                Err(e) => Err(e),
+            }
+        } else {
+            self.unlink(ctx, parent, name).await.map(|_| ())
+        };
+        if r.is_ok() {
+            removed.fetch_add(1, Ordering::Relaxed);
+        }
+        // the tree is removed behind the back of the kernel.
+        self.notify_change(Change::Entry {
+            parent,
+            name: name.as_bytes().to_vec(),
+        });
+        self.notify_change(Change::Inode(parent));
+        r.map(|_| removed.load(Ordering::Relaxed))
+    }
+
+    // remove_children empties the directory depth first, counting the
+    // removed entries.
+    async fn remove_children(
+        &self,
+        ctx: &Arc<FuseContext>,
+        root: Ino,
+        removed: &AtomicU64,
+    ) -> Result<()> {
+        const BATCH: i64 = 1024;
+        // the directories being emptied: their parents, names and inodes.
+        let mut dirs: Vec<(Ino, Vec<u8>, Ino)> = vec![];
+        let mut current = root;
+        loop {
+            ensure!(!ctx.is_cancelled(), LibcSnafu { errno: libc::EINTR });
+            // the removed entries are gone, so the listing always starts over.
+            let entries = self.backend.list_dentry(current, None, BATCH)?;
+            if entries.is_empty() {
+                let Some((parent, name, _)) = dirs.pop() else {
+                    return Ok(());
+                };
+                self.rmdir(ctx.clone(), parent, OsStr::from_bytes(&name))
+                    .await?;
+                removed.fetch_add(1, Ordering::Relaxed);
+                current = dirs.last().map_or(root, |(_, _, inode)| *inode);
+                continue;
+            }
+            for entry in entries {
+                if entry.typ == FileType::Directory {
+                    dirs.push((current, entry.name, entry.inode));
+                    current = entry.inode;
+                    break;
+                }
+                self.unlink(ctx.clone(), current, OsStr::from_bytes(&entry.name))
+                    .await?;
+                removed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// [summary] sums up the file, or the directory and the tree under it.
+    pub async fn summary(&self, ctx: &FuseContext, inode: Ino) -> Result<Summary> {
+        let inode = self.check_root(inode);
+        let attr = self.get_attr(inode).await?;
+        let mut summary = Summary::default();
+        let mut add = |attr: &InodeAttr| {
+            if attr.is_dir() {
+                summary.dirs += 1;
+            } else {
+                summary.files += 1;
+            }
+            if attr.is_file() {
+                summary.length += attr.length;
+            }
+            summary.size += clone_space(attr);
+        };
+        add(&attr);
+        if attr.is_dir() {
+            ctx.check_access(&attr, MODE_MASK_R | MODE_MASK_X)?;
+            self.walk_tree(ctx, inode, |_, attrs| {
+                attrs.iter().flatten().for_each(&mut add);
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(summary)
+    }
+
+    /// [compact] drops the slices covered by the later writes of the file, or
+    /// of the files under the directory which the caller can write, returns
+    /// how many slices are freed. The objects of the freed slices are deleted
+    /// in the background.
+    pub async fn compact(&self, ctx: &FuseContext, inode: Ino) -> Result<usize> {
+        ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
+        let inode = self.check_root(inode);
+        let attr = self.get_attr(inode).await?;
+        ctx.check_access(&attr, MODE_MASK_W)?;
+        let mut files = vec![];
+        if attr.is_file() {
+            files.push((inode, attr.length));
+        } else if attr.is_dir() {
+            ctx.check_access(&attr, MODE_MASK_R | MODE_MASK_X)?;
+            self.walk_tree(ctx, inode, |entries, attrs| {
+                for (entry, attr) in entries.iter().zip(attrs) {
+                    if let Some(attr) = attr.as_ref().filter(|attr| {
+                        attr.is_file() && ctx.check_access(attr, MODE_MASK_W).is_ok()
+                    }) {
+                        files.push((entry.inode, attr.length));
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+        }
+
+        let mut freed = 0;
+        for (inode, length) in files {
+            ensure!(!ctx.is_cancelled(), LibcSnafu { errno: libc::EINTR });
+            let mut compacted = false;
+            for chunk_idx in 0..(length as usize).div_ceil(CHUNK_SIZE) {
+                let slices = self.backend.do_compact_chunk(inode, chunk_idx)?;
+                compacted |= !slices.is_empty();
+                freed += slices.len();
+            }
+            if compacted {
+                self.open_files.invalid(inode, InvalidReq::All).await;
+            }
+        }
+        Ok(freed)
+    }
+
+    /// [gc] deletes the chunks left behind by the removed files, as the
+    /// client crashed or failed to delete them, returns how many files are
+    /// cleaned up and how many slices are freed. Only root can run it.
+    pub async fn gc(&self, ctx: &FuseContext) -> Result<(usize, usize)> {
+        const BATCH: usize = 1024;
+        ensure!(ctx.uid == 0, LibcSnafu { errno: libc::EPERM });
+        ensure!(!self.config.read_only, LibcSnafu { errno: libc::EROFS });
+        let now = unix_now();
+        let (mut files, mut freed) = (0, 0);
+        let mut start = ZERO_INO;
+        loop {
+            let pending = self.backend.list_delete_chunks(start, BATCH)?;
+            for (inode, removed_at) in &pending {
+                // leave the recent ones to the deletion in the background.
+                if removed_at + GC_GRACE_PERIOD.as_secs() > now {
+                    continue;
+                }
+                freed += self.backend.do_delete_chunks(*inode)?.len();
+                files += 1;
+            }
+            match pending.last() {
+                Some((last, _)) if pending.len() == BATCH => start = Ino(last.0 + 1),
+                _ => break,
+            }
+        }
+        Ok((files, freed))
+    }
+
+    // walk_tree lists the tree under the directory page by page, the visit
+    // gets the entries of each page along with their attributes, None for the
+    // ones removed in between.
+    async fn walk_tree<F>(&self, ctx: &FuseContext, root: Ino, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&[DEntry], &[Option<InodeAttr>]) -> Result<()>,
+    {
+        const BATCH: i64 = 1024;
+        let mut dirs = vec![root];
+        while let Some(dir) = dirs.pop() {
+            let mut after: Option<Vec<u8>> = None;
+            loop {
+                ensure!(!ctx.is_cancelled(), LibcSnafu { errno: libc::EINTR });
+                let entries = self.backend.list_dentry(dir, after.as_deref(), BATCH)?;
+                let inodes = entries.iter().map(|e| e.inode).collect::<Vec<_>>();
+                let attrs = self.backend.batch_get_attr(&inodes)?;
+                for (entry, attr) in entries.iter().zip(&attrs) {
+                    if let Some(attr) = attr.as_ref().filter(|attr| attr.is_dir()) {
+                        ctx.check_access(attr, MODE_MASK_R | MODE_MASK_X)?;
+                        dirs.push(entry.inode);
+                    }
+                }
+                visit(&entries, &attrs)?;
+                match entries.last() {
+                    Some(last) if entries.len() as i64 == BATCH => {
+                        after = Some(last.name.clone());
+                    }
+                    _ => break,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 // Delete Helper
 impl MetaEngine {
     // delete_file may be unable to delete the file directly since the file may be
@@ -1067,7 +2214,10 @@ impl MetaEngine {
             tokio::spawn(async move {
                 // Safety: the semaphore's lifetime is binding to the MetaEngine.
                 let _permit = sem.acquire().await.unwrap();
-                backend.do_delete_chunks(inode);
+                match backend.do_delete_chunks(inode) {
+                    Ok(freed) => debug!("delete {inode}, {} slices freed", freed.len()),
+                    Err(e) => error!("failed to delete the chunks of {inode}: {:?}", e),
+                }
             });
         }
     }
@@ -1081,11 +2231,12 @@ impl MetaEngine {
         &self,
         ctx: Arc<FuseContext>,
         old_parent: Ino,
-        old_name: &str,
+        old_name: &OsStr,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &OsStr,
         flags: u32,
     ) -> Result<()> {
+        let (old_name, new_name) = (old_name.as_bytes(), new_name.as_bytes());
         let rename_flags = RenameFlags::from_bits(flags).expect("invalid rename flags");
         ensure!(
             matches!(
@@ -1112,6 +2263,18 @@ impl MetaEngine {
             )
             .await?;
 
+        self.meta_cache.invalid_entry(old_parent, old_name);
+        self.meta_cache.invalid_entry(new_parent, new_name);
+        self.meta_cache.invalid_attr(old_parent);
+        self.meta_cache.invalid_attr(new_parent);
+        self.meta_cache.invalid_attr(rename_result.inode);
+        if let Some(replaced) = rename_result.replaced {
+            self.meta_cache.invalid_inode(replaced);
+            self.record_change(Change::Inode(replaced));
+        }
+        self.record_change(Change::Inode(rename_result.inode));
+        self.record_entry_change(old_parent, old_name);
+        self.record_entry_change(new_parent, new_name);
         if let Some((inode, opened)) = rename_result.need_delete {
             self.delete_file(opened, inode).await;
         }
@@ -1144,3 +2307,648 @@ bitflags! {
         const WHITEOUT = 4;
     }
 }
+
+#[cfg(feature = "meta-rocksdb")]
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use kiseki_types::ToErrno;
+
+    use super::*;
+    use crate::test_util::{mkdir, mkfile, test_dsn, test_meta, test_meta_with};
+
+    #[test]
+    fn update_existing_format() {
+        let (_dir, dsn) = test_dsn();
+        let mut format = Format::default();
+        format.with_name("test-format");
+        update_format(&dsn, format.clone(), false).unwrap();
+
+        // mutable fields are applied without force.
+        format.max_capacity = Some(1 << 30);
+        format.trash_days = 7;
+        update_format(&dsn, format.clone(), false).unwrap();
+        let loaded = load_format(&dsn).unwrap();
+        assert_eq!(loaded.max_capacity, Some(1 << 30));
+        assert_eq!(loaded.trash_days, 7);
+        // a removed file stays in the trash for a day at least.
+        let mut invalid = format.clone();
+        invalid.trash_days = 0;
+        assert!(matches!(
+            update_format(&dsn, invalid, false),
+            Err(Error::InvalidFormat { .. })
+        ));
+        assert_eq!(load_format(&dsn).unwrap().trash_days, 7);
+
+        // changing the layout of an empty volume requires force.
+        let mut changed = format.clone();
+        changed.block_size = format.block_size / 2;
+        assert!(matches!(
+            update_format(&dsn, changed.clone(), false),
+            Err(Error::ImmutableSetting { .. })
+        ));
+        update_format(&dsn, changed.clone(), true).unwrap();
+        assert_eq!(load_format(&dsn).unwrap().block_size, changed.block_size);
+
+        // once the volume holds something, even force cannot change it, the
+        // removed files which are still open count as well.
+        let backend = open_backend(&dsn, Duration::from_millis(100)).unwrap();
+        let attr = InodeAttr::default()
+            .set_kind(FileType::RegularFile)
+            .to_owned();
+        backend.set_attr(Ino(2), &attr).unwrap();
+        drop(backend);
+        assert!(matches!(
+            update_format(&dsn, format.clone(), true),
+            Err(Error::ImmutableSetting { .. })
+        ));
+        let backend = open_backend(&dsn, Duration::from_millis(100)).unwrap();
+        backend
+            .set_dentry(ROOT_INO, b"f1", Ino(2), FileType::RegularFile)
+            .unwrap();
+        drop(backend);
+        assert!(matches!(
+            update_format(&dsn, format, true),
+            Err(Error::ImmutableSetting { .. })
+        ));
+        assert_eq!(load_format(&dsn).unwrap().block_size, changed.block_size);
+    }
+
+    #[tokio::test]
+    async fn cache_follows_local_changes() {
+        let (_dir, meta) = test_meta_with(|config| {
+            config.attr_cache_ttl = Duration::from_secs(60);
+            config.entry_cache_ttl = Duration::from_secs(60);
+            config.negative_entry_cache_ttl = Duration::from_secs(60);
+        });
+        let ctx = Arc::new(FuseContext::background());
+
+        let (f1, f2) = (OsStr::new("f1"), OsStr::new("f2"));
+
+        // remembered as missing, then created locally.
+        assert!(meta.lookup(ctx.clone(), ROOT_INO, f1, true).await.is_err());
+        let inode = mkfile(&meta, &ctx, ROOT_INO, "f1").await;
+        let (found, _) = meta.lookup(ctx.clone(), ROOT_INO, f1, true).await.unwrap();
+        assert_eq!(found, inode);
+
+        meta.rename(ctx.clone(), ROOT_INO, f1, ROOT_INO, f2, 0)
+            .await
+            .unwrap();
+        assert!(meta.lookup(ctx.clone(), ROOT_INO, f1, true).await.is_err());
+        let (found, _) = meta.lookup(ctx.clone(), ROOT_INO, f2, true).await.unwrap();
+        assert_eq!(found, inode);
+
+        // the cached attr is replaced by the local change.
+        let mut new_attr = InodeAttr::default().set_mode(0o600).to_owned();
+        meta.set_attr(&ctx, SetAttrFlags::MODE, inode, &mut new_attr)
+            .await
+            .unwrap();
+        assert_eq!(meta.get_attr(inode).await.unwrap().mode, 0o600);
+
+        meta.unlink(ctx.clone(), ROOT_INO, f2).await.unwrap();
+        assert!(meta.lookup(ctx.clone(), ROOT_INO, f2, true).await.is_err());
+        assert!(meta.get_attr(inode).await.is_err());
+    }
+
+    #[test]
+    fn atime_modes() {
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(60 * 60);
+        let mut attr = InodeAttr::default();
+        attr.set_atime(now - hour)
+            .set_mtime(now - 2 * hour)
+            .set_ctime(now - 2 * hour);
+        let need_update = |mode, attr: &InodeAttr| atime_need_update(mode, attr, now);
+
+        // read an hour ago, and untouched since.
+        assert!(!need_update(AccessTimeMode::Never, &attr));
+        assert!(need_update(AccessTimeMode::Everytime, &attr));
+        assert!(!need_update(AccessTimeMode::Relative, &attr));
+        // modified or changed after the last read.
+        attr.set_mtime(now - hour / 2);
+        assert!(need_update(AccessTimeMode::Relative, &attr));
+        attr.set_mtime(now - 2 * hour).set_ctime(now - hour / 2);
+        assert!(need_update(AccessTimeMode::Relative, &attr));
+        // read long ago.
+        attr.set_atime(now - RELATIME_INTERVAL - hour)
+            .set_ctime(now - RELATIME_INTERVAL - 2 * hour);
+        assert!(need_update(AccessTimeMode::Relative, &attr));
+        assert!(!need_update(AccessTimeMode::Never, &attr));
+        // read just now.
+        attr.set_atime(now);
+        assert!(!need_update(AccessTimeMode::Everytime, &attr));
+    }
+
+    #[tokio::test]
+    async fn flush_buffered_atime() {
+        let (_dir, meta) = test_meta_with(|config| {
+            config.atime_mode = AccessTimeMode::Everytime;
+            config.attr_cache_ttl = Duration::from_secs(60);
+        });
+        let ctx = Arc::new(FuseContext::background());
+
+        let inode = mkfile(&meta, &ctx, ROOT_INO, "f").await;
+        let read_at = SystemTime::now() - Duration::from_secs(60 * 60);
+        let mut new_attr = InodeAttr::default().set_atime(read_at).to_owned();
+        meta.set_attr(&ctx, SetAttrFlags::ATIME, inode, &mut new_attr)
+            .await
+            .unwrap();
+
+        // the read is seen at once, but only buffered.
+        meta.touch_atime(inode).await.unwrap();
+        let atime = meta.get_attr(inode).await.unwrap().atime;
+        assert!(atime > read_at);
+        assert_eq!(meta.backend.get_attr(inode).unwrap().atime, read_at);
+
+        meta.flush_atime().await.unwrap();
+        assert!(meta.pending_atime.is_empty());
+        assert_eq!(meta.backend.get_attr(inode).unwrap().atime, atime);
+    }
+
+    #[tokio::test]
+    async fn elect_backup_client() {
+        let (_dir, meta) = test_meta();
+        let interval = Duration::from_secs(60 * 60);
+
+        // one client takes the round, the others wait for it.
+        assert!(meta.try_elect_backup(interval).await.unwrap());
+        assert!(!meta.try_elect_backup(interval).await.unwrap());
+        // the backup is recorded only once it has been uploaded.
+        assert_eq!(meta.backend.load_count(Counter::LastBackup).unwrap_or(0), 0);
+
+        // the lease of a client which failed halfway expires.
+        let expired = unix_now() - interval.as_secs();
+        meta.backend
+            .set_count(Counter::BackupLease, expired)
+            .unwrap();
+        assert!(meta.try_elect_backup(interval).await.unwrap());
+        meta.finish_backup().await.unwrap();
+        meta.backend
+            .set_count(Counter::BackupLease, expired)
+            .unwrap();
+        assert!(!meta.try_elect_backup(interval).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn read_dir_in_pages() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+
+        let parent = mkdir(&meta, &ctx, ROOT_INO, "d").await;
+        let mut inodes = vec![];
+        for i in 0..5 {
+            inodes.push(mkfile(&meta, &ctx, parent, &format!("f{i}")).await);
+        }
+        let list = |plus: bool| {
+            let (meta, ctx) = (meta.clone(), ctx.clone());
+            async move {
+                let (mut pages, mut after) = (vec![], None::<OsString>);
+                loop {
+                    let page = meta
+                        .read_dir(&ctx, parent, plus, after.as_deref(), 2)
+                        .await
+                        .unwrap();
+                    after = page.cursor().map(OsStr::to_os_string);
+                    let names = page
+                        .entries
+                        .iter()
+                        .map(|e| e.get_name().to_string_lossy().to_string())
+                        .collect::<Vec<_>>();
+                    pages.push(names);
+                    if page.eof {
+                        return pages;
+                    }
+                }
+            }
+        };
+
+        // the directory is larger than a page.
+        assert_eq!(
+            list(false).await,
+            vec![vec![".", "..", "f0", "f1"], vec!["f2", "f3"], vec!["f4"]]
+        );
+
+        // f2 and f3 are removed after they are listed, but before their
+        // attributes are fetched, the page left empty doesn't end the listing.
+        meta.backend.do_purge_inode(inodes[2]).unwrap();
+        meta.backend.do_purge_inode(inodes[3]).unwrap();
+        assert_eq!(
+            list(true).await,
+            vec![vec![".", "..", "f0", "f1"], vec![], vec!["f4"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn clone_directory_tree() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+
+        let d = mkdir(&meta, &ctx, ROOT_INO, "d").await;
+        let sub = mkdir(&meta, &ctx, d, "sub").await;
+        let (f, _) = meta
+            .create(ctx.clone(), sub, OsStr::new("f"), 0o644, 0, 0)
+            .await
+            .unwrap();
+        meta.symlink(ctx.clone(), d, OsStr::new("l"), Path::new("sub/f"))
+            .await
+            .unwrap();
+
+        let (e, attr) = meta
+            .clone_entry(ctx.clone(), d, ROOT_INO, OsStr::new("e"), false)
+            .await
+            .unwrap();
+        assert_ne!(e, d);
+        assert_eq!(attr.nlink, 3);
+        let (found, _) = meta
+            .lookup(ctx.clone(), ROOT_INO, OsStr::new("e"), true)
+            .await
+            .unwrap();
+        assert_eq!(found, e);
+        let (sub2, _) = meta
+            .lookup(ctx.clone(), e, OsStr::new("sub"), true)
+            .await
+            .unwrap();
+        let (f2, _) = meta
+            .lookup(ctx.clone(), sub2, OsStr::new("f"), true)
+            .await
+            .unwrap();
+        assert_ne!(f2, f);
+        let (l2, _) = meta
+            .lookup(ctx.clone(), e, OsStr::new("l"), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            meta.readlink(ctx.clone(), l2).await.unwrap().as_ref(),
+            b"sub/f"
+        );
+
+        // the name is taken, and a tree can't be cloned into itself.
+        assert!(
+            meta.clone_entry(ctx.clone(), f, ROOT_INO, OsStr::new("e"), false)
+                .await
+                .is_err()
+        );
+        assert!(
+            meta.clone_entry(ctx.clone(), d, sub, OsStr::new("d"), false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_clone_is_purged() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+
+        let d = mkdir(&meta, &ctx, ROOT_INO, "d").await;
+        let (a, _) = meta
+            .create(ctx.clone(), d, OsStr::new("a"), 0o644, 0, 0)
+            .await
+            .unwrap();
+        meta.backend
+            .set_raw_chunk_slices(a, 0, Slice::new_owned(0, 10, 4096).encode())
+            .unwrap();
+        // b can't be read by the caller, the clone fails after copying a.
+        let (b, _) = meta
+            .create(ctx.clone(), d, OsStr::new("b"), 0o000, 0, 0)
+            .await
+            .unwrap();
+
+        let r = meta
+            .clone_entry(ctx.clone(), d, ROOT_INO, OsStr::new("e"), false)
+            .await;
+        assert!(matches!(r, Err(LibcError { errno, .. }) if errno == libc::EACCES));
+        assert!(meta.backend.get_dentry(ROOT_INO, b"e").is_err());
+        // the copies are gone, along with their references to the slices.
+        assert_eq!(meta.backend.get_slice_ref(10).unwrap(), 0);
+        assert!(meta.backend.list_delete_slices(10).unwrap().is_empty());
+        let left = meta
+            .backend
+            .scan_raw(&key::inode_keys_prefix(Ino(b.0 + 1)), 1)
+            .unwrap();
+        assert!(
+            left.first()
+                .map_or(true, |(k, _)| *k >= key::sustained(0, ZERO_INO))
+        );
+    }
+
+    #[tokio::test]
+    async fn immutable_flag_protects_inode() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+        let root = FuseContext {
+            uid: 0,
+            ..FuseContext::background()
+        };
+
+        let (f, g) = (OsStr::new("f"), OsStr::new("g"));
+        let inode = mkfile(&meta, &ctx, ROOT_INO, "f").await;
+        let immutable = InodeAttr::default()
+            .set_flags(kiseki_types::attr::Flags::IMMUTABLE.bits() as u32)
+            .to_owned();
+        // only the root is allowed to set it, even the owner isn't.
+        let err = meta
+            .set_attr(&ctx, SetAttrFlags::FLAG, inode, &mut immutable.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        meta.set_attr(&root, SetAttrFlags::FLAG, inode, &mut immutable.clone())
+            .await
+            .unwrap();
+
+        let mut new_attr = InodeAttr::default().set_mode(0o600).to_owned();
+        let err = meta
+            .set_attr(&root, SetAttrFlags::MODE, inode, &mut new_attr)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta
+            .link(ctx.clone(), inode, ROOT_INO, g)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta
+            .rename(ctx.clone(), ROOT_INO, f, ROOT_INO, g, 0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta.unlink(ctx.clone(), ROOT_INO, f).await.unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta
+            .truncate(ctx.clone(), inode, 10, false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        for flags in [libc::O_WRONLY, libc::O_RDWR | libc::O_APPEND] {
+            let err = meta.open_inode(&ctx, inode, flags).await.unwrap_err();
+            assert_eq!(err.to_errno(), libc::EPERM);
+        }
+        meta.open_inode(&ctx, inode, libc::O_RDONLY).await.unwrap();
+
+        // once the root clears it, the file can go.
+        meta.set_attr(&root, SetAttrFlags::FLAG, inode, &mut InodeAttr::default())
+            .await
+            .unwrap();
+        meta.unlink(ctx.clone(), ROOT_INO, f).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn append_flag_protects_data() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+        let root = FuseContext {
+            uid: 0,
+            ..FuseContext::background()
+        };
+
+        let (f, g) = (OsStr::new("f"), OsStr::new("g"));
+        let inode = mkfile(&meta, &ctx, ROOT_INO, "f").await;
+        let append = InodeAttr::default()
+            .set_flags(kiseki_types::attr::Flags::APPEND.bits() as u32)
+            .to_owned();
+        meta.set_attr(&root, SetAttrFlags::FLAG, inode, &mut append.clone())
+            .await
+            .unwrap();
+
+        // the file can only grow by appending.
+        meta.open_inode(&ctx, inode, libc::O_WRONLY | libc::O_APPEND)
+            .await
+            .unwrap();
+        for flags in [
+            libc::O_WRONLY,
+            libc::O_RDWR | libc::O_APPEND | libc::O_TRUNC,
+        ] {
+            let err = meta.open_inode(&ctx, inode, flags).await.unwrap_err();
+            assert_eq!(err.to_errno(), libc::EPERM);
+        }
+        let err = meta
+            .truncate(ctx.clone(), inode, 10, false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let mut new_attr = InodeAttr::default().set_mode(0o600).to_owned();
+        let err = meta
+            .set_attr(&root, SetAttrFlags::MODE, inode, &mut new_attr)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta
+            .link(ctx.clone(), inode, ROOT_INO, g)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = meta.unlink(ctx.clone(), ROOT_INO, f).await.unwrap_err();
+        assert_eq!(err.to_errno(), libc::EPERM);
+    }
+
+    #[tokio::test]
+    async fn set_id_and_sticky_bits() {
+        let (_dir, meta) = test_meta();
+        let owner = Arc::new(FuseContext::background());
+        let alice = Arc::new(FuseContext {
+            uid: 4,
+            gid: 4,
+            gid_list: vec![4],
+            ..FuseContext::background()
+        });
+        let bob = Arc::new(FuseContext {
+            uid: 5,
+            gid: 5,
+            gid_list: vec![5],
+            ..FuseContext::background()
+        });
+        let root = FuseContext {
+            uid: 0,
+            ..FuseContext::background()
+        };
+        let file = |ctx: Arc<FuseContext>, parent: Ino, name: &'static str, mode: u32| {
+            let meta = meta.clone();
+            async move {
+                meta.mknod(
+                    ctx,
+                    parent,
+                    OsStr::new(name),
+                    FileType::RegularFile,
+                    mode,
+                    0,
+                    0,
+                    Vec::new(),
+                )
+                .await
+            }
+        };
+
+        // mkdir/00.t: the entries of a set-group-ID directory belong to its
+        // group, the subdirectories inherit the bit.
+        let (shared, _) = meta
+            .mkdir(owner.clone(), ROOT_INO, OsStr::new("shared"), 0o2777, 0)
+            .await
+            .unwrap();
+        let (_, attr) = meta
+            .mkdir(alice.clone(), shared, OsStr::new("sub"), 0o755, 0)
+            .await
+            .unwrap();
+        assert_eq!((attr.gid, attr.mode), (owner.gid, 0o2755));
+        // open/00.t: alice isn't in the group, she can't create an
+        // executable running as it.
+        let (_, attr) = file(alice.clone(), shared, "exe", 0o2755).await.unwrap();
+        assert_eq!((attr.gid, attr.mode), (owner.gid, 0o755));
+
+        // chmod/12.t: writing drops the set-id bits, unless the root writes.
+        let (exe, _) = file(owner.clone(), ROOT_INO, "suid", 0o6755).await.unwrap();
+        assert!(meta.kill_suid(&root, exe).await.unwrap().is_none());
+        let attr = meta.kill_suid(&owner, exe).await.unwrap().unwrap();
+        assert_eq!(attr.mode, 0o755);
+        assert_eq!(meta.get_attr(exe).await.unwrap().mode, 0o755);
+        assert!(meta.kill_suid(&owner, exe).await.unwrap().is_none());
+        // the set-group-ID bit of a file which is not group-executable stays.
+        let (lock, _) = file(owner.clone(), ROOT_INO, "lock", 0o2644).await.unwrap();
+        assert!(meta.kill_suid(&owner, lock).await.unwrap().is_none());
+
+        // chown/00.t: chown drops them too, even by the root.
+        let mut new_attr = InodeAttr::default().set_mode(0o6755).to_owned();
+        meta.set_attr(&owner, SetAttrFlags::MODE, exe, &mut new_attr)
+            .await
+            .unwrap();
+        let mut new_attr = InodeAttr::default().set_uid(alice.uid).to_owned();
+        meta.set_attr(&root, SetAttrFlags::UID, exe, &mut new_attr)
+            .await
+            .unwrap();
+        assert_eq!(meta.get_attr(exe).await.unwrap().mode, 0o755);
+        let mut new_attr = InodeAttr::default().set_gid(3).to_owned();
+        meta.set_attr(&owner, SetAttrFlags::GID, lock, &mut new_attr)
+            .await
+            .unwrap();
+        assert_eq!(meta.get_attr(lock).await.unwrap().mode, 0o2644);
+        // chmod/05.t: the bit is silently dropped if the owner isn't in the
+        // group of the file.
+        let (plain, _) = file(alice.clone(), ROOT_INO, "plain", 0o644).await.unwrap();
+        let mut new_attr = InodeAttr::default().set_gid(2).to_owned();
+        meta.set_attr(&root, SetAttrFlags::GID, plain, &mut new_attr)
+            .await
+            .unwrap();
+        let mut new_attr = InodeAttr::default().set_mode(0o2644).to_owned();
+        meta.set_attr(&alice, SetAttrFlags::MODE, plain, &mut new_attr)
+            .await
+            .unwrap();
+        assert_eq!(meta.get_attr(plain).await.unwrap().mode, 0o644);
+
+        // unlink/11.t and rename/09.t: only the owner of the entry or of the
+        // sticky directory can remove or rename the entry.
+        let (tmp, _) = meta
+            .mkdir(owner.clone(), ROOT_INO, OsStr::new("tmp"), 0o1777, 0)
+            .await
+            .unwrap();
+        let (a, b) = (OsStr::new("a"), OsStr::new("b"));
+        file(alice.clone(), tmp, "a", 0o644).await.unwrap();
+        file(bob.clone(), tmp, "b", 0o644).await.unwrap();
+        let is_eperm =
+            |r: Result<()>| matches!(r, Err(LibcError { errno, .. }) if errno == libc::EPERM);
+        assert!(is_eperm(meta.unlink(bob.clone(), tmp, a).await));
+        assert!(is_eperm(
+            meta.rename(bob.clone(), tmp, a, tmp, OsStr::new("c"), 0)
+                .await
+        ));
+        // bob can't replace alice's file with his own either.
+        assert!(is_eperm(meta.rename(bob.clone(), tmp, b, tmp, a, 0).await));
+        meta.rename(alice.clone(), tmp, a, tmp, OsStr::new("c"), 0)
+            .await
+            .unwrap();
+        meta.unlink(owner.clone(), tmp, b).await.unwrap();
+        meta.mkdir(alice.clone(), tmp, OsStr::new("d"), 0o777, 0)
+            .await
+            .unwrap();
+        assert!(is_eperm(
+            meta.rmdir(bob.clone(), tmp, OsStr::new("d")).await
+        ));
+        meta.rmdir(alice.clone(), tmp, OsStr::new("d"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_only_client_writes_nothing() {
+        let (_dir, meta) = test_meta_with(|config| config.read_only = true);
+
+        // it never shows up as a client, so nobody logs changes for it.
+        assert!(meta.backend.list_clients().unwrap().is_empty());
+        meta.sync_changes().await.unwrap();
+        assert!(meta.backend.list_clients().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replay_changes_of_other_clients() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+        let mut changes = meta.subscribe_changes();
+
+        let inode = mkfile(&meta, &ctx, ROOT_INO, "f").await;
+        meta.get_attr(inode).await.unwrap();
+        assert!(meta.meta_cache.get_attr(inode).is_some());
+        // no one else follows the log, the changes aren't written.
+        meta.sync_changes().await.unwrap();
+        assert!(meta.backend.load_changes(0, 10).unwrap().is_empty());
+
+        // another client joins and changes the file.
+        meta.backend
+            .touch_client(meta.client_id + 1, unix_now())
+            .unwrap();
+        meta.record_change(Change::Inode(inode));
+        let other = ChangeRecord {
+            client: meta.client_id + 1,
+            change: Change::Inode(inode),
+        };
+        meta.backend.append_changes(&[other]).unwrap();
+        meta.sync_changes().await.unwrap();
+        // our own changes are written to the log but never replayed.
+        assert!(meta.backend.load_changes(0, 10).unwrap().len() > 1);
+        assert_eq!(changes.try_recv().unwrap(), Change::Inode(inode));
+        assert!(changes.try_recv().is_err());
+        assert!(meta.meta_cache.get_attr(inode).is_none());
+
+        // the changes which bypass the kernel go to the subscribers at once.
+        let entry = Change::Entry {
+            parent: ROOT_INO,
+            name:   b"g".to_vec(),
+        };
+        meta.notify_change(entry.clone());
+        assert_eq!(changes.try_recv().unwrap(), entry);
+    }
+
+    #[tokio::test]
+    async fn summarize_and_remove_tree() {
+        let (_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+
+        let top = mkdir(&meta, &ctx, ROOT_INO, "d").await;
+        let sub = mkdir(&meta, &ctx, top, "sub").await;
+        mkfile(&meta, &ctx, top, "a").await;
+        mkfile(&meta, &ctx, sub, "b").await;
+        mkfile(&meta, &ctx, sub, "c").await;
+        let summary = meta.summary(&ctx, top).await.unwrap();
+        assert_eq!((summary.dirs, summary.files, summary.length), (2, 3, 0));
+        assert_eq!(summary.size, 5 * 4096);
+
+        let mut changes = meta.subscribe_changes();
+        let progress = AtomicU64::new(0);
+        let removed = meta
+            .remove_tree(ctx.clone(), ROOT_INO, OsStr::new("d"), &progress)
+            .await
+            .unwrap();
+        assert_eq!(removed, 5);
+        assert_eq!(progress.load(Ordering::Relaxed), 5);
+        assert!(meta.backend.get_dentry(ROOT_INO, b"d").is_err());
+        assert!(meta.backend.list_dentry(sub, None, -1).unwrap().is_empty());
+        // the kernel is told the entry is gone.
+        assert_eq!(
+            changes.try_recv().unwrap(),
+            Change::Entry {
+                parent: ROOT_INO,
+                name:   b"d".to_vec(),
+            }
+        );
+        let summary = meta.summary(&ctx, ROOT_INO).await.unwrap();
+        assert_eq!((summary.dirs, summary.files), (1, 0));
+    }
+}
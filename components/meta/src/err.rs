@@ -46,6 +46,56 @@ pub enum Error {
         location: Location,
     },
 
+    #[snafu(display(
+        "FileSystem {} has been initialized already. Location: {}",
+        name,
+        location
+    ))]
+    InitializedEngine {
+        #[snafu(implicit)]
+        location: Location,
+        name:     String,
+    },
+
+    #[snafu(display(
+        "FileSystem has schema version {}, but {} is expected, try `kiseki upgrade`. Location: {}",
+        found,
+        expected,
+        location
+    ))]
+    SchemaVersionMismatch {
+        #[snafu(implicit)]
+        location: Location,
+        found:    u32,
+        expected: u32,
+    },
+
+    #[snafu(display(
+        "Cannot change {} of the FileSystem, {}. Location: {}",
+        field,
+        reason,
+        location
+    ))]
+    ImmutableSetting {
+        #[snafu(implicit)]
+        location: Location,
+        field:    String,
+        reason:   String,
+    },
+
+    #[snafu(display(
+        "Invalid {} of the FileSystem, {}. Location: {}",
+        field,
+        reason,
+        location
+    ))]
+    InvalidFormat {
+        #[snafu(implicit)]
+        location: Location,
+        field:    String,
+        reason:   String,
+    },
+
     #[snafu(display("Invalid setting: {:?}, {:?}", String::from_utf8_lossy(key.as_slice()).to_string(), location))]
     InvalidSetting {
         #[snafu(implicit)]
@@ -58,12 +108,37 @@ pub enum Error {
         location: Location,
         errno:    libc::c_int,
     },
+
+    // Dump & Load
+    #[snafu(display(
+        "Failed to encode or decode the metadata dump: {}, {:?}",
+        source,
+        location
+    ))]
+    JsonError {
+        #[snafu(implicit)]
+        location: Location,
+        source:   serde_json::Error,
+    },
+    IOError {
+        #[snafu(implicit)]
+        location: Location,
+        source:   std::io::Error,
+    },
 }
 
 impl Error {
     pub fn is_not_found(&self) -> bool {
         matches!(self, Error::ModelError { source, .. } if source.is_not_found())
     }
+
+    /// [is_conflict] tells if the transaction fails to commit since another
+    /// writer has changed the keys it read for update.
+    #[cfg(feature = "meta-rocksdb")]
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, Error::RocksdbError { source, .. }
+            if matches!(source.kind(), rocksdb::ErrorKind::Busy | rocksdb::ErrorKind::TryAgain))
+    }
 }
 
 pub mod model_err {
@@ -81,6 +156,10 @@ pub mod model_err {
         HardLinkCount,
         Sustained,
         DeleteInode,
+        SliceRef,
+        DeleteSlice,
+        Change,
+        Client,
     }
 
     #[derive(Debug, Snafu)]
@@ -123,8 +202,14 @@ impl ToErrno for Error {
                 }
             }
             Error::UninitializedEngine { .. } => libc::EINTR,
+            Error::InitializedEngine { .. } => libc::EEXIST,
+            Error::SchemaVersionMismatch { .. } => libc::EINVAL,
+            Error::ImmutableSetting { .. } => libc::EPERM,
+            Error::InvalidFormat { .. } => libc::EINVAL,
             Error::InvalidSetting { .. } => libc::EINTR,
             Error::LibcError { errno, .. } => *errno,
+            Error::JsonError { .. } => libc::EIO,
+            Error::IOError { .. } => libc::EIO,
         }
     }
 }
@@ -1,10 +1,22 @@
 pub mod backend;
+mod changes;
+pub use changes::Change;
 mod config;
-pub use config::MetaConfig;
+pub use config::{AccessTimeMode, MetaConfig};
 pub mod context;
+mod dump;
+pub use dump::{dump, load, DumpedMeta};
 mod engine;
-pub use engine::{open, update_format, MetaEngineRef};
+pub use engine::{load_format, open, update_format, MetaEngineRef};
 mod err;
 pub use err::Error;
 mod id_table;
+mod meta_cache;
 mod open_files;
+mod stats;
+pub use stats::MetaCounters;
+#[cfg(feature = "meta-rocksdb")]
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod upgrade;
+pub use upgrade::upgrade;
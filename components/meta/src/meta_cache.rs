@@ -0,0 +1,223 @@
+use std::{
+    hash::Hash,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use kiseki_types::{attr::InodeAttr, ino::Ino};
+
+use crate::config::MetaConfig;
+
+/// [MetaCache] keeps the recently used attributes, directory entries and
+/// symlink targets on the client, so the hot paths like lookup and getattr
+/// don't have to go to the backend every time.
+///
+/// The local mutations update or drop the affected items, the changes made by
+/// other clients are picked up once the items expire, so the staleness is
+/// bounded by the TTLs. A zero TTL disables that kind of item.
+pub(crate) struct MetaCache {
+    attr_ttl:     Duration,
+    entry_ttl:    Duration,
+    negative_ttl: Duration,
+    symlink_ttl:  Duration,
+    // max number of items of each kind, 0 means unlimited.
+    capacity:     usize,
+
+    attrs:    DashMap<Ino, Cached<InodeAttr>>,
+    // None for a name which doesn't exist.
+    entries:  DashMap<(Ino, Vec<u8>), Cached<Option<Ino>>>,
+    symlinks: DashMap<Ino, Cached<Bytes>>,
+}
+
+struct Cached<V> {
+    value:     V,
+    expire_at: Instant,
+}
+
+impl MetaCache {
+    pub(crate) fn new(config: &MetaConfig) -> Self {
+        Self {
+            attr_ttl:     config.attr_cache_ttl,
+            entry_ttl:    config.entry_cache_ttl,
+            negative_ttl: config.negative_entry_cache_ttl,
+            symlink_ttl:  config.symlink_cache_ttl,
+            capacity:     config.meta_cache_limit,
+            attrs:        Default::default(),
+            entries:      Default::default(),
+            symlinks:     Default::default(),
+        }
+    }
+
+    pub(crate) fn get_attr(&self, inode: Ino) -> Option<InodeAttr> { lookup(&self.attrs, &inode) }
+
+    pub(crate) fn put_attr(&self, inode: Ino, attr: &InodeAttr) {
+        insert(
+            &self.attrs,
+            self.capacity,
+            inode,
+            attr.clone(),
+            self.attr_ttl,
+        );
+    }
+
+    /// [update_atime] moves the access time of the cached attr forward.
+    pub(crate) fn update_atime(&self, inode: Ino, atime: SystemTime) {
+        if let Some(mut cached) = self.attrs.get_mut(&inode) {
+            if cached.value.atime < atime {
+                cached.value.atime = atime;
+            }
+        }
+    }
+
+    pub(crate) fn invalid_attr(&self, inode: Ino) { self.attrs.remove(&inode); }
+
+    /// [get_entry] returns Some(None) if the name is known to be missing.
+    pub(crate) fn get_entry(&self, parent: Ino, name: &[u8]) -> Option<Option<Ino>> {
+        lookup(&self.entries, &(parent, name.to_vec()))
+    }
+
+    pub(crate) fn put_entry(&self, parent: Ino, name: &[u8], inode: Ino) {
+        insert(
+            &self.entries,
+            self.capacity,
+            (parent, name.to_vec()),
+            Some(inode),
+            self.entry_ttl,
+        );
+    }
+
+    pub(crate) fn put_negative_entry(&self, parent: Ino, name: &[u8]) {
+        insert(
+            &self.entries,
+            self.capacity,
+            (parent, name.to_vec()),
+            None,
+            self.negative_ttl,
+        );
+    }
+
+    pub(crate) fn invalid_entry(&self, parent: Ino, name: &[u8]) {
+        self.entries.remove(&(parent, name.to_vec()));
+    }
+
+    pub(crate) fn get_symlink(&self, inode: Ino) -> Option<Bytes> { lookup(&self.symlinks, &inode) }
+
+    pub(crate) fn put_symlink(&self, inode: Ino, target: Bytes) {
+        insert(
+            &self.symlinks,
+            self.capacity,
+            inode,
+            target,
+            self.symlink_ttl,
+        );
+    }
+
+    /// [invalid_inode] drops everything cached about the inode itself.
+    pub(crate) fn invalid_inode(&self, inode: Ino) {
+        self.attrs.remove(&inode);
+        self.symlinks.remove(&inode);
+    }
+}
+
+fn lookup<K: Eq + Hash, V: Clone>(map: &DashMap<K, Cached<V>>, key: &K) -> Option<V> {
+    let cached = map.get(key)?;
+    if cached.expire_at > Instant::now() {
+        return Some(cached.value.clone());
+    }
+    drop(cached);
+    map.remove_if(key, |_, v| v.expire_at <= Instant::now());
+    None
+}
+
+fn insert<K: Eq + Hash + Clone, V>(
+    map: &DashMap<K, Cached<V>>,
+    capacity: usize,
+    key: K,
+    value: V,
+    ttl: Duration,
+) {
+    if ttl.is_zero() {
+        return;
+    }
+    if capacity > 0 && map.len() >= capacity && !map.contains_key(&key) {
+        evict(map, capacity);
+    }
+    map.insert(
+        key,
+        Cached {
+            value,
+            expire_at: Instant::now() + ttl,
+        },
+    );
+}
+
+/// [evict] drops the expired items, and then the ones expiring first until a
+/// quarter of the room is free, so it doesn't run on every insertion.
+fn evict<K: Eq + Hash + Clone, V>(map: &DashMap<K, Cached<V>>, capacity: usize) {
+    let now = Instant::now();
+    map.retain(|_, v| v.expire_at > now);
+    let target = capacity - capacity / 4;
+    if map.len() < target {
+        return;
+    }
+    let mut items = map
+        .iter()
+        .map(|e| (e.expire_at, e.key().clone()))
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(expire_at, _)| *expire_at);
+    let count = map.len() - target + 1;
+    for (_, key) in items.into_iter().take(count) {
+        map.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_cache(ttl: Duration, capacity: usize) -> MetaCache {
+        let mut config = MetaConfig::default();
+        config.attr_cache_ttl = ttl;
+        config.entry_cache_ttl = ttl;
+        config.negative_entry_cache_ttl = ttl;
+        config.symlink_cache_ttl = ttl;
+        config.meta_cache_limit = capacity;
+        MetaCache::new(&config)
+    }
+
+    #[test]
+    fn expire_and_invalid() {
+        let cache = new_cache(Duration::from_millis(50), 0);
+        cache.put_attr(Ino(2), &InodeAttr::default());
+        cache.put_entry(Ino(1), b"a", Ino(2));
+        cache.put_negative_entry(Ino(1), b"b");
+        assert!(cache.get_attr(Ino(2)).is_some());
+        assert_eq!(cache.get_entry(Ino(1), b"a"), Some(Some(Ino(2))));
+        assert_eq!(cache.get_entry(Ino(1), b"b"), Some(None));
+        assert_eq!(cache.get_entry(Ino(1), b"c"), None);
+
+        cache.invalid_entry(Ino(1), b"a");
+        assert_eq!(cache.get_entry(Ino(1), b"a"), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.get_attr(Ino(2)).is_none());
+        assert_eq!(cache.get_entry(Ino(1), b"b"), None);
+
+        // a zero ttl disables the cache.
+        let cache = new_cache(Duration::ZERO, 0);
+        cache.put_attr(Ino(2), &InodeAttr::default());
+        assert!(cache.get_attr(Ino(2)).is_none());
+    }
+
+    #[test]
+    fn bounded() {
+        let cache = new_cache(Duration::from_secs(60), 8);
+        for i in 0..100 {
+            cache.put_attr(Ino(i), &InodeAttr::default());
+            assert!(cache.attrs.len() <= 8);
+        }
+        // the latest one is always kept.
+        assert!(cache.get_attr(Ino(99)).is_some());
+    }
+}
@@ -189,6 +189,19 @@ impl OpenFiles {
         }
     }
 
+    /// [update_atime] moves the access time of the cached [InodeAttr]
+    /// forward.
+    pub(crate) async fn update_atime(&self, ino: Ino, atime: SystemTime) {
+        let read_guard = self.files.read().await;
+        if let Some(of) = read_guard.get(&ino).map(|of| of.clone()) {
+            drop(read_guard);
+            let mut write_guard = of.0.write().await;
+            if write_guard.attr.atime < atime {
+                write_guard.attr.atime = atime;
+            }
+        }
+    }
+
     pub(crate) async fn refresh_slices(&self, ino: Ino, chunk_idx: ChunkIndex, views: Arc<Slices>) {
         let read_guard = self.files.read().await;
         if let Some(of) = read_guard.get(&ino) {
@@ -216,6 +229,17 @@ impl OpenFiles {
         }
     }
 
+    /// [forget] drops the [OpenFile] once nobody opens it, it is called
+    /// after the kernel has forgotten the inode.
+    pub(crate) async fn forget(&self, inode: Ino) {
+        let mut write_guard = self.files.write().await;
+        if let Some(of) = write_guard.get(&inode) {
+            if !of.is_opened().await {
+                write_guard.remove(&inode);
+            }
+        }
+    }
+
     /// [close] a file, under the hood, it just decreases the reference count.
     pub(crate) async fn close(&self, ino: Ino) -> bool {
         let read_guard = self.files.read().await;
@@ -0,0 +1,259 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use bytes::Bytes;
+use kiseki_common::ChunkIndex;
+use kiseki_types::{
+    attr::InodeAttr,
+    entry::DEntry,
+    ino::Ino,
+    setting::Format,
+    slice::{Slice, SliceID, Slices},
+    stat::DirStat,
+    FileType,
+};
+
+use crate::{
+    backend::{
+        key::Counter, Backend, BackendRef, CopyResult, LoadBatch, RenameResult, Snapshot,
+        UnlinkResult, WriteResult,
+    },
+    changes::ChangeRecord,
+    context::FuseContext,
+    engine::RenameFlags,
+    err::Result,
+    open_files::OpenFilesRef,
+};
+
+/// [MetaStats] counts the operations on the meta backend and how long they
+/// take, the ones served by the caches of the engine don't count.
+#[derive(Debug, Default)]
+pub(crate) struct MetaStats {
+    calls:      AtomicU64,
+    latency_us: AtomicU64,
+}
+
+impl MetaStats {
+    /// [track] counts a call, which ends once the guard is dropped.
+    pub(crate) fn track(&self) -> CallGuard<'_> {
+        CallGuard {
+            stats:    self,
+            start_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn load(&self) -> MetaCounters {
+        MetaCounters {
+            calls:      self.calls.load(Ordering::Relaxed),
+            latency_us: self.latency_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) struct CallGuard<'a> {
+    stats:    &'a MetaStats,
+    start_at: Instant,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        let latency = self.start_at.elapsed().as_micros() as u64;
+        self.stats.calls.fetch_add(1, Ordering::Relaxed);
+        self.stats.latency_us.fetch_add(latency, Ordering::Relaxed);
+    }
+}
+
+/// [MetaCounters] is a snapshot of the counters of the meta engine.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetaCounters {
+    pub calls:      u64,
+    /// The sum of the latencies of the calls, in microseconds.
+    pub latency_us: u64,
+}
+
+/// [TrackedBackend] counts each call into the backend it wraps, so an
+/// operation is counted once however the engine gets there.
+pub(crate) struct TrackedBackend {
+    inner: BackendRef,
+    stats: Arc<MetaStats>,
+}
+
+impl TrackedBackend {
+    pub(crate) fn new(inner: BackendRef, stats: Arc<MetaStats>) -> Self {
+        TrackedBackend { inner, stats }
+    }
+}
+
+macro_rules! tracked {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)*) => {
+        $(
+            fn $name(&self $(, $arg: $ty)*) -> $ret {
+                let _op = self.stats.track();
+                self.inner.$name($($arg),*)
+            }
+        )*
+    };
+}
+
+#[async_trait::async_trait]
+impl Backend for TrackedBackend {
+    tracked! {
+        fn set_format(&self, format: &Format) -> Result<()>;
+        fn load_format(&self) -> Result<Format>;
+        fn increase_count_by(&self, counter: Counter, step: usize) -> Result<u64>;
+        fn load_count(&self, counter: Counter) -> Result<u64>;
+        fn set_count(&self, counter: Counter, value: u64) -> Result<()>;
+        fn compare_and_set_count(&self, counter: Counter, old: u64, new: u64) -> Result<bool>;
+        fn get_attr(&self, inode: Ino) -> Result<InodeAttr>;
+        fn batch_get_attr(&self, inodes: &[Ino]) -> Result<Vec<Option<InodeAttr>>>;
+        fn set_attr(&self, inode: Ino, attr: &InodeAttr) -> Result<()>;
+        fn do_kill_suid(&self, inode: Ino) -> Result<Option<InodeAttr>>;
+        fn batch_update_atime(&self, updates: &[(Ino, SystemTime)]) -> Result<()>;
+        fn get_dentry(&self, parent: Ino, name: &[u8]) -> Result<DEntry>;
+        fn set_dentry(&self, parent: Ino, name: &[u8], inode: Ino, typ: FileType) -> Result<()>;
+        fn list_dentry(&self, parent: Ino, after: Option<&[u8]>, limit: i64)
+            -> Result<Vec<DEntry>>;
+        fn set_symlink(&self, inode: Ino, path: Vec<u8>) -> Result<()>;
+        fn get_symlink(&self, inode: Ino) -> Result<Vec<u8>>;
+        fn set_xattr(&self, inode: Ino, name: &[u8], value: &[u8]) -> Result<()>;
+        fn set_hard_link_count(&self, inode: Ino, parent: Ino, count: u64) -> Result<()>;
+        fn set_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex, slices: Slices)
+            -> Result<()>;
+        fn set_raw_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex, buf: Vec<u8>)
+            -> Result<()>;
+        fn get_raw_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex)
+            -> Result<Option<Vec<u8>>>;
+        fn get_chunk_slices(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Slices>;
+        fn get_slice_ref(&self, slice_id: SliceID) -> Result<u64>;
+        fn set_slice_ref(&self, slice_id: SliceID, count: u64) -> Result<()>;
+        fn set_dir_stat(&self, inode: Ino, dir_stat: DirStat) -> Result<()>;
+        fn get_dir_stat(&self, inode: Ino) -> Result<DirStat>;
+        fn append_changes(&self, records: &[ChangeRecord]) -> Result<()>;
+        fn load_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, ChangeRecord)>>;
+        fn trim_changes(&self, until: u64) -> Result<()>;
+        fn touch_client(&self, client: u64, now: u64) -> Result<()>;
+        fn list_clients(&self) -> Result<Vec<(u64, u64)>>;
+        fn remove_clients(&self, clients: &[u64]) -> Result<()>;
+        fn do_mknod(
+            &self,
+            ctx: Arc<FuseContext>,
+            new_inode: Ino,
+            new_inode_attr: InodeAttr,
+            parent: Ino,
+            name: &[u8],
+            typ: FileType,
+            path: Vec<u8>
+        ) -> Result<(Ino, InodeAttr)>;
+        fn do_rmdir(
+            &self,
+            ctx: Arc<FuseContext>,
+            parent: Ino,
+            name: &[u8],
+            skip_dir_mtime: Duration
+        ) -> Result<(DEntry, InodeAttr)>;
+        fn do_truncate(
+            &self,
+            ctx: Arc<FuseContext>,
+            inode: Ino,
+            length: u64,
+            skip_perm_check: bool
+        ) -> Result<InodeAttr>;
+        fn do_link(
+            &self,
+            ctx: Arc<FuseContext>,
+            inode: Ino,
+            new_parent: Ino,
+            new_name: &[u8]
+        ) -> Result<InodeAttr>;
+        fn do_delete_chunks(&self, inode: Ino) -> Result<Vec<Slice>>;
+        fn list_delete_chunks(&self, start: Ino, limit: usize) -> Result<Vec<(Ino, u64)>>;
+        fn list_delete_slices(&self, limit: usize) -> Result<Vec<(SliceID, usize)>>;
+        fn remove_delete_slices(&self, slice_ids: &[SliceID]) -> Result<()>;
+        fn do_write_slice(
+            &self,
+            inode: Ino,
+            chunk_index: ChunkIndex,
+            chunk_pos: usize,
+            slice: &Slice
+        ) -> Result<Option<WriteResult>>;
+        fn do_copy_file_range(
+            &self,
+            src: Ino,
+            src_off: u64,
+            dst: Ino,
+            dst_off: u64,
+            size: u64
+        ) -> Result<CopyResult>;
+        fn do_compact_chunk(&self, inode: Ino, chunk_index: ChunkIndex) -> Result<Vec<Slice>>;
+        fn do_clone_entry(
+            &self,
+            ctx: Arc<FuseContext>,
+            src: Ino,
+            new_inode: Ino,
+            parent: Ino,
+            name: &[u8],
+            attach: bool,
+            preserve: bool
+        ) -> Result<InodeAttr>;
+        fn do_attach_entry(
+            &self,
+            ctx: Arc<FuseContext>,
+            inode: Ino,
+            parent: Ino,
+            name: &[u8]
+        ) -> Result<InodeAttr>;
+        fn do_purge_inode(&self, inode: Ino) -> Result<Vec<Slice>>;
+        fn do_readlink(&self, inode: Ino) -> Result<Bytes>;
+        fn scan_raw(&self, start: &[u8], limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+        fn write_raw(&self, puts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<()>;
+        fn write_load_batch(&self, batch: &LoadBatch) -> Result<()>;
+    }
+
+    async fn do_unlink(
+        &self,
+        ctx: Arc<FuseContext>,
+        parent: Ino,
+        name: Vec<u8>,
+        session_id: u64,
+        open_files_ref: OpenFilesRef,
+    ) -> Result<UnlinkResult> {
+        let _op = self.stats.track();
+        self.inner
+            .do_unlink(ctx, parent, name, session_id, open_files_ref)
+            .await
+    }
+
+    async fn do_rename(
+        &self,
+        ctx: Arc<FuseContext>,
+        session_id: u64,
+        old_parent: Ino,
+        old_name: &[u8],
+        new_parent: Ino,
+        new_name: &[u8],
+        flags: RenameFlags,
+        open_files_ref: OpenFilesRef,
+    ) -> Result<RenameResult> {
+        let _op = self.stats.track();
+        self.inner
+            .do_rename(
+                ctx,
+                session_id,
+                old_parent,
+                old_name,
+                new_parent,
+                new_name,
+                flags,
+                open_files_ref,
+            )
+            .await
+    }
+
+    // the snapshot serves the dump and the backup, which aren't counted.
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + '_>> { self.inner.snapshot() }
+}
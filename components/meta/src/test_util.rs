@@ -0,0 +1,77 @@
+//! Fixtures for the tests of the engine, the backends and the dependent
+//! crates. Each volume lives in a temporary dir, which is removed once the
+//! returned [TempDir] drops.
+
+use std::{ffi::OsStr, sync::Arc, time::Duration};
+
+use kiseki_types::{ino::Ino, setting::Format, FileType};
+use tempfile::TempDir;
+
+use crate::{
+    backend::{open_backend, BackendRef},
+    context::FuseContext,
+    engine::MetaEngine,
+    open, update_format, MetaConfig, MetaEngineRef,
+};
+
+/// [test_dsn] returns the DSN of a volume which is not formatted yet.
+pub fn test_dsn() -> (TempDir, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let dsn = format!("rocksdb://:{}", dir.path().to_str().unwrap());
+    (dir, dsn)
+}
+
+/// [format_test_volume] formats the volume by the default settings.
+pub fn format_test_volume(dsn: &str) {
+    let mut format = Format::default();
+    format.with_name("test");
+    update_format(dsn, format, true).unwrap();
+}
+
+/// [test_backend] opens the backend of a volume which is not formatted yet.
+pub fn test_backend() -> (TempDir, BackendRef) {
+    let (dir, dsn) = test_dsn();
+    let backend = open_backend(&dsn, Duration::from_millis(100)).unwrap();
+    (dir, backend)
+}
+
+/// [test_meta] formats a volume and opens it by the default config.
+pub fn test_meta() -> (TempDir, MetaEngineRef) { test_meta_with(|_| {}) }
+
+/// [test_meta_with] formats a volume and opens it by the default config,
+/// tuned by the given closure.
+pub fn test_meta_with(configure: impl FnOnce(&mut MetaConfig)) -> (TempDir, MetaEngineRef) {
+    let (dir, dsn) = test_dsn();
+    format_test_volume(&dsn);
+    let mut config = MetaConfig::default();
+    config.with_dsn(&dsn);
+    configure(&mut config);
+    (dir, open(config).unwrap())
+}
+
+/// [mkfile] creates a regular file of mode 0644.
+pub async fn mkfile(meta: &MetaEngine, ctx: &Arc<FuseContext>, parent: Ino, name: &str) -> Ino {
+    let (inode, _) = meta
+        .mknod(
+            ctx.clone(),
+            parent,
+            OsStr::new(name),
+            FileType::RegularFile,
+            0o644,
+            0,
+            0,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+    inode
+}
+
+/// [mkdir] creates a directory of mode 0755.
+pub async fn mkdir(meta: &MetaEngine, ctx: &Arc<FuseContext>, parent: Ino, name: &str) -> Ino {
+    let (inode, _) = meta
+        .mkdir(ctx.clone(), parent, OsStr::new(name), 0o755, 0)
+        .await
+        .unwrap();
+    inode
+}
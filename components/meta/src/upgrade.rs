@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use kiseki_types::{
+    attr::InodeAttr,
+    entry::DEntry,
+    setting::{Format, SCHEMA_VERSION},
+    stat::DirStat,
+};
+use snafu::{ensure, ResultExt};
+use tracing::{debug, info, warn};
+
+use crate::{
+    backend::{codec, codec::Versioned, key, open_backend, Backend},
+    err::{model_err, model_err::ModelKind, ModelSnafu, Result, SchemaVersionMismatchSnafu},
+};
+
+// Where to continue when an upgrade gets interrupted, it is written together
+// with each batch of the rewritten keys.
+const UPGRADE_PROGRESS: &str = "upgrade_progress";
+const UPGRADE_BATCH_SIZE: usize = 1024;
+
+// (key, value) -> the new (key, value), or None if it is left as it is.
+type UpgradeStep = fn(&[u8], &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>>;
+
+/// [check_schema_version] makes sure the volume is written in the layout this
+/// build understands.
+pub(crate) fn check_schema_version(format: &Format) -> Result<()> {
+    ensure!(
+        format.schema_version == SCHEMA_VERSION,
+        SchemaVersionMismatchSnafu {
+            found:    format.schema_version,
+            expected: SCHEMA_VERSION,
+        }
+    );
+    Ok(())
+}
+
+/// [upgrade] migrates the metadata behind the dsn to [SCHEMA_VERSION] in
+/// place and returns the version it was upgraded from. It can be run again
+/// after being interrupted, but no client should mount the volume meanwhile.
+pub fn upgrade(dsn: &str) -> Result<u32> {
+    let backend = open_backend(dsn, Duration::from_millis(100))?;
+    upgrade_backend(backend.as_ref())
+}
+
+pub(crate) fn upgrade_backend(backend: &dyn Backend) -> Result<u32> {
+    let mut format = backend.load_format()?;
+    let from = format.schema_version;
+    ensure!(
+        from <= SCHEMA_VERSION,
+        SchemaVersionMismatchSnafu {
+            found:    from,
+            expected: SCHEMA_VERSION,
+        }
+    );
+
+    while format.schema_version < SCHEMA_VERSION {
+        let step: UpgradeStep = match format.schema_version {
+            0 => upgrade_v0_value,
+            1 => upgrade_v1_key,
+            v => unreachable!("no upgrade step for schema version {}", v),
+        };
+        rewrite_all(backend, step)?;
+
+        format.schema_version += 1;
+        let buf = codec::encode_format(&format)
+            .context(model_err::CorruptionSnafu {
+                kind: ModelKind::Setting,
+                key:  key::CURRENT_FORMAT.to_string(),
+            })
+            .context(ModelSnafu)?;
+        // bump the version and forget the progress of the step at once.
+        let mut deletes = vec![key::setting(UPGRADE_PROGRESS)];
+        let format_key = if format.schema_version < 2 {
+            key::CURRENT_FORMAT.as_bytes().to_vec()
+        } else {
+            deletes.push(key::CURRENT_FORMAT.as_bytes().to_vec());
+            key::format()
+        };
+        backend.write_raw(vec![(format_key, buf)], deletes)?;
+        info!(
+            "upgrade {} to schema version {}",
+            format.name, format.schema_version
+        );
+    }
+    Ok(from)
+}
+
+fn rewrite_all(backend: &dyn Backend, step: UpgradeStep) -> Result<()> {
+    let progress_key = key::setting(UPGRADE_PROGRESS);
+    let format_keys = [key::format(), key::CURRENT_FORMAT.as_bytes().to_vec()];
+    let mut start = match backend.scan_raw(&progress_key, 1)?.pop() {
+        Some((k, v)) if k == progress_key => {
+            debug!("resume the upgrade from {:?}", String::from_utf8_lossy(&v));
+            v
+        }
+        _ => vec![],
+    };
+
+    loop {
+        let kvs = backend.scan_raw(&start, UPGRADE_BATCH_SIZE)?;
+        let Some((last, _)) = kvs.last() else {
+            return Ok(());
+        };
+        // the smallest key which is greater than the last one.
+        let mut next = last.clone();
+        next.push(0);
+
+        let mut puts = Vec::with_capacity(kvs.len() + 1);
+        let mut deletes = vec![];
+        for (k, v) in kvs.iter() {
+            if *k == progress_key || format_keys.contains(k) {
+                continue;
+            }
+            if let Some((new_k, new_v)) = step(k, v)? {
+                if new_k != *k {
+                    deletes.push(k.clone());
+                }
+                puts.push((new_k, new_v));
+            }
+        }
+        puts.push((progress_key.clone(), next.clone()));
+        backend.write_raw(puts, deletes)?;
+
+        if kvs.len() < UPGRADE_BATCH_SIZE {
+            return Ok(());
+        }
+        start = next;
+    }
+}
+
+/// [upgrade_v0_value] adds the version byte to the structured values.
+fn upgrade_v0_value(k: &[u8], v: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let Some((tag, rest)) = k.split_first() else {
+        return Ok(None);
+    };
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return Ok(None);
+    }
+    let new_v = match (tag, &rest[digits..]) {
+        (b'A', b"I") => reencode::<InodeAttr>(ModelKind::Attr, k, v)?,
+        (b'A', [b'D', b'/', ..]) => reencode::<DEntry>(ModelKind::DEntry, k, v)?,
+        (b'U', b"I") => reencode::<DirStat>(ModelKind::DirStat, k, v)?,
+        _ => return Ok(None),
+    };
+    Ok(Some((k.to_vec(), new_v)))
+}
+
+/// [upgrade_v1_key] moves the values from the string keys to the binary ones,
+/// which sort before all the string keys, so they are never visited again.
+fn upgrade_v1_key(k: &[u8], v: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    if !key::is_legacy(k) {
+        return Ok(None);
+    }
+    match key::from_legacy(k) {
+        Some(new_k) => Ok(Some((new_k, v.to_vec()))),
+        None => {
+            warn!(
+                "leave unknown key {:?} as it is",
+                String::from_utf8_lossy(k)
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn reencode<V: Versioned>(kind: ModelKind, k: &[u8], v: &[u8]) -> Result<Vec<u8>> {
+    let buf = codec::decode_legacy::<V>(v)
+        .and_then(|value| codec::encode(&value))
+        .context(model_err::CorruptionSnafu {
+            kind,
+            key: String::from_utf8_lossy(k).to_string(),
+        })
+        .context(ModelSnafu)?;
+    Ok(buf)
+}
+
+#[cfg(feature = "meta-rocksdb")]
+#[cfg(test)]
+mod tests {
+    use kiseki_types::{ino::Ino, slice::Slices, FileType};
+
+    use super::*;
+    use crate::{backend::key::Counter, test_util::test_backend};
+
+    // A volume written by schema version 0, one "<key> <value>" in hex per
+    // line. It holds /d1/f1 with one slice and the symlink /s1 -> d1/f1.
+    const FIXTURE_V0: &str = include_str!("../fixtures/schema-v0.kv");
+    // A volume written by schema version 1. /h1 and /d1/f1 are hard links of
+    // a file with an xattr and three chunks.
+    const FIXTURE_V1: &str = include_str!("../fixtures/schema-v1.kv");
+
+    fn decode_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn load_fixture(backend: &dyn Backend, fixture: &str) {
+        let puts = fixture
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                let (k, v) = l.split_once(' ').unwrap();
+                (decode_hex(k), decode_hex(v.trim()))
+            })
+            .collect();
+        backend.write_raw(puts, vec![]).unwrap();
+    }
+
+    #[test]
+    fn upgrade_from_v0() {
+        let (_dir, backend) = test_backend();
+        load_fixture(backend.as_ref(), FIXTURE_V0);
+
+        let format = backend.load_format().unwrap();
+        assert_eq!(format.schema_version, 0);
+        assert_eq!(format.name, "test-v0");
+        assert!(check_schema_version(&format).is_err());
+        // the values cannot be read before the upgrade.
+        assert!(backend.get_attr(Ino(1)).is_err());
+
+        assert_eq!(upgrade_backend(backend.as_ref()).unwrap(), 0);
+        // upgrading an upgraded volume does nothing.
+        assert_eq!(upgrade_backend(backend.as_ref()).unwrap(), SCHEMA_VERSION);
+
+        let format = backend.load_format().unwrap();
+        assert_eq!(format.schema_version, SCHEMA_VERSION);
+        assert_eq!(format.name, "test-v0");
+        assert_eq!(format.block_size, 4 << 20);
+        assert_eq!(format.max_inodes, Some(1000));
+
+        let root = backend.get_attr(Ino(1)).unwrap();
+        assert_eq!(root.kind, FileType::Directory);
+        assert_eq!(root.mode, 0o777);
+        let entries = backend.list_dentry(Ino(1), None, -1).unwrap();
+        let mut names = entries
+            .iter()
+            .map(|e| e.name.as_slice())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec![b"d1".as_slice(), b"s1".as_slice()]);
+        let entries = backend.list_dentry(Ino(2), None, -1).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, b"f1");
+        assert_eq!(entries[0].inode, Ino(3));
+
+        let f1 = backend.get_attr(Ino(3)).unwrap();
+        assert_eq!(f1.kind, FileType::RegularFile);
+        assert_eq!(f1.length, 4096);
+        assert_eq!(f1.parent, Ino(2));
+        let slices: Slices = backend.get_chunk_slices(Ino(3), 0).unwrap();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices.0[0].get_id(), 10);
+        assert_eq!(backend.get_symlink(Ino(4)).unwrap(), b"d1/f1");
+
+        let stat = backend.get_dir_stat(Ino(1)).unwrap();
+        assert_eq!(stat.inodes, 2);
+        assert_eq!(backend.load_count(Counter::NextInode).unwrap(), 5);
+    }
+
+    #[test]
+    fn upgrade_from_v1() {
+        let (_dir, backend) = test_backend();
+        load_fixture(backend.as_ref(), FIXTURE_V1);
+        assert_eq!(backend.load_format().unwrap().schema_version, 1);
+
+        assert_eq!(upgrade_backend(backend.as_ref()).unwrap(), 1);
+        // all the string keys are gone.
+        let kvs = backend.scan_raw(&[], usize::MAX).unwrap();
+        assert!(kvs.iter().all(|(k, _)| !key::is_legacy(k)));
+        assert_eq!(backend.load_format().unwrap().name, "test-v1");
+
+        let f1 = backend.get_attr(Ino(3)).unwrap();
+        assert_eq!(f1.nlink, 2);
+        assert!(f1.parent.is_zero());
+        let entries = backend.list_dentry(Ino(1), None, -1).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().any(|e| e.name == b"h1" && e.inode == Ino(3)));
+
+        let snapshot = backend.snapshot().unwrap();
+        assert_eq!(
+            snapshot.list_xattr(Ino(3)).unwrap(),
+            vec![(b"user.k".to_vec(), b"v".to_vec())]
+        );
+        // the chunks are in the numeric order now.
+        let chunks = snapshot.list_chunk_slices(Ino(3)).unwrap();
+        let indexes = chunks.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
+        assert_eq!(indexes, vec![0, 2, 10]);
+        assert_eq!(snapshot.load_count(Counter::NextSlice).unwrap(), 13);
+    }
+}
@@ -38,6 +38,7 @@ use tracing::{debug, error, warn};
 use crate::{
     err::{CacheSnafu, Error::CacheError, ObjectStorageSnafu, Result, UnknownIOSnafu},
     pool::Page,
+    stats::STORAGE_STATS,
 };
 
 pub const DEFAULT_STAGE_CACHE_SIZE: u64 = 10 << 30;
@@ -155,7 +156,9 @@ impl FileCache {
         offset: usize,
         length: usize,
     ) -> Result<Option<Bytes>> {
-        return match self.index.get(slice_key).await {
+        let index = self.index.get(slice_key).await;
+        STORAGE_STATS.lookup_stage_cache(index.is_some());
+        return match index {
             None => {
                 warn!("block not found in the stage cache: {:?}", slice_key);
                 Ok(None)
@@ -271,6 +274,7 @@ async fn migrate_from_local_to_remote(
     writer.flush().await.context(UnknownIOSnafu)?;
     writer.shutdown().await.context(UnknownIOSnafu)?;
     assert_eq!(copy_len, expect_copy_len as u64);
+    STORAGE_STATS.put_object(expect_copy_len);
 
     local_storage
         .delete(&path)
@@ -5,7 +5,10 @@ use kiseki_types::slice::SliceKey;
 use kiseki_utils::readable_size::ReadableSize;
 use snafu::ResultExt;
 
-use crate::err::{Error::CacheError, ObjectStorageSnafu, Result};
+use crate::{
+    err::{Error::CacheError, ObjectStorageSnafu, Result},
+    stats::{MemCacheLookup, STORAGE_STATS},
+};
 
 #[derive(Debug)]
 pub struct Config {
@@ -46,9 +49,15 @@ impl MemCache {
     }
 
     pub async fn get(&self, key: &SliceKey) -> Result<Option<Bytes>> {
-        match self
+        if let Some(v) = self.inner.get(key).await {
+            STORAGE_STATS.lookup_mem_cache(MemCacheLookup::Hit);
+            return Ok(Some(v));
+        }
+        let mut loaded = false;
+        let got = self
             .inner
             .try_get_with_by_ref(key, async {
+                loaded = true;
                 let path = key.make_object_storage_path();
                 let object = self
                     .remote_storage
@@ -56,10 +65,18 @@ impl MemCache {
                     .await
                     .context(ObjectStorageSnafu)?;
                 let v = object.bytes().await.context(ObjectStorageSnafu)?;
+                STORAGE_STATS.get_object(v.len());
                 Ok(v) as Result<Bytes>
             })
-            .await
-        {
+            .await;
+        // the block is loaded by one of the callers who miss it, the others
+        // wait for it.
+        STORAGE_STATS.lookup_mem_cache(if loaded {
+            MemCacheLookup::Miss
+        } else {
+            MemCacheLookup::Wait
+        });
+        match got {
             Ok(v) => Ok(Some(v)),
             Err(e) => {
                 if e.is_not_found() {
@@ -3,10 +3,14 @@ mod pool;
 
 pub fn get_pool_free_ratio() -> f64 { pool::GLOBAL_HYBRID_PAGE_POOL.free_ratio() }
 
+pub fn get_storage_stats() -> stats::StorageCounters { stats::STORAGE_STATS.load() }
+
 pub mod slice_buffer;
 
 pub mod cache;
 
+pub mod stats;
+
 // pub mod raw_buffer;
 
 pub struct Storage {}
@@ -29,6 +29,7 @@ use crate::{
         ObjectStorageSnafu, OpenDalSnafu, Result, UnknownIOSnafu,
     },
     pool::{Page, GLOBAL_HYBRID_PAGE_POOL},
+    stats::STORAGE_STATS,
 };
 
 // read_slice_from_object_storage will allocate memory in place and then drop
@@ -80,6 +81,7 @@ pub async fn read_slice_from_object_storage<F: Fn(BlockIndex, BlockSize) -> Stri
             let path = kiseki_utils::object_storage::ObjectStoragePath::parse(&key).unwrap();
             let block = sto.get(&path).await.context(ObjectStorageSnafu)?;
             let block_buf = block.bytes().await.context(ObjectStorageSnafu)?;
+            STORAGE_STATS.get_object(block_buf.len());
 
             // let block_buf = sto.read(&key).await.context(OpenDalSnafu)?;
             let mut cursor = Cursor::new(dst_slice);
@@ -439,6 +441,7 @@ impl SliceBuffer {
                         );
                     }
                     writer.shutdown().await.context(UnknownIOSnafu)?;
+                    STORAGE_STATS.put_object(total_flush_data);
                     debug!(
                         "write object to {:?}, len: {:?}",
                         key,
@@ -0,0 +1,109 @@
+//! The counters of the data path since the process starts, they are shown
+//! by the `.stats` of the mount.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// [STORAGE_STATS] is shared by the caches and the buffers of the process,
+/// just like the page pool.
+pub(crate) static STORAGE_STATS: StorageStats = StorageStats::new();
+
+pub(crate) struct StorageStats {
+    mem_cache_hits:     AtomicU64,
+    mem_cache_misses:   AtomicU64,
+    mem_cache_waits:    AtomicU64,
+    stage_cache_hits:   AtomicU64,
+    stage_cache_misses: AtomicU64,
+    object_gets:        AtomicU64,
+    object_get_bytes:   AtomicU64,
+    object_puts:        AtomicU64,
+    object_put_bytes:   AtomicU64,
+}
+
+impl StorageStats {
+    const fn new() -> Self {
+        Self {
+            mem_cache_hits:     AtomicU64::new(0),
+            mem_cache_misses:   AtomicU64::new(0),
+            mem_cache_waits:    AtomicU64::new(0),
+            stage_cache_hits:   AtomicU64::new(0),
+            stage_cache_misses: AtomicU64::new(0),
+            object_gets:        AtomicU64::new(0),
+            object_get_bytes:   AtomicU64::new(0),
+            object_puts:        AtomicU64::new(0),
+            object_put_bytes:   AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn lookup_mem_cache(&self, lookup: MemCacheLookup) {
+        let counter = match lookup {
+            MemCacheLookup::Hit => &self.mem_cache_hits,
+            MemCacheLookup::Miss => &self.mem_cache_misses,
+            MemCacheLookup::Wait => &self.mem_cache_waits,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn lookup_stage_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.stage_cache_hits
+        } else {
+            &self.stage_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// [get_object] counts a GET of the object storage which returns len
+    /// bytes.
+    pub(crate) fn get_object(&self, len: usize) {
+        self.object_gets.fetch_add(1, Ordering::Relaxed);
+        self.object_get_bytes
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// [put_object] counts a PUT of len bytes to the object storage.
+    pub(crate) fn put_object(&self, len: usize) {
+        self.object_puts.fetch_add(1, Ordering::Relaxed);
+        self.object_put_bytes
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> StorageCounters {
+        StorageCounters {
+            mem_cache_hits:     self.mem_cache_hits.load(Ordering::Relaxed),
+            mem_cache_misses:   self.mem_cache_misses.load(Ordering::Relaxed),
+            mem_cache_waits:    self.mem_cache_waits.load(Ordering::Relaxed),
+            stage_cache_hits:   self.stage_cache_hits.load(Ordering::Relaxed),
+            stage_cache_misses: self.stage_cache_misses.load(Ordering::Relaxed),
+            object_gets:        self.object_gets.load(Ordering::Relaxed),
+            object_get_bytes:   self.object_get_bytes.load(Ordering::Relaxed),
+            object_puts:        self.object_puts.load(Ordering::Relaxed),
+            object_put_bytes:   self.object_put_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// [MemCacheLookup] is how a block read is served by the mem cache.
+pub(crate) enum MemCacheLookup {
+    Hit,
+    /// the block is loaded by this read.
+    Miss,
+    /// the block is being loaded by another read, which this one waits for.
+    Wait,
+}
+
+/// [StorageCounters] is a snapshot of the counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StorageCounters {
+    pub mem_cache_hits:     u64,
+    pub mem_cache_misses:   u64,
+    /// The block reads which wait for another one to load the block, they
+    /// are neither hits nor misses.
+    pub mem_cache_waits:    u64,
+    /// The block reads served by the stage cache on the local disk.
+    pub stage_cache_hits:   u64,
+    pub stage_cache_misses: u64,
+    pub object_gets:        u64,
+    pub object_get_bytes:   u64,
+    pub object_puts:        u64,
+    pub object_put_bytes:   u64,
+}
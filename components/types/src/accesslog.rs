@@ -0,0 +1,119 @@
+//! The records of the `.accesslog` file in the root of a mount, one line for
+//! each FUSE operation served while someone reads the file:
+//!
+//! ```text
+//! 1760774771.003330 [uid:0,gid:0,pid:4403] read (17669) <0.000210>
+//! ```
+//!
+//! which is when it is served in seconds since the epoch, the caller, the
+//! operation and the inode it works on, then how long it takes in seconds.
+
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::ino::Ino;
+
+/// [AccessRecord] is a line of the access log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    /// When the operation is served.
+    pub time:    SystemTime,
+    pub uid:     u32,
+    pub gid:     u32,
+    pub pid:     u32,
+    pub op:      String,
+    pub inode:   Ino,
+    pub latency: Duration,
+}
+
+impl Display for AccessRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "{}.{:06} [uid:{},gid:{},pid:{}] {} ({}) <{:.6}>",
+            time.as_secs(),
+            time.subsec_micros(),
+            self.uid,
+            self.gid,
+            self.pid,
+            self.op,
+            self.inode,
+            self.latency.as_secs_f64()
+        )
+    }
+}
+
+impl AccessRecord {
+    /// [parse] parses a line of the access log, returns None if it isn't
+    /// one.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (time, rest) = line.trim_end().split_once(" [")?;
+        let (caller, rest) = rest.split_once("] ")?;
+        let (op, rest) = rest.split_once(" (")?;
+        let (inode, latency) = rest.split_once(") <")?;
+        let latency = latency.strip_suffix('>')?;
+
+        let mut ids = [0u32; 3];
+        let mut fields = caller.split(',');
+        for (id, key) in ids.iter_mut().zip(["uid:", "gid:", "pid:"]) {
+            *id = fields.next()?.strip_prefix(key)?.parse().ok()?;
+        }
+        Some(Self {
+            time:    UNIX_EPOCH + parse_seconds(time)?,
+            uid:     ids[0],
+            gid:     ids[1],
+            pid:     ids[2],
+            op:      op.to_string(),
+            inode:   Ino(inode.parse().ok()?),
+            latency: parse_seconds(latency)?,
+        })
+    }
+}
+
+// parse_seconds parses the seconds with at most 9 decimal places, it doesn't
+// go through f64 so the micros written come back as they are.
+fn parse_seconds(s: &str) -> Option<Duration> {
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{frac:0<9}").parse().ok()?;
+    Some(Duration::new(secs.parse().ok()?, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_record() {
+        let record = AccessRecord {
+            time:    UNIX_EPOCH + Duration::from_micros(1_760_774_771_003_330),
+            uid:     1000,
+            gid:     100,
+            pid:     4403,
+            op:      "read".to_string(),
+            inode:   Ino(17669),
+            latency: Duration::from_micros(210),
+        };
+        let line = record.to_string();
+        assert_eq!(
+            line,
+            "1760774771.003330 [uid:1000,gid:100,pid:4403] read (17669) <0.000210>"
+        );
+        let parsed = AccessRecord::parse(&line).unwrap();
+        assert_eq!(parsed.uid, 1000);
+        assert_eq!(parsed.pid, 4403);
+        assert_eq!(parsed.op, "read");
+        assert_eq!(parsed.inode, Ino(17669));
+        assert_eq!(parsed.latency.as_micros(), 210);
+        assert_eq!(parsed.to_string(), line);
+
+        assert!(AccessRecord::parse("").is_none());
+        assert!(AccessRecord::parse("1 [uid:0,gid:0] read (1) <0.1>").is_none());
+        assert!(AccessRecord::parse("1 [uid:0,gid:0,pid:0] read (x) <0.1>").is_none());
+    }
+}
@@ -317,6 +317,21 @@ impl InodeAttr {
         let contains = flag.contains(Flags::IMMUTABLE) || flag.contains(Flags::APPEND);
         !contains
     }
+
+    /// [suid_kill_bits] returns the set-id bits which go away once the file
+    /// is modified or chowned. The set-group-ID bit of a file which is not
+    /// group-executable marks mandatory locking, so it stays, and a directory
+    /// keeps both.
+    pub fn suid_kill_bits(&self) -> u32 {
+        if self.is_dir() {
+            return 0;
+        }
+        let mut kill = self.mode & 0o4000;
+        if self.mode & 0o2010 == 0o2010 {
+            kill |= 0o2000;
+        }
+        kill
+    }
 }
 
 fn get_mode_t_from_filetype(kind: &FileType) -> libc::mode_t {
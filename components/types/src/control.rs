@@ -0,0 +1,142 @@
+//! The commands of the `.control` file in the root of a mount, they let the
+//! tools on the host ask the mount to do the work on a path, with the
+//! permissions of the caller.
+//!
+//! A process opens the file, writes a request and reads the response back
+//! from the same handle. Both are framed as magic(4) len(4) body(len) in
+//! little endian, the body is the bincode of a [ControlRequest] or a
+//! [ControlResponse]. The kernel may split a large write, so the request is
+//! run once its frame is complete.
+//!
+//! The requests which walk a tree, [ControlRequest::Rmr] and
+//! [ControlRequest::Warmup], run in the background of the mount. Until they
+//! are done, each read returns a response with the progress so far, and the
+//! process keeps reading for the final one. Closing the handle cancels them.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{ino::Ino, Errno};
+
+/// [CONTROL_MAGIC] starts every frame, it reads "KCTL".
+pub const CONTROL_MAGIC: u32 = 0x4b43_544c;
+/// [MAX_CONTROL_FRAME] is the max size of a frame, the header included.
+pub const MAX_CONTROL_FRAME: usize = 1 << 20;
+const HEADER_SIZE: usize = 8;
+
+/// [ControlRequest] is the command to run in the mount.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlRequest {
+    /// Remove the entry, and the tree under it if it is a directory.
+    Rmr { parent: Ino, name: Vec<u8> },
+    /// Read the file, or the files under the directory, into the cache.
+    Warmup { inode: Ino },
+    /// Sum up the file, or the tree under the directory.
+    Info { inode: Ino },
+    /// Drop the slices covered by the later writes of the file, or of the
+    /// files under the directory.
+    Compact { inode: Ino },
+    /// Delete the chunks left behind by the removed files.
+    Gc,
+    /// Write the buffered data of the file to the object storage.
+    Flush { inode: Ino },
+}
+
+/// [ControlResponse] is the result of a [ControlRequest].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlResponse {
+    /// 0 on success, otherwise the errno of the failure.
+    pub errno:   Errno,
+    /// The human readable result, or what went wrong.
+    pub message: String,
+    /// False while the request is still running, the message is its
+    /// progress then.
+    pub done:    bool,
+}
+
+impl ControlResponse {
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self {
+            errno:   0,
+            message: message.into(),
+            done:    true,
+        }
+    }
+
+    pub fn err(errno: Errno, message: impl Into<String>) -> Self {
+        Self {
+            errno,
+            message: message.into(),
+            done: true,
+        }
+    }
+
+    pub fn progress(message: impl Into<String>) -> Self {
+        Self {
+            errno:   0,
+            message: message.into(),
+            done:    false,
+        }
+    }
+}
+
+/// [encode_frame] frames the message.
+pub fn encode_frame<T: Serialize>(msg: &T) -> Vec<u8> {
+    let body = bincode::serialize(msg).expect("unable to serialize the control message");
+    let mut buf = Vec::with_capacity(HEADER_SIZE + body.len());
+    buf.extend_from_slice(&CONTROL_MAGIC.to_le_bytes());
+    buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&body);
+    buf
+}
+
+/// [decode_frame] decodes the frame the buf holds, returns None if the frame
+/// is incomplete yet, and EINVAL if the buf is not exactly one valid frame.
+pub fn decode_frame<T: DeserializeOwned>(buf: &[u8]) -> Option<Result<T, Errno>> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+    let magic = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+    let len = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+    let size = HEADER_SIZE + len;
+    if magic != CONTROL_MAGIC || size > MAX_CONTROL_FRAME || buf.len() > size {
+        return Some(Err(libc::EINVAL));
+    }
+    if buf.len() < size {
+        return None;
+    }
+    Some(bincode::deserialize(&buf[HEADER_SIZE..]).map_err(|_| libc::EINVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_frame() {
+        let req = ControlRequest::Rmr {
+            parent: Ino(1),
+            name:   b"caf\xe9".to_vec(),
+        };
+        let buf = encode_frame(&req);
+        assert_eq!(decode_frame(&buf), Some(Ok(req)));
+        // the frame may come in pieces.
+        assert_eq!(decode_frame::<ControlRequest>(&buf[..4]), None);
+        assert_eq!(decode_frame::<ControlRequest>(&buf[..buf.len() - 1]), None);
+
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_frame::<ControlRequest>(&trailing),
+            Some(Err(libc::EINVAL))
+        );
+        assert_eq!(
+            decode_frame::<ControlRequest>(&[0; 8]),
+            Some(Err(libc::EINVAL))
+        );
+
+        let resp = ControlResponse::err(libc::EPERM, "denied");
+        assert_eq!(decode_frame(&encode_frame(&resp)), Some(Ok(resp)));
+        let resp = ControlResponse::progress("removed: 1");
+        assert_eq!(decode_frame(&encode_frame(&resp)), Some(Ok(resp)));
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
 use fuser::FileType;
 use kiseki_common::{DOT, DOT_DOT};
 use serde::{Deserialize, Serialize};
@@ -31,19 +33,22 @@ pub enum Entry {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FullEntry {
     pub inode: Ino,
-    pub name:  String,
+    /// the raw bytes of the name, which may not be valid UTF-8.
+    pub name:  Vec<u8>,
     pub attr:  InodeAttr,
 }
 
 impl FullEntry {
-    pub fn new(ino: Ino, name: &str, attr: InodeAttr) -> Self {
+    pub fn new(ino: Ino, name: &OsStr, attr: InodeAttr) -> Self {
         FullEntry {
             inode: ino,
-            name: name.to_string(),
+            name: name.as_bytes().to_vec(),
             attr,
         }
     }
 
+    pub fn get_name(&self) -> &OsStr { OsStr::from_bytes(&self.name) }
+
     pub fn to_fuse_attr<I: Into<u64>>(&self, ino: I) -> fuser::FileAttr {
         self.attr.to_fuse_attr(ino)
     }
@@ -53,7 +58,8 @@ impl FullEntry {
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub struct DEntry {
     pub parent: Ino,
-    pub name:   String,
+    /// the raw bytes of the name, it is encoded the same as a String.
+    pub name:   Vec<u8>,
     pub inode:  Ino,
     pub typ:    FileType,
 }
@@ -63,13 +69,13 @@ impl Entry {
         vec![
             Entry::DEntry(DEntry {
                 parent: ZERO_INO,
-                name:   DOT.to_string(),
+                name:   DOT.as_bytes().to_vec(),
                 inode:  ino,
                 typ:    FileType::Directory,
             }),
             Entry::DEntry(DEntry {
                 parent: ZERO_INO,
-                name:   DOT_DOT.to_string(),
+                name:   DOT_DOT.as_bytes().to_vec(),
                 inode:  parent,
                 typ:    FileType::Directory,
             }),
@@ -97,10 +103,26 @@ impl Entry {
         }
     }
 
-    pub fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &OsStr {
         match self {
-            Entry::Full(e) => &e.name,
-            Entry::DEntry(e) => &e.name,
+            Entry::Full(e) => e.get_name(),
+            Entry::DEntry(e) => OsStr::from_bytes(&e.name),
         }
     }
 }
+
+/// [DirPage] is a page of the listing of a directory.
+#[derive(Clone, Debug, Default)]
+pub struct DirPage {
+    pub entries: Vec<Entry>,
+    /// the name of the last entry listed from the directory, the next page
+    /// starts right after it. The entry itself may be left out of the
+    /// entries, as it has been removed since it was listed.
+    pub cursor:  Option<Vec<u8>>,
+    /// the whole directory has been listed.
+    pub eof:     bool,
+}
+
+impl DirPage {
+    pub fn cursor(&self) -> Option<&OsStr> { self.cursor.as_deref().map(OsStr::from_bytes) }
+}
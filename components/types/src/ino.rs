@@ -17,7 +17,6 @@ use std::{
     ops::{Add, AddAssign},
 };
 
-use byteorder::{LittleEndian, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 
 pub const ZERO_INO: Ino = Ino(0);
@@ -66,34 +65,4 @@ impl Ino {
     pub fn is_zero(&self) -> bool { self.0 == 0 }
 
     pub fn is_root(&self) -> bool { self.0 == ROOT_INO.0 }
-
-    // FIXME: use a better way
-    // key: AiiiiiiiiI
-    // key-len: 10
-    pub fn generate_key(&self) -> Vec<u8> {
-        let mut buf = vec![0u8; 10];
-        buf.write_u8(b'A').unwrap();
-        buf.write_u64::<LittleEndian>(self.0).unwrap();
-        buf.write_u8(b'I').unwrap();
-        buf
-    }
-
-    pub fn generate_key_str(&self) -> String {
-        // let key_buf = self.generate_key();
-        // String::from_utf8_lossy(&key_buf).to_string()
-        format!("A{:x}I", self.0)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn ino() {
-        let key = ROOT_INO.generate_key();
-        println!("{:?}", key);
-        let key_str = ROOT_INO.generate_key_str();
-        println!("{:?}", key_str)
-    }
 }
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, ffi::OsStr, time::Duration};
 
 use fuser::FileType;
 
@@ -36,22 +36,24 @@ impl InternalNodeTable {
         let mut map = HashMap::new();
         let control_inode: InternalNode = InternalNode(FullEntry {
             inode: CONTROL_INODE,
-            name:  CONTROL_INODE_NAME.to_string(),
+            name:  CONTROL_INODE_NAME.as_bytes().to_vec(),
             attr:  InodeAttr::default().set_mode(0o666).to_owned(),
         });
+        // the access log shows the operations of every user, so only root
+        // may read it.
         let log_inode: InternalNode = InternalNode(FullEntry {
             inode: LOG_INODE,
-            name:  LOG_INODE_NAME.to_string(),
+            name:  LOG_INODE_NAME.as_bytes().to_vec(),
             attr:  InodeAttr::default().set_mode(0o400).to_owned(),
         });
         let stats_inode: InternalNode = InternalNode(FullEntry {
             inode: STATS_INODE,
-            name:  STATS_INODE_NAME.to_string(),
-            attr:  InodeAttr::default().set_mode(0o400).to_owned(),
+            name:  STATS_INODE_NAME.as_bytes().to_vec(),
+            attr:  InodeAttr::default().set_mode(0o444).to_owned(),
         });
         let config_inode: InternalNode = InternalNode(FullEntry {
             inode: CONFIG_INODE,
-            name:  CONFIG_INODE_NAME.to_string(),
+            name:  CONFIG_INODE_NAME.as_bytes().to_vec(),
             attr:  InodeAttr::default().set_mode(0o400).to_owned(),
         });
         map.insert(LOG_INODE_NAME, log_inode);
@@ -63,8 +65,8 @@ impl InternalNodeTable {
 }
 
 impl InternalNodeTable {
-    pub fn get_internal_node_by_name(&self, name: &str) -> Option<&InternalNode> {
-        self.nodes.get(name)
+    pub fn get_internal_node_by_name(&self, name: &OsStr) -> Option<&InternalNode> {
+        self.nodes.get(name.to_str()?)
     }
 
     pub fn get_mut_internal_node_by_name(&mut self, name: &str) -> Option<&mut InternalNode> {
@@ -77,11 +79,13 @@ impl InternalNodeTable {
 
     pub fn add_prefix(&mut self) {
         for n in self.nodes.values_mut() {
-            n.0.name = format!(".kfs{}", n.0.name);
+            n.0.name = [b".kfs".as_slice(), &n.0.name].concat();
         }
     }
 
-    pub fn contains_name(&self, name: &str) -> bool { self.nodes.contains_key(name) }
+    pub fn contains_name(&self, name: &OsStr) -> bool {
+        self.get_internal_node_by_name(name).is_some()
+    }
 }
 
 #[derive(Debug)]
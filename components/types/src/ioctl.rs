@@ -0,0 +1,128 @@
+//! The private ioctl commands of kiseki, they let the tools on the host ask
+//! the mount to do the work which has no syscall of its own.
+
+use kiseki_common::MAX_NAME_LENGTH;
+
+use crate::{attr::Flags, ino::Ino};
+
+// _IOW and _IOR of asm-generic/ioctl.h
+const fn iow(typ: u8, nr: u8, size: usize) -> u32 {
+    (1 << 30) | ((size as u32) << 16) | ((typ as u32) << 8) | nr as u32
+}
+const fn ior(typ: u8, nr: u8, size: usize) -> u32 {
+    (2 << 30) | ((size as u32) << 16) | ((typ as u32) << 8) | nr as u32
+}
+
+/// [FS_IOC_GETFLAGS] reads the inode flags, see chattr(1). The argument is an
+/// int despite of the size encoded in the command.
+pub const FS_IOC_GETFLAGS: u32 = ior(b'f', 1, std::mem::size_of::<libc::c_long>());
+/// [FS_IOC_SETFLAGS] sets the inode flags.
+pub const FS_IOC_SETFLAGS: u32 = iow(b'f', 2, std::mem::size_of::<libc::c_long>());
+
+const FS_IMMUTABLE_FL: u32 = 0x00000010;
+const FS_APPEND_FL: u32 = 0x00000020;
+
+/// [to_fs_flags] converts the [Flags] to the FS_*_FL flags of linux/fs.h.
+pub fn to_fs_flags(flags: Flags) -> u32 {
+    let mut fs_flags = 0;
+    if flags.contains(Flags::IMMUTABLE) {
+        fs_flags |= FS_IMMUTABLE_FL;
+    }
+    if flags.contains(Flags::APPEND) {
+        fs_flags |= FS_APPEND_FL;
+    }
+    fs_flags
+}
+
+/// [from_fs_flags] converts the FS_*_FL flags of linux/fs.h to the [Flags],
+/// returns None if some of them are not supported.
+pub fn from_fs_flags(fs_flags: u32) -> Option<Flags> {
+    if fs_flags & !(FS_IMMUTABLE_FL | FS_APPEND_FL) != 0 {
+        return None;
+    }
+    let mut flags = Flags::empty();
+    flags.set(Flags::IMMUTABLE, fs_flags & FS_IMMUTABLE_FL != 0);
+    flags.set(Flags::APPEND, fs_flags & FS_APPEND_FL != 0);
+    Some(flags)
+}
+
+/// [CLONE_REQUEST_SIZE] is the size of an encoded [CloneRequest]:
+/// parent(8) flags(4) name_len(4) name(256).
+pub const CLONE_REQUEST_SIZE: usize = 16 + MAX_NAME_LENGTH + 1;
+
+/// [IOC_CLONE] clones the file or the directory tree the ioctl is issued on,
+/// the argument is an encoded [CloneRequest].
+pub const IOC_CLONE: u32 = iow(b'K', 1, CLONE_REQUEST_SIZE);
+
+const CLONE_PRESERVE: u32 = 0x01;
+
+/// [CloneRequest] tells where to place the clone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneRequest {
+    /// The directory to place the clone in.
+    pub parent:   Ino,
+    /// The name of the clone, at most [MAX_NAME_LENGTH] bytes.
+    pub name:     Vec<u8>,
+    /// Keep the owner and the timestamps of the src, otherwise the clone
+    /// belongs to the caller.
+    pub preserve: bool,
+}
+
+impl CloneRequest {
+    pub fn encode(&self) -> [u8; CLONE_REQUEST_SIZE] {
+        assert!(self.name.len() <= MAX_NAME_LENGTH, "name too long");
+        let mut buf = [0; CLONE_REQUEST_SIZE];
+        let flags = if self.preserve { CLONE_PRESERVE } else { 0 };
+        buf[0..8].copy_from_slice(&self.parent.0.to_le_bytes());
+        buf[8..12].copy_from_slice(&flags.to_le_bytes());
+        buf[12..16].copy_from_slice(&(self.name.len() as u32).to_le_bytes());
+        buf[16..16 + self.name.len()].copy_from_slice(&self.name);
+        buf
+    }
+
+    /// [decode] returns None if the buf is not a valid [CloneRequest].
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() != CLONE_REQUEST_SIZE {
+            return None;
+        }
+        let parent = u64::from_le_bytes(buf[0..8].try_into().ok()?);
+        let flags = u32::from_le_bytes(buf[8..12].try_into().ok()?);
+        let name_len = u32::from_le_bytes(buf[12..16].try_into().ok()?) as usize;
+        if name_len > MAX_NAME_LENGTH || flags & !CLONE_PRESERVE != 0 {
+            return None;
+        }
+        Some(Self {
+            parent:   Ino(parent),
+            name:     buf[16..16 + name_len].to_vec(),
+            preserve: flags & CLONE_PRESERVE != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_request() {
+        let req = CloneRequest {
+            parent:   Ino(42),
+            name:     b"caf\xe9".to_vec(),
+            preserve: true,
+        };
+        assert_eq!(CloneRequest::decode(&req.encode()), Some(req));
+        assert_eq!(IOC_CLONE, 0x4110_4b01);
+        assert!(CloneRequest::decode(&[0; 16]).is_none());
+    }
+
+    #[test]
+    fn fs_flags() {
+        assert_eq!(FS_IOC_GETFLAGS, 0x8008_6601);
+        assert_eq!(FS_IOC_SETFLAGS, 0x4008_6602);
+        let flags = Flags::IMMUTABLE | Flags::APPEND;
+        assert_eq!(from_fs_flags(to_fs_flags(flags)), Some(flags));
+        assert_eq!(from_fs_flags(0), Some(Flags::empty()));
+        // FS_NOATIME_FL
+        assert_eq!(from_fs_flags(0x80), None);
+    }
+}
@@ -1,7 +1,10 @@
+pub mod accesslog;
 pub mod attr;
+pub mod control;
 pub mod entry;
 pub mod ino;
 pub mod internal_nodes;
+pub mod ioctl;
 pub mod setting;
 pub mod slice;
 pub mod stat;
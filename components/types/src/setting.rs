@@ -1,17 +1,32 @@
 use std::fmt::Display;
 
-use kiseki_common::{BLOCK_SIZE, CHUNK_SIZE, KISEKI, PAGE_SIZE};
+use kiseki_common::{BLOCK_SIZE, CHUNK_SIZE, KISEKI, KISEKI_DEBUG_OBJECT_STORAGE, PAGE_SIZE};
 use serde::{Deserialize, Serialize};
 
+/// [SCHEMA_VERSION] is the version of the layout of the metadata written by
+/// this build, volumes of an older version need to be upgraded before use.
+///
+/// - 0: values are bare bincode, the format is bincode or JSON without a schema
+///   version.
+/// - 1: structured values carry a version byte, the format is JSON.
+/// - 2: keys are binary with type tags and big-endian numbers.
+pub const SCHEMA_VERSION: u32 = 2;
+
 /// [Format] can be thought of as the configuration of the filesystem.
 /// We can set up different filesystems with different configurations
 /// on the same infrastructure, kind of like tenants. We can use
 /// Rocksdb's column family to implement this feature. But tikv doesn't
 /// open that feature yet. So there may some work to implement that.
+///
+/// It is stored as JSON, so new fields must have a sensible default for the
+/// volumes created before them.
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Format {
     /// [name] of the filesystem
-    pub name: String,
+    pub name:    String,
+    /// [storage] is the address of the object storage which holds the data.
+    pub storage: String,
 
     /// [chunk_size] is the max size can one buffer
     /// hold no matter it is for reading or writing.
@@ -32,17 +47,29 @@ pub struct Format {
     pub max_capacity: Option<usize>,
     /// [max_inodes] set limit on the number of inodes
     pub max_inodes:   Option<usize>,
+    /// [trash_days] is the number of days a removed file is kept in the
+    /// trash, at least 1.
+    pub trash_days:   usize,
+
+    /// [schema_version] is the layout version of the stored metadata, the
+    /// JSON formats written before it are of version 0.
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl Default for Format {
     fn default() -> Self {
         Format {
             name:         String::from(KISEKI),
+            storage:      String::from(KISEKI_DEBUG_OBJECT_STORAGE),
             chunk_size:   CHUNK_SIZE, // 64MB
             block_size:   BLOCK_SIZE, // 4MB
             page_size:    PAGE_SIZE,  // 64KB
             max_capacity: None,
             max_inodes:   None,
+            trash_days:   1,
+
+            schema_version: SCHEMA_VERSION,
         }
     }
 }
@@ -52,4 +79,26 @@ impl Format {
         self.name = name.to_string();
         self
     }
+
+    /// [immutable_changes] returns the fields which cannot be changed once
+    /// the filesystem holds data, but differ between self and the other.
+    pub fn immutable_changes(&self, other: &Format) -> Vec<&'static str> {
+        let mut changes = vec![];
+        if self.name != other.name {
+            changes.push("name");
+        }
+        if self.storage != other.storage {
+            changes.push("storage");
+        }
+        if self.chunk_size != other.chunk_size {
+            changes.push("chunk_size");
+        }
+        if self.block_size != other.block_size {
+            changes.push("block_size");
+        }
+        if self.page_size != other.page_size {
+            changes.push("page_size");
+        }
+        changes
+    }
 }
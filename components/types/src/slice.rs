@@ -1,9 +1,10 @@
 use std::{
-    cmp::Ordering,
+    cmp::{max, Ordering},
     collections::hash_map::DefaultHasher,
     fmt::{Debug, Display, Formatter},
     hash::{Hash, Hasher},
     num::ParseIntError,
+    ops::Range,
     str::FromStr,
     sync::Arc,
 };
@@ -90,6 +91,27 @@ impl Slices {
     }
 
     pub fn len(&self) -> usize { self.0.len() }
+
+    /// [seek_data] returns the first chunk position in the range which holds
+    /// data.
+    pub fn seek_data(&self, range: Range<usize>) -> Option<usize> {
+        self.overlook()
+            .overlapping(&range)
+            .find(|(_, s)| !s.is_hole())
+            .map(|(r, _)| max(r.start, range.start))
+    }
+
+    /// [seek_hole] returns the first chunk position in the range which holds
+    /// no data, either no slice covers it or it is covered by a hole.
+    pub fn seek_hole(&self, range: Range<usize>) -> Option<usize> {
+        let visible = self.overlook();
+        let gap = visible.gaps(&range).next().map(|r| r.start);
+        let hole = visible
+            .overlapping(&range)
+            .find(|(_, s)| s.is_hole())
+            .map(|(r, _)| max(r.start, range.start));
+        gap.into_iter().chain(hole).min()
+    }
 }
 
 #[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
@@ -105,8 +127,10 @@ pub enum Slice {
         _padding:  u64,
     },
     /// The slice is borrowed from other slice, built by File RangeCopy.
+    /// A borrowed slice with the [EMPTY_SLICE_ID] is a hole, which reads as
+    /// zeros.
     Borrowed {
-        /// The chunk position of the slice.
+        /// The chunk position where the borrowed part starts.
         chunk_pos: u32,
         /// The unique id of the slice.
         id:        SliceID,
@@ -164,6 +188,28 @@ impl Slice {
         }
     }
 
+    pub fn new_borrowed(
+        chunk_pos: usize,
+        slice_id: u64,
+        size: usize,
+        off: usize,
+        len: usize,
+    ) -> Self {
+        Slice::Borrowed {
+            chunk_pos: chunk_pos as u32,
+            id:        slice_id,
+            size:      size as u32,
+            off:       off as u32,
+            len:       len as u32,
+        }
+    }
+
+    /// [new_hole] makes a borrowed slice which reads as zeros, it is used to
+    /// overwrite the existing data with a hole.
+    pub fn new_hole(chunk_pos: usize, len: usize) -> Self {
+        Self::new_borrowed(chunk_pos, EMPTY_SLICE_ID, len, 0, len)
+    }
+
     pub fn decode(buf: &[u8]) -> Result<Self, Error> {
         ensure!(
             buf.len() >= SLICE_BYTES,
@@ -173,13 +219,28 @@ impl Slice {
         Ok(x)
     }
 
+    /// [encode] serializes the slice into [SLICE_BYTES] bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("slice should always be serializable")
+    }
+
     pub fn get_chunk_pos(&self) -> usize {
         (match self {
             Slice::Owned { chunk_pos, .. } => *chunk_pos,
-            Slice::Borrowed { chunk_pos, off, .. } => *chunk_pos + off,
+            Slice::Borrowed { chunk_pos, .. } => *chunk_pos,
         }) as usize
     }
 
+    /// [get_off] returns where the visible part starts in the underlying data.
+    pub fn get_off(&self) -> usize {
+        (match self {
+            Slice::Owned { .. } => 0,
+            Slice::Borrowed { off, .. } => *off,
+        }) as usize
+    }
+
+    pub fn is_hole(&self) -> bool { self.get_id() == EMPTY_SLICE_ID }
+
     pub fn get_id(&self) -> SliceID {
         (match self {
             Slice::Owned { id: slice_id, .. } => *slice_id,
@@ -203,6 +264,10 @@ impl Slice {
     }
 }
 
+/// [SLICE_BYTES] is the size of an encoded [Slice]. The slices of a chunk are
+/// stored as fixed width records appended one after another, the leading
+/// bincode enum tag tells the layout of each record, so a new layout has to
+/// be added as a new variant of the same size.
 pub const SLICE_BYTES: usize = 28;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord)]
@@ -344,4 +409,23 @@ mod tests {
         let slices2 = bincode::deserialize(&buf).unwrap();
         assert_eq!(slices, slices2);
     }
+
+    #[test]
+    fn seek_data_and_hole() {
+        let slices = Slices(vec![
+            Slice::new_owned(100, 1, 100),
+            Slice::new_hole(150, 20),
+            Slice::new_owned(300, 2, 100),
+        ]);
+        assert_eq!(slices.seek_data(0..1000), Some(100));
+        assert_eq!(slices.seek_data(160..1000), Some(170));
+        assert_eq!(slices.seek_data(210..1000), Some(300));
+        assert_eq!(slices.seek_data(400..1000), None);
+        assert_eq!(slices.seek_hole(0..1000), Some(0));
+        assert_eq!(slices.seek_hole(100..1000), Some(150));
+        assert_eq!(slices.seek_hole(170..1000), Some(200));
+        assert_eq!(slices.seek_hole(300..400), None);
+        assert_eq!(Slices(vec![]).seek_hole(10..20), Some(10));
+        assert_eq!(Slices(vec![]).seek_data(10..20), None);
+    }
 }
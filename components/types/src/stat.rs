@@ -10,6 +10,17 @@ pub struct DirStat {
     pub inodes: i64,
 }
 
+/// [Summary] sums up a file, or a directory and the tree under it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    pub files:  u64,
+    pub dirs:   u64,
+    /// The sum of the lengths of the files.
+    pub length: u64,
+    /// The space accounted for the inodes, the same as [FSStat::used_size].
+    pub size:   u64,
+}
+
 /// [FSStat] represents the filesystem statistics.
 #[derive(Clone, Copy)]
 pub struct FSStat {
@@ -0,0 +1,88 @@
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use kiseki_meta::context::FuseContext;
+use kiseki_types::{
+    accesslog::AccessRecord,
+    ino::{Ino, LOG_INODE},
+};
+use libc::{EBADF, EINTR};
+use snafu::OptionExt;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+use crate::{
+    err::{LibcSnafu, Result},
+    handle::InternalHandle,
+    KisekiVFS,
+};
+
+// How many lines a reader of the access log may fall behind, it loses the
+// older ones beyond that.
+pub(crate) const ACCESS_LOG_BUFFER: usize = 4096;
+
+impl KisekiVFS {
+    /// [log_access] counts the FUSE operation on the inode as served, and
+    /// writes a line of it to the `.accesslog` while someone reads the file.
+    /// The lines aren't filtered by the reader, the file is readable by root
+    /// only.
+    pub fn log_access(&self, ctx: &FuseContext, op: &str, inode: Ino) {
+        let latency = ctx.start_at.elapsed();
+        self.stats.served(latency);
+        // the reads of the log would feed the log itself.
+        if inode == LOG_INODE || self.access_log.receiver_count() == 0 {
+            return;
+        }
+        let record = AccessRecord {
+            time: SystemTime::now(),
+            uid: ctx.uid,
+            gid: ctx.gid,
+            pid: ctx.pid,
+            op: op.to_string(),
+            inode,
+            latency,
+        };
+        let _ = self.access_log.send(format!("{record}\n"));
+    }
+
+    /// [read_access_log] waits for the lines logged since the handle is
+    /// opened, and returns at most size bytes of them. The caller shouldn't
+    /// count the read against the limit of the requests served at once, the
+    /// lines come from the other requests.
+    pub(crate) async fn read_access_log(
+        &self,
+        ctx: &FuseContext,
+        h: &InternalHandle,
+        size: usize,
+    ) -> Result<Bytes> {
+        let mut inner = h.inner.write().await;
+        let inner = &mut *inner;
+        let log = inner.log.as_mut().context(LibcSnafu { errno: EBADF })?;
+        if inner.data.is_empty() {
+            // an empty read ends the reader, so wait for a line.
+            let line = tokio::select! {
+                line = log.recv() => line,
+                _ = ctx.cancellation_token.cancelled() => {
+                    return LibcSnafu { errno: EINTR }.fail();
+                }
+            };
+            match line {
+                Ok(line) => inner.data.extend_from_slice(line.as_bytes()),
+                Err(RecvError::Lagged(n)) => inner
+                    .data
+                    .extend_from_slice(format!("# {n} lines dropped\n").as_bytes()),
+                Err(RecvError::Closed) => return Ok(Bytes::new()),
+            }
+        }
+        while inner.data.len() < size {
+            match log.try_recv() {
+                Ok(line) => inner.data.extend_from_slice(line.as_bytes()),
+                Err(TryRecvError::Lagged(n)) => inner
+                    .data
+                    .extend_from_slice(format!("# {n} lines dropped\n").as_bytes()),
+                Err(_) => break,
+            }
+        }
+        let n = size.min(inner.data.len());
+        Ok(inner.data.drain(..n).collect::<Vec<_>>().into())
+    }
+}
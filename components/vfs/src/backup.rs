@@ -0,0 +1,241 @@
+use std::{
+    cmp::min,
+    io,
+    io::{BufWriter, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use futures::TryStreamExt;
+use kiseki_meta::MetaEngineRef;
+use kiseki_utils::object_storage::{ObjectStorage, ObjectStoragePath};
+use snafu::ResultExt;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tracing::{debug, error, info};
+
+use crate::err::{IOSnafu, JoinErrSnafu, MetaSnafu, ObjectStorageSnafu, Result};
+
+// The directory in the bucket which holds the metadata backups.
+const BACKUP_DIR: &str = "meta";
+const BACKUP_PREFIX: &str = "dump-";
+const BACKUP_SUFFIX: &str = ".json.gz";
+// The longest time a client waits before checking whether it should back up.
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// The compressed dump is handed over to the upload in parts of this size, at
+// most UPLOAD_QUEUE_SIZE of them wait to be uploaded.
+const UPLOAD_PART_SIZE: usize = 4 << 20;
+const UPLOAD_QUEUE_SIZE: usize = 4;
+
+/// [spawn_backup_task] backs up the metadata into the object storage every
+/// `interval`, and keeps the latest `copies` of them, one at least. All
+/// clients of the volume run this task, the backend elects one of them for
+/// each round.
+pub(crate) fn spawn_backup_task(
+    meta: MetaEngineRef,
+    object_storage: ObjectStorage,
+    interval: Duration,
+    copies: usize,
+) {
+    if interval.is_zero() {
+        debug!("metadata backup is disabled");
+        return;
+    }
+    // never remove the backup just uploaded.
+    let copies = copies.max(1);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(min(interval, BACKUP_CHECK_INTERVAL));
+        loop {
+            ticker.tick().await;
+            match meta.try_elect_backup(interval).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("failed to elect the backup client: {:?}", e);
+                    continue;
+                }
+            }
+            match backup_meta(meta.clone(), object_storage.clone()).await {
+                Ok(path) => info!("backup metadata to {}", path),
+                Err(e) => {
+                    error!("failed to backup metadata: {:?}", e);
+                    continue;
+                }
+            }
+            if let Err(e) = meta.finish_backup().await {
+                error!("failed to record the metadata backup: {:?}", e);
+            }
+            if let Err(e) = cleanup_backups(&object_storage, copies).await {
+                error!("failed to clean up old metadata backups: {:?}", e);
+            }
+        }
+    });
+}
+
+/// [backup_meta] uploads a gzipped dump of the metadata as
+/// `meta/dump-<unix timestamp>.json.gz`, it can be restored by
+/// `kiseki restore`. The dump is uploaded while it is being written, the
+/// upload is aborted if either side fails.
+pub(crate) async fn backup_meta(
+    meta: MetaEngineRef,
+    object_storage: ObjectStorage,
+) -> Result<ObjectStoragePath> {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = ObjectStoragePath::from_iter([
+        BACKUP_DIR.to_string(),
+        format!("{}{}{}", BACKUP_PREFIX, ts, BACKUP_SUFFIX),
+    ]);
+    let (id, mut writer) = object_storage
+        .put_multipart(&path)
+        .await
+        .context(ObjectStorageSnafu)?;
+
+    let (tx, mut rx) = mpsc::channel(UPLOAD_QUEUE_SIZE);
+    let dumping = tokio::task::spawn_blocking(move || {
+        let parts = BufWriter::with_capacity(UPLOAD_PART_SIZE, PartSender(tx));
+        let mut encoder = GzEncoder::new(parts, Compression::default());
+        meta.dump(&mut encoder).context(MetaSnafu)?;
+        encoder.finish().context(IOSnafu)?.flush().context(IOSnafu)
+    });
+    let uploaded = async {
+        while let Some(part) = rx.recv().await {
+            writer.write_all(&part).await.context(IOSnafu)?;
+        }
+        writer.shutdown().await.context(IOSnafu)
+    }
+    .await;
+    // the dump stops at its next write once the upload is gone.
+    drop(rx);
+    let dumped = dumping.await.context(JoinErrSnafu).and_then(|r| r);
+
+    if let Err(e) = dumped.and(uploaded) {
+        if let Err(e) = object_storage.abort_multipart(&path, &id).await {
+            debug!("failed to abort the upload of {}: {:?}", path, e);
+        }
+        return Err(e);
+    }
+    Ok(path)
+}
+
+// PartSender hands the parts of the dump over to the upload.
+struct PartSender(mpsc::Sender<Vec<u8>>);
+
+impl Write for PartSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// [cleanup_backups] keeps the latest `copies` backups and removes the rest.
+async fn cleanup_backups(object_storage: &ObjectStorage, copies: usize) -> Result<()> {
+    let dir = ObjectStoragePath::from(BACKUP_DIR);
+    let mut backups = object_storage
+        .list(Some(&dir))
+        .try_filter_map(|m| async move {
+            Ok(m.location
+                .filename()
+                .and_then(parse_backup_timestamp)
+                .map(|ts| (ts, m.location.clone())))
+        })
+        .try_collect::<Vec<_>>()
+        .await
+        .context(ObjectStorageSnafu)?;
+    if backups.len() <= copies {
+        return Ok(());
+    }
+
+    backups.sort_by_key(|(ts, _)| *ts);
+    let expired = backups.len() - copies;
+    for (_, path) in backups.into_iter().take(expired) {
+        debug!("remove expired metadata backup {}", path);
+        object_storage
+            .delete(&path)
+            .await
+            .context(ObjectStorageSnafu)?;
+    }
+    Ok(())
+}
+
+fn parse_backup_timestamp(filename: &str) -> Option<u64> {
+    filename
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, sync::Arc};
+
+    use bytes::Bytes;
+    use flate2::read::GzDecoder;
+    use kiseki_meta::{
+        context::FuseContext,
+        test_util::{mkdir, test_dsn, test_meta},
+        MetaConfig,
+    };
+    use kiseki_types::ino::ROOT_INO;
+    use kiseki_utils::object_storage::new_memory_object_store;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn backup_then_load() {
+        let (_src_dir, meta) = test_meta();
+        let ctx = Arc::new(FuseContext::background());
+        let inode = mkdir(&meta, &ctx, ROOT_INO, "d").await;
+
+        let sto = new_memory_object_store();
+        let path = backup_meta(meta, sto.clone()).await.unwrap();
+        let buf = sto.get(&path).await.unwrap().bytes().await.unwrap();
+
+        let (_dst_dir, dst_dsn) = test_dsn();
+        kiseki_meta::load(&dst_dsn, GzDecoder::new(buf.as_ref())).unwrap();
+        let mut config = MetaConfig::default();
+        config.with_dsn(&dst_dsn);
+        let loaded = kiseki_meta::open(config).unwrap();
+        let (found, _) = loaded
+            .lookup(ctx, ROOT_INO, OsStr::new("d"), true)
+            .await
+            .unwrap();
+        assert_eq!(found, inode);
+    }
+
+    #[tokio::test]
+    async fn keep_latest_backups() {
+        let sto = new_memory_object_store();
+        for ts in [3, 1, 4, 2, 5] {
+            let path = ObjectStoragePath::from_iter([
+                BACKUP_DIR.to_string(),
+                format!("{}{}{}", BACKUP_PREFIX, ts, BACKUP_SUFFIX),
+            ]);
+            sto.put(&path, Bytes::from_static(b"{}")).await.unwrap();
+        }
+        // not a backup, should be left alone.
+        sto.put(
+            &ObjectStoragePath::from("meta/readme"),
+            Bytes::from_static(b""),
+        )
+        .await
+        .unwrap();
+
+        cleanup_backups(&sto, 2).await.unwrap();
+
+        let mut left = sto
+            .list(Some(&ObjectStoragePath::from(BACKUP_DIR)))
+            .map_ok(|m| m.location.filename().unwrap().to_string())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        left.sort();
+        assert_eq!(left, vec!["dump-4.json.gz", "dump-5.json.gz", "readme"]);
+    }
+}
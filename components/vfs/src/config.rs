@@ -5,7 +5,11 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// How often the metadata gets backed up into the object storage,
+    /// zero disables the backup.
     pub backup_meta_interval: Duration,
+    /// How many metadata backups to keep, at least 1.
+    pub backup_meta_copies:   usize,
     pub prefix_internal:      bool,
     pub hide_internal:        bool,
 
@@ -46,6 +50,7 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             backup_meta_interval:  Default::default(),
+            backup_meta_copies:    7,
             prefix_internal:       false,
             hide_internal:         false,
             attr_timeout:          Duration::from_secs(1),
@@ -61,6 +66,15 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    /// [to_json] is what the `.config` of the mount reads.
+    pub(crate) fn to_json(&self) -> Vec<u8> {
+        let mut buf = serde_json::to_vec_pretty(self).expect("unable to serialize vfs config");
+        buf.push(b'\n');
+        buf
+    }
+}
+
 /// Divide cpu num by a non-zero `divisor` and returns at least 1.
 fn divide_num_cpus(divisor: usize) -> usize {
     debug_assert!(divisor > 0);
@@ -0,0 +1,296 @@
+use std::{
+    ffi::{OsStr, OsString},
+    os::unix::ffi::OsStrExt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use kiseki_common::BLOCK_SIZE;
+use kiseki_meta::context::FuseContext;
+use kiseki_types::{
+    control::{decode_frame, encode_frame, ControlRequest, ControlResponse},
+    entry::Entry,
+    ino::Ino,
+    ToErrno,
+};
+use kiseki_utils::readable_size::ReadableSize;
+use snafu::{ensure, ResultExt};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    err::{Error, JoinErrSnafu, LibcSnafu, MetaSnafu, Result},
+    handle::{is_dot_entry, InternalHandle},
+    KisekiVFS,
+};
+
+// How many entries are listed from the meta engine at a time by warmup.
+const WARMUP_PAGE_SIZE: usize = 1024;
+// The longest time a read waits for a background job before it returns the
+// progress instead.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// [ControlJob] is a request running in the background of its handle, it is
+/// cancelled once the handle goes away.
+pub(crate) struct ControlJob {
+    task:     JoinHandle<Result<String>>,
+    // renders the progress of the job so far.
+    progress: Box<dyn Fn() -> String + Send + Sync>,
+    cancel:   CancellationToken,
+}
+
+impl Drop for ControlJob {
+    fn drop(&mut self) { self.cancel.cancel(); }
+}
+
+// WarmupProgress counts what the warmup has read so far.
+#[derive(Default)]
+struct WarmupProgress {
+    files: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl WarmupProgress {
+    fn render(&self) -> String {
+        format!(
+            "files: {}\nlength: {}",
+            self.files.load(Ordering::Relaxed),
+            ReadableSize(self.bytes.load(Ordering::Relaxed))
+        )
+    }
+}
+
+impl KisekiVFS {
+    /// [write_control] appends the data to the request written to the
+    /// `.control`, and runs the request once its frame is complete. The
+    /// response is read back from the same handle, the requests which walk
+    /// a tree are left to a background job.
+    pub(crate) async fn write_control(
+        self: &Arc<Self>,
+        ctx: Arc<FuseContext>,
+        h: &InternalHandle,
+        data: &[u8],
+    ) -> Result<()> {
+        // the requests on the same handle run one by one.
+        let mut inner = h.inner.write().await;
+        ensure!(inner.job.is_none(), LibcSnafu { errno: libc::EBUSY });
+        inner.request.extend_from_slice(data);
+        let req = match decode_frame::<ControlRequest>(&inner.request) {
+            None => return Ok(()),
+            Some(req) => req,
+        };
+        inner.request.clear();
+        let req = req.map_err(|errno| LibcSnafu { errno }.build())?;
+        info!(
+            uid = ctx.uid,
+            pid = ctx.pid,
+            "run control request {:?}",
+            req
+        );
+        inner.data.clear();
+        match req {
+            ControlRequest::Rmr { .. } | ControlRequest::Warmup { .. } => {
+                inner.job = Some(self.spawn_control_job(&ctx, req));
+            }
+            req => inner.data = encode_frame(&control_response(self.run_control(ctx, req).await)),
+        }
+        Ok(())
+    }
+
+    /// [read_control] reads the response of the request on the handle. While
+    /// its job is running, it waits a while for the job, and returns the
+    /// progress if the job is still not done.
+    pub(crate) async fn read_control(&self, h: &InternalHandle, size: usize) -> Result<Bytes> {
+        let mut inner = h.inner.write().await;
+        if inner.data.is_empty() {
+            if let Some(job) = inner.job.as_mut() {
+                let resp = match tokio::time::timeout(PROGRESS_INTERVAL, &mut job.task).await {
+                    Ok(r) => {
+                        let r = r.context(JoinErrSnafu)?;
+                        inner.job = None;
+                        control_response(r)
+                    }
+                    Err(_) => ControlResponse::progress((job.progress)()),
+                };
+                inner.data = encode_frame(&resp);
+            }
+        }
+        let n = size.min(inner.data.len());
+        Ok(inner.data.drain(..n).collect::<Vec<_>>().into())
+    }
+
+    // spawn_control_job runs the request in the background, with the
+    // permissions of the caller, until it is done or its handle goes away.
+    fn spawn_control_job(self: &Arc<Self>, ctx: &FuseContext, req: ControlRequest) -> ControlJob {
+        let cancel = CancellationToken::new();
+        let mut job_ctx = ctx.clone();
+        job_ctx.cancellation_token = cancel.clone();
+        let ctx = Arc::new(job_ctx);
+        let vfs = self.clone();
+        let (task, progress): (_, Box<dyn Fn() -> String + Send + Sync>) = match req {
+            ControlRequest::Rmr { parent, name } => {
+                let removed = Arc::new(AtomicU64::new(0));
+                let progress = removed.clone();
+                let task = tokio::spawn(async move {
+                    let name = OsStr::from_bytes(&name);
+                    ensure!(
+                        !parent.is_special()
+                            && !(parent.is_root() && vfs.internal_nodes.contains_name(name)),
+                        LibcSnafu { errno: libc::EPERM }
+                    );
+                    let removed = vfs
+                        .meta
+                        .remove_tree(ctx, parent, name, &removed)
+                        .await
+                        .context(MetaSnafu)?;
+                    Ok(format!("removed: {removed}"))
+                });
+                let render = move || format!("removed: {}", progress.load(Ordering::Relaxed));
+                (task, Box::new(render))
+            }
+            ControlRequest::Warmup { inode } => {
+                let warmed = Arc::new(WarmupProgress::default());
+                let progress = warmed.clone();
+                let task = tokio::spawn(async move {
+                    vfs.warmup(&ctx, inode, &warmed).await?;
+                    Ok(warmed.render())
+                });
+                (task, Box::new(move || progress.render()))
+            }
+            req => unreachable!("{:?} doesn't run in the background", req),
+        };
+        ControlJob {
+            task,
+            progress,
+            cancel,
+        }
+    }
+
+    async fn run_control(&self, ctx: Arc<FuseContext>, req: ControlRequest) -> Result<String> {
+        match req {
+            ControlRequest::Info { inode } => {
+                let summary = self.meta.summary(&ctx, inode).await.context(MetaSnafu)?;
+                Ok(format!(
+                    "inode: {}\nfiles: {}\ndirs: {}\nlength: {}\nsize: {}",
+                    inode,
+                    summary.files,
+                    summary.dirs,
+                    ReadableSize(summary.length),
+                    ReadableSize(summary.size)
+                ))
+            }
+            ControlRequest::Compact { inode } => {
+                let freed = self.meta.compact(&ctx, inode).await.context(MetaSnafu)?;
+                Ok(format!("freed slices: {freed}"))
+            }
+            ControlRequest::Gc => {
+                let (files, freed) = self.meta.gc(&ctx).await.context(MetaSnafu)?;
+                Ok(format!("cleaned files: {files}\nfreed slices: {freed}"))
+            }
+            ControlRequest::Flush { inode } => {
+                ensure!(!inode.is_special(), LibcSnafu { errno: libc::EPERM });
+                self.data_manager.direct_flush(inode).await?;
+                Ok(format!("flushed: {inode}"))
+            }
+            req => unreachable!("{:?} runs in the background", req),
+        }
+    }
+
+    // warmup reads the file, or the files under the directory, into the
+    // cache, counting the files and bytes read.
+    async fn warmup(
+        &self,
+        ctx: &Arc<FuseContext>,
+        inode: Ino,
+        warmed: &WarmupProgress,
+    ) -> Result<()> {
+        ensure!(!inode.is_special(), LibcSnafu { errno: libc::EPERM });
+        let attr = self.meta.get_attr(inode).await.context(MetaSnafu)?;
+        let warmup_file = |inode| async move {
+            ensure!(!ctx.is_cancelled(), LibcSnafu { errno: libc::EINTR });
+            let bytes = self.warmup_file(ctx, inode).await?;
+            warmed.files.fetch_add(1, Ordering::Relaxed);
+            warmed.bytes.fetch_add(bytes, Ordering::Relaxed);
+            Ok::<_, Error>(())
+        };
+        let mut dirs = vec![];
+        if attr.is_dir() {
+            dirs.push(inode);
+        } else if attr.is_file() {
+            warmup_file(inode).await?;
+        }
+        while let Some(dir) = dirs.pop() {
+            let mut after: Option<OsString> = None;
+            loop {
+                let page = self
+                    .meta
+                    .read_dir(ctx, dir, true, after.as_deref(), WARMUP_PAGE_SIZE)
+                    .await
+                    .context(MetaSnafu)?;
+                after = page.cursor().map(OsStr::to_os_string);
+                for entry in page.entries.iter().filter(|e| !is_dot_entry(e)) {
+                    let Entry::Full(entry) = entry else {
+                        continue;
+                    };
+                    if entry.attr.is_dir() {
+                        dirs.push(entry.inode);
+                    } else if entry.attr.is_file() {
+                        warmup_file(entry.inode).await?;
+                    }
+                }
+                if page.eof {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // warmup_file reads the whole file through a handle of its own.
+    async fn warmup_file(&self, ctx: &Arc<FuseContext>, inode: Ino) -> Result<u64> {
+        let opened = self.open(ctx, inode, libc::O_RDONLY).await?;
+        let length = opened.attr.length;
+        let (mut offset, mut read) = (0, Ok(()));
+        while offset < length && read.is_ok() {
+            let size = (length - offset).min(BLOCK_SIZE as u64);
+            read = self
+                .read(
+                    ctx.clone(),
+                    inode,
+                    opened.fh,
+                    offset as i64,
+                    size as u32,
+                    0,
+                    None,
+                )
+                .await
+                .map(|_| ());
+            offset += size;
+        }
+        self.release(ctx.clone(), inode, opened.fh)
+            .await?
+            .await
+            .context(JoinErrSnafu)?;
+        read.map(|_| length)
+    }
+}
+
+// control_response turns the result of a request into its response.
+fn control_response(r: Result<String>) -> ControlResponse {
+    match r {
+        Ok(message) => ControlResponse::ok(message),
+        Err(e) => {
+            debug!("control request failed: {}", e);
+            let errno = match &e {
+                Error::LibcError { .. } | Error::MetaError { .. } => e.to_errno(),
+                _ => libc::EIO,
+            };
+            ControlResponse::err(errno, e.to_string())
+        }
+    }
+}
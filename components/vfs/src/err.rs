@@ -22,6 +22,12 @@ pub enum Error {
         source:   kiseki_utils::object_storage::ObjectStorageError,
     },
 
+    IOError {
+        #[snafu(implicit)]
+        location: Location,
+        source:   std::io::Error,
+    },
+
     ObjectBlockNotFound {
         #[snafu(implicit)]
         location: Location,
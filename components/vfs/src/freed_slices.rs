@@ -0,0 +1,114 @@
+use std::{cmp::min, time::Duration};
+
+use kiseki_meta::MetaEngineRef;
+use kiseki_types::slice::{SliceID, SliceKey};
+use kiseki_utils::object_storage::{is_not_found_error, ObjectStorage};
+use snafu::ResultExt;
+use tracing::{debug, error};
+
+use crate::err::{MetaSnafu, ObjectStorageSnafu, Result};
+
+// How often the freed slices are looked for.
+const DELETE_INTERVAL: Duration = Duration::from_secs(10);
+const DELETE_BATCH: usize = 1024;
+
+/// [spawn_delete_task] deletes the objects of the slices which no one
+/// references anymore, as the files are removed, overwritten or compacted.
+/// The slices are queued in the meta engine, so the ones left by a crashed
+/// client are deleted by the others.
+pub(crate) fn spawn_delete_task(
+    meta: MetaEngineRef,
+    object_storage: ObjectStorage,
+    block_size: usize,
+) {
+    if meta.get_config().read_only {
+        debug!("the freed slices are left to the writable clients");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(DELETE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = delete_freed_slices(&meta, &object_storage, block_size).await {
+                error!("failed to delete the freed slices: {:?}", e);
+            }
+        }
+    });
+}
+
+// delete_freed_slices deletes the objects of all the queued slices, the
+// slices are dropped from the queue once their objects are gone.
+async fn delete_freed_slices(
+    meta: &MetaEngineRef,
+    object_storage: &ObjectStorage,
+    block_size: usize,
+) -> Result<()> {
+    loop {
+        let slices = meta
+            .list_freed_slices(DELETE_BATCH)
+            .await
+            .context(MetaSnafu)?;
+        let mut deleted = Vec::with_capacity(slices.len());
+        for (slice_id, size) in &slices {
+            delete_slice_objects(object_storage, *slice_id, *size, block_size).await?;
+            deleted.push(*slice_id);
+        }
+        meta.forget_freed_slices(&deleted)
+            .await
+            .context(MetaSnafu)?;
+        if !deleted.is_empty() {
+            debug!("delete the objects of {} freed slices", deleted.len());
+        }
+        if slices.len() < DELETE_BATCH {
+            return Ok(());
+        }
+    }
+}
+
+// delete_slice_objects deletes the blocks of the slice, the missing ones are
+// skipped as the slice may not be fully uploaded, or deleted before.
+async fn delete_slice_objects(
+    object_storage: &ObjectStorage,
+    slice_id: SliceID,
+    size: usize,
+    block_size: usize,
+) -> Result<()> {
+    for block_idx in 0..size.div_ceil(block_size) {
+        let len = min(size - block_idx * block_size, block_size);
+        let path = SliceKey::new(slice_id, block_idx, len).make_object_storage_path();
+        match object_storage.delete(&path).await {
+            Err(e) if !is_not_found_error(&e) => return Err(e).context(ObjectStorageSnafu),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use kiseki_utils::object_storage::new_memory_object_store;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn delete_all_blocks() {
+        let sto = new_memory_object_store();
+        // slice 7 of 10 bytes in blocks of 4 bytes, and a block of slice 8.
+        for (slice_id, block_idx, len) in [(7, 0, 4), (7, 1, 4), (7, 2, 2), (8, 0, 4)] {
+            let path = SliceKey::new(slice_id, block_idx, len).make_object_storage_path();
+            sto.put(&path, Bytes::from(vec![0; len])).await.unwrap();
+        }
+
+        delete_slice_objects(&sto, 7, 10, 4).await.unwrap();
+        let left = sto.list(None).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(
+            left[0].location,
+            SliceKey::new(8, 0, 4).make_object_storage_path()
+        );
+        // the blocks gone already are skipped.
+        delete_slice_objects(&sto, 7, 10, 4).await.unwrap();
+    }
+}
@@ -14,6 +14,7 @@
 
 use std::{
     collections::{BTreeMap, HashMap},
+    ffi::OsString,
     fmt::Debug,
     marker::PhantomData,
     sync::{
@@ -22,21 +23,23 @@ use std::{
     },
 };
 
+use bytes::Bytes;
 use dashmap::DashMap;
-use kiseki_common::FH;
+use kiseki_common::{DOT, DOT_DOT, FH};
 use kiseki_meta::context::FuseContext;
 use kiseki_types::{entry::Entry, ino::Ino};
 use kiseki_utils::readable_size::ReadableSize;
 use libc::clone;
 use snafu::ResultExt;
 use tokio::{
-    sync::{Notify, RwLock},
+    sync::{broadcast, Notify, RwLock},
     time::Instant,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, instrument, Instrument};
 
 use crate::{
+    control::ControlJob,
     data_manager::DataManagerRef,
     err::{Error, JoinErrSnafu, LibcSnafu, Result},
     reader::FileReader,
@@ -48,6 +51,8 @@ pub(crate) type HandleTableRef = Arc<HandleTable>;
 pub(crate) struct HandleTable {
     data_manager: DataManagerRef,
     handles:      RwLock<HashMap<Ino, Arc<RwLock<BTreeMap<FH, Handle>>>>>,
+    // how many handles are in the table.
+    count:        AtomicU64,
     _next_fh:     AtomicU64,
 }
 
@@ -56,12 +61,16 @@ impl HandleTable {
         Arc::new(HandleTable {
             data_manager: data_manager_ref,
             handles:      Default::default(),
+            count:        AtomicU64::new(0),
             _next_fh:     AtomicU64::new(1),
         })
     }
 
     fn next_fh(&self) -> FH { self._next_fh.fetch_add(1, Ordering::SeqCst) }
 
+    /// [count] returns how many handles are open.
+    pub(crate) fn count(&self) -> u64 { self.count.load(Ordering::Relaxed) }
+
     pub(crate) async fn new_dir_handle(self: &Arc<Self>, inode: Ino) -> FH {
         let fh = self.next_fh();
         let dir_handle = Handle::Dir(Arc::new(DirHandle::new(inode, fh)));
@@ -69,6 +78,20 @@ impl HandleTable {
         fh
     }
 
+    /// [new_internal_handle] opens an internal node, the data is what the
+    /// handle reads first, and the log is where the access log comes from.
+    pub(crate) async fn new_internal_handle(
+        self: &Arc<Self>,
+        inode: Ino,
+        data: Vec<u8>,
+        log: Option<broadcast::Receiver<String>>,
+    ) -> FH {
+        let fh = self.next_fh();
+        let internal_handle = Handle::Internal(Arc::new(InternalHandle::new(inode, fh, data, log)));
+        self.insert_handle(inode, fh, internal_handle).await;
+        fh
+    }
+
     pub(crate) async fn new_file_handle(
         self: &Arc<Self>,
         inode: Ino,
@@ -124,6 +147,7 @@ impl HandleTable {
         let mut write_guard = inner_map.write().await;
         write_guard.insert(fh, handle);
         drop(write_guard);
+        self.count.fetch_add(1, Ordering::Relaxed);
     }
 
     pub(crate) async fn find_handle(&self, ino: Ino, fh: u64) -> Option<Handle> {
@@ -158,6 +182,7 @@ impl HandleTable {
             let mut inner_write_guard = inner_map.write().await;
             if let Some(h) = inner_write_guard.remove(&fh) {
                 drop(inner_write_guard);
+                self.count.fetch_sub(1, Ordering::Relaxed);
                 // remove the handle from the handle table.
                 if let Some(fh) = h.as_file_handle() {
                     if let Err(e) = fh.wait_all_operations_done(None).await {
@@ -174,6 +199,7 @@ impl HandleTable {
 pub(crate) enum Handle {
     File(Arc<FileHandle>),
     Dir(Arc<DirHandle>),
+    Internal(Arc<InternalHandle>),
 }
 
 impl Handle {
@@ -181,6 +207,7 @@ impl Handle {
         match self {
             Handle::File(h) => h.fh,
             Handle::Dir(h) => h.fh,
+            Handle::Internal(h) => h.fh,
         }
     }
 
@@ -188,6 +215,7 @@ impl Handle {
         match self {
             Handle::File(h) => h.inode,
             Handle::Dir(h) => h.inode,
+            Handle::Internal(h) => h.inode,
         }
     }
 
@@ -205,10 +233,17 @@ impl Handle {
         }
     }
 
+    pub(crate) fn as_internal_handle(&self) -> Option<Arc<InternalHandle>> {
+        match self {
+            Handle::Internal(h) => Some(h.clone()),
+            _ => None,
+        }
+    }
+
     pub(crate) async fn wait_all_operations_done(&self, ctx: Arc<FuseContext>) -> Result<()> {
         match self {
             Handle::File(h) => h.wait_all_operations_done(Some(ctx)).await,
-            Handle::Dir(_) => return Ok(()),
+            Handle::Dir(_) | Handle::Internal(_) => return Ok(()),
         }
     }
 }
@@ -518,6 +553,9 @@ impl DirHandle {
             inode,
             inner: RwLock::new(DirHandleInner {
                 children:  Vec::new(),
+                plus:      false,
+                cursor:    None,
+                eof:       false,
                 read_at:   None,
                 ofd_owner: 0,
             }),
@@ -525,8 +563,78 @@ impl DirHandle {
     }
 }
 
+/// [DirHandleInner] holds the listing snapshot of the directory, which is
+/// taken page by page and only ever appended to until the listing restarts
+/// from offset 0. The entry at `children[i]` is always served at the FUSE
+/// offset `i`, and the name of the last entry listed from the meta engine is
+/// the cursor for the next page.
 pub(crate) struct DirHandleInner {
     pub(crate) children:  Vec<Entry>,
+    // whether the children carry their attributes.
+    pub(crate) plus:      bool,
+    // where the next page starts, the name of the last entry listed.
+    pub(crate) cursor:    Option<OsString>,
+    // the whole directory has been listed.
+    pub(crate) eof:       bool,
     pub(crate) read_at:   Option<Instant>,
     pub(crate) ofd_owner: u64, // OFD lock
 }
+
+impl DirHandleInner {
+    pub(crate) fn reset(&mut self, plus: bool) {
+        self.children.clear();
+        self.plus = plus;
+        self.cursor = None;
+        self.eof = false;
+        self.read_at = Some(Instant::now());
+    }
+}
+
+/// [InternalHandle] is opened on an internal node, whose data comes from the
+/// mount itself instead of the object storage, so the offsets don't matter:
+/// the writes are appended to the request, and the reads consume the data.
+pub(crate) struct InternalHandle {
+    fh:               FH,
+    inode:            Ino,
+    pub(crate) inner: RwLock<InternalHandleInner>,
+}
+
+impl InternalHandle {
+    fn new(inode: Ino, fh: FH, data: Vec<u8>, log: Option<broadcast::Receiver<String>>) -> Self {
+        InternalHandle {
+            fh,
+            inode,
+            inner: RwLock::new(InternalHandleInner {
+                request: Vec::new(),
+                data,
+                log,
+                job: None,
+            }),
+        }
+    }
+
+    /// [read] takes at most size bytes of the data left.
+    pub(crate) async fn read(&self, size: usize) -> Bytes {
+        let mut inner = self.inner.write().await;
+        let n = size.min(inner.data.len());
+        inner.data.drain(..n).collect::<Vec<_>>().into()
+    }
+}
+
+pub(crate) struct InternalHandleInner {
+    // the request written so far, it waits for the rest of its frame.
+    pub(crate) request: Vec<u8>,
+    // the data left to read.
+    pub(crate) data:    Vec<u8>,
+    // the lines of the access log yet to be read, only for the `.accesslog`.
+    pub(crate) log:     Option<broadcast::Receiver<String>>,
+    // the request running in the background, only for the `.control`.
+    pub(crate) job:     Option<ControlJob>,
+}
+
+/// [is_dot_entry] tells whether the entry is the "." or ".." which starts
+/// the listing.
+pub(crate) fn is_dot_entry(entry: &Entry) -> bool {
+    let name = entry.get_name();
+    name == DOT || name == DOT_DOT
+}
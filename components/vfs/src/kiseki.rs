@@ -14,6 +14,7 @@
 
 use std::{
     collections::HashMap,
+    ffi::OsStr,
     fmt::{Debug, Display, Formatter},
     path::{Path, PathBuf},
     sync::{
@@ -30,45 +31,61 @@ use kiseki_common::{
     DOT, DOT_DOT, FH, MAX_FILE_SIZE, MAX_NAME_LENGTH, MAX_SYMLINK_LEN, MODE_MASK_R, MODE_MASK_W,
     MODE_MASK_X,
 };
-use kiseki_meta::{context::FuseContext, MetaEngineRef};
+use kiseki_meta::{context::FuseContext, Change, MetaEngineRef};
 use kiseki_storage::slice_buffer::SliceBuffer;
 use kiseki_types::{
-    attr::{InodeAttr, SetAttrFlags},
+    attr::{Flags, InodeAttr, SetAttrFlags},
     entry::{Entry, FullEntry},
-    ino::{Ino, CONTROL_INODE, ROOT_INO},
+    ino::{Ino, CONFIG_INODE, CONTROL_INODE, LOG_INODE, ROOT_INO, STATS_INODE},
     internal_nodes::{InternalNodeTable, CONFIG_INODE_NAME, CONTROL_INODE_NAME},
     slice::SliceID,
     ToErrno,
 };
 use kiseki_utils::{object_storage, object_storage::ObjectStorage};
-use libc::{mode_t, EACCES, EBADF, EFBIG, EINTR, EINVAL, ENOENT, EPERM};
+use libc::{mode_t, EACCES, EBADF, EFBIG, EINTR, EINVAL, ENOENT, ENOTTY, EPERM};
 use scopeguard::defer;
 use snafu::{ensure, location, Location, OptionExt, ResultExt};
-use tokio::{task::JoinHandle, time::Instant};
+use tokio::{sync::broadcast, task::JoinHandle};
 use tracing::{debug, error, info, instrument, trace, Instrument};
 
 use crate::{
+    accesslog::ACCESS_LOG_BUFFER,
+    backup,
     config::Config,
     data_manager::{DataManager, DataManagerRef},
     err::{
         Error, Error::LibcError, JoinErrSnafu, LibcSnafu, MetaSnafu, ObjectStorageSnafu,
         OpenDalSnafu, Result, StorageSnafu,
     },
+    freed_slices,
     handle::{FileHandleWriteGuard, Handle, HandleTable, HandleTableRef},
+    stats::VfsStats,
     writer::{FileWriter, FileWritersRef},
 };
 
+// The interval to write the buffered atime updates to the meta backend.
+const ATIME_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// How many entries are listed from the meta engine at a time by readdir.
+const READDIR_PAGE_SIZE: usize = 1024;
+
 pub struct KisekiVFS {
     pub config: Config,
 
     // Runtime status
-    internal_nodes:          InternalNodeTable,
-    modified_at:             DashMap<Ino, std::time::Instant>,
-    pub(crate) handle_table: HandleTableRef,
-    pub(crate) data_manager: DataManagerRef,
+    pub(crate) internal_nodes: InternalNodeTable,
+    modified_at:               DashMap<Ino, std::time::Instant>,
+    // how many times each inode has been handed to the kernel, the state
+    // kept for an inode goes away once the kernel forgets all of them.
+    lookups:                   DashMap<Ino, u64>,
+    pub(crate) handle_table:   HandleTableRef,
+    pub(crate) data_manager:   DataManagerRef,
+    pub(crate) stats:          VfsStats,
+    // the lines of the access log go to the readers of the `.accesslog`.
+    pub(crate) access_log:     broadcast::Sender<String>,
 
     // Dependencies
-    pub(crate) meta: MetaEngineRef,
+    pub(crate) meta:           MetaEngineRef,
+    pub(crate) object_storage: ObjectStorage,
 }
 
 impl Debug for KisekiVFS {
@@ -85,8 +102,10 @@ impl KisekiVFS {
         let config_inode = internal_nodes
             .get_mut_internal_node_by_name(CONFIG_INODE_NAME)
             .unwrap();
-        let config_buf = bincode::serialize(&vfs_config).expect("unable to serialize vfs config");
-        config_inode.0.attr.set_length(config_buf.len() as u64);
+        config_inode
+            .0
+            .attr
+            .set_length(vfs_config.to_json().len() as u64);
         // if meta.config.sub_dir.is_some() {
         //     don't show trash directory
         // internal_nodes.remove_trash_node();
@@ -111,20 +130,22 @@ impl KisekiVFS {
             vfs_config.block_size,
             vfs_config.chunk_size,
             meta.clone(),
-            object_storage,
+            object_storage.clone(),
         ));
 
         let vfs = Self {
             config: vfs_config,
             internal_nodes,
             modified_at: DashMap::new(),
+            lookups: DashMap::new(),
             handle_table: HandleTable::new(data_manager.clone()),
             data_manager,
+            stats: VfsStats::default(),
+            access_log: broadcast::channel(ACCESS_LOG_BUFFER).0,
             meta,
+            object_storage,
         };
 
-        // TODO: spawn a background task to clean up modified time.
-
         Ok(vfs)
     }
 
@@ -163,10 +184,11 @@ impl KisekiVFS {
         // TODO: call meta to truncate the file length
         let attr = self
             .meta
-            .truncate(ctx, ino, size, _fh.is_some())
+            .truncate(ctx.clone(), ino, size, _fh.is_some())
             .await
             .context(MetaSnafu)?;
-        Ok(attr)
+        let killed = self.meta.kill_suid(&ctx, ino).await.context(MetaSnafu)?;
+        Ok(killed.unwrap_or(attr))
     }
 
     /// [get_entry_ttl] return the entry timeout according to the given file
@@ -201,9 +223,30 @@ impl KisekiVFS {
     }
 
     fn invalidate_length(&self, ino: Ino) {
-        self.modified_at
-            .get_mut(&ino)
-            .map(|mut v| *v = std::time::Instant::now());
+        self.modified_at.insert(ino, std::time::Instant::now());
+    }
+
+    /// [add_lookup] counts an entry handed to the kernel, the kernel keeps
+    /// the inode until it forgets as many lookups.
+    pub fn add_lookup(&self, inode: Ino) { *self.lookups.entry(inode).or_default() += 1; }
+
+    /// [forget] is called when the kernel drops nlookup references of the
+    /// inode, the state kept for the inode is reclaimed once the kernel has
+    /// forgotten it completely.
+    pub async fn forget(&self, inode: Ino, nlookup: u64) {
+        let mut remaining = 0;
+        self.lookups.remove_if_mut(&inode, |_, n| {
+            *n = n.saturating_sub(nlookup);
+            remaining = *n;
+            remaining == 0
+        });
+        if remaining > 0 {
+            return;
+        }
+        trace!("fs:forget {:?}, reclaim its state", inode);
+        self.modified_at.remove(&inode);
+        self.data_manager.forget_readers(inode).await;
+        self.meta.forget(inode).await;
     }
 
     #[cfg(test)]
@@ -238,9 +281,68 @@ impl KisekiVFS {
         // }
 
         // TODO: handle the meta format
+
+        // the volume is of no use without its root.
+        let root = self.meta.get_attr(ROOT_INO).await.context(MetaSnafu)?;
+        ensure!(
+            root.is_dir(),
+            LibcSnafu {
+                errno: libc::ENOTDIR,
+            }
+        );
+
+        // write the buffered atime updates back periodically.
+        let meta = self.meta.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ATIME_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = meta.flush_atime().await {
+                    error!("failed to flush atime updates: {:?}", e);
+                }
+            }
+        });
+        // exchange the changes with the other clients of the volume.
+        let interval = self.meta.get_config().change_poll_interval;
+        if !interval.is_zero() {
+            let meta = self.meta.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = meta.sync_changes().await {
+                        error!("failed to sync the changes: {:?}", e);
+                    }
+                }
+            });
+        }
+        backup::spawn_backup_task(
+            self.meta.clone(),
+            self.object_storage.clone(),
+            self.config.backup_meta_interval,
+            self.config.backup_meta_copies,
+        );
+        freed_slices::spawn_delete_task(
+            self.meta.clone(),
+            self.object_storage.clone(),
+            self.data_manager.block_size,
+        );
         Ok(())
     }
 
+    /// [destroy] is called once the filesystem is unmounted, it writes back
+    /// what is still buffered in memory.
+    pub async fn destroy(&self) {
+        debug!("vfs:destroy");
+        if let Err(e) = self.meta.flush_atime().await {
+            error!("failed to flush atime updates: {:?}", e);
+        }
+    }
+
+    /// [subscribe_changes] returns the changes the kernel hasn't seen, its
+    /// caches of them should be dropped.
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<Change> { self.meta.subscribe_changes() }
+
     pub fn stat_fs<I: Into<Ino>>(
         self: &Arc<Self>,
         ctx: Arc<FuseContext>,
@@ -256,7 +358,7 @@ impl KisekiVFS {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
     ) -> Result<FullEntry> {
         trace!("fs:lookup with parent {:?} name {:?}", parent, name);
         // TODO: handle the special case
@@ -271,11 +373,7 @@ impl KisekiVFS {
             }
         }
         let (inode, attr) = self.meta.lookup(ctx, parent, name, true).await?;
-        Ok(FullEntry {
-            inode,
-            name: name.to_string(),
-            attr,
-        })
+        Ok(FullEntry::new(inode, name, attr))
     }
 
     pub async fn get_attr(&self, inode: Ino) -> Result<InodeAttr> {
@@ -401,26 +499,83 @@ impl KisekiVFS {
         Ok(new_attr)
     }
 
+    /// [KisekiVFS::get_flags] returns the immutable and append-only flags of
+    /// the inode.
+    pub async fn get_flags(&self, ino: Ino) -> Result<Flags> {
+        ensure!(!ino.is_special(), LibcSnafu { errno: ENOTTY });
+        let attr = self.meta.get_attr(ino).await.context(MetaSnafu)?;
+        Ok(Flags::from_bits_truncate(attr.flags as u8))
+    }
+
+    /// [KisekiVFS::set_flags] sets the immutable and append-only flags of the
+    /// inode, only the root is allowed to change them.
+    pub async fn set_flags(&self, ctx: Arc<FuseContext>, ino: Ino, flags: Flags) -> Result<()> {
+        ensure!(!ino.is_special(), LibcSnafu { errno: EPERM });
+        let mut new_attr = InodeAttr::default()
+            .set_flags(flags.bits() as u32)
+            .to_owned();
+        self.meta
+            .set_attr(&ctx, SetAttrFlags::FLAG, ino, &mut new_attr)
+            .await
+            .context(MetaSnafu)?;
+        Ok(())
+    }
+
     pub async fn open(&self, ctx: &FuseContext, inode: Ino, flags: i32) -> Result<Opened> {
         debug!(
             "fs:open with ino {:?} flags {:#b} pid {:?}",
             inode, flags, ctx.pid
         );
 
+        if inode.is_special() {
+            let attr = self
+                .internal_nodes
+                .get_internal_node(inode)
+                .context(LibcSnafu { errno: ENOENT })?
+                .get_attr();
+            let mask = match flags & libc::O_ACCMODE {
+                libc::O_RDONLY => MODE_MASK_R,
+                libc::O_WRONLY => MODE_MASK_W,
+                _ => MODE_MASK_R | MODE_MASK_W,
+            };
+            ctx.check_access(&attr, mask).context(MetaSnafu)?;
+            // the content is taken when it is opened, the log is read as it
+            // goes.
+            let (data, log) = match inode {
+                CONFIG_INODE => (self.config.to_json(), None),
+                STATS_INODE => (self.render_stats().into_bytes(), None),
+                LOG_INODE => (Vec::new(), Some(self.access_log.subscribe())),
+                _ => (Vec::new(), None),
+            };
+            return Ok(Opened {
+                fh: self
+                    .handle_table
+                    .new_internal_handle(inode, data, log)
+                    .await,
+                // the data is made up on the fly, the kernel mustn't cache it.
+                flags: fuser::consts::FOPEN_DIRECT_IO,
+                inode,
+                attr,
+            });
+        }
+
         let mut attr = self
             .meta
             .open_inode(ctx, inode, flags)
             .await
             .context(MetaSnafu)?;
+        // with FUSE_ATOMIC_O_TRUNC, the kernel leaves the truncation to the
+        // open instead of sending a setattr.
+        if flags & libc::O_TRUNC != 0 && attr.length > 0 {
+            attr = self.truncate(Arc::new(ctx.clone()), inode, 0, None).await?;
+        }
         self.try_update_file_reader_length(inode, &mut attr).await;
         let opened_fh = self
             .handle_table
             .new_file_handle(inode, attr.length, flags)
             .await?;
 
-        let opened_flags = if inode.is_special() {
-            fuser::consts::FOPEN_DIRECT_IO
-        } else if attr.keep_cache {
+        let opened_flags = if attr.keep_cache {
             fuser::consts::FOPEN_KEEP_CACHE
         } else {
             0
@@ -451,7 +606,18 @@ impl KisekiVFS {
         );
 
         if ino.is_special() {
-            todo!()
+            let h = self
+                .handle_table
+                .find_handle(ino, fh)
+                .await
+                .context(LibcSnafu { errno: EBADF })?;
+            let h = h.as_internal_handle().context(LibcSnafu { errno: EBADF })?;
+            match ino {
+                LOG_INODE => return self.read_access_log(&ctx, &h, size as usize).await,
+                CONTROL_INODE => return self.read_control(&h, size as usize).await,
+                _ => {}
+            }
+            return Ok(h.read(size as usize).await);
         }
 
         // just convert it.
@@ -480,8 +646,18 @@ impl KisekiVFS {
 
         self.data_manager.direct_flush(ino).await?;
 
-        let _read_len = read_guard.read(offset as usize, buf.as_mut_slice()).await?;
+        // the object storage may be slow, give up once the caller is
+        // interrupted.
+        let read = tokio::select! {
+            r = read_guard.read(offset as usize, buf.as_mut_slice()) => Some(r),
+            _ = ctx.cancellation_token.cancelled() => None,
+        };
         file_handle.remove_operation(&ctx).await;
+        let _read_len = read.context(LibcSnafu { errno: EINTR })??;
+        self.stats.read(_read_len);
+        if let Err(e) = self.meta.touch_atime(ino).await {
+            debug!("failed to update atime of {:?}: {:?}", ino, e);
+        }
         debug!(
             "vfs:read with ino {:?} fh {:?} offset {:?} expected_read_size {:?} actual_read_len: \
              {:?}",
@@ -492,7 +668,7 @@ impl KisekiVFS {
 
     #[allow(clippy::too_many_arguments)]
     pub async fn write(
-        &self,
+        self: &Arc<Self>,
         ctx: Arc<FuseContext>,
         ino: Ino,
         fh: u64,
@@ -517,8 +693,13 @@ impl KisekiVFS {
             .await
             .context(LibcSnafu { errno: EBADF })?;
         if ino == CONTROL_INODE {
-            todo!()
+            let h = _handle
+                .as_internal_handle()
+                .context(LibcSnafu { errno: EBADF })?;
+            self.write_control(ctx, &h, data).await?;
+            return Ok(size as u32);
         }
+        ensure!(!ino.is_special(), LibcSnafu { errno: EPERM });
 
         let handle = _handle
             .as_file_handle()
@@ -528,8 +709,14 @@ impl KisekiVFS {
             .write_lock(ctx.clone())
             .await
             .context(LibcSnafu { errno: EINTR })?;
+        // the wait for the lock can be interrupted, the write itself can't,
+        // the caller can't tell how much of it is buffered otherwise.
         let write_len = write_guard.write(offset, data).await?;
         handle.remove_operation(&ctx).await;
+        self.stats.written(write_len);
+        if write_len > 0 {
+            self.meta.kill_suid(&ctx, ino).await.context(MetaSnafu)?;
+        }
 
         self.data_manager
             .truncate_reader(ino, write_guard.get_length() as u64)
@@ -546,15 +733,15 @@ impl KisekiVFS {
         fh: u64,
         lock_owner: u64,
     ) -> Result<()> {
+        if ino.is_special() {
+            return Ok(());
+        };
         let h = self
             .handle_table
             .find_handle(ino, fh)
             .await
             .context(LibcSnafu { errno: ENOENT })?;
         let h = h.as_file_handle().context(LibcSnafu { errno: EBADF })?;
-        if ino.is_special() {
-            return Ok(());
-        };
 
         if h.has_writer() {
             let guard = loop {
@@ -573,6 +760,8 @@ impl KisekiVFS {
                 }
             };
             let guard = guard.context(LibcSnafu { errno: EINTR })?;
+            // the flush runs to the end once it holds the lock, an
+            // interrupted one would leave the data half uploaded.
             guard.flush().await?;
             h.remove_operation(&ctx).await;
         } else {
@@ -653,15 +842,169 @@ impl KisekiVFS {
         todo!()
     }
 
+    /// [KisekiVFS::copy_file_range] copies a range of data from one file to
+    /// another. The dst borrows the slices of the src, so no data is moved
+    /// no matter how large the range is.
+    ///
+    /// # References
+    /// * [copy_file_range(2)](https://man7.org/linux/man-pages/man2/copy_file_range.2.html)
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, ctx), fields(src, dst))]
+    pub async fn copy_file_range(
+        &self,
+        ctx: Arc<FuseContext>,
+        src: Ino,
+        src_fh: FH,
+        src_off: i64,
+        dst: Ino,
+        dst_fh: FH,
+        dst_off: i64,
+        size: u64,
+        flags: u32,
+    ) -> Result<u64> {
+        ensure!(src_off >= 0 && dst_off >= 0, LibcSnafu { errno: EINVAL });
+        ensure!(
+            !src.is_special() && !dst.is_special(),
+            LibcSnafu { errno: EPERM }
+        );
+        let _ = self
+            .handle_table
+            .find_handle(src, src_fh)
+            .await
+            .and_then(|h| h.as_file_handle())
+            .context(LibcSnafu { errno: EBADF })?;
+        let dst_handle = self
+            .handle_table
+            .find_handle(dst, dst_fh)
+            .await
+            .and_then(|h| h.as_file_handle())
+            .context(LibcSnafu { errno: EBADF })?;
+        ensure!(dst_handle.has_writer(), LibcSnafu { errno: EBADF });
+
+        let write_guard = dst_handle
+            .write_lock(ctx.clone())
+            .await
+            .context(LibcSnafu { errno: EINTR })?;
+        // the buffered data has to reach the meta before it can be borrowed.
+        self.data_manager.direct_flush(src).await?;
+        if dst != src {
+            self.data_manager.direct_flush(dst).await?;
+        }
+        let (copied, attr) = self
+            .meta
+            .copy_file_range(src, src_off as u64, dst, dst_off as u64, size, flags)
+            .await
+            .context(MetaSnafu)?;
+        dst_handle.remove_operation(&ctx).await;
+        drop(write_guard);
+
+        if copied > 0 {
+            self.data_manager.truncate_reader(dst, attr.length).await;
+            self.invalidate_length(dst);
+            self.meta.kill_suid(&ctx, dst).await.context(MetaSnafu)?;
+        }
+        Ok(copied)
+    }
+
+    /// [KisekiVFS::clone_entry] makes a copy of the src file or directory tree
+    /// as name in the parent. The copies borrow the slices of the src, so it
+    /// takes no time no matter how much data the src holds.
+    #[instrument(skip(self, ctx), fields(src, parent, name = ?name))]
+    pub async fn clone_entry(
+        &self,
+        ctx: Arc<FuseContext>,
+        src: Ino,
+        parent: Ino,
+        name: &OsStr,
+        preserve: bool,
+    ) -> Result<FullEntry> {
+        ensure!(
+            !src.is_special() && !parent.is_special(),
+            LibcSnafu { errno: EPERM }
+        );
+        ensure!(
+            !(parent.is_root() && self.internal_nodes.contains_name(name)),
+            LibcSnafu {
+                errno: libc::EEXIST,
+            }
+        );
+        ensure!(
+            name.len() <= MAX_NAME_LENGTH,
+            LibcSnafu {
+                errno: libc::ENAMETOOLONG,
+            }
+        );
+        // the buffered data has to reach the meta before it can be borrowed.
+        let src_attr = self.meta.get_attr(src).await.context(MetaSnafu)?;
+        if src_attr.is_dir() {
+            for inode in self.data_manager.writing_inodes() {
+                if self.is_under(src, inode).await? {
+                    self.data_manager.direct_flush(inode).await?;
+                }
+            }
+        } else {
+            self.data_manager.direct_flush(src).await?;
+        }
+        let (ino, attr) = self
+            .meta
+            .clone_entry(ctx, src, parent, name, preserve)
+            .await
+            .context(MetaSnafu)?;
+        Ok(FullEntry::new(ino, name, attr))
+    }
+
+    // is_under tells whether the inode may be in the tree under the dir, a
+    // file with hard links has no single parent, so it may always be.
+    async fn is_under(&self, dir: Ino, inode: Ino) -> Result<bool> {
+        let mut p = inode;
+        while p != dir {
+            if p == ROOT_INO {
+                return Ok(false);
+            }
+            p = match self.meta.get_attr(p).await {
+                Ok(attr) if attr.parent.is_zero() => return Ok(true),
+                Ok(attr) => attr.parent,
+                // removed in the meantime, nothing to borrow from it.
+                Err(e) if e.is_not_found() => return Ok(false),
+                Err(e) => return Err(e).context(MetaSnafu),
+            };
+        }
+        Ok(true)
+    }
+
+    /// [KisekiVFS::lseek] serves SEEK_DATA and SEEK_HOLE, the kernel handles
+    /// the others by itself.
+    #[instrument(skip(self, _ctx), fields(inode, fh, offset, whence))]
+    pub async fn lseek(
+        &self,
+        _ctx: Arc<FuseContext>,
+        inode: Ino,
+        fh: FH,
+        offset: i64,
+        whence: i32,
+    ) -> Result<i64> {
+        ensure!(!inode.is_special(), LibcSnafu { errno: EINVAL });
+        ensure!(offset >= 0, LibcSnafu { errno: libc::ENXIO });
+        self.handle_table
+            .find_handle(inode, fh)
+            .await
+            .context(LibcSnafu { errno: EBADF })?;
+        // the buffered data is not in the slices yet.
+        self.data_manager.direct_flush(inode).await?;
+        let offset = self
+            .meta
+            .lseek(inode, offset as u64, whence)
+            .await
+            .context(MetaSnafu)?;
+        Ok(offset as i64)
+    }
+
     pub async fn release(
         &self,
         ctx: Arc<FuseContext>,
         inode: Ino,
         fh: FH,
     ) -> Result<JoinHandle<()>> {
-        if inode.is_special() {
-            todo!()
-        }
         if let Some(handle) = self.handle_table.find_handle(inode, fh).await {
             handle.wait_all_operations_done(ctx.clone()).await?;
             if let Some(fh) = handle.as_file_handle() {
@@ -690,7 +1033,10 @@ impl KisekiVFS {
                 }
             }
         }
-        self.meta.close(inode).await?;
+        // the internal nodes are never opened in the meta engine.
+        if !inode.is_special() {
+            self.meta.close(inode).await?;
+        }
         let ht = self.handle_table.clone();
         Ok(tokio::spawn(async move {
             ht.release_file_handle(inode, fh).await;
@@ -743,42 +1089,34 @@ impl KisekiVFS {
             .context(LibcSnafu { errno: EBADF })?;
         let h = h.as_dir_handle().context(LibcSnafu { errno: EBADF })?;
 
-        let mut read_guard = h.inner.read().await;
-        if read_guard.children.is_empty() || offset == 0 {
-            drop(read_guard);
-            let mut write_guard = h.inner.write().await;
-            // FIXME
-            write_guard.read_at = Some(Instant::now());
-            write_guard.children = self
+        let offset = offset as usize;
+        let mut inner = h.inner.write().await;
+        // the listing starts over from the beginning, or on rewinddir.
+        if offset == 0 || inner.read_at.is_none() || inner.plus != plus {
+            inner.reset(plus);
+        }
+        while inner.children.len() <= offset && !inner.eof {
+            let page = self
                 .meta
-                .read_dir(ctx, inode, plus)
+                .read_dir(ctx, inode, plus, inner.cursor.as_deref(), READDIR_PAGE_SIZE)
                 .await
                 .context(MetaSnafu)?;
-            if (offset as usize) < write_guard.children.len() {
-                let result = write_guard.children[offset as usize..]
-                    .iter()
-                    .cloned()
-                    .collect::<Vec<_>>();
-                return Ok(result);
-            } else {
-                return Ok(vec![]);
-            }
+            inner.cursor = page.cursor().map(OsStr::to_os_string);
+            inner.eof = page.eof;
+            inner.children.extend(page.entries);
         }
-        if (offset as usize) < read_guard.children.len() {
-            let result = read_guard.children[offset as usize..]
-                .iter()
-                .cloned()
-                .collect::<Vec<_>>();
-            return Ok(result);
+        if offset >= inner.children.len() {
+            return Ok(vec![]);
         }
-        Ok(Vec::new())
+        let end = inner.children.len().min(offset + READDIR_PAGE_SIZE);
+        Ok(inner.children[offset..end].to_vec())
     }
 
     pub async fn mkdir(
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         mode: u32,
         umask: u32,
     ) -> Result<FullEntry> {
@@ -804,7 +1142,7 @@ impl KisekiVFS {
         Ok(FullEntry::new(ino, name, attr))
     }
 
-    pub async fn rmdir(&self, ctx: Arc<FuseContext>, parent: Ino, name: &str) -> Result<()> {
+    pub async fn rmdir(&self, ctx: Arc<FuseContext>, parent: Ino, name: &OsStr) -> Result<()> {
         debug!("fs:rmdir with parent {:?} name {:?}", parent, name);
         ensure!(name != DOT, LibcSnafu { errno: EINVAL });
         ensure!(name != DOT_DOT, LibcSnafu { errno: EINVAL });
@@ -837,12 +1175,12 @@ impl KisekiVFS {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: String,
+        name: &OsStr,
         mode: u32,
         umask: u32,
         rdev: u32,
     ) -> Result<FullEntry> {
-        if parent.is_root() && self.internal_nodes.contains_name(&name) {
+        if parent.is_root() && self.internal_nodes.contains_name(name) {
             return LibcSnafu {
                 errno: libc::EEXIST,
             }
@@ -861,23 +1199,23 @@ impl KisekiVFS {
             .mknod(
                 ctx,
                 parent,
-                &name,
+                name,
                 file_type,
                 mode & 0o7777,
                 umask,
                 rdev,
-                String::new(),
+                Vec::new(),
             )
             .await
             .context(MetaSnafu)?;
-        Ok(FullEntry::new(ino, &name, attr))
+        Ok(FullEntry::new(ino, name, attr))
     }
 
     pub async fn create(
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         mode: u32,
         umask: u32,
         flags: libc::c_int,
@@ -919,7 +1257,7 @@ impl KisekiVFS {
         ctx: Arc<FuseContext>,
         inode: Ino,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &OsStr,
     ) -> Result<FullEntry> {
         ensure!(
             new_name.len() < MAX_NAME_LENGTH,
@@ -955,7 +1293,7 @@ impl KisekiVFS {
     ///
     /// # References
     /// * [unlink(2)](https://man7.org/linux/man-pages/man2/unlink.2.html)
-    pub async fn unlink(&self, ctx: Arc<FuseContext>, parent: Ino, name: &str) -> Result<()> {
+    pub async fn unlink(&self, ctx: Arc<FuseContext>, parent: Ino, name: &OsStr) -> Result<()> {
         ensure!(
             name.len() < MAX_NAME_LENGTH,
             LibcSnafu {
@@ -988,7 +1326,7 @@ impl KisekiVFS {
     ///   information like uid, gid, etc.
     /// * `parent`: [Ino] - the inode number of the parent directory where the
     ///   symbolic link will be created.
-    /// * `link_name`: [&str] - the name of the symbolic link.
+    /// * `link_name`: [&OsStr] - the name of the symbolic link.
     /// * `target`: [&Path] - the path to the file or directory that the
     ///   symbolic link
     ///
@@ -1009,7 +1347,7 @@ impl KisekiVFS {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        link_name: &str,
+        link_name: &OsStr,
         target: &Path,
     ) -> Result<FullEntry> {
         ensure!(
@@ -1019,7 +1357,7 @@ impl KisekiVFS {
             }
         );
         ensure!(
-            target.to_string_lossy().len() < MAX_SYMLINK_LEN,
+            target.as_os_str().len() < MAX_SYMLINK_LEN,
             LibcSnafu { errno: EINVAL }
         );
 
@@ -1052,9 +1390,9 @@ impl KisekiVFS {
         &self,
         ctx: Arc<FuseContext>,
         parent: Ino,
-        name: &str,
+        name: &OsStr,
         new_parent: Ino,
-        new_name: &str,
+        new_name: &OsStr,
         flags: u32,
     ) -> Result<()> {
         ensure!(
@@ -1103,6 +1441,10 @@ fn get_file_type(mode: mode_t) -> Result<FileType> {
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::ffi::OsStrExt;
+
+    use kiseki_common::CHUNK_SIZE;
+    use kiseki_types::accesslog::AccessRecord;
     use kiseki_utils::logger::install_fmt_log;
 
     use super::*;
@@ -1128,7 +1470,14 @@ mod tests {
         let ctx = Arc::new(FuseContext::background());
 
         let (entry, fh) = vfs
-            .create(ctx.clone(), ROOT_INO, "f", 0o755, 0, libc::O_RDWR)
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("f"),
+                0o755,
+                0,
+                libc::O_RDWR,
+            )
             .await
             .unwrap();
 
@@ -1200,6 +1549,307 @@ mod tests {
         // sequential_write(&vfs, entry.inode, fh).await;
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn copy_file_range_without_data() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        let (src, src_fh) = vfs
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("src"),
+                0o644,
+                0,
+                libc::O_RDWR,
+            )
+            .await?;
+        let (dst, dst_fh) = vfs
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("dst"),
+                0o644,
+                0,
+                libc::O_RDWR,
+            )
+            .await?;
+        vfs.write(
+            ctx.clone(),
+            src.inode,
+            src_fh,
+            0,
+            b"hello world",
+            0,
+            0,
+            None,
+        )
+        .await?;
+        vfs.write(ctx.clone(), dst.inode, dst_fh, 0, b"0123456789", 0, 0, None)
+            .await?;
+
+        // the data of the src is still buffered in the writer.
+        let copied = vfs
+            .copy_file_range(
+                ctx.clone(),
+                src.inode,
+                src_fh,
+                6,
+                dst.inode,
+                dst_fh,
+                2,
+                100,
+                0,
+            )
+            .await?;
+        assert_eq!(copied, 5);
+        let content = vfs
+            .read(ctx.clone(), dst.inode, dst_fh, 0, 10, 0, None)
+            .await?;
+        assert_eq!(content.as_ref(), b"01world789");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn forget_reclaims_inode_state() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        let (entry, fh) = vfs
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("f"),
+                0o644,
+                0,
+                libc::O_RDWR,
+            )
+            .await?;
+        vfs.add_lookup(entry.inode);
+        vfs.add_lookup(entry.inode);
+        vfs.write(ctx.clone(), entry.inode, fh, 0, b"hello", 0, 0, None)
+            .await?;
+        vfs.release(ctx.clone(), entry.inode, fh)
+            .await?
+            .await
+            .unwrap();
+        assert!(vfs.modified_at.contains_key(&entry.inode));
+
+        // the kernel still holds one lookup.
+        vfs.forget(entry.inode, 1).await;
+        assert!(vfs.modified_at.contains_key(&entry.inode));
+        vfs.forget(entry.inode, 1).await;
+        assert!(!vfs.modified_at.contains_key(&entry.inode));
+        assert!(!vfs.lookups.contains_key(&entry.inode));
+        let readers = vfs.data_manager.file_readers.read().await;
+        assert!(!readers.contains_key(&entry.inode));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn control_file_runs_requests() -> Result<()> {
+        use kiseki_types::control::{decode_frame, encode_frame, ControlRequest, ControlResponse};
+
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        vfs.mkdir(ctx.clone(), ROOT_INO, OsStr::new("d"), 0o755, 0)
+            .await?;
+        let opened = vfs.open(&ctx, CONTROL_INODE, libc::O_RDWR).await?;
+        let call = |req: ControlRequest| {
+            let (vfs, ctx) = (vfs.clone(), ctx.clone());
+            async move {
+                // the kernel may split the frame.
+                let buf = encode_frame(&req);
+                let (head, tail) = buf.split_at(5);
+                vfs.write(ctx.clone(), CONTROL_INODE, opened.fh, 0, head, 0, 0, None)
+                    .await?;
+                vfs.write(ctx.clone(), CONTROL_INODE, opened.fh, 5, tail, 0, 0, None)
+                    .await?;
+                // the jobs report their progress until they are done.
+                loop {
+                    let resp = vfs
+                        .read(ctx.clone(), CONTROL_INODE, opened.fh, 0, 4096, 0, None)
+                        .await?;
+                    let resp: ControlResponse = decode_frame(&resp).unwrap().unwrap();
+                    if resp.done {
+                        return Ok::<ControlResponse, Error>(resp);
+                    }
+                }
+            }
+        };
+
+        let resp = call(ControlRequest::Info { inode: ROOT_INO }).await?;
+        assert_eq!(resp.errno, 0);
+        assert!(resp.message.contains("dirs: 2"), "{}", resp.message);
+        let resp = call(ControlRequest::Rmr {
+            parent: ROOT_INO,
+            name:   b"d".to_vec(),
+        })
+        .await?;
+        assert_eq!(resp, ControlResponse::ok("removed: 1"));
+        let resp = call(ControlRequest::Info { inode: Ino(1000) }).await?;
+        assert_eq!(resp.errno, libc::ENOENT);
+        // the internal nodes can't be removed.
+        let resp = call(ControlRequest::Rmr {
+            parent: ROOT_INO,
+            name:   CONTROL_INODE_NAME.as_bytes().to_vec(),
+        })
+        .await?;
+        assert_eq!(resp.errno, libc::EPERM);
+        let resp = call(ControlRequest::Warmup { inode: ROOT_INO }).await?;
+        assert_eq!(resp, ControlResponse::ok("files: 0\nlength: 0B"));
+        // only root can collect the garbage.
+        let resp = call(ControlRequest::Gc).await?;
+        assert_eq!(resp.errno, libc::EPERM);
+
+        // a broken frame fails the write.
+        let r = vfs
+            .write(
+                ctx.clone(),
+                CONTROL_INODE,
+                opened.fh,
+                0,
+                &[0; 8],
+                0,
+                0,
+                None,
+            )
+            .await;
+        assert!(matches!(r, Err(LibcError { errno, .. }) if errno == libc::EINVAL));
+        vfs.release(ctx, CONTROL_INODE, opened.fh)
+            .await?
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn internal_nodes_are_readable() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+
+        let attr = vfs.get_attr(CONFIG_INODE).await?;
+        let opened = vfs.open(&ctx, CONFIG_INODE, libc::O_RDONLY).await?;
+        let config = vfs
+            .read(ctx.clone(), CONFIG_INODE, opened.fh, 0, 1 << 20, 0, None)
+            .await?;
+        assert_eq!(config.len() as u64, attr.length);
+        let config: Config = serde_json::from_slice(&config).unwrap();
+        assert_eq!(config.block_size, vfs.config.block_size);
+        vfs.release(ctx.clone(), CONFIG_INODE, opened.fh)
+            .await?
+            .await
+            .unwrap();
+
+        let opened = vfs.open(&ctx, STATS_INODE, libc::O_RDONLY).await?;
+        let stats = vfs
+            .read(ctx.clone(), STATS_INODE, opened.fh, 0, 1 << 20, 0, None)
+            .await?;
+        let stats = String::from_utf8(stats.to_vec()).unwrap();
+        // the open .stats is counted.
+        assert!(stats.contains("kiseki_open_handles 1\n"), "{stats}");
+        assert!(stats.contains("kiseki_object_gets "), "{stats}");
+        vfs.release(ctx.clone(), STATS_INODE, opened.fh)
+            .await?
+            .await
+            .unwrap();
+
+        // nothing is logged until someone reads the log.
+        vfs.log_access(&ctx, "getattr", ROOT_INO);
+        let opened = vfs.open(&ctx, LOG_INODE, libc::O_RDONLY).await?;
+        vfs.log_access(&ctx, "lookup", ROOT_INO);
+        vfs.log_access(&ctx, "read", LOG_INODE);
+        let log = vfs
+            .read(ctx.clone(), LOG_INODE, opened.fh, 0, 4096, 0, None)
+            .await?;
+        let log = String::from_utf8(log.to_vec()).unwrap();
+        let records = log
+            .lines()
+            .map(|l| AccessRecord::parse(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 1, "{log}");
+        assert_eq!(records[0].op, "lookup");
+        assert_eq!(records[0].inode, ROOT_INO);
+
+        // the read waits for the next line, until the caller is interrupted.
+        let reader = {
+            let (vfs, ctx) = (vfs.clone(), ctx.clone());
+            tokio::spawn(async move { vfs.read(ctx, LOG_INODE, opened.fh, 0, 4096, 0, None).await })
+        };
+        ctx.cancellation_token.cancel();
+        let r = reader.await.unwrap();
+        assert!(matches!(r, Err(LibcError { errno, .. }) if errno == libc::EINTR));
+        vfs.release(ctx, LOG_INODE, opened.fh).await?.await.unwrap();
+        assert_eq!(vfs.handle_table.count(), 0);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn clone_file_with_buffered_data() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        let (src, src_fh) = vfs
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("src"),
+                0o644,
+                0,
+                libc::O_RDWR,
+            )
+            .await?;
+        vfs.write(
+            ctx.clone(),
+            src.inode,
+            src_fh,
+            0,
+            b"hello world",
+            0,
+            0,
+            None,
+        )
+        .await?;
+
+        let cloned = vfs
+            .clone_entry(ctx.clone(), src.inode, ROOT_INO, OsStr::new("dst"), false)
+            .await?;
+        assert_eq!(cloned.attr.length, 11);
+        let opened = vfs.open(&ctx, cloned.inode, libc::O_RDONLY).await?;
+        let content = vfs
+            .read(ctx.clone(), cloned.inode, opened.fh, 0, 11, 0, None)
+            .await?;
+        assert_eq!(content.as_ref(), b"hello world");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn lseek_data_and_hole() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        let (file, fh) = vfs
+            .create(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new("sparse"),
+                0o644,
+                0,
+                libc::O_RDWR,
+            )
+            .await?;
+        let far = 2 * CHUNK_SIZE as i64 + 5;
+        vfs.write(ctx.clone(), file.inode, fh, 0, b"0123456789", 0, 0, None)
+            .await?;
+        vfs.write(ctx.clone(), file.inode, fh, far, b"x", 0, 0, None)
+            .await?;
+
+        let seek = |offset, whence| vfs.lseek(ctx.clone(), file.inode, fh, offset, whence);
+        assert_eq!(seek(0, libc::SEEK_DATA).await?, 0);
+        assert_eq!(seek(0, libc::SEEK_HOLE).await?, 10);
+        // the chunk in the middle has no slices at all.
+        assert_eq!(seek(10, libc::SEEK_DATA).await?, far);
+        assert_eq!(seek(far, libc::SEEK_HOLE).await?, far + 1);
+        assert!(seek(far + 1, libc::SEEK_DATA).await.is_err());
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn vfs_basic() -> Result<()> {
         install_fmt_log();
@@ -1214,35 +1864,43 @@ mod tests {
         assert_eq!(root.mode, 511);
 
         // dirs
-        let dir1 = vfs.mkdir(ctx.clone(), ROOT_INO, "d1", 0o755, 0).await?;
+        let dir1 = vfs
+            .mkdir(ctx.clone(), ROOT_INO, OsStr::new("d1"), 0o755, 0)
+            .await?;
         assert_eq!(dir1.attr.mode, 493);
-        let dir2 = vfs.mkdir(ctx.clone(), dir1.inode, "d2", 0o755, 0).await?;
+        let dir2 = vfs
+            .mkdir(ctx.clone(), dir1.inode, OsStr::new("d2"), 0o755, 0)
+            .await?;
         assert_eq!(dir2.attr.mode, 493);
 
         let root_dir_handle = vfs.open_dir(&ctx, ROOT_INO, libc::O_RDONLY).await?;
         let entries = vfs
             .read_dir(&ctx, ROOT_INO, root_dir_handle, 0, true)
             .await?;
-        assert_eq!(entries.len(), 1);
+        // ".", ".." and d1
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].get_inode(), ROOT_INO);
         let dir1_handle = vfs.open_dir(&ctx, dir1.inode, libc::O_RDONLY).await?;
         let entries = vfs.read_dir(&ctx, dir1.inode, dir1_handle, 0, true).await?;
-        assert_eq!(entries.len(), 1);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].get_inode(), dir1.inode);
+        assert_eq!(entries[2].get_inode(), dir2.inode);
 
         assert_eq!(
-            vfs.rmdir(ctx.clone(), ROOT_INO, &dir1.name)
+            vfs.rmdir(ctx.clone(), ROOT_INO, dir1.get_name())
                 .await
                 .unwrap_err()
                 .to_errno(),
             libc::ENOTEMPTY
         );
-        vfs.rmdir(ctx.clone(), dir1.inode, &dir2.name).await?;
+        vfs.rmdir(ctx.clone(), dir1.inode, dir2.get_name()).await?;
 
         // files
         let f1 = vfs
             .mknod(
                 ctx.clone(),
                 dir1.inode,
-                "f1".to_string(),
+                OsStr::new("f1"),
                 0o644 | libc::S_IFREG,
                 0,
                 0,
@@ -1286,25 +1944,34 @@ mod tests {
         vfs.check_access(ctx.clone(), f1.inode, &f1_attr, libc::X_OK)?;
 
         // link root/f2 -> d1/f1
-        let f2_entry = vfs.link(ctx.clone(), f1.inode, ROOT_INO, "f2").await?;
+        let f2_entry = vfs
+            .link(ctx.clone(), f1.inode, ROOT_INO, OsStr::new("f2"))
+            .await?;
         let f1_attr = vfs.get_attr(f1.inode).await?;
         assert_eq!(f1_attr.nlink, 2);
 
         // unlink d1/f1
-        vfs.unlink(ctx.clone(), dir1.inode, &f1.name).await?;
+        vfs.unlink(ctx.clone(), dir1.inode, f1.get_name()).await?;
         // lookup root/f2
-        if let Ok(e) = vfs.lookup(ctx.clone(), ROOT_INO, &f2_entry.name).await {
+        if let Ok(e) = vfs.lookup(ctx.clone(), ROOT_INO, f2_entry.get_name()).await {
             assert_eq!(e.attr.nlink, 1);
         } else {
             panic!("lookup f2 failed");
         }
 
         // rename root/f2 -> root/f3
-        vfs.rename(ctx.clone(), ROOT_INO, &f2_entry.name, ROOT_INO, "f3", 0)
-            .await?;
+        vfs.rename(
+            ctx.clone(),
+            ROOT_INO,
+            f2_entry.get_name(),
+            ROOT_INO,
+            OsStr::new("f3"),
+            0,
+        )
+        .await?;
         // we should not be able to find the root/f2
         assert_eq!(
-            vfs.lookup(ctx.clone(), ROOT_INO, &f2_entry.name)
+            vfs.lookup(ctx.clone(), ROOT_INO, f2_entry.get_name())
                 .await
                 .unwrap_err()
                 .to_errno(),
@@ -1319,12 +1986,113 @@ mod tests {
 
         // symlink f2_sym -> f2
         let symlink = vfs
-            .symlink(ctx.clone(), ROOT_INO, "f2_sym", Path::new("f2"))
+            .symlink(ctx.clone(), ROOT_INO, OsStr::new("f2_sym"), Path::new("f2"))
             .await?;
-        assert_eq!(symlink.name, "f2_sym");
+        assert_eq!(symlink.get_name(), "f2_sym");
         let target = vfs.readlink(ctx.clone(), symlink.inode).await?;
         assert_eq!(target.as_ref(), b"f2");
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn read_dir_with_stable_offsets() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        let count = READDIR_PAGE_SIZE + 6;
+        for i in 0..count {
+            vfs.mknod(
+                ctx.clone(),
+                ROOT_INO,
+                OsStr::new(&format!("f{:04}", i)),
+                0o644 | libc::S_IFREG,
+                0,
+                0,
+            )
+            .await?;
+        }
+
+        let fh = vfs.open_dir(&ctx, ROOT_INO, libc::O_RDONLY).await?;
+        let first = vfs.read_dir(&ctx, ROOT_INO, fh, 0, false).await?;
+        // ".", ".." and the first page of the files.
+        assert_eq!(first.len(), READDIR_PAGE_SIZE + 2);
+
+        // change the directory in the middle of the listing.
+        vfs.unlink(ctx.clone(), ROOT_INO, OsStr::new("f0000"))
+            .await?;
+        vfs.unlink(
+            ctx.clone(),
+            ROOT_INO,
+            OsStr::new(&format!("f{:04}", count - 1)),
+        )
+        .await?;
+        vfs.mknod(
+            ctx.clone(),
+            ROOT_INO,
+            OsStr::new("a"),
+            0o644 | libc::S_IFREG,
+            0,
+            0,
+        )
+        .await?;
+        vfs.mknod(
+            ctx.clone(),
+            ROOT_INO,
+            OsStr::new("z"),
+            0o644 | libc::S_IFREG,
+            0,
+            0,
+        )
+        .await?;
+
+        // the offsets which have been served point to the same entries.
+        let again = vfs.read_dir(&ctx, ROOT_INO, fh, 3, false).await?;
+        assert_eq!(again[0].get_name(), first[3].get_name());
+        let rest = vfs
+            .read_dir(&ctx, ROOT_INO, fh, first.len() as i64, false)
+            .await?;
+        let mut names = first
+            .iter()
+            .chain(rest.iter())
+            .map(|e| e.get_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), count + 2);
+        assert_eq!(names.pop().unwrap(), "z");
+        assert_eq!(names.last().unwrap(), &format!("f{:04}", count - 2));
+
+        // starting over sees the latest state.
+        let entries = vfs.read_dir(&ctx, ROOT_INO, fh, 0, true).await?;
+        assert_eq!(entries[2].get_name(), "a");
+        assert!(entries.iter().all(|e| matches!(e, Entry::Full(_))));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn non_utf8_names() -> Result<()> {
+        let vfs = Arc::new(make_vfs().await);
+        let ctx = Arc::new(FuseContext::background());
+        // latin-1 names, as written by an old client.
+        let name = OsStr::from_bytes(b"caf\xe9");
+        let new_name = OsStr::from_bytes(b"r\xe9sum\xe9");
+
+        let created = vfs
+            .mknod(ctx.clone(), ROOT_INO, name, 0o644 | libc::S_IFREG, 0, 0)
+            .await?;
+        assert_eq!(created.name, name.as_bytes());
+        let found = vfs.lookup(ctx.clone(), ROOT_INO, name).await?;
+        assert_eq!(found.inode, created.inode);
+
+        let fh = vfs.open_dir(&ctx, ROOT_INO, libc::O_RDONLY).await?;
+        let entries = vfs.read_dir(&ctx, ROOT_INO, fh, 0, true).await?;
+        assert!(entries.iter().any(|e| e.get_name() == name));
+        vfs.release_dir(ROOT_INO, fh).await?;
+
+        vfs.rename(ctx.clone(), ROOT_INO, name, ROOT_INO, new_name, 0)
+            .await?;
+        assert!(vfs.lookup(ctx.clone(), ROOT_INO, name).await.is_err());
+        let renamed = vfs.lookup(ctx.clone(), ROOT_INO, new_name).await?;
+        assert_eq!(renamed.inode, created.inode);
+        assert_eq!(renamed.name, new_name.as_bytes());
+        Ok(())
+    }
 }
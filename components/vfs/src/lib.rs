@@ -1,11 +1,16 @@
+mod accesslog;
+mod backup;
 mod config;
+mod control;
 
 pub use config::Config;
 mod err;
+mod freed_slices;
 mod handle;
 mod kiseki;
 pub use kiseki::KisekiVFS;
 mod data_manager;
 mod reader;
+mod stats;
 mod writer;
 // mod writer_v1;
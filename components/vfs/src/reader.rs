@@ -92,6 +92,18 @@ impl DataManager {
         return fr;
     }
 
+    /// [forget_readers] drops the reader map of the inode once all of its
+    /// readers have been closed.
+    pub(crate) async fn forget_readers(self: &Arc<Self>, inode: Ino) {
+        // nobody waits for the outer lock while holding an inner one.
+        let mut out_write_guard = self.file_readers.write().await;
+        if let Some(inner_map) = out_write_guard.get(&inode) {
+            if inner_map.read().await.is_empty() {
+                out_write_guard.remove(&inode);
+            }
+        }
+    }
+
     /// [truncate_reader] is called when we modify the file length.
     /// Invoke this method to truncate the file reader's length.
     pub(crate) async fn truncate_reader(self: &Arc<Self>, inode: Ino, length: u64) {
@@ -253,6 +265,9 @@ impl FileReader {
                             dst[i - chunk_pos] = 0;
                         }
                     }
+                    VirtualSlice::Slice(s) if s.is_hole() => {
+                        dst[start..end].fill(0);
+                    }
                     VirtualSlice::Slice(s) => {
                         debug!(
                             "find slice in chunk: {:?}, range: {:?}, slice: {:?}, write buf \
@@ -260,13 +275,15 @@ impl FileReader {
                             chunk_idx, r, s
                         );
                         let sid = s.get_id();
+                        // where the range starts in the underlying data of the slice.
+                        let slice_offset = s.get_off() + r.start - s.get_chunk_pos();
 
                         let read_len = read_slice_from_cache(
                             sid,
                             engine.file_cache.clone(),
                             engine.mem_cache.clone(),
                             s.get_underlying_size(),
-                            0,
+                            slice_offset,
                             &mut dst[start..end],
                         )
                         .await?;
@@ -425,6 +442,8 @@ fn cal_object_block_size(length: usize, block_idx: BlockIndex, block_size: Block
 
 #[cfg(test)]
 mod tests {
+    use std::ffi::OsStr;
+
     use kiseki_meta::{context::FuseContext, MetaConfig};
     use kiseki_types::{ino::ROOT_INO, setting::Format};
     use kiseki_utils::{logger::install_fmt_log, object_storage::new_memory_object_store};
@@ -490,7 +509,7 @@ mod tests {
         let meta_engine = kiseki_meta::open(meta_config).unwrap();
         let fuse_ctx = Arc::new(FuseContext::background());
         let (inode, _attr) = meta_engine
-            .create(fuse_ctx, ROOT_INO, "a", 0o650, 0, 0)
+            .create(fuse_ctx, ROOT_INO, OsStr::new("a"), 0o650, 0, 0)
             .await
             .unwrap();
 
@@ -564,7 +583,7 @@ mod tests {
         let meta_engine = kiseki_meta::open(meta_config).unwrap();
         let fuse_ctx = Arc::new(FuseContext::background());
         let (inode, _attr) = meta_engine
-            .create(fuse_ctx, ROOT_INO, "a", 0o650, 0, 0)
+            .create(fuse_ctx, ROOT_INO, OsStr::new("a"), 0o650, 0, 0)
             .await
             .unwrap();
 
@@ -0,0 +1,87 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::KisekiVFS;
+
+/// [VfsStats] counts what the mount has served since it starts, the
+/// `.stats` in its root shows them along with the counters of the data path.
+#[derive(Debug, Default)]
+pub(crate) struct VfsStats {
+    fuse_ops:        AtomicU64,
+    // the sum of the latencies of the FUSE operations, in microseconds.
+    fuse_latency_us: AtomicU64,
+    read_bytes:      AtomicU64,
+    written_bytes:   AtomicU64,
+}
+
+impl VfsStats {
+    pub(crate) fn served(&self, latency: Duration) {
+        self.fuse_ops.fetch_add(1, Ordering::Relaxed);
+        self.fuse_latency_us
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn read(&self, len: usize) {
+        self.read_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn written(&self, len: usize) {
+        self.written_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+impl KisekiVFS {
+    /// [render_stats] shows the counters as "name value" lines, the counters
+    /// only grow, so the rates come from the differences of two reads.
+    pub(crate) fn render_stats(&self) -> String {
+        let storage = kiseki_storage::get_storage_stats();
+        let meta = self.meta.get_stats();
+        let ratio = |hits: u64, misses: u64| {
+            let ratio = if hits + misses == 0 {
+                0.0
+            } else {
+                hits as f64 / (hits + misses) as f64
+            };
+            format!("{ratio:.4}")
+        };
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed).to_string();
+        let stats = [
+            ("fuse_ops", load(&self.stats.fuse_ops)),
+            ("fuse_latency_us", load(&self.stats.fuse_latency_us)),
+            ("read_bytes", load(&self.stats.read_bytes)),
+            ("written_bytes", load(&self.stats.written_bytes)),
+            ("meta_ops", meta.calls.to_string()),
+            ("meta_latency_us", meta.latency_us.to_string()),
+            ("open_handles", self.handle_table.count().to_string()),
+            (
+                "buffer_pool_used_ratio",
+                format!("{:.4}", 1.0 - kiseki_storage::get_pool_free_ratio()),
+            ),
+            ("mem_cache_hits", storage.mem_cache_hits.to_string()),
+            ("mem_cache_misses", storage.mem_cache_misses.to_string()),
+            ("mem_cache_waits", storage.mem_cache_waits.to_string()),
+            (
+                "mem_cache_hit_ratio",
+                ratio(storage.mem_cache_hits, storage.mem_cache_misses),
+            ),
+            ("stage_cache_hits", storage.stage_cache_hits.to_string()),
+            ("stage_cache_misses", storage.stage_cache_misses.to_string()),
+            (
+                "stage_cache_hit_ratio",
+                ratio(storage.stage_cache_hits, storage.stage_cache_misses),
+            ),
+            ("object_gets", storage.object_gets.to_string()),
+            ("object_get_bytes", storage.object_get_bytes.to_string()),
+            ("object_puts", storage.object_puts.to_string()),
+            ("object_put_bytes", storage.object_put_bytes.to_string()),
+        ];
+        let mut buf = String::new();
+        for (name, value) in stats {
+            let _ = writeln!(buf, "kiseki_{name} {value}");
+        }
+        buf
+    }
+}
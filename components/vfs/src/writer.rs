@@ -140,6 +140,11 @@ impl DataManager {
         }
         Ok(())
     }
+
+    /// [writing_inodes] returns the files being written.
+    pub(crate) fn writing_inodes(&self) -> Vec<Ino> {
+        self.file_writers.iter().map(|fw| *fw.key()).collect()
+    }
 }
 
 type InternalSliceSeq = u64;